@@ -1,4 +1,4 @@
-use crate::{Db, Error, Event, Task, User};
+use crate::{Db, Error, Event, EventId, Task, User, UserId};
 
 #[derive(
     Clone,
@@ -11,6 +11,7 @@ use crate::{Db, Error, Event, Task, User};
 )]
 pub enum Action {
     NewUser(User),
+    DeletedUser(UserId),
     NewTask(
         Task,
         #[generator(bolero::gen_with::<String>().len(0..100usize))] String,
@@ -23,6 +24,7 @@ impl Action {
     pub async fn is_authorized<D: Db>(&self, db: &mut D) -> anyhow::Result<bool> {
         match self {
             Action::NewUser(_) => Ok(false), // Only admin can create a user for now
+            Action::DeletedUser(_) => Ok(false),
             Action::NewTask(t, _) => Ok(t.owner_id == db.current_user()),
             Action::NewEvent(e) => e.is_authorized(db).await,
         }
@@ -34,7 +36,7 @@ impl Action {
     /// to this in order to ensure safety of your code. (parsing is better than validation)
     pub fn validate(&self) -> Result<(), Error> {
         match self {
-            Action::NewUser(_) => Err(Error::PermissionDenied),
+            Action::NewUser(_) | Action::DeletedUser(_) => Err(Error::PermissionDenied),
             Action::NewTask(t, top_comm) => {
                 crate::validate_string(&top_comm)?;
                 t.validate()
@@ -42,4 +44,15 @@ impl Action {
             Action::NewEvent(e) => e.validate(),
         }
     }
+
+    /// Returns the id of the event that gets committed along with this action, if any
+    ///
+    /// This is what allows checking whether an action was already committed to the server.
+    pub fn committed_event_id(&self) -> Option<EventId> {
+        match self {
+            Action::NewUser(_) | Action::DeletedUser(_) => None,
+            Action::NewTask(t, _) => Some(t.top_comment_id),
+            Action::NewEvent(e) => Some(e.id),
+        }
+    }
 }
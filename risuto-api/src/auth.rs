@@ -19,15 +19,21 @@ pub struct NewSession {
 impl NewSession {
     pub fn new(user: String, password: String, device: String) -> NewSession {
         NewSession {
-            pow: bcrypt::hash_with_salt(&password, BCRYPT_POW_COST, [0; 16])
-                .expect("failed hashing password")
-                .to_string(),
+            pow: NewSession::compute_pow(&password),
             user,
             password,
             device,
         }
     }
 
+    /// Computes the proof of work for `password`. This is slow by design, so callers that
+    /// cannot afford blocking should run it in the background.
+    pub fn compute_pow(password: &str) -> String {
+        bcrypt::hash_with_salt(password, BCRYPT_POW_COST, [0; 16])
+            .expect("failed hashing password")
+            .to_string()
+    }
+
     pub fn validate_except_pow(&self) -> Result<(), Error> {
         crate::validate_string(&self.user)?;
         crate::validate_string(&self.password)?;
@@ -93,6 +99,63 @@ impl AuthInfo {
     }
 }
 
+/// Named sets of permissions, to avoid having users configure each `AuthInfo` bit separately
+#[derive(
+    Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, serde::Deserialize, serde::Serialize,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum Role {
+    /// Can see the tasks and their comments
+    Viewer,
+
+    /// Can additionally comment on the tasks
+    Commenter,
+
+    /// Can additionally edit, triage and archive the tasks
+    Editor,
+
+    /// Can do everything the owner of the tag can do
+    Admin,
+}
+
+impl Role {
+    pub const ALL: [Role; 4] = [Role::Viewer, Role::Commenter, Role::Editor, Role::Admin];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Role::Viewer => "Viewer",
+            Role::Commenter => "Commenter",
+            Role::Editor => "Editor",
+            Role::Admin => "Admin",
+        }
+    }
+
+    pub fn auth_info(&self) -> AuthInfo {
+        let at_least = |r: Role| *self >= r;
+        AuthInfo {
+            can_read: true,
+            can_comment: at_least(Role::Commenter),
+            can_edit: at_least(Role::Editor),
+            can_triage: at_least(Role::Editor),
+            can_archive: at_least(Role::Editor),
+            can_relabel_to_any: at_least(Role::Admin),
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Role> {
+        Role::ALL
+            .into_iter()
+            .find(|r| r.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Returns the role exactly matching `auth`, if any
+    ///
+    /// Permissions set before roles existed may not match any role.
+    pub fn from_auth_info(auth: &AuthInfo) -> Option<Role> {
+        Role::ALL.into_iter().find(|r| r.auth_info() == *auth)
+    }
+}
+
 impl BitOr for AuthInfo {
     type Output = Self;
 
@@ -108,3 +171,52 @@ impl BitOr for AuthInfo {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roles_grant_increasing_permissions() {
+        let viewer = Role::Viewer.auth_info();
+        assert!(viewer.can_read);
+        assert!(!viewer.can_comment && !viewer.can_edit && !viewer.can_relabel_to_any);
+
+        let commenter = Role::Commenter.auth_info();
+        assert!(commenter.can_read && commenter.can_comment);
+        assert!(!commenter.can_edit && !commenter.can_triage && !commenter.can_archive);
+
+        let editor = Role::Editor.auth_info();
+        assert!(editor.can_comment && editor.can_edit && editor.can_triage && editor.can_archive);
+        assert!(!editor.can_relabel_to_any);
+
+        assert_eq!(Role::Admin.auth_info(), AuthInfo::owner());
+
+        for w in Role::ALL.windows(2) {
+            let (lower, higher) = (w[0].auth_info(), w[1].auth_info());
+            assert_eq!(lower | higher, higher, "{:?} loses permissions", w[1]);
+        }
+    }
+
+    #[test]
+    fn roles_round_trip_through_auth_info() {
+        for r in Role::ALL {
+            assert_eq!(Role::from_auth_info(&r.auth_info()), Some(r));
+        }
+        let legacy = AuthInfo {
+            can_edit: true,
+            ..Role::Viewer.auth_info()
+        };
+        assert_eq!(Role::from_auth_info(&legacy), None);
+        assert_eq!(Role::from_auth_info(&AuthInfo::none()), None);
+    }
+
+    #[test]
+    fn roles_parse_from_their_name() {
+        for r in Role::ALL {
+            assert_eq!(Role::parse(r.name()), Some(r));
+        }
+        assert_eq!(Role::parse("editor"), Some(Role::Editor));
+        assert_eq!(Role::parse("owner"), None);
+    }
+}
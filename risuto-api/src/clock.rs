@@ -0,0 +1,44 @@
+use std::sync::{Arc, Mutex};
+
+use crate::Time;
+
+/// Source of the current time
+///
+/// The default clock follows the system time. A clock built with `Clock::manual` instead stays
+/// at the time it is given until explicitly moved, so that time-dependent behavior like day
+/// rollover or reminders can be tested deterministically. Clones of a manual clock share the
+/// same time.
+#[derive(Clone, Debug, Default)]
+pub struct Clock(Option<Arc<Mutex<Time>>>);
+
+impl Clock {
+    pub fn system() -> Clock {
+        Clock(None)
+    }
+
+    pub fn manual(start: Time) -> Clock {
+        Clock(Some(Arc::new(Mutex::new(start))))
+    }
+
+    pub fn now(&self) -> Time {
+        match &self.0 {
+            None => chrono::Utc::now(),
+            Some(t) => *t.lock().expect("clock mutex got poisoned"),
+        }
+    }
+
+    /// Moves a manual clock to `t`
+    ///
+    /// Panics if this clock follows the system time.
+    pub fn set(&self, t: Time) {
+        let clock = self.0.as_ref().expect("tried setting the system clock");
+        *clock.lock().expect("clock mutex got poisoned") = t;
+    }
+
+    /// Moves a manual clock forward by `d`
+    ///
+    /// Panics if this clock follows the system time.
+    pub fn advance(&self, d: chrono::Duration) {
+        self.set(self.now() + d);
+    }
+}
@@ -0,0 +1,226 @@
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveTime, TimeZone};
+
+use crate::{Error, Time};
+
+const MINUTES_PER_DAY: u16 = 24 * 60;
+
+/// Time at which a user's days start, eg. 3am for someone who wants tasks scheduled at 1am to
+/// still show up in Today the evening before
+#[derive(
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    Eq,
+    Hash,
+    PartialEq,
+    arbitrary::Arbitrary,
+    bolero::generator::TypeGenerator,
+    serde::Deserialize,
+    serde::Serialize,
+)]
+pub struct DayStart {
+    /// Minutes after midnight
+    pub minutes: u16,
+}
+
+impl DayStart {
+    pub const MIDNIGHT: DayStart = DayStart { minutes: 0 };
+
+    pub fn validate(&self) -> Result<(), Error> {
+        match self.minutes < MINUTES_PER_DAY {
+            true => Ok(()),
+            false => Err(Error::IntegerOutOfRange(i64::from(self.minutes))),
+        }
+    }
+
+    /// Returns the day `t` belongs to, as seen from `tz`
+    pub fn day_of<Tz: TimeZone>(&self, t: &Time, tz: &Tz) -> NaiveDate {
+        (t.with_timezone(tz).naive_local() - Duration::minutes(i64::from(self.minutes))).date()
+    }
+
+    /// Returns the first instant of `date` in `tz`
+    ///
+    /// When DST transitions make the start time not exist on that date, the day starts at the
+    /// first existing quarter of an hour after it.
+    // See https://github.com/chronotope/chrono/issues/948
+    pub fn start_of<Tz>(&self, date: NaiveDate, tz: &Tz) -> DateTime<Tz>
+    where
+        Tz: Clone + std::fmt::Debug + TimeZone,
+    {
+        let base = date.and_time(NaiveTime::MIN) + Duration::minutes(i64::from(self.minutes));
+        for multiple in 0..=24 {
+            let start = base + Duration::minutes(multiple * 15);
+            match start.and_local_timezone(tz.clone()) {
+                chrono::LocalResult::None => continue,
+                chrono::LocalResult::Single(dt) => return dt,
+                chrono::LocalResult::Ambiguous(dt1, dt2) => {
+                    if dt1.naive_utc() < dt2.naive_utc() {
+                        return dt1;
+                    } else {
+                        return dt2;
+                    }
+                }
+            }
+        }
+
+        panic!(
+            "Unable to calculate start time for date {} and time zone {:?}",
+            date, tz
+        )
+    }
+
+    /// Returns the start of the day `day_offset` days after the one `now` belongs to
+    pub fn start_relative<Tz>(
+        &self,
+        now: &Time,
+        tz: &Tz,
+        day_offset: i64,
+    ) -> Result<DateTime<Tz>, Error>
+    where
+        Tz: Clone + std::fmt::Debug + TimeZone,
+    {
+        self.validate()?;
+        let today = self.day_of(now, tz);
+        let date = match day_offset >= 0 {
+            true => today.checked_add_days(chrono::naive::Days::new(day_offset as u64)),
+            false => day_offset
+                .checked_neg()
+                .map(|d| chrono::naive::Days::new(d as u64))
+                .and_then(|offset| today.checked_sub_days(offset)),
+        };
+        // Leave room for `start_of` to look for an existing time after the start of the day
+        match date.filter(|d| d.year() > NaiveDate::MIN.year() && d.year() < NaiveDate::MAX.year())
+        {
+            Some(date) => Ok(self.start_of(date, tz)),
+            None => Err(Error::IntegerOutOfRange(day_offset)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use chrono_tz::Tz;
+
+    use super::*;
+
+    const PARIS: Tz = Tz::Europe__Paris;
+
+    fn paris(y: i32, m: u32, d: u32, h: u32, min: u32) -> Time {
+        PARIS
+            .with_ymd_and_hms(y, m, d, h, min, 0)
+            .single()
+            .expect("ambiguous or non-existent test time")
+            .with_timezone(&Utc)
+    }
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn day_of_near_midnight() {
+        let midnight = DayStart::MIDNIGHT;
+        let three_am = DayStart { minutes: 3 * 60 };
+        // 00:30 in Paris is still the previous day in UTC
+        assert_eq!(
+            midnight.day_of(&paris(2023, 1, 10, 0, 30), &PARIS),
+            date(2023, 1, 10)
+        );
+        assert_eq!(
+            midnight.day_of(&paris(2023, 1, 9, 23, 30), &PARIS),
+            date(2023, 1, 9)
+        );
+        assert_eq!(
+            three_am.day_of(&paris(2023, 1, 10, 2, 30), &PARIS),
+            date(2023, 1, 9)
+        );
+        assert_eq!(
+            three_am.day_of(&paris(2023, 1, 10, 3, 0), &PARIS),
+            date(2023, 1, 10)
+        );
+    }
+
+    #[test]
+    fn today_ends_at_next_day_start() {
+        let three_am = DayStart { minutes: 3 * 60 };
+        // Right after midnight, Today still lasts until 3am
+        assert_eq!(
+            three_am
+                .start_relative(&paris(2023, 1, 10, 0, 30), &PARIS, 1)
+                .unwrap()
+                .with_timezone(&Utc),
+            paris(2023, 1, 10, 3, 0),
+        );
+        assert_eq!(
+            DayStart::MIDNIGHT
+                .start_relative(&paris(2023, 1, 10, 0, 30), &PARIS, 1)
+                .unwrap()
+                .with_timezone(&Utc),
+            paris(2023, 1, 11, 0, 0),
+        );
+    }
+
+    #[test]
+    fn dst_transitions() {
+        // Spring forward: 2023-03-26 02:00 does not exist in Paris
+        let two_am = DayStart { minutes: 2 * 60 };
+        assert_eq!(
+            two_am
+                .start_of(date(2023, 3, 26), &PARIS)
+                .with_timezone(&Utc),
+            paris(2023, 3, 26, 3, 0),
+        );
+        assert_eq!(
+            two_am
+                .start_relative(&paris(2023, 3, 25, 12, 0), &PARIS, 1)
+                .unwrap()
+                .with_timezone(&Utc),
+            paris(2023, 3, 26, 3, 0),
+        );
+        // Fall back: 2023-10-29 02:30 happens twice in Paris, the day starts at the first one
+        let half_past_two = DayStart {
+            minutes: 2 * 60 + 30,
+        };
+        assert_eq!(
+            half_past_two
+                .start_of(date(2023, 10, 29), &PARIS)
+                .with_timezone(&Utc),
+            Utc.with_ymd_and_hms(2023, 10, 29, 0, 30, 0).unwrap(),
+        );
+        // Days around DST transitions are 23 or 25 hours long, not 24
+        let midnight = DayStart::MIDNIGHT;
+        let spring = midnight.start_of(date(2023, 3, 26), &PARIS);
+        let spring_next = midnight.start_of(date(2023, 3, 27), &PARIS);
+        assert_eq!(spring_next - spring, Duration::hours(23));
+        let fall = midnight.start_of(date(2023, 10, 29), &PARIS);
+        let fall_next = midnight.start_of(date(2023, 10, 30), &PARIS);
+        assert_eq!(fall_next - fall, Duration::hours(25));
+    }
+
+    #[test]
+    fn midnight_skipped_by_dst() {
+        // São Paulo sprang forward at midnight, so 2018-11-04 started at 01:00
+        let tz = Tz::America__Sao_Paulo;
+        let start = DayStart::MIDNIGHT.start_of(date(2018, 11, 4), &tz);
+        assert_eq!(
+            start.naive_local(),
+            date(2018, 11, 4).and_hms_opt(1, 0, 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn invalid_day_start() {
+        let now = paris(2023, 1, 10, 12, 0);
+        assert!(DayStart { minutes: 24 * 60 }
+            .start_relative(&now, &PARIS, 0)
+            .is_err());
+        assert!(DayStart::MIDNIGHT
+            .start_relative(&now, &PARIS, i64::MAX)
+            .is_err());
+        assert!(DayStart::MIDNIGHT
+            .start_relative(&now, &PARIS, i64::MIN)
+            .is_err());
+    }
+}
@@ -4,7 +4,7 @@ use anyhow::{anyhow, Context};
 use serde_json::json;
 use uuid::Uuid;
 
-use crate::Time;
+use crate::{EventId, TagId, TaskId, Time};
 
 #[derive(Debug, Eq, PartialEq, thiserror::Error)]
 pub enum Error {
@@ -34,6 +34,24 @@ pub enum Error {
 
     #[error("Integer is out of expected range")]
     IntegerOutOfRange(i64),
+
+    #[error("Uploaded file was rejected: {0}")]
+    InvalidFile(String),
+
+    #[error("Server is in read-only maintenance mode")]
+    ReadOnly,
+
+    #[error("Task not found {0:?}")]
+    TaskNotFound(TaskId),
+
+    #[error("Tag not found {0:?}")]
+    TagNotFound(TagId),
+
+    #[error("Parent comment not found {0:?}")]
+    ParentCommentNotFound(EventId),
+
+    #[error("Event not found {0:?}")]
+    EventNotFound(EventId),
 }
 
 impl Error {
@@ -49,6 +67,12 @@ impl Error {
             Error::InvalidName(_) => StatusCode::BAD_REQUEST,
             Error::InvalidTime(_) => StatusCode::BAD_REQUEST,
             Error::IntegerOutOfRange(_) => StatusCode::BAD_REQUEST,
+            Error::InvalidFile(_) => StatusCode::BAD_REQUEST,
+            Error::ReadOnly => StatusCode::SERVICE_UNAVAILABLE,
+            Error::TaskNotFound(_) => StatusCode::NOT_FOUND,
+            Error::TagNotFound(_) => StatusCode::NOT_FOUND,
+            Error::ParentCommentNotFound(_) => StatusCode::NOT_FOUND,
+            Error::EventNotFound(_) => StatusCode::NOT_FOUND,
         }
     }
 
@@ -96,6 +120,35 @@ impl Error {
                 "type": "integer-out-of-range",
                 "int": i,
             }),
+            Error::InvalidFile(r) => json!({
+                "message": "uploaded file was rejected",
+                "type": "invalid-file",
+                "reason": r,
+            }),
+            Error::ReadOnly => json!({
+                "message": "server is in read-only maintenance mode",
+                "type": "read-only",
+            }),
+            Error::TaskNotFound(t) => json!({
+                "message": "task not found",
+                "type": "task-not-found",
+                "task": t,
+            }),
+            Error::TagNotFound(t) => json!({
+                "message": "tag not found",
+                "type": "tag-not-found",
+                "tag": t,
+            }),
+            Error::ParentCommentNotFound(e) => json!({
+                "message": "parent comment not found",
+                "type": "parent-comment-not-found",
+                "event": e,
+            }),
+            Error::EventNotFound(e) => json!({
+                "message": "event not found",
+                "type": "event-not-found",
+                "event": e,
+            }),
         })
         .expect("serializing conflict")
     }
@@ -151,6 +204,34 @@ impl Error {
                         )
                     })?,
                 ),
+                "invalid-file" => Error::InvalidFile(String::from(
+                    data.get("reason").and_then(|r| r.as_str()).ok_or_else(|| {
+                        anyhow!("error is about an invalid file but no reason was provided")
+                    })?,
+                )),
+                "read-only" => Error::ReadOnly,
+                "task-not-found" => Error::TaskNotFound(
+                    data.get("task")
+                        .and_then(|t| serde_json::from_value(t.clone()).ok())
+                        .ok_or_else(|| anyhow!("error is a task not found without a task"))?,
+                ),
+                "tag-not-found" => Error::TagNotFound(
+                    data.get("tag")
+                        .and_then(|t| serde_json::from_value(t.clone()).ok())
+                        .ok_or_else(|| anyhow!("error is a tag not found without a tag"))?,
+                ),
+                "parent-comment-not-found" => Error::ParentCommentNotFound(
+                    data.get("event")
+                        .and_then(|e| serde_json::from_value(e.clone()).ok())
+                        .ok_or_else(|| {
+                            anyhow!("error is a parent comment not found without an event")
+                        })?,
+                ),
+                "event-not-found" => Error::EventNotFound(
+                    data.get("event")
+                        .and_then(|e| serde_json::from_value(e.clone()).ok())
+                        .ok_or_else(|| anyhow!("error is an event not found without an event"))?,
+                ),
                 _ => return Err(anyhow!("error contents has unknown type")),
             },
         )
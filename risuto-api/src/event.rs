@@ -1,8 +1,9 @@
 use anyhow::Context;
-use chrono::Utc;
 use uuid::Uuid;
 
-use crate::{Db, Error, TagId, TaskId, Time, UserId, STUB_UUID, UUID_TODAY, UUID_UNTAGGED};
+use crate::{
+    Db, Error, Priority, Query, TagId, TaskId, Time, UserId, STUB_UUID, UUID_TODAY, UUID_UNTAGGED,
+};
 
 #[derive(
     Clone,
@@ -35,6 +36,7 @@ impl OrderId {
     Copy,
     Debug,
     Eq,
+    Hash,
     PartialEq,
     bolero::generator::TypeGenerator,
     serde::Deserialize,
@@ -42,6 +44,84 @@ impl OrderId {
 )]
 pub struct EventId(#[generator(bolero::gen_arbitrary())] pub Uuid);
 
+/// Where an event was submitted from
+#[derive(Clone, Debug, Eq, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct EventProvenance {
+    pub event: EventId,
+
+    /// Name of the device whose session submitted the event, if it is still logged in
+    pub device: Option<String>,
+
+    /// Version of the client app that submitted the event, as reported by it
+    pub app_version: Option<String>,
+}
+
+/// Request to be reminded of a comment if nobody else answered it in time, only allowed to its author
+#[derive(Clone, Debug, Eq, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct CommentReminder {
+    pub comment: EventId,
+
+    /// Time at which the task gets scheduled again for the author if there was no reply, `None` to
+    /// cancel the reminder
+    pub remind_at: Option<Time>,
+}
+
+impl CommentReminder {
+    // See comments on other `validate` functions throughout risuto-api
+    pub fn validate(&self) -> Result<(), Error> {
+        match &self.remind_at {
+            None => Ok(()),
+            Some(t) => crate::validate_time(t),
+        }
+    }
+}
+
+/// Replacement of some text in the titles and comments of all the tasks matching a query, only
+/// allowed to admins
+///
+/// Each change is recorded as a `SetTitle` or `EditComment` event by the task's or comment's author.
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct TextReplacement {
+    pub query: Query,
+    pub find: String,
+    pub replace: String,
+    pub titles: bool,
+    pub comments: bool,
+
+    /// Only compute the events that would be submitted, without submitting them
+    pub dry_run: bool,
+}
+
+impl TextReplacement {
+    // See comments on other `validate` functions throughout risuto-api
+    pub fn validate(&self) -> Result<(), Error> {
+        self.query.validate()?;
+        crate::validate_string(&self.find)?;
+        crate::validate_string(&self.replace)
+    }
+}
+
+/// Maximum number of events that can be fetched at once with an `ActivityPage`
+pub const MAX_ACTIVITY_PAGE_SIZE: i64 = 500;
+
+/// Request for a page of the latest events on the tasks visible to the user, most recent first
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct ActivityPage {
+    /// Only return events that come strictly before this one, to fetch the next page
+    pub before: Option<EventId>,
+    pub limit: i64,
+}
+
+impl ActivityPage {
+    // See comments on other `validate` functions throughout risuto-api
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.limit <= 0 || self.limit > MAX_ACTIVITY_PAGE_SIZE {
+            return Err(Error::IntegerOutOfRange(self.limit));
+        }
+        Ok(())
+    }
+}
+
 #[derive(
     Clone,
     Debug,
@@ -76,6 +156,7 @@ pub enum EventData {
     SetArchived(bool),
     BlockedUntil(#[generator(bolero::gen_arbitrary())] Option<Time>),
     ScheduleFor(#[generator(bolero::gen_arbitrary())] Option<Time>),
+    SetPriority(Option<Priority>),
     SetOrder {
         order: OrderId,
         prio: i64,
@@ -86,6 +167,11 @@ pub enum EventData {
         backlog: bool,
     },
     RmTag(TagId),
+    /// Moves the task to a section of one of its tags, `None` to take it out of all sections
+    SetSection {
+        tag: TagId,
+        section: Option<String>,
+    },
     AddComment {
         #[generator(bolero::gen_with::<String>().len(0..100usize))]
         text: String,
@@ -103,11 +189,11 @@ pub enum EventData {
 }
 
 impl Event {
-    pub fn now(owner_id: UserId, task_id: TaskId, data: EventData) -> Event {
+    pub fn at(owner_id: UserId, task_id: TaskId, date: Time, data: EventData) -> Event {
         Event {
             id: EventId(Uuid::new_v4()),
             owner_id,
-            date: Utc::now(),
+            date,
             task_id,
             data,
         }
@@ -140,14 +226,14 @@ impl Event {
         }
         Ok(match self.data {
             EventData::SetTitle { .. } => auth!(self.task_id).can_edit,
-            EventData::SetDone { .. } | EventData::BlockedUntil { .. } => {
-                auth!(self.task_id).can_triage
-            }
+            EventData::SetDone { .. }
+            | EventData::BlockedUntil { .. }
+            | EventData::SetPriority { .. } => auth!(self.task_id).can_triage,
             EventData::SetArchived { .. } => auth!(self.task_id).can_archive,
             EventData::ScheduleFor { .. } | EventData::SetOrder { .. } => {
                 auth!(self.task_id).can_read
             }
-            EventData::AddTag { tag, .. } => {
+            EventData::AddTag { tag, .. } | EventData::SetSection { tag, .. } => {
                 let auth = auth!(self.task_id);
                 auth.can_relabel_to_any
                     || (auth.can_triage
@@ -200,6 +286,7 @@ impl EventData {
             EventData::BlockedUntil(Some(t)) => crate::validate_time(t),
             EventData::ScheduleFor(None) => Ok(()),
             EventData::ScheduleFor(Some(t)) => crate::validate_time(t),
+            EventData::SetPriority(_) => Ok(()),
             EventData::SetOrder { order: _, prio: _ } => Ok(()),
             EventData::AddTag {
                 tag: _,
@@ -207,6 +294,10 @@ impl EventData {
                 backlog: _,
             } => Ok(()),
             EventData::RmTag(_) => Ok(()),
+            EventData::SetSection { tag: _, section } => match section {
+                None => Ok(()),
+                Some(s) => crate::validate_string(s),
+            },
             EventData::AddComment { text, parent_id: _ } => crate::validate_string(text),
             EventData::EditComment {
                 text,
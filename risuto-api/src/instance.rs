@@ -0,0 +1,95 @@
+use crate::Time;
+
+/// Public information about a risuto deployment, to tell deployments apart
+#[derive(Clone, Debug, Eq, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct InstanceInfo {
+    pub name: String,
+    pub logo_url: Option<String>,
+    pub registration: RegistrationPolicy,
+
+    /// Maximum size in bytes of uploaded files
+    pub max_upload_size: usize,
+
+    /// Oldest client version the server still supports, older clients should ask for a refresh
+    pub min_client_version: Option<String>,
+
+    /// Whether the instance is in maintenance mode, rejecting all changes until it is lifted
+    pub read_only: bool,
+
+    /// Whether the instance collects the anonymous usage counters users can opt into sending,
+    /// set from the `[telemetry]` section of the server's config
+    pub telemetry: bool,
+}
+
+impl Default for InstanceInfo {
+    fn default() -> InstanceInfo {
+        InstanceInfo {
+            name: String::from("risuto"),
+            logo_url: None,
+            registration: RegistrationPolicy::Closed,
+            max_upload_size: 256 * 1024,
+            min_client_version: None,
+            read_only: false,
+            telemetry: false,
+        }
+    }
+}
+
+impl InstanceInfo {
+    /// Whether a client at `version`, eg. its `CARGO_PKG_VERSION`, is still supported
+    pub fn supports_client(&self, version: &str) -> bool {
+        match &self.min_client_version {
+            None => true,
+            Some(min) => parse_version(version) >= parse_version(min),
+        }
+    }
+}
+
+/// Parses a `major.minor.patch` version into comparable numbers, ignoring any suffix
+fn parse_version(v: &str) -> [u64; 3] {
+    let mut res = [0; 3];
+    let numbers = v.split(['-', '+']).next().unwrap_or("").split('.');
+    for (r, n) in res.iter_mut().zip(numbers) {
+        *r = n.parse().unwrap_or(0);
+    }
+    res
+}
+
+/// What changed for users in a version of the server, served by `/api/changelog`
+#[derive(Clone, Debug, Eq, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct ChangelogEntry {
+    pub version: String,
+
+    /// When this version first started on the instance
+    pub installed_at: Time,
+
+    pub notes: Vec<String>,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RegistrationPolicy {
+    /// No new accounts are being created
+    Closed,
+
+    /// Accounts can be requested from the administrator of the instance
+    OnRequest,
+
+    /// Accounts are created through invite links sent by existing users
+    Invite,
+}
+
+impl RegistrationPolicy {
+    pub fn description(&self) -> &'static str {
+        match self {
+            RegistrationPolicy::Closed => "This instance does not accept new accounts.",
+            RegistrationPolicy::OnRequest => {
+                "Accounts on this instance can be requested from its administrator."
+            }
+            RegistrationPolicy::Invite => {
+                "Accounts on this instance are created through invite links from its users."
+            }
+        }
+    }
+}
@@ -1,29 +1,63 @@
 mod action;
 mod auth;
+mod clock;
+mod day;
 mod db;
 mod error;
 mod event;
+mod instance;
+mod migration;
+mod priority;
+mod protocol;
 mod query;
+mod rule;
 mod search;
 mod tag;
 mod task;
+mod telemetry;
 mod user;
 
 pub use action::Action;
-pub use auth::{AuthInfo, AuthToken, NewSession};
+pub use auth::{AuthInfo, AuthToken, NewSession, Role};
 use chrono::Datelike;
+pub use clock::Clock;
+pub use day::DayStart;
 pub use db::Db;
 pub use error::Error;
-pub use event::{Event, EventData, EventId, OrderId};
+pub use event::{
+    ActivityPage, CommentReminder, Event, EventData, EventId, EventProvenance, OrderId,
+    TextReplacement, MAX_ACTIVITY_PAGE_SIZE,
+};
+pub use instance::{ChangelogEntry, InstanceInfo, RegistrationPolicy};
+pub use migration::MigrationStatus;
+pub use priority::Priority;
+pub use protocol::{
+    FeedCloseCode, FeedProtocol, FeedSide, FeedState, FeedTransition, FEED_AUTH_DENIED,
+    FEED_AUTH_OK, FEED_CLOSE_PERMISSION_DENIED, FEED_CLOSE_PROTOCOL_ERROR, FEED_CLOSE_RESYNC,
+    FEED_PATH, FEED_PING, FEED_PING_INTERVAL_SECS,
+};
 pub use query::{Query, TimeQuery};
-pub use search::{Order, OrderType, Search, SearchId};
-pub use tag::{Tag, TagId};
-pub use task::{Task, TaskId};
-pub use user::{NewUser, User, UserId};
+pub use rule::{Rule, RuleAction, RuleId, RuleTrigger, MAX_RULES_PER_USER, MAX_RULE_CHAIN_DEPTH};
+pub use search::{
+    BadgeCounts, Order, OrderType, Search, SearchExport, SearchId, SearchResults,
+    MAX_SEARCHES_PER_USER, MAX_SEARCH_BYTES, MAX_SEARCH_EVENTS, MAX_SEARCH_TASKS,
+};
+pub use tag::{Tag, TagId, TagPermission, TagSections, Tickler};
+pub use task::{expand_title_template, DuplicateCandidate, Task, TaskId};
+pub use telemetry::{
+    action_counter, TelemetryReport, FEATURE_COUNTER_PREFIX, MAX_TELEMETRY_COUNTERS,
+};
+pub use user::{NewInvite, NewUser, Registration, User, UserId, INVITE_VALIDITY_DAYS};
 
 pub use uuid::{uuid, Uuid};
 pub type Time = chrono::DateTime<chrono::Utc>;
 
+/// HTTP header through which clients report their version when submitting actions
+pub const APP_VERSION_HEADER: &str = "risuto-app-version";
+
+/// HTTP header set to `true` or `false` on badges, like `BadgeCounts::fresh`
+pub const COUNTS_FRESH_HEADER: &str = "risuto-counts-fresh";
+
 pub const STUB_UUID: Uuid = uuid!("ffffffff-ffff-ffff-ffff-ffffffffffff");
 
 // picked with a totally fair dice roll
@@ -34,6 +68,8 @@ const UUID_UNTAGGED: Uuid = uuid!("07A66EDa-aaaa-aaaa-aaaa-aaaaaaaaaaaa");
 pub enum FeedMessage {
     Pong, // TODO: this should be replaced with axum::extract::ws::Message::{Ping,Pong}, once ws_stream_wasm also gets them
     Action(Action),
+    /// Multiple actions relayed at once, eg. after a bulk edit, to be applied in a single pass
+    Actions(Vec<Action>),
 }
 
 /// Helper function to easily know whether a string is valid to send to the API
@@ -45,6 +81,21 @@ pub fn validate_string(s: &str) -> Result<(), Error> {
     }
 }
 
+/// Helper function to easily know whether a user name is valid
+pub fn validate_user_name(name: &str) -> Result<(), Error> {
+    validate_string(name)?;
+    if name.chars().any(|c| {
+        !((c >= 'a' && c <= 'z')
+            || (c >= 'A' && c <= 'Z')
+            || (c >= '0' && c <= '9')
+            || c == '_'
+            || c == '-')
+    }) {
+        return Err(Error::InvalidName(String::from(name)));
+    }
+    Ok(())
+}
+
 /// Helper function to easily know whether a timestamp is valid to send to the API
 pub fn validate_time(s: &Time) -> Result<(), Error> {
     let year = s.year();
@@ -55,29 +106,9 @@ pub fn validate_time(s: &Time) -> Result<(), Error> {
     }
 }
 
-// See https://github.com/chronotope/chrono/issues/948
 pub fn midnight_on<Tz>(date: chrono::NaiveDate, tz: &Tz) -> chrono::DateTime<Tz>
 where
     Tz: Clone + std::fmt::Debug + chrono::TimeZone,
 {
-    let base = chrono::NaiveTime::MIN;
-    for multiple in 0..=24 {
-        let start_time = base + chrono::Duration::minutes(multiple * 15);
-        match date.and_time(start_time).and_local_timezone(tz.clone()) {
-            chrono::LocalResult::None => continue,
-            chrono::LocalResult::Single(dt) => return dt,
-            chrono::LocalResult::Ambiguous(dt1, dt2) => {
-                if dt1.naive_utc() < dt2.naive_utc() {
-                    return dt1;
-                } else {
-                    return dt2;
-                }
-            }
-        }
-    }
-
-    panic!(
-        "Unable to calculate start time for date {} and time zone {:?}",
-        date, tz
-    )
+    DayStart::MIDNIGHT.start_of(date, tz)
 }
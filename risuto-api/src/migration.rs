@@ -0,0 +1,7 @@
+/// Schema migration known to the server, along with whether the database already has it
+#[derive(Clone, Debug, Eq, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct MigrationStatus {
+    pub version: i64,
+    pub description: String,
+    pub applied: bool,
+}
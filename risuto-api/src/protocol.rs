@@ -0,0 +1,158 @@
+/// Path of the websocket that relays actions to clients as they happen
+pub const FEED_PATH: &str = "/ws/action-feed";
+
+/// Text frame the server answers a valid session token with
+pub const FEED_AUTH_OK: &str = "ok";
+
+/// Text frame the server answers an invalid session token with, before closing the socket
+pub const FEED_AUTH_DENIED: &str = "permission denied";
+
+/// Text frame clients send to check the connection is still alive, answered with a `Pong`
+pub const FEED_PING: &str = "ping";
+
+/// Interval at which clients should ping the server, considering the connection lost when
+/// there was no `Pong` for twice that long
+pub const FEED_PING_INTERVAL_SECS: u64 = 10;
+
+/// Close code sent by the server after `FEED_AUTH_DENIED`
+pub const FEED_CLOSE_PERMISSION_DENIED: u16 = 4001;
+
+/// Close code sent by the server when the client sent something it did not expect
+pub const FEED_CLOSE_PROTOCOL_ERROR: u16 = 1002;
+
+/// Close code sent by the server when what the user can see changed in a way actions cannot
+/// convey, eg. a tag got shared with them, so that clients reconnect and fetch everything again
+pub const FEED_CLOSE_RESYNC: u16 = 4002;
+
+/// Machine-readable description of the action feed, served at `/api/feed-protocol`
+///
+/// This is meant for authors of third-party clients, along with the `feed-conformance`
+/// binary of the `tests` crate that checks a client follows it.
+#[derive(Clone, Debug, Eq, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct FeedProtocol {
+    /// Bumped upon any change that existing clients cannot handle
+    pub version: u32,
+    pub path: String,
+    pub ping_interval_secs: u64,
+    /// State a freshly opened websocket is in
+    pub initial_state: String,
+    pub states: Vec<FeedState>,
+    pub close_codes: Vec<FeedCloseCode>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct FeedState {
+    pub name: String,
+    pub description: String,
+    pub transitions: Vec<FeedTransition>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct FeedTransition {
+    pub sender: FeedSide,
+    /// Websocket frame that triggers the transition, eg. `text "ping"`
+    pub frame: String,
+    /// Name of the state the feed is in after this frame
+    pub to: String,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FeedSide {
+    Client,
+    Server,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct FeedCloseCode {
+    pub code: u16,
+    pub description: String,
+}
+
+fn transition(sender: FeedSide, frame: &str, to: &str) -> FeedTransition {
+    FeedTransition {
+        sender,
+        frame: String::from(frame),
+        to: String::from(to),
+    }
+}
+
+impl FeedProtocol {
+    /// Describes the protocol implemented by this version of risuto
+    pub fn current() -> FeedProtocol {
+        use FeedSide::*;
+        let token = "text <session token, as a hyphenated lowercase uuid>";
+        let auth_ok = format!("text {FEED_AUTH_OK:?}");
+        let auth_denied = format!("text {FEED_AUTH_DENIED:?}");
+        let ping = format!("text {FEED_PING:?}");
+        FeedProtocol {
+            version: 1,
+            path: String::from(FEED_PATH),
+            ping_interval_secs: FEED_PING_INTERVAL_SECS,
+            initial_state: String::from("auth"),
+            states: vec![
+                FeedState {
+                    name: String::from("auth"),
+                    description: String::from(
+                        "The client must authenticate by sending the token it got from /api/auth",
+                    ),
+                    transitions: vec![transition(Client, token, "auth-reply")],
+                },
+                FeedState {
+                    name: String::from("auth-reply"),
+                    description: String::from("The server checks the session token"),
+                    transitions: vec![
+                        transition(Server, &auth_ok, "relaying"),
+                        transition(Server, &auth_denied, "closed"),
+                    ],
+                },
+                FeedState {
+                    name: String::from("relaying"),
+                    description: String::from(
+                        "The server relays the actions the user can see as JSON FeedMessages, \
+                         and answers pings with a Pong FeedMessage. Actions that happened before \
+                         authentication are not relayed, so clients should fetch the data they \
+                         display only once authenticated.",
+                    ),
+                    transitions: vec![
+                        transition(Server, "binary <json FeedMessage::Action>", "relaying"),
+                        transition(Server, "binary <json FeedMessage::Actions>", "relaying"),
+                        transition(Client, &ping, "relaying"),
+                        transition(Server, "binary <json FeedMessage::Pong>", "relaying"),
+                        transition(Client, "close", "closed"),
+                        transition(Client, "any other frame", "closed"),
+                        transition(Server, "close", "closed"),
+                    ],
+                },
+                FeedState {
+                    name: String::from("closed"),
+                    description: String::from(
+                        "To resume, clients open a new websocket and authenticate again. Actions \
+                         relayed in-between are lost, so clients should then fetch again the \
+                         data they display, and use /api/committed-events to know which of the \
+                         actions they were submitting went through. Clients should not retry \
+                         with a session token that was denied.",
+                    ),
+                    transitions: vec![],
+                },
+            ],
+            close_codes: vec![
+                FeedCloseCode {
+                    code: FEED_CLOSE_PERMISSION_DENIED,
+                    description: String::from("The session token was invalid or revoked"),
+                },
+                FeedCloseCode {
+                    code: FEED_CLOSE_PROTOCOL_ERROR,
+                    description: String::from("The client sent a frame it should not have"),
+                },
+                FeedCloseCode {
+                    code: FEED_CLOSE_RESYNC,
+                    description: String::from(
+                        "The data the user can see changed, eg. a tag got shared with them, and \
+                         clients should reconnect to fetch it again",
+                    ),
+                },
+            ],
+        }
+    }
+}
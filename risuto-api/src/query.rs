@@ -1,4 +1,7 @@
-use crate::{Error, TagId, Time};
+use crate::{DayStart, Error, Priority, TagId, Time};
+
+/// About a thousand years either way
+const MAX_DAY_OFFSET: i64 = 366 * 1000;
 
 #[derive(
     Clone,
@@ -13,39 +16,48 @@ use crate::{Error, TagId, Time};
 pub enum TimeQuery {
     Absolute(#[generator(bolero::gen_arbitrary())] Time),
 
-    /// Offset today().and_hms(0, 0, 0) by day_offset days
+    /// Start of the day `day_offset` days after today
     DayRelative {
         #[generator(bolero::gen_arbitrary())]
         timezone: chrono_tz::Tz,
         day_offset: i64,
+        #[serde(default)]
+        day_start: DayStart,
     },
 }
 
 impl TimeQuery {
+    /// Checks the query independently of the current time
+    ///
+    /// Relative queries are bounded so that they can be evaluated at any realistic date.
     pub fn validate(&self) -> Result<(), Error> {
-        crate::validate_time(&self.eval_now()?)
+        match self {
+            TimeQuery::Absolute(t) => crate::validate_time(t),
+            TimeQuery::DayRelative {
+                timezone: _,
+                day_offset,
+                day_start,
+            } => {
+                day_start.validate()?;
+                match day_offset.checked_abs() {
+                    Some(o) if o <= MAX_DAY_OFFSET => Ok(()),
+                    _ => Err(Error::IntegerOutOfRange(*day_offset)),
+                }
+            }
+        }
     }
 
-    pub fn eval_now(&self) -> Result<Time, Error> {
+    /// Evaluates the query as if the current time were `now`
+    pub fn eval_at(&self, now: &Time) -> Result<Time, Error> {
         match self {
             TimeQuery::Absolute(t) => Ok(*t),
             TimeQuery::DayRelative {
                 timezone,
                 day_offset,
-            } => {
-                // TODO: for safety, see (currently open) https://github.com/chronotope/chrono/pull/927
-                let date = chrono::Utc::now().date_naive();
-                let date = match *day_offset >= 0 {
-                    true => date.checked_add_days(chrono::naive::Days::new(*day_offset as u64)),
-                    false => day_offset
-                        .checked_neg()
-                        .map(|d| chrono::naive::Days::new(d as u64))
-                        .and_then(|offset| date.checked_sub_days(offset)),
-                };
-                date.map(|d| crate::midnight_on(d, timezone))
-                    .map(|d| d.with_timezone(&chrono::Utc))
-                    .ok_or(Error::IntegerOutOfRange(*day_offset))
-            }
+                day_start,
+            } => day_start
+                .start_relative(now, timezone, *day_offset)
+                .map(|d| d.with_timezone(&chrono::Utc)),
         }
     }
 }
@@ -69,12 +81,27 @@ pub enum Query {
     Not(#[generator(bolero::gen_arbitrary())] Box<Query>),
     Archived(bool),
     Done(bool),
-    Tag { tag: TagId, backlog: Option<bool> },
+    Tag {
+        tag: TagId,
+        backlog: Option<bool>,
+    },
+    /// Archived tags count as tags, so a task only in archived tags is not untagged
     Untagged(bool),
+    /// Tasks in at least one archived tag the user can see if true, in none of them otherwise,
+    /// as of when the query is evaluated
+    TagArchived(bool),
     ScheduledForBefore(TimeQuery),
     ScheduledForAfter(TimeQuery),
     BlockedUntilAtMost(TimeQuery),
     BlockedUntilAtLeast(TimeQuery),
+    /// Tasks whose last event happened before this time
+    LastEventBefore(TimeQuery),
+    /// Tasks whose last event happened at or after this time
+    LastEventAfter(TimeQuery),
+    /// Tasks with a priority at least as urgent as this one
+    PriorityAtLeast(Priority),
+    /// Tasks with a priority at most as urgent as this one
+    PriorityAtMost(Priority),
     Phrase(#[generator(bolero::gen_with::<String>().len(0..15usize))] String), // full-text search of one contiguous word vec
 }
 
@@ -102,11 +129,40 @@ impl Query {
             Query::Done(_) => Ok(()),
             Query::Tag { tag: _, backlog: _ } => Ok(()),
             Query::Untagged(_) => Ok(()),
+            Query::TagArchived(_) => Ok(()),
             Query::ScheduledForBefore(t) => t.validate(),
             Query::ScheduledForAfter(t) => t.validate(),
             Query::BlockedUntilAtMost(t) => t.validate(),
             Query::BlockedUntilAtLeast(t) => t.validate(),
+            Query::LastEventBefore(t) => t.validate(),
+            Query::LastEventAfter(t) => t.validate(),
+            Query::PriorityAtLeast(_) => Ok(()),
+            Query::PriorityAtMost(_) => Ok(()),
             Query::Phrase(s) => crate::validate_string(s),
         }
     }
+
+    /// Tags the query refers to, possibly with duplicates
+    pub fn tags(&self) -> Vec<TagId> {
+        match self {
+            Query::Any(queries) | Query::All(queries) => {
+                queries.iter().flat_map(|q| q.tags()).collect()
+            }
+            Query::Not(q) => q.tags(),
+            Query::Tag { tag, backlog: _ } => vec![*tag],
+            Query::Archived(_)
+            | Query::Done(_)
+            | Query::Untagged(_)
+            | Query::TagArchived(_)
+            | Query::ScheduledForBefore(_)
+            | Query::ScheduledForAfter(_)
+            | Query::BlockedUntilAtMost(_)
+            | Query::BlockedUntilAtLeast(_)
+            | Query::LastEventBefore(_)
+            | Query::LastEventAfter(_)
+            | Query::PriorityAtLeast(_)
+            | Query::PriorityAtMost(_)
+            | Query::Phrase(_) => Vec::new(),
+        }
+    }
 }
@@ -0,0 +1,134 @@
+use crate::{Action, Error, EventData, Query, TagId, TaskId, TimeQuery, UserId, Uuid};
+
+/// Maximum number of rule applications chained after a single action, rule applications being
+/// actions that can themselves trigger rules
+pub const MAX_RULE_CHAIN_DEPTH: usize = 4;
+
+/// Maximum number of rules a single user can have, as each action may check all of them
+pub const MAX_RULES_PER_USER: usize = 64;
+
+#[derive(
+    Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, serde::Deserialize, serde::Serialize,
+)]
+pub struct RuleId(pub Uuid);
+
+/// Automation of a user: when `trigger` happens on a task matching `filter`, `action` gets applied
+/// to it on behalf of the rule's owner
+#[derive(Clone, Debug, Eq, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct Rule {
+    pub id: RuleId,
+    pub owner_id: UserId,
+    pub name: String,
+    pub enabled: bool,
+    pub trigger: RuleTrigger,
+    /// Tasks the rule applies to, as seen by its owner once the triggering action got applied
+    pub filter: Query,
+    pub action: RuleAction,
+}
+
+impl Rule {
+    /// Tags the rule refers to, that its owner must be able to see
+    pub fn tags(&self) -> Vec<TagId> {
+        let mut tags = self.filter.tags();
+        if let RuleAction::AddTag(tag) = &self.action {
+            tags.push(*tag);
+        }
+        tags.sort_unstable();
+        tags.dedup();
+        tags
+    }
+
+    pub fn validate(&self) -> Result<(), Error> {
+        crate::validate_string(&self.name)?;
+        self.filter.validate()?;
+        match &self.action {
+            RuleAction::AddTag(_) | RuleAction::Notify => Ok(()),
+            RuleAction::ScheduleFor(t) => t.validate(),
+        }
+    }
+}
+
+/// Kind of action that makes a rule consider the task it happened on
+#[derive(
+    Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, serde::Deserialize, serde::Serialize,
+)]
+pub enum RuleTrigger {
+    NewTask,
+    SetTitle,
+    SetDone,
+    SetArchived,
+    BlockedUntil,
+    ScheduleFor,
+    SetPriority,
+    AddTag,
+    RmTag,
+    AddComment,
+}
+
+impl RuleTrigger {
+    pub const ALL: [RuleTrigger; 10] = [
+        RuleTrigger::NewTask,
+        RuleTrigger::SetTitle,
+        RuleTrigger::SetDone,
+        RuleTrigger::SetArchived,
+        RuleTrigger::BlockedUntil,
+        RuleTrigger::ScheduleFor,
+        RuleTrigger::SetPriority,
+        RuleTrigger::AddTag,
+        RuleTrigger::RmTag,
+        RuleTrigger::AddComment,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            RuleTrigger::NewTask => "a task is created",
+            RuleTrigger::SetTitle => "a task is renamed",
+            RuleTrigger::SetDone => "a task is marked done or undone",
+            RuleTrigger::SetArchived => "a task is archived or unarchived",
+            RuleTrigger::BlockedUntil => "a task is blocked or unblocked",
+            RuleTrigger::ScheduleFor => "a task is scheduled or unscheduled",
+            RuleTrigger::SetPriority => "a task's priority changes",
+            RuleTrigger::AddTag => "a tag is added to a task",
+            RuleTrigger::RmTag => "a tag is removed from a task",
+            RuleTrigger::AddComment => "a task is commented on",
+        }
+    }
+
+    /// The trigger `a` fires, along with the task it fires on
+    pub fn of(a: &Action) -> Option<(RuleTrigger, TaskId)> {
+        match a {
+            Action::NewUser(_) | Action::DeletedUser(_) => None,
+            Action::NewTask(t, _) => Some((RuleTrigger::NewTask, t.id)),
+            Action::NewEvent(e) => {
+                let trigger = match &e.data {
+                    EventData::SetTitle(_) => RuleTrigger::SetTitle,
+                    EventData::SetDone(_) => RuleTrigger::SetDone,
+                    EventData::SetArchived(_) => RuleTrigger::SetArchived,
+                    EventData::BlockedUntil(_) => RuleTrigger::BlockedUntil,
+                    EventData::ScheduleFor(_) => RuleTrigger::ScheduleFor,
+                    EventData::SetPriority(_) => RuleTrigger::SetPriority,
+                    EventData::AddTag { .. } => RuleTrigger::AddTag,
+                    EventData::RmTag(_) => RuleTrigger::RmTag,
+                    EventData::AddComment { .. } => RuleTrigger::AddComment,
+                    EventData::SetOrder { .. }
+                    | EventData::SetSection { .. }
+                    | EventData::EditComment { .. }
+                    | EventData::SetEventRead { .. } => return None,
+                };
+                Some((trigger, e.task_id))
+            }
+        }
+    }
+}
+
+/// What a rule does to the tasks it applies to
+#[derive(Clone, Debug, Eq, PartialEq, serde::Deserialize, serde::Serialize)]
+pub enum RuleAction {
+    /// Adds the tag at the top of its tasks, outside of the backlog
+    AddTag(TagId),
+    /// Schedules the task for the rule's owner
+    ScheduleFor(TimeQuery),
+    /// Brings the task back to the rule's owner's attention by scheduling it for now, like
+    /// comment reminders do
+    Notify,
+}
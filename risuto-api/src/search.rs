@@ -1,4 +1,90 @@
-use crate::{OrderId, Query, Tag, TagId, TimeQuery, Uuid, STUB_UUID, UUID_TODAY, UUID_UNTAGGED};
+use std::collections::HashMap;
+
+use crate::{
+    DayStart, Error, Event, OrderId, Query, Tag, TagId, Task, TaskId, Time, TimeQuery, Uuid,
+    STUB_UUID, UUID_TODAY, UUID_UNTAGGED,
+};
+
+/// Maximum number of tasks returned by a single task search
+pub const MAX_SEARCH_TASKS: usize = 5_000;
+
+/// Maximum number of events returned by a single task search
+pub const MAX_SEARCH_EVENTS: usize = 100_000;
+
+/// Maximum size in bytes of the JSON tasks and events returned by a single task search
+pub const MAX_SEARCH_BYTES: usize = 16 * 1024 * 1024;
+
+/// Maximum number of saved searches a single user can have
+pub const MAX_SEARCHES_PER_USER: usize = 256;
+
+/// Tasks matching a search, along with all their events
+///
+/// Tasks come most recently created first, each followed by its events in chronological order.
+/// When the matching tasks do not fit within the `MAX_SEARCH_*` limits, only the most recently
+/// created ones that do are returned, and `truncated` is set. This does not depend on the order
+/// the search is displayed in, so truncated results sorted by eg. priority can miss tasks that
+/// would have come first.
+#[derive(Clone, Debug, Eq, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct SearchResults {
+    pub tasks: Vec<Task>,
+    pub events: Vec<Event>,
+    pub truncated: bool,
+}
+
+/// Counts over the results of a shared search, served at `/api/badge/<share token>.json` for
+/// dashboards
+#[derive(Clone, Debug, Eq, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct BadgeCounts {
+    /// Tasks neither done nor archived
+    pub open: i64,
+
+    /// Open tasks that were scheduled for before `computed_at`
+    pub overdue: i64,
+
+    pub computed_at: Time,
+
+    /// Unset when the counts come from the server's cache of slow searches, and tasks changed
+    /// since they were computed
+    pub fresh: bool,
+}
+
+impl SearchResults {
+    /// Sorts `tasks` and their `events`, keeping only the most recently created tasks that fit
+    /// within the limits
+    pub fn capped(mut tasks: Vec<Task>, events: Vec<Event>) -> SearchResults {
+        tasks.sort_unstable_by(|a, b| b.date.cmp(&a.date).then(a.id.cmp(&b.id)));
+        let mut events_by_task = HashMap::<TaskId, Vec<Event>>::new();
+        for e in events {
+            events_by_task.entry(e.task_id).or_default().push(e);
+        }
+        let mut res = SearchResults {
+            tasks: Vec::new(),
+            events: Vec::new(),
+            truncated: false,
+        };
+        let mut bytes = 0;
+        for t in tasks {
+            let mut events = events_by_task.remove(&t.id).unwrap_or_default();
+            events.sort_unstable_by_key(|e| (e.date, e.id.0));
+            let size = json_size(&t) + events.iter().map(json_size).sum::<usize>();
+            if res.tasks.len() >= MAX_SEARCH_TASKS
+                || res.events.len() + events.len() > MAX_SEARCH_EVENTS
+                || bytes + size > MAX_SEARCH_BYTES
+            {
+                res.truncated = true;
+                break;
+            }
+            bytes += size;
+            res.tasks.push(t);
+            res.events.extend(events);
+        }
+        res
+    }
+}
+
+fn json_size<T: serde::Serialize>(t: &T) -> usize {
+    serde_json::to_vec(t).map(|v| v.len()).unwrap_or(0)
+}
 
 #[derive(
     Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd, serde::Deserialize, serde::Serialize,
@@ -40,13 +126,14 @@ impl Search {
         }
     }
 
-    pub fn today(timezone: chrono_tz::Tz) -> Search {
+    pub fn today(timezone: chrono_tz::Tz, day_start: DayStart) -> Search {
         Search {
             id: SearchId::today(),
             name: String::from("Today"),
             filter: Query::ScheduledForBefore(TimeQuery::DayRelative {
                 timezone,
                 day_offset: 1,
+                day_start,
             }),
             order: Order::Custom(OrderId::today()),
             priority: 0,
@@ -91,6 +178,42 @@ impl Search {
     }
 }
 
+/// A saved search as shared between users, eg. as a JSON snippet, to be imported as a new search
+///
+/// A custom order is exported as such, and becomes the imported search's own custom order.
+#[derive(Clone, Debug, Eq, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct SearchExport {
+    pub name: String,
+    pub filter: Query,
+    pub order: Order,
+}
+
+impl SearchExport {
+    pub fn from_search(search: &Search) -> SearchExport {
+        SearchExport {
+            name: search.name.clone(),
+            filter: search.filter.clone(),
+            order: search.order.clone(),
+        }
+    }
+
+    /// Tags the search refers to, that the importing user must be able to see
+    pub fn tags(&self) -> Vec<TagId> {
+        let mut tags = self.filter.tags();
+        if let Order::Tag(tag) = &self.order {
+            tags.push(*tag);
+        }
+        tags.sort_unstable();
+        tags.dedup();
+        tags
+    }
+
+    pub fn validate(&self) -> Result<(), Error> {
+        crate::validate_string(&self.name)?;
+        self.filter.validate()
+    }
+}
+
 #[derive(
     Clone,
     Debug,
@@ -107,6 +230,8 @@ pub enum Order {
     LastEventDate(OrderType),
     ScheduledFor(OrderType),
     BlockedUntil(OrderType),
+    /// Most urgent first, tasks without a priority last
+    Priority,
 }
 
 #[derive(
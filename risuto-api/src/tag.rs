@@ -1,6 +1,6 @@
 use uuid::Uuid;
 
-use crate::{UserId, STUB_UUID};
+use crate::{Error, Role, UserId, STUB_UUID};
 
 #[derive(
     Clone,
@@ -30,4 +30,56 @@ pub struct Tag {
     pub owner_id: UserId,
     pub name: String,
     pub archived: bool,
+
+    /// Names of the sections the tag's list is split into, in display order
+    #[serde(default)]
+    pub sections: Vec<String>,
+}
+
+/// Change of the permissions a user has on a tag, only allowed to the tag's owner
+#[derive(Clone, Debug, Eq, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct TagPermission {
+    pub tag: TagId,
+    pub user: UserId,
+
+    /// Role to give to the user, `None` to revoke all access
+    pub role: Option<Role>,
+}
+
+/// Change of the sections a tag's list is split into, only allowed to the tag's owner
+#[derive(Clone, Debug, Eq, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct TagSections {
+    pub tag: TagId,
+    pub sections: Vec<String>,
+}
+
+impl TagSections {
+    // See comments on other `validate` functions throughout risuto-api
+    pub fn validate(&self) -> Result<(), Error> {
+        for s in &self.sections {
+            crate::validate_string(s)?;
+            if s.trim().is_empty() {
+                return Err(Error::InvalidName(s.clone()));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Configuration of a tag whose archived tasks automatically come back after some time
+#[derive(Clone, Debug, Eq, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct Tickler {
+    pub tag: TagId,
+
+    /// Delay after archival at which tasks get unarchived and scheduled, `None` to disable
+    pub interval_secs: Option<i64>,
+}
+
+impl Tickler {
+    pub fn validate(&self) -> Result<(), Error> {
+        match self.interval_secs {
+            Some(i) if i <= 0 => Err(Error::IntegerOutOfRange(i)),
+            _ => Ok(()),
+        }
+    }
 }
@@ -23,6 +23,16 @@ impl TaskId {
     }
 }
 
+/// Pair of open tasks whose titles are similar enough that they may be duplicates
+#[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct DuplicateCandidate {
+    pub task: TaskId,
+    pub other: TaskId,
+
+    /// Between 0 for completely different titles and 1 for identical ones
+    pub similarity: f32,
+}
+
 #[derive(
     Clone,
     Debug,
@@ -49,3 +59,140 @@ impl Task {
         crate::validate_string(&self.initial_title)
     }
 }
+
+/// Offsets in templates are limited to this many days or weeks, way beyond any sensible use
+const MAX_TEMPLATE_OFFSET: i64 = 100_000;
+
+/// Expands the variables in a task title template
+///
+/// Supported variables are `{user}`, `{date}` and `{week}`, the latter two optionally
+/// followed by an offset like in `{date+3d}` or `{week-1w}`. Unknown variables are kept
+/// as-is, so that titles that just happen to contain braces are left untouched.
+pub fn expand_title_template<Tz>(title: &str, now: &chrono::DateTime<Tz>, user: &str) -> String
+where
+    Tz: chrono::TimeZone,
+{
+    let mut res = String::with_capacity(title.len());
+    let mut rest = title;
+    while let Some(start) = rest.find('{') {
+        res.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let end = match rest.find('}') {
+            Some(end) => end,
+            None => break,
+        };
+        match expand_variable(&rest[1..end], now, user) {
+            Some(expanded) => res.push_str(&expanded),
+            None => res.push_str(&rest[..=end]),
+        }
+        rest = &rest[end + 1..];
+    }
+    res.push_str(rest);
+    res
+}
+
+fn expand_variable<Tz>(var: &str, now: &chrono::DateTime<Tz>, user: &str) -> Option<String>
+where
+    Tz: chrono::TimeZone,
+{
+    if var == "user" {
+        return Some(String::from(user));
+    }
+    let (name, offset) = match var.find(|c| c == '+' || c == '-') {
+        None => (var, chrono::Duration::zero()),
+        Some(i) => (&var[..i], parse_offset(&var[i..])?),
+    };
+    let date = now.date_naive().checked_add_signed(offset)?;
+    match name {
+        "date" => Some(date.format("%Y-%m-%d").to_string()),
+        "week" => Some(date.format("%G-W%V").to_string()),
+        _ => None,
+    }
+}
+
+/// Parses offsets like `+3d` or `-1w`
+fn parse_offset(offset: &str) -> Option<chrono::Duration> {
+    let (negative, offset) = match (offset.strip_prefix('+'), offset.strip_prefix('-')) {
+        (Some(offset), _) => (false, offset),
+        (_, Some(offset)) => (true, offset),
+        _ => return None,
+    };
+    let (unit_idx, unit) = offset.char_indices().last()?;
+    let amount = offset[..unit_idx].parse::<i64>().ok()?;
+    let amount = match negative {
+        true => amount.checked_neg()?,
+        false => amount,
+    };
+    if amount.checked_abs()? > MAX_TEMPLATE_OFFSET {
+        return None;
+    }
+    match unit {
+        'd' => Some(chrono::Duration::days(amount)),
+        'w' => Some(chrono::Duration::weeks(amount)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use super::*;
+
+    #[test]
+    fn offsets_parse() {
+        assert_eq!(parse_offset("+3d"), Some(chrono::Duration::days(3)));
+        assert_eq!(parse_offset("-1w"), Some(chrono::Duration::weeks(-1)));
+        assert_eq!(parse_offset("+0d"), Some(chrono::Duration::zero()));
+        assert_eq!(
+            parse_offset("-100000d"),
+            Some(chrono::Duration::days(-MAX_TEMPLATE_OFFSET))
+        );
+    }
+
+    #[test]
+    fn invalid_offsets_are_rejected() {
+        for offset in [
+            "",
+            "+",
+            "-",
+            "3d",
+            "+d",
+            "+3",
+            "+3m",
+            "+1000000w",
+            "*3d",
+            "+3dd",
+        ] {
+            assert_eq!(parse_offset(offset), None, "accepted {offset:?}");
+        }
+    }
+
+    #[test]
+    fn weird_offsets_do_not_panic() {
+        for offset in [
+            "é3d",
+            "+3é",
+            "-é",
+            "+\u{1F600}",
+            "-9223372036854775808d",
+            "+9223372036854775808d",
+            "--9223372036854775808d",
+        ] {
+            assert_eq!(parse_offset(offset), None, "accepted {offset:?}");
+        }
+    }
+
+    #[test]
+    fn templates_with_weird_offsets_are_kept_as_is() {
+        let now = Utc.with_ymd_and_hms(2023, 1, 9, 12, 0, 0).unwrap();
+        assert_eq!(
+            expand_title_template("review {date+1d} and {date-é}", &now, "alice"),
+            "review 2023-01-10 and {date-é}"
+        );
+        assert_eq!(
+            expand_title_template("{date-9223372036854775808d}", &now, "alice"),
+            "{date-9223372036854775808d}"
+        );
+    }
+}
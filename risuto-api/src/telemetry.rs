@@ -0,0 +1,143 @@
+use std::collections::BTreeMap;
+
+use chrono::NaiveDate;
+
+use crate::{Action, Error, EventData};
+
+/// Maximum number of counters a client can report for a single day
+pub const MAX_TELEMETRY_COUNTERS: usize = 64;
+
+/// Prefix of the counters clients report, the server counting actions by itself
+pub const FEATURE_COUNTER_PREFIX: &str = "feature:";
+
+/// Anonymous usage counters, aggregated per day where they got collected
+///
+/// Counters only ever count how many times something happened, eg. `action:set-done` or
+/// `feature:print-view`, without anything about who did it or on what.
+#[derive(Clone, Debug, Default, Eq, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct TelemetryReport {
+    pub days: BTreeMap<NaiveDate, BTreeMap<String, u64>>,
+}
+
+impl TelemetryReport {
+    pub fn is_empty(&self) -> bool {
+        self.days.is_empty()
+    }
+
+    pub fn record(&mut self, day: NaiveDate, counter: &str) {
+        let count = self
+            .days
+            .entry(day)
+            .or_default()
+            .entry(String::from(counter))
+            .or_default();
+        *count = count.saturating_add(1);
+    }
+
+    pub fn merge(&mut self, other: TelemetryReport) {
+        for (day, counters) in other.days {
+            let day = self.days.entry(day).or_default();
+            for (counter, count) in counters {
+                let total = day.entry(counter).or_default();
+                *total = total.saturating_add(count);
+            }
+        }
+    }
+
+    /// Removes and returns the days before `today`, that will not get counted in anymore
+    pub fn take_before(&mut self, today: NaiveDate) -> TelemetryReport {
+        let from_today = self.days.split_off(&today);
+        TelemetryReport {
+            days: std::mem::replace(&mut self.days, from_today),
+        }
+    }
+
+    /// Checks a report sent by a client only has a reasonable number of feature counters, for
+    /// `today` or the day before
+    ///
+    /// Older days already got sent, and clients only report days once they are over.
+    pub fn validate(&self, today: NaiveDate) -> Result<(), Error> {
+        for (day, counters) in self.days.iter() {
+            if *day != today && Some(*day) != today.pred_opt() {
+                return Err(Error::InvalidTime(crate::midnight_on(*day, &chrono::Utc)));
+            }
+            if counters.len() > MAX_TELEMETRY_COUNTERS {
+                return Err(Error::IntegerOutOfRange(counters.len() as i64));
+            }
+            for counter in counters.keys() {
+                let valid = counter
+                    .strip_prefix(FEATURE_COUNTER_PREFIX)
+                    .map(|name| {
+                        !name.is_empty()
+                            && name.len() <= 64
+                            && name.chars().all(|c| c.is_ascii_lowercase() || c == '-')
+                    })
+                    .unwrap_or(false);
+                if !valid {
+                    return Err(Error::InvalidName(counter.clone()));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Name of the counter that counts actions like `a`
+pub fn action_counter(a: &Action) -> &'static str {
+    match a {
+        Action::NewUser(_) => "action:new-user",
+        Action::DeletedUser(_) => "action:deleted-user",
+        Action::NewTask(..) => "action:new-task",
+        Action::NewEvent(e) => match e.data {
+            EventData::SetTitle(_) => "action:set-title",
+            EventData::SetDone(_) => "action:set-done",
+            EventData::SetArchived(_) => "action:set-archived",
+            EventData::BlockedUntil(_) => "action:blocked-until",
+            EventData::ScheduleFor(_) => "action:schedule-for",
+            EventData::SetPriority(_) => "action:set-priority",
+            EventData::SetOrder { .. } => "action:set-order",
+            EventData::AddTag { .. } => "action:add-tag",
+            EventData::RmTag(_) => "action:rm-tag",
+            EventData::SetSection { .. } => "action:set-section",
+            EventData::AddComment { .. } => "action:add-comment",
+            EventData::EditComment { .. } => "action:edit-comment",
+            EventData::SetEventRead { .. } => "action:set-event-read",
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    fn report(day: NaiveDate, count: u64) -> TelemetryReport {
+        let mut counters = BTreeMap::new();
+        counters.insert(String::from("feature:print-view"), count);
+        let mut days = BTreeMap::new();
+        days.insert(day, counters);
+        TelemetryReport { days }
+    }
+
+    #[test]
+    fn only_today_and_yesterday_are_valid() {
+        let today = date(2023, 3, 1);
+        assert_eq!(report(today, 1).validate(today), Ok(()));
+        assert_eq!(report(date(2023, 2, 28), 1).validate(today), Ok(()));
+        assert!(report(date(2023, 2, 27), 1).validate(today).is_err());
+        assert!(report(date(2023, 3, 2), 1).validate(today).is_err());
+    }
+
+    #[test]
+    fn merging_saturates() {
+        let day = date(2023, 3, 1);
+        let mut total = report(day, u64::MAX - 1);
+        total.merge(report(day, 5));
+        assert_eq!(total, report(day, u64::MAX));
+        total.record(day, "feature:print-view");
+        assert_eq!(total, report(day, u64::MAX));
+    }
+}
@@ -1,4 +1,4 @@
-use crate::{auth::BCRYPT_POW_COST, Error, STUB_UUID};
+use crate::{auth::BCRYPT_POW_COST, Error, Role, TagId, STUB_UUID};
 
 use uuid::Uuid;
 
@@ -35,6 +35,10 @@ impl UserId {
 pub struct User {
     pub id: UserId,
     pub name: String,
+
+    /// Hash of the user's avatar, to be fetched from `/api/avatars/{hash}`
+    #[serde(default)]
+    pub avatar_hash: Option<String>,
 }
 
 #[derive(Clone, Debug, bolero::generator::TypeGenerator, serde::Deserialize, serde::Serialize)]
@@ -61,17 +65,25 @@ impl NewUser {
     /// Note that you should not rely on the fact that a NewUser struct is "valid" according
     /// to this in order to ensure safety of your code. (parsing is better than validation)
     pub fn validate(&self) -> Result<(), Error> {
-        crate::validate_string(&self.name)?;
+        crate::validate_user_name(&self.name)?;
         crate::validate_string(&self.initial_password_hash)?;
-        if self.name.chars().any(|c| {
-            !((c >= 'a' && c <= 'z')
-                || (c >= 'A' && c <= 'Z')
-                || (c >= '0' && c <= '9')
-                || c == '_'
-                || c == '-')
-        }) {
-            return Err(Error::InvalidName(self.name.clone()));
-        }
         Ok(())
     }
 }
+
+/// Invites expire if they did not get used within this many days
+pub const INVITE_VALIDITY_DAYS: i64 = 7;
+
+/// Invitation for someone to create an account, sharing some tags with them upon signup
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct NewInvite {
+    pub tags: Vec<TagId>,
+    pub role: Role,
+}
+
+/// Account creation through an invite token
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct Registration {
+    pub invite: Uuid,
+    pub user: NewUser,
+}
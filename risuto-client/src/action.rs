@@ -0,0 +1,134 @@
+use crate::{
+    api::{Action, Event, EventData, Order, OrderId, TagId, Time, UserId},
+    DbDump, Task,
+};
+
+/// Number of characters of comments shown in action descriptions
+const COMMENT_PREVIEW_LEN: usize = 40;
+
+pub trait ActionExt {
+    /// Human-readable description of the action, eg. for showing it while pending submission
+    ///
+    /// Assumes the action already got applied to `db`.
+    fn describe(&self, db: &DbDump) -> String;
+}
+
+impl ActionExt for Action {
+    fn describe(&self, db: &DbDump) -> String {
+        match self {
+            Action::NewUser(u) => format!("Create user {}", u.name),
+            Action::DeletedUser(u) => format!("Deactivate user {}", user_name(db, u)),
+            Action::NewTask(t, _) => format!("Create task '{}'", t.initial_title),
+            Action::NewEvent(e) => describe_event(db, e),
+        }
+    }
+}
+
+fn describe_event(db: &DbDump, e: &Event) -> String {
+    let task = db.tasks.get(&e.task_id);
+    let title = task
+        .map(|t| format!("'{}'", t.current_title))
+        .unwrap_or_else(|| String::from("an unknown task"));
+    match &e.data {
+        EventData::SetTitle(new) => match task {
+            Some(t) => format!("Rename '{}' → '{new}'", previous_title(t, e)),
+            None => format!("Rename an unknown task to '{new}'"),
+        },
+        EventData::SetDone(true) => format!("Mark {title} as done"),
+        EventData::SetDone(false) => format!("Mark {title} as not done"),
+        EventData::SetArchived(true) => format!("Archive {title}"),
+        EventData::SetArchived(false) => format!("Unarchive {title}"),
+        EventData::BlockedUntil(Some(t)) => format!("Block {title} until {}", date(t)),
+        EventData::BlockedUntil(None) => format!("Unblock {title}"),
+        EventData::ScheduleFor(Some(t)) => format!("Schedule {title} for {}", date(t)),
+        EventData::ScheduleFor(None) => format!("Unschedule {title}"),
+        EventData::SetPriority(Some(p)) => {
+            format!("Set the priority of {title} to {}", p.name().to_uppercase())
+        }
+        EventData::SetPriority(None) => format!("Remove the priority of {title}"),
+        EventData::SetOrder { order, .. } => match order_name(db, order) {
+            Some(name) => format!("Move {title} in {name}"),
+            None => format!("Move {title}"),
+        },
+        EventData::AddTag {
+            tag, backlog: true, ..
+        } => format!("Add {title} to the backlog of {}", tag_name(db, tag)),
+        EventData::AddTag { tag, .. } => format!("Add {title} to {}", tag_name(db, tag)),
+        EventData::RmTag(tag) => format!("Remove {title} from {}", tag_name(db, tag)),
+        EventData::SetSection {
+            tag,
+            section: Some(section),
+        } => format!(
+            "Move {title} to section '{section}' of {}",
+            tag_name(db, tag)
+        ),
+        EventData::SetSection { tag, section: None } => {
+            format!("Take {title} out of the sections of {}", tag_name(db, tag))
+        }
+        EventData::AddComment {
+            text,
+            parent_id: None,
+        } => format!("Comment on {title}: {}", preview(text)),
+        EventData::AddComment { text, .. } => {
+            format!("Reply to a comment on {title}: {}", preview(text))
+        }
+        EventData::EditComment { text, .. } => {
+            format!("Edit a comment on {title}: {}", preview(text))
+        }
+        EventData::SetEventRead { now_read: true, .. } => {
+            format!("Mark a comment on {title} as read")
+        }
+        EventData::SetEventRead {
+            now_read: false, ..
+        } => format!("Mark a comment on {title} as unread"),
+    }
+}
+
+/// Title of the task right before `e` got applied
+fn previous_title(task: &Task, e: &Event) -> String {
+    task.events
+        .range(..=e.date)
+        .rev()
+        .flat_map(|(_, evts)| evts.iter().rev())
+        .filter(|evt| evt.id != e.id)
+        .find_map(|evt| match &evt.data {
+            EventData::SetTitle(title) => Some(title.clone()),
+            _ => None,
+        })
+        .unwrap_or_else(|| (*task.initial_title).clone())
+}
+
+fn user_name(db: &DbDump, user: &UserId) -> String {
+    db.users
+        .get(user)
+        .map(|u| u.name.clone())
+        .unwrap_or_else(|| String::from("an unknown user"))
+}
+
+fn tag_name(db: &DbDump, tag: &TagId) -> String {
+    db.tags
+        .get(tag)
+        .map(|t| format!("#{}", t.name))
+        .unwrap_or_else(|| String::from("an unknown tag"))
+}
+
+/// Name of the search sorted by `order`, if any
+fn order_name(db: &DbDump, order: &OrderId) -> Option<String> {
+    db.searches
+        .values()
+        .find(|s| s.order == Order::Custom(*order))
+        .map(|s| s.name.clone())
+}
+
+fn date(t: &Time) -> String {
+    t.format("%Y-%m-%d").to_string()
+}
+
+fn preview(text: &str) -> String {
+    let mut chars = text.chars();
+    let mut res = chars.by_ref().take(COMMENT_PREVIEW_LEN).collect::<String>();
+    if chars.next().is_some() {
+        res.push('…');
+    }
+    res
+}
@@ -50,6 +50,18 @@ impl Comment {
         }
     }
 
+    /// Lists the creation ids of this comment and its descendants that `user` did not read yet
+    pub fn unread_by(&self, user: &UserId) -> Vec<EventId> {
+        let mut res = Vec::new();
+        if !self.read.contains(user) {
+            res.push(self.creation_id);
+        }
+        for c in self.children.values().flat_map(|v| v.iter()) {
+            res.extend(c.unread_by(user));
+        }
+        res
+    }
+
     pub fn find_in<'a>(
         comments: &'a mut im::OrdMap<Time, im::Vector<Comment>>,
         creation_id: &EventId,
@@ -0,0 +1,38 @@
+use chrono::{Datelike, TimeZone};
+
+use crate::api::Time;
+
+/// Label for `t` relative to `now`, eg. "yesterday 14:00", "in 3 days" or "Jan 15"
+///
+/// Days are counted in `tz`, so that "tomorrow" starts at the user's midnight.
+pub fn relative_label<Tz>(t: &Time, now: &Time, tz: &Tz) -> String
+where
+    Tz: TimeZone,
+    Tz::Offset: std::fmt::Display,
+{
+    let t = t.with_timezone(tz);
+    let now = now.with_timezone(tz);
+    let days = t
+        .date_naive()
+        .signed_duration_since(now.date_naive())
+        .num_days();
+    let time = t.format("%H:%M");
+    match days {
+        0 => format!("today {time}"),
+        1 => format!("tomorrow {time}"),
+        -1 => format!("yesterday {time}"),
+        2..=6 => format!("in {days} days"),
+        -6..=-2 => format!("{} days ago", -days),
+        _ if t.year() == now.year() => t.format("%b %-d").to_string(),
+        _ => t.format("%b %-d, %Y").to_string(),
+    }
+}
+
+/// Full date and time of `t` in `tz`, eg. for tooltips completing a relative label
+pub fn absolute_label<Tz>(t: &Time, tz: &Tz) -> String
+where
+    Tz: TimeZone,
+    Tz::Offset: std::fmt::Display,
+{
+    t.with_timezone(tz).format("%Y-%m-%d %H:%M").to_string()
+}
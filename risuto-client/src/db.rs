@@ -1,15 +1,26 @@
-use std::sync::Arc;
+use std::{
+    collections::{hash_map, HashMap},
+    sync::Arc,
+};
 
 use anyhow::anyhow;
 use async_trait::async_trait;
 use risuto_api::Error;
 
 use crate::{
-    api::{self, AuthInfo, Db, EventId, Search, SearchId, Tag, TagId, TaskId, Time, User, UserId},
-    OrderExt, QueryExt, Task,
+    api::{
+        self, Action, AuthInfo, Db, Event, EventData, EventId, Search, SearchId, Tag, TagId,
+        TaskId, Time, User, UserId,
+    },
+    OrderExt, QueryExt, Task, TaskIndex,
 };
 
-#[derive(Clone, Debug, Eq, PartialEq)]
+/// Local copy of all the data the user has access to
+///
+/// All fields are persistent data structures, so cloning a `DbDump` only bumps a few reference
+/// counts. Applying an action to a clone with `apply_action` then only copies the task it is
+/// about, leaving the rest shared with the original.
+#[derive(Clone, Debug)]
 pub struct DbDump {
     pub owner: UserId,
     pub users: im::HashMap<UserId, User>,
@@ -17,6 +28,7 @@ pub struct DbDump {
     pub searches: im::HashMap<SearchId, Search>,
     pub perms: im::HashMap<TagId, AuthInfo>,
     pub tasks: im::HashMap<TaskId, Arc<Task>>,
+    pub index: TaskIndex,
 }
 
 impl DbDump {
@@ -28,6 +40,7 @@ impl DbDump {
             searches: im::HashMap::new(),
             perms: im::HashMap::new(),
             tasks: im::HashMap::new(),
+            index: TaskIndex::default(),
         }
     }
 
@@ -48,8 +61,88 @@ impl DbDump {
     }
 
     pub fn add_tasks(&mut self, tasks: Vec<api::Task>) {
-        self.tasks
-            .extend(tasks.into_iter().map(|t| (t.id, Arc::new(Task::from(t)))))
+        for t in tasks {
+            self.insert_task(Arc::new(Task::from(t)));
+        }
+    }
+
+    /// Inserts or replaces a task, keeping the index up-to-date
+    pub fn insert_task(&mut self, task: Arc<Task>) {
+        self.index.update(self.tasks.get(&task.id), &task);
+        self.tasks.insert(task.id, task);
+    }
+
+    /// Applies an action, only touching the data it is about
+    pub fn apply_action(&mut self, a: Action) {
+        match a {
+            Action::NewUser(u) => {
+                self.add_users(vec![u]);
+            }
+            Action::DeletedUser(u) => {
+                self.users.remove(&u);
+            }
+            Action::NewTask(t, top_comm) => {
+                let mut task = Task::from(t.clone());
+                task.add_event(Event {
+                    id: t.top_comment_id,
+                    owner_id: t.owner_id,
+                    date: t.date,
+                    task_id: t.id,
+                    data: EventData::AddComment {
+                        text: top_comm,
+                        parent_id: None,
+                    },
+                });
+                task.refresh_metadata(&self.owner);
+                self.insert_task(Arc::new(task));
+            }
+            Action::NewEvent(e) => {
+                let (evt, task) = (e.id, e.task_id);
+                if !self.apply_event(e) {
+                    tracing::warn!(?evt, ?task, "got event for task not in db");
+                }
+            }
+        }
+    }
+
+    /// Applies a batch of actions, refreshing each task they touch only once
+    pub fn apply_actions(&mut self, actions: Vec<Action>) {
+        let mut changed = HashMap::<TaskId, Arc<Task>>::new();
+        for a in actions {
+            match a {
+                Action::NewEvent(e) => {
+                    let task = match changed.entry(e.task_id) {
+                        hash_map::Entry::Occupied(o) => o.into_mut(),
+                        hash_map::Entry::Vacant(v) => match self.tasks.get(&e.task_id) {
+                            Some(t) => v.insert(t.clone()),
+                            None => {
+                                tracing::warn!(evt=?e.id, task=?e.task_id, "got event for task not in db");
+                                continue;
+                            }
+                        },
+                    };
+                    Arc::make_mut(task).add_event(e);
+                }
+                a => self.apply_action(a),
+            }
+        }
+        for (_, mut task) in changed {
+            Arc::make_mut(&mut task).refresh_metadata(&self.owner);
+            self.insert_task(task);
+        }
+    }
+
+    /// Applies an event to the task it is about, returning `false` if that task is not known
+    pub fn apply_event(&mut self, e: api::Event) -> bool {
+        let mut task = match self.tasks.get(&e.task_id) {
+            None => return false,
+            Some(t) => t.clone(),
+        };
+        let t = Arc::make_mut(&mut task);
+        t.add_event(e);
+        t.refresh_metadata(&self.owner);
+        self.insert_task(task);
+        true
     }
 
     pub fn add_events_and_refresh_all(&mut self, events: Vec<api::Event>) {
@@ -59,9 +152,11 @@ impl DbDump {
                 t.add_event(e);
             }
         }
+        self.index = TaskIndex::default();
         for (_, t) in self.tasks.iter_mut() {
             let t = Arc::make_mut(t);
             t.refresh_metadata(&self.owner);
+            self.index.insert(t);
         }
     }
 
@@ -80,20 +175,64 @@ impl DbDump {
             .map(|t| t.clone())
     }
 
-    /// Returns a list of all the tasks matching this search, ordered by increasing
-    /// priority according to the search order
-    pub fn search(&self, s: &Search) -> Result<Vec<Arc<Task>>, Error> {
+    pub fn event(&self, id: &EventId) -> Option<&Event> {
+        self.tasks
+            .values()
+            .flat_map(|t| t.events.values())
+            .flat_map(|evts| evts.iter())
+            .find(|e| e.id == *id)
+    }
+
+    /// Returns a list of all the tasks matching this search as if the current time were
+    /// `now`, ordered by increasing priority according to the search order
+    pub fn search_at(&self, s: &Search, now: &Time) -> Result<Vec<Arc<Task>>, Error> {
         let mut res = Vec::new();
-        for t in self.tasks.values() {
-            if s.filter.matches(t)? {
+        let mut check = |t: &Arc<Task>| -> Result<(), Error> {
+            if s.filter.matches_at(t, &self.tags, now)? {
                 res.push(t.clone());
             }
+            Ok(())
+        };
+        match s.filter.candidates(&self.index, now) {
+            Some(ids) => {
+                for t in ids.iter().filter_map(|id| self.tasks.get(id)) {
+                    check(t)?;
+                }
+            }
+            None => {
+                for t in self.tasks.values() {
+                    check(t)?;
+                }
+            }
         }
         s.order.sort(&mut res);
         Ok(res)
     }
 }
 
+// Maps are first compared by pointer, so that comparing a dump with an updated clone of itself,
+// as yew does on each props change, only walks through the maps that actually changed. `index`
+// is derived from `tasks`, so it does not need to be compared.
+impl PartialEq for DbDump {
+    fn eq(&self, other: &DbDump) -> bool {
+        fn map_eq<K, V>(a: &im::HashMap<K, V>, b: &im::HashMap<K, V>) -> bool
+        where
+            K: Clone + Eq + std::hash::Hash,
+            V: Clone + PartialEq,
+        {
+            a.ptr_eq(b) || a == b
+        }
+        self.owner == other.owner
+            && map_eq(&self.users, &other.users)
+            && map_eq(&self.tags, &other.tags)
+            && map_eq(&self.searches, &other.searches)
+            && map_eq(&self.perms, &other.perms)
+            && map_eq(&self.tasks, &other.tasks)
+    }
+}
+
+impl Eq for DbDump {}
+
 impl DbDump {
     fn get_task_for_event(&self, event: EventId) -> anyhow::Result<TaskId> {
         for (task, t) in self.tasks.iter() {
@@ -0,0 +1,240 @@
+use std::{fmt, hash::Hash, sync::Arc};
+
+use crate::{
+    api::{SearchId, TagId, TaskId},
+    DbDump,
+};
+
+/// How an item differs between two `DbDump`s
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Change<Id> {
+    Added(Id),
+    Removed(Id),
+    /// The item is in both dumps, with the listed fields being different
+    Modified(Id, Vec<&'static str>),
+}
+
+impl<Id: Copy> Change<Id> {
+    pub fn id(&self) -> Id {
+        match self {
+            Change::Added(id) | Change::Removed(id) | Change::Modified(id, _) => *id,
+        }
+    }
+}
+
+/// Structured list of what changed between two `DbDump`s, as returned by `DbDump::diff`
+///
+/// Each list is sorted by id, so that diffs can be compared with each other.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct DbDiff {
+    pub tasks: Vec<Change<TaskId>>,
+    pub tags: Vec<Change<TagId>>,
+    pub searches: Vec<Change<SearchId>>,
+}
+
+impl DbDiff {
+    pub fn is_empty(&self) -> bool {
+        self.tasks.is_empty() && self.tags.is_empty() && self.searches.is_empty()
+    }
+}
+
+impl fmt::Display for DbDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fn write_changes<Id: fmt::Debug>(
+            f: &mut fmt::Formatter<'_>,
+            kind: &str,
+            changes: &[Change<Id>],
+        ) -> fmt::Result {
+            for c in changes {
+                match c {
+                    Change::Added(id) => writeln!(f, "+ {kind} {id:?}")?,
+                    Change::Removed(id) => writeln!(f, "- {kind} {id:?}")?,
+                    Change::Modified(id, fields) => {
+                        writeln!(f, "~ {kind} {id:?}: {}", fields.join(", "))?
+                    }
+                }
+            }
+            Ok(())
+        }
+        write_changes(f, "task", &self.tasks)?;
+        write_changes(f, "tag", &self.tags)?;
+        write_changes(f, "search", &self.searches)
+    }
+}
+
+/// Diffs two maps, `fields` listing the names of the fields that differ between two values
+fn diff_maps<K, V>(
+    old: &im::HashMap<K, V>,
+    new: &im::HashMap<K, V>,
+    fields: impl Fn(&K, &V, &V) -> Vec<&'static str>,
+) -> Vec<Change<K>>
+where
+    K: Copy + Eq + Hash + Ord,
+    V: Clone,
+{
+    if old.ptr_eq(new) {
+        return Vec::new();
+    }
+    let mut res = Vec::new();
+    for (id, o) in old.iter() {
+        match new.get(id) {
+            None => res.push(Change::Removed(*id)),
+            Some(n) => {
+                let changed = fields(id, o, n);
+                if !changed.is_empty() {
+                    res.push(Change::Modified(*id, changed));
+                }
+            }
+        }
+    }
+    res.extend(
+        new.keys()
+            .filter(|id| !old.contains_key(id))
+            .map(|id| Change::Added(*id)),
+    );
+    res.sort_by_key(|c| c.id());
+    res
+}
+
+/// Lists the names of the fields that differ between `$old` and `$new`
+macro_rules! changed_fields {
+    ($old:expr, $new:expr, [$($field:ident => $name:literal),* $(,)?]) => {{
+        let mut res = Vec::new();
+        $(
+            if $old.$field != $new.$field {
+                res.push($name);
+            }
+        )*
+        res
+    }};
+}
+
+impl DbDump {
+    /// Lists the tasks, tags and searches that differ from `self` in `other`
+    ///
+    /// The `revision` of tasks is left out, as it depends on how the events were received
+    /// rather than on what the task looks like.
+    pub fn diff(&self, other: &DbDump) -> DbDiff {
+        DbDiff {
+            tasks: diff_maps(&self.tasks, &other.tasks, |_, o, n| {
+                if Arc::ptr_eq(o, n) {
+                    return Vec::new();
+                }
+                changed_fields!(o, n, [
+                    owner_id => "owner",
+                    date => "date",
+                    current_title => "title",
+                    title_conflict => "title_conflict",
+                    top_comment => "top_comment",
+                    is_done => "done",
+                    is_archived => "archived",
+                    blocked_until => "blocked_until",
+                    scheduled_for => "scheduled_for",
+                    priority => "priority",
+                    current_tags => "tags",
+                    orders => "orders",
+                    current_comments => "comments",
+                    events => "events",
+                ])
+            }),
+            tags: diff_maps(&self.tags, &other.tags, |id, o, n| {
+                let mut res = changed_fields!(o, n, [
+                    owner_id => "owner",
+                    name => "name",
+                    archived => "archived",
+                    sections => "sections",
+                ]);
+                if self.perms.get(id) != other.perms.get(id) {
+                    res.push("perms");
+                }
+                res
+            }),
+            searches: diff_maps(&self.searches, &other.searches, |_, o, n| {
+                changed_fields!(o, n, [
+                    name => "name",
+                    filter => "filter",
+                    order => "order",
+                    priority => "priority",
+                ])
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api::*;
+
+    fn example_db() -> (DbDump, TaskId) {
+        let mut db = DbDump::stub();
+        let task = TaskId(Uuid::new_v4());
+        db.apply_action(Action::NewTask(
+            Task {
+                id: task,
+                owner_id: db.owner,
+                date: chrono::Utc::now(),
+                initial_title: String::from("write report"),
+                top_comment_id: EventId(Uuid::new_v4()),
+            },
+            String::new(),
+        ));
+        (db, task)
+    }
+
+    #[test]
+    fn same_dump_has_no_diff() {
+        let (db, _) = example_db();
+        assert!(db.diff(&db.clone()).is_empty());
+    }
+
+    #[test]
+    fn modified_task_lists_changed_fields() {
+        let (db, task) = example_db();
+        let mut new = db.clone();
+        new.apply_action(Action::NewEvent(Event::at(
+            db.owner,
+            task,
+            chrono::Utc::now(),
+            EventData::SetDone(true),
+        )));
+        assert_eq!(
+            db.diff(&new),
+            DbDiff {
+                tasks: vec![Change::Modified(task, vec!["done", "events"])],
+                ..DbDiff::default()
+            },
+        );
+    }
+
+    #[test]
+    fn added_and_removed_items() {
+        let (db, task) = example_db();
+        let mut new = db.clone();
+        new.tasks.remove(&task);
+        let tag = Tag {
+            id: TagId(Uuid::new_v4()),
+            owner_id: db.owner,
+            name: String::from("work"),
+            archived: false,
+            sections: vec![],
+        };
+        new.add_tags(vec![(tag.clone(), AuthInfo::all())]);
+        assert_eq!(
+            db.diff(&new),
+            DbDiff {
+                tasks: vec![Change::Removed(task)],
+                tags: vec![Change::Added(tag.id)],
+                searches: vec![],
+            },
+        );
+        assert_eq!(
+            new.diff(&db),
+            DbDiff {
+                tasks: vec![Change::Added(task)],
+                tags: vec![Change::Removed(tag.id)],
+                searches: vec![],
+            },
+        );
+    }
+}
@@ -0,0 +1,96 @@
+use std::sync::Arc;
+
+use crate::{
+    api::{TagId, TaskId, Time},
+    Task,
+};
+
+/// Secondary indexes over the tasks of a `DbDump`, to avoid scanning all tasks on each search
+///
+/// This is kept in sync by the `DbDump` methods that modify tasks, so tasks should only be
+/// modified through them.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct TaskIndex {
+    pub by_tag: im::HashMap<TagId, im::HashSet<TaskId>>,
+    pub untagged: im::HashSet<TaskId>,
+
+    /// Tasks by (is_done, is_archived)
+    pub by_state: im::HashMap<(bool, bool), im::HashSet<TaskId>>,
+
+    /// Scheduled tasks by the time they are scheduled for
+    pub by_scheduled_for: im::OrdMap<Time, im::HashSet<TaskId>>,
+}
+
+impl TaskIndex {
+    pub fn insert(&mut self, t: &Task) {
+        for tag in t.current_tags.keys() {
+            self.by_tag.entry(*tag).or_default().insert(t.id);
+        }
+        if t.current_tags.is_empty() {
+            self.untagged.insert(t.id);
+        }
+        self.by_state
+            .entry((t.is_done, t.is_archived))
+            .or_default()
+            .insert(t.id);
+        if let Some(s) = t.scheduled_for {
+            self.by_scheduled_for.entry(s).or_default().insert(t.id);
+        }
+    }
+
+    pub fn remove(&mut self, t: &Task) {
+        fn remove_from<K>(map: &mut im::HashMap<K, im::HashSet<TaskId>>, k: &K, t: &TaskId)
+        where
+            K: Clone + Eq + std::hash::Hash,
+        {
+            if let Some(set) = map.get_mut(k) {
+                set.remove(t);
+                if set.is_empty() {
+                    map.remove(k);
+                }
+            }
+        }
+        for tag in t.current_tags.keys() {
+            remove_from(&mut self.by_tag, tag, &t.id);
+        }
+        self.untagged.remove(&t.id);
+        remove_from(&mut self.by_state, &(t.is_done, t.is_archived), &t.id);
+        if let Some(s) = t.scheduled_for {
+            if let Some(set) = self.by_scheduled_for.get_mut(&s) {
+                set.remove(&t.id);
+                if set.is_empty() {
+                    self.by_scheduled_for.remove(&s);
+                }
+            }
+        }
+    }
+
+    /// Updates the index for task `new`, that was previously indexed as `old` if any
+    pub fn update(&mut self, old: Option<&Arc<Task>>, new: &Task) {
+        if let Some(old) = old {
+            self.remove(old);
+        }
+        self.insert(new);
+    }
+
+    pub fn with_state(&self, done: Option<bool>, archived: Option<bool>) -> im::HashSet<TaskId> {
+        self.by_state
+            .iter()
+            .filter(|((d, a), _)| {
+                done.map(|x| x == *d).unwrap_or(true) && archived.map(|x| x == *a).unwrap_or(true)
+            })
+            .flat_map(|(_, set)| set.iter().copied())
+            .collect()
+    }
+
+    /// Tasks scheduled for a time within `range`
+    pub fn scheduled_within<R>(&self, range: R) -> im::HashSet<TaskId>
+    where
+        R: std::ops::RangeBounds<Time>,
+    {
+        self.by_scheduled_for
+            .range(range)
+            .flat_map(|(_, set)| set.iter().copied())
+            .collect()
+    }
+}
@@ -1,12 +1,25 @@
+mod action;
+pub use action::ActionExt;
+
+pub mod date;
+
 mod db;
 pub use db::DbDump;
 
+mod diff;
+pub use diff::{Change, DbDiff};
+
+mod index;
+pub use index::TaskIndex;
+
 mod comment;
 pub use comment::Comment;
 
 mod order;
 pub use order::OrderExt;
 
+pub mod plan;
+
 mod query;
 pub use query::QueryExt;
 
@@ -18,5 +31,5 @@ pub mod api {
 }
 
 pub mod prelude {
-    pub use crate::{OrderExt, QueryExt};
+    pub use crate::{ActionExt, OrderExt, QueryExt};
 }
@@ -6,11 +6,16 @@ use crate::{
 };
 
 pub trait OrderExt {
+    /// Sorts `tasks` according to this order
+    ///
+    /// This is a total order: ties are always broken by creation date (most recent first) then
+    /// by task id, so that the result only depends on the set of tasks and not on their initial
+    /// order. Tasks with no value for the sorting key (eg. not scheduled) come last, except for
+    /// custom orders where tasks not ordered yet come first, so that they get noticed and ordered.
     fn sort(&self, tasks: &mut [Arc<Task>]);
 }
 
 impl OrderExt for Order {
-    /// Panics if any task is not actually in this tag
     fn sort(&self, tasks: &mut [Arc<Task>]) {
         match self {
             Order::Custom(o) => {
@@ -34,22 +39,149 @@ impl OrderExt for Order {
                 };
                 (category, tag_data.priority, Reverse(t.date), t.id)
             }),
-            Order::CreationDate(OrderType::Asc) => tasks.sort_unstable_by_key(|t| t.date),
-            Order::CreationDate(OrderType::Desc) => tasks.sort_unstable_by_key(|t| Reverse(t.date)),
-            Order::LastEventDate(OrderType::Asc) => {
-                tasks.sort_unstable_by_key(|t| t.last_event_time())
-            }
-            Order::LastEventDate(OrderType::Desc) => {
-                tasks.sort_unstable_by_key(|t| Reverse(t.last_event_time()))
-            }
-            Order::ScheduledFor(OrderType::Asc) => tasks.sort_unstable_by_key(|t| t.scheduled_for),
-            Order::ScheduledFor(OrderType::Desc) => {
-                tasks.sort_unstable_by_key(|t| Reverse(t.scheduled_for))
+            Order::CreationDate(OrderType::Asc) => tasks.sort_unstable_by_key(|t| (t.date, t.id)),
+            Order::CreationDate(OrderType::Desc) => {
+                tasks.sort_unstable_by_key(|t| (Reverse(t.date), t.id))
             }
-            Order::BlockedUntil(OrderType::Asc) => tasks.sort_unstable_by_key(|t| t.blocked_until),
-            Order::BlockedUntil(OrderType::Desc) => {
-                tasks.sort_unstable_by_key(|t| Reverse(t.blocked_until))
+            Order::LastEventDate(OrderType::Asc) => {
+                tasks.sort_unstable_by_key(|t| (t.last_event_time(), Reverse(t.date), t.id))
             }
+            Order::LastEventDate(OrderType::Desc) => tasks
+                .sort_unstable_by_key(|t| (Reverse(t.last_event_time()), Reverse(t.date), t.id)),
+            Order::ScheduledFor(OrderType::Asc) => tasks.sort_unstable_by_key(|t| {
+                (
+                    t.scheduled_for.is_none(),
+                    t.scheduled_for,
+                    Reverse(t.date),
+                    t.id,
+                )
+            }),
+            Order::ScheduledFor(OrderType::Desc) => tasks.sort_unstable_by_key(|t| {
+                (
+                    t.scheduled_for.is_none(),
+                    Reverse(t.scheduled_for),
+                    Reverse(t.date),
+                    t.id,
+                )
+            }),
+            Order::BlockedUntil(OrderType::Asc) => tasks.sort_unstable_by_key(|t| {
+                (
+                    t.blocked_until.is_none(),
+                    t.blocked_until,
+                    Reverse(t.date),
+                    t.id,
+                )
+            }),
+            Order::BlockedUntil(OrderType::Desc) => tasks.sort_unstable_by_key(|t| {
+                (
+                    t.blocked_until.is_none(),
+                    Reverse(t.blocked_until),
+                    Reverse(t.date),
+                    t.id,
+                )
+            }),
+            Order::Priority => tasks.sort_unstable_by_key(|t| {
+                // Tasks without a priority go after all the prioritized ones
+                (
+                    t.is_done,
+                    t.priority.is_none(),
+                    t.priority,
+                    Reverse(t.date),
+                    t.id,
+                )
+            }),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        api::{self, EventId, OrderId, Priority, TagId, TaskId, UserId, Uuid},
+        TaskInTag,
+    };
+
+    /// Creation date, schedule, blocked-until, priority level, done and tag priority of a task,
+    /// all taken in small ranges so that ties are frequent
+    type TaskSpec = (u8, Option<u8>, Option<u8>, Option<u8>, bool, Option<u8>);
+
+    fn time(secs: u8) -> api::Time {
+        chrono::DateTime::from_utc(
+            chrono::NaiveDateTime::from_timestamp_opt(i64::from(secs % 4), 0).unwrap(),
+            chrono::Utc,
+        )
+    }
+
+    fn build_tasks(specs: &[TaskSpec]) -> Vec<Arc<Task>> {
+        specs
+            .iter()
+            .enumerate()
+            .map(|(i, (date, sched, blocked, prio, done, tag_prio))| {
+                let mut t = Task::from(api::Task {
+                    id: TaskId(Uuid::from_u128(i as u128)),
+                    owner_id: UserId::stub(),
+                    date: time(*date),
+                    initial_title: String::new(),
+                    top_comment_id: EventId(Uuid::from_u128(i as u128)),
+                });
+                t.scheduled_for = sched.map(time);
+                t.blocked_until = blocked.map(time);
+                t.priority = prio.and_then(|p| Priority::from_level(i64::from(p % 5)));
+                t.is_done = *done;
+                if let Some(p) = tag_prio {
+                    t.current_tags.insert(
+                        TagId::stub(),
+                        TaskInTag {
+                            priority: i64::from(p % 4),
+                            backlog: p % 3 == 0,
+                            section: None,
+                        },
+                    );
+                    t.orders.insert(OrderId::stub(), i64::from(p % 4));
+                }
+                Arc::new(t)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn sort_is_deterministic() {
+        let orders = [
+            Order::Custom(OrderId::stub()),
+            Order::Tag(TagId::stub()),
+            Order::CreationDate(OrderType::Asc),
+            Order::CreationDate(OrderType::Desc),
+            Order::LastEventDate(OrderType::Asc),
+            Order::LastEventDate(OrderType::Desc),
+            Order::ScheduledFor(OrderType::Asc),
+            Order::ScheduledFor(OrderType::Desc),
+            Order::BlockedUntil(OrderType::Asc),
+            Order::BlockedUntil(OrderType::Desc),
+            Order::Priority,
+        ];
+        bolero::check!()
+            .with_type::<Vec<TaskSpec>>()
+            .for_each(|specs| {
+                let tasks = build_tasks(specs);
+                for order in orders.iter() {
+                    let mut forward = tasks.clone();
+                    let mut backward = tasks.iter().rev().cloned().collect::<Vec<_>>();
+                    order.sort(&mut forward);
+                    order.sort(&mut backward);
+                    let ids = |v: &[Arc<Task>]| v.iter().map(|t| t.id).collect::<Vec<_>>();
+                    assert_eq!(ids(&forward), ids(&backward), "order {order:?}");
+                }
+            });
+    }
+
+    #[test]
+    fn unordered_tasks_come_first_in_custom_orders() {
+        let mut tasks = build_tasks(&[
+            (0, None, None, None, false, Some(1)),
+            (0, None, None, None, false, None),
+        ]);
+        Order::Custom(OrderId::stub()).sort(&mut tasks);
+        assert_eq!(tasks[0].id, TaskId(Uuid::from_u128(1)));
+    }
+}
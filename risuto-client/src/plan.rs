@@ -0,0 +1,29 @@
+use std::sync::Arc;
+
+use crate::{api::Time, Task};
+
+/// Proposes which of `tasks` to work on today, in the order they should be done
+///
+/// Tasks that are done or still blocked at `now` are left out. The others are ranked by
+/// priority, then by how long ago they were scheduled or created, and greedily picked until
+/// `capacity` tasks are planned.
+pub fn plan_day(tasks: &[Arc<Task>], now: &Time, capacity: usize) -> Vec<Arc<Task>> {
+    let mut candidates = tasks
+        .iter()
+        .filter(|t| !t.is_done)
+        .filter(|t| t.blocked_until.map(|b| b <= *now).unwrap_or(true))
+        .cloned()
+        .collect::<Vec<_>>();
+    candidates.sort_unstable_by_key(|t| {
+        (
+            t.priority.is_none(),
+            t.priority,
+            t.scheduled_for.is_none(),
+            t.scheduled_for,
+            t.date,
+            t.id,
+        )
+    });
+    candidates.truncate(capacity);
+    candidates
+}
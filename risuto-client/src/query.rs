@@ -1,21 +1,54 @@
 use std::str::FromStr;
 
 use crate::{
-    api::{Query, Time, TimeQuery},
-    Comment, DbDump, Task,
+    api::{DayStart, Priority, Query, Tag, TagId, TaskId, Time, TimeQuery},
+    Comment, DbDump, Task, TaskIndex,
 };
 
 use pest::{iterators::Pairs, pratt_parser::PrattParser, Parser as PestParser};
-use risuto_api::{midnight_on, Error};
+use risuto_api::Error;
 
 pub trait QueryExt {
-    fn from_search(db: &DbDump, tz: &chrono_tz::Tz, search: &str) -> Query;
-    fn validate_now(&self) -> Result<(), Error>;
-    fn matches(&self, task: &Task) -> Result<bool, Error>;
+    fn from_search(db: &DbDump, tz: &chrono_tz::Tz, day_start: DayStart, search: &str) -> Query;
+
+    /// Writes this query as a human-readable search string, that `from_search` parses back to
+    /// `self.normalize()` when given a `db` with these `tags`
+    ///
+    /// Only the tags are needed, so that eg. lists of saved searches can show their filter
+    /// without access to the whole database. Details the search syntax cannot express, like the
+    /// backlog status of a tag query or a time that is not the start of a day, are approximated
+    /// by the closest search it can express.
+    fn to_search(
+        &self,
+        tags: &im::HashMap<TagId, Tag>,
+        tz: &chrono_tz::Tz,
+        day_start: DayStart,
+    ) -> String;
+
+    /// Returns the equivalent query that `to_search` actually writes out
+    ///
+    /// This flattens nested `All` and `Any` the way the parser does, removes single-element
+    /// `All` and `Any`, and forgets the backlog status of tag queries.
+    fn normalize(&self) -> Query;
+
+    fn validate_at(&self, now: &Time) -> Result<(), Error>;
+
+    /// Checks whether `task` matches this query, as if the current time were `now` and the
+    /// user could see `tags`
+    fn matches_at(
+        &self,
+        task: &Task,
+        tags: &im::HashMap<TagId, Tag>,
+        now: &Time,
+    ) -> Result<bool, Error>;
+
+    /// Returns a superset of the tasks matching this query at `now` according to `index`, or
+    /// `None` if the index cannot narrow them down and all tasks need to be checked
+    fn candidates(&self, index: &TaskIndex, now: &Time) -> Option<im::HashSet<TaskId>>;
 }
 
 impl QueryExt for Query {
-    fn from_search(db: &DbDump, tz: &chrono_tz::Tz, search: &str) -> Query {
+    fn from_search(db: &DbDump, tz: &chrono_tz::Tz, day_start: DayStart, search: &str) -> Query {
         tracing::trace!(?search, "parsing query");
         let res = match Parser::parse(Rule::everything, search) {
             Ok(mut pairs) => {
@@ -23,7 +56,7 @@ impl QueryExt for Query {
                 let search_res = pairs
                     .next()
                     .expect("Rule::everything result without search result");
-                parse_search(db, tz, search_res.into_inner())
+                parse_search(db, tz, day_start, search_res.into_inner())
             }
             e => todo!("should have proper error handling here: {:?}", e),
         };
@@ -31,33 +64,110 @@ impl QueryExt for Query {
         res
     }
 
-    fn validate_now(&self) -> Result<(), Error> {
+    fn to_search(
+        &self,
+        tags: &im::HashMap<TagId, Tag>,
+        tz: &chrono_tz::Tz,
+        day_start: DayStart,
+    ) -> String {
+        Printer {
+            tags,
+            tz,
+            day_start,
+        }
+        .search(&self.normalize())
+    }
+
+    fn normalize(&self) -> Query {
+        match self {
+            Query::Any(q) => {
+                let q = normalize_list(q, |q| match q {
+                    Query::Any(q) => Some(q),
+                    _ => None,
+                });
+                match q.len() {
+                    1 => q.into_iter().next().unwrap(),
+                    _ => Query::Any(q),
+                }
+            }
+            Query::All(q) => {
+                let q = normalize_list(q, |q| match q {
+                    Query::All(q) => Some(q),
+                    _ => None,
+                });
+                match q.len() {
+                    1 => q.into_iter().next().unwrap(),
+                    _ => Query::All(q),
+                }
+            }
+            Query::Not(q) => Query::Not(Box::new(q.normalize())),
+            Query::Tag { tag, backlog: _ } => Query::tag(*tag),
+            q => q.clone(),
+        }
+    }
+
+    fn validate_at(&self, now: &Time) -> Result<(), Error> {
         self.validate()?;
         match self {
             Query::Any(q) => q
                 .iter()
-                .map(QueryExt::validate_now)
+                .map(|q| q.validate_at(now))
                 .collect::<Result<(), Error>>(),
             Query::All(q) => q
                 .iter()
-                .map(QueryExt::validate_now)
+                .map(|q| q.validate_at(now))
                 .collect::<Result<(), Error>>(),
-            Query::Not(q) => q.validate_now(),
+            Query::Not(q) => q.validate_at(now),
             Query::Archived(_) => Ok(()),
             Query::Done(_) => Ok(()),
             Query::Tag { tag: _, backlog: _ } => Ok(()),
             Query::Untagged(_) => Ok(()),
-            Query::ScheduledForBefore(q) => timeq_validate_now(q),
-            Query::ScheduledForAfter(q) => timeq_validate_now(q),
-            Query::BlockedUntilAtMost(q) => timeq_validate_now(q),
-            Query::BlockedUntilAtLeast(q) => timeq_validate_now(q),
+            Query::TagArchived(_) => Ok(()),
+            Query::ScheduledForBefore(q) => timeq_validate_at(q, now),
+            Query::ScheduledForAfter(q) => timeq_validate_at(q, now),
+            Query::BlockedUntilAtMost(q) => timeq_validate_at(q, now),
+            Query::BlockedUntilAtLeast(q) => timeq_validate_at(q, now),
+            Query::LastEventBefore(q) => timeq_validate_at(q, now),
+            Query::LastEventAfter(q) => timeq_validate_at(q, now),
+            Query::PriorityAtLeast(_) => Ok(()),
+            Query::PriorityAtMost(_) => Ok(()),
             Query::Phrase(_) => Ok(()),
         }
     }
 
-    fn matches(&self, task: &Task) -> Result<bool, Error> {
+    fn matches_at(
+        &self,
+        task: &Task,
+        tags: &im::HashMap<TagId, Tag>,
+        now: &Time,
+    ) -> Result<bool, Error> {
         let tokenized = has_fts(self).then(|| tokenize_task(task));
-        matches_impl(self, task, &tokenized)
+        matches_impl(self, task, tags, now, &tokenized)
+    }
+
+    fn candidates(&self, index: &TaskIndex, now: &Time) -> Option<im::HashSet<TaskId>> {
+        match self {
+            Query::All(queries) => queries
+                .iter()
+                .filter_map(|q| q.candidates(index, now))
+                .reduce(|a, b| a.intersection(b)),
+            Query::Any(queries) => queries
+                .iter()
+                .map(|q| q.candidates(index, now))
+                .collect::<Option<Vec<_>>>()
+                .map(im::HashSet::unions),
+            Query::Archived(a) => Some(index.with_state(None, Some(*a))),
+            Query::Done(d) => Some(index.with_state(Some(*d), None)),
+            Query::Tag { tag, backlog: _ } => {
+                Some(index.by_tag.get(tag).cloned().unwrap_or_default())
+            }
+            Query::Untagged(true) => Some(index.untagged.clone()),
+            Query::ScheduledForBefore(q) => {
+                q.eval_at(now).ok().map(|t| index.scheduled_within(..=t))
+            }
+            Query::ScheduledForAfter(q) => q.eval_at(now).ok().map(|t| index.scheduled_within(t..)),
+            _ => None,
+        }
     }
 }
 
@@ -70,31 +180,38 @@ fn has_fts(q: &Query) -> bool {
         Query::Done(_) => false,
         Query::Tag { .. } => false,
         Query::Untagged(_) => false,
+        Query::TagArchived(_) => false,
         Query::ScheduledForAfter(_) => false,
         Query::ScheduledForBefore(_) => false,
         Query::BlockedUntilAtLeast(_) => false,
         Query::BlockedUntilAtMost(_) => false,
+        Query::LastEventBefore(_) => false,
+        Query::LastEventAfter(_) => false,
+        Query::PriorityAtLeast(_) => false,
+        Query::PriorityAtMost(_) => false,
         Query::Phrase(_) => true,
     }
 }
 
-fn timeq_validate_now(q: &TimeQuery) -> Result<(), Error> {
-    q.eval_now().map(|_| ())
+fn timeq_validate_at(q: &TimeQuery, now: &Time) -> Result<(), Error> {
+    q.eval_at(now).map(|_| ())
 }
 
 fn matches_impl(
     q: &Query,
     task: &Task,
+    tags: &im::HashMap<TagId, Tag>,
+    now: &Time,
     tokenized: &Option<Vec<Vec<String>>>,
 ) -> Result<bool, Error> {
     Ok(match q {
         Query::Any(queries) => queries
             .iter()
-            .any(|q| matches_impl(q, task, tokenized) == Ok(true)),
+            .any(|q| matches_impl(q, task, tags, now, tokenized) == Ok(true)),
         Query::All(queries) => queries
             .iter()
-            .all(|q| matches_impl(q, task, tokenized) == Ok(true)),
-        Query::Not(q) => matches_impl(q, task, tokenized) == Ok(false),
+            .all(|q| matches_impl(q, task, tags, now, tokenized) == Ok(true)),
+        Query::Not(q) => matches_impl(q, task, tags, now, tokenized) == Ok(false),
         Query::Archived(a) => task.is_archived == *a,
         Query::Done(d) => task.is_done == *d,
         Query::Tag { tag, backlog } => match task.current_tags.get(tag) {
@@ -105,10 +222,26 @@ fn matches_impl(
             },
         },
         Query::Untagged(u) => task.current_tags.is_empty() == *u,
-        Query::ScheduledForAfter(d) => timeq_matches(d, &task.scheduled_for, |q, t| t >= q)?,
-        Query::ScheduledForBefore(d) => timeq_matches(d, &task.scheduled_for, |q, t| t <= q)?,
-        Query::BlockedUntilAtLeast(d) => timeq_matches(d, &task.blocked_until, |q, t| t >= q)?,
-        Query::BlockedUntilAtMost(d) => timeq_matches(d, &task.blocked_until, |q, t| t <= q)?,
+        Query::TagArchived(a) => {
+            let in_archived = task
+                .current_tags
+                .keys()
+                .any(|t| tags.get(t).map(|t| t.archived).unwrap_or(false));
+            in_archived == *a
+        }
+        Query::ScheduledForAfter(d) => timeq_matches(d, now, &task.scheduled_for, |q, t| t >= q)?,
+        Query::ScheduledForBefore(d) => timeq_matches(d, now, &task.scheduled_for, |q, t| t <= q)?,
+        Query::BlockedUntilAtLeast(d) => timeq_matches(d, now, &task.blocked_until, |q, t| t >= q)?,
+        Query::BlockedUntilAtMost(d) => timeq_matches(d, now, &task.blocked_until, |q, t| t <= q)?,
+        Query::LastEventBefore(d) => {
+            timeq_matches(d, now, &Some(task.last_event_time()), |q, t| t < q)?
+        }
+        Query::LastEventAfter(d) => {
+            timeq_matches(d, now, &Some(task.last_event_time()), |q, t| t >= q)?
+        }
+        // Lower priorities are more urgent
+        Query::PriorityAtLeast(p) => task.priority.map(|t| t <= *p).unwrap_or(false),
+        Query::PriorityAtMost(p) => task.priority.map(|t| t >= *p).unwrap_or(false),
         Query::Phrase(p) => {
             let q = tokenize(p);
             if q.is_empty() {
@@ -129,10 +262,11 @@ fn matches_impl(
 
 fn timeq_matches(
     q: &TimeQuery,
+    now: &Time,
     t: &Option<Time>,
     check: impl FnOnce(&Time, &Time) -> bool,
 ) -> Result<bool, Error> {
-    let q = q.eval_now()?;
+    let q = q.eval_at(now)?;
     match t {
         None => Ok(false),
         Some(t) => Ok(check(&q, t)),
@@ -212,9 +346,15 @@ fn unescape(s: &str) -> String {
     res
 }
 
-fn parse_search(db: &DbDump, tz: &chrono_tz::Tz, pairs: Pairs<Rule>) -> Query {
+fn parse_search(db: &DbDump, tz: &chrono_tz::Tz, day_start: DayStart, pairs: Pairs<Rule>) -> Query {
     SEARCH_PARSER
         .map_primary(|p| match p.as_rule() {
+            // Matching everything and nothing, like the empty conjunction and disjunction
+            Rule::constant => match p.into_inner().next().map(|p| p.as_rule()) {
+                Some(Rule::r#true) => Query::All(vec![]),
+                Some(Rule::r#false) => Query::Any(vec![]),
+                r => unreachable!("Rule::constant unexpected atom: {:?}", r),
+            },
             Rule::archived => Query::Archived(match p.into_inner().next().map(|p| p.as_rule()) {
                 Some(Rule::r#true) => true,
                 Some(Rule::r#false) => false,
@@ -230,30 +370,46 @@ fn parse_search(db: &DbDump, tz: &chrono_tz::Tz, pairs: Pairs<Rule>) -> Query {
                 Some(Rule::r#false) => false,
                 r => unreachable!("Rule::untagged unexpected atom: {:?}", r),
             }),
+            Rule::tagarchived => {
+                Query::TagArchived(match p.into_inner().next().map(|p| p.as_rule()) {
+                    Some(Rule::r#true) => true,
+                    Some(Rule::r#false) => false,
+                    r => unreachable!("Rule::tagarchived unexpected atom: {:?}", r),
+                })
+            }
             Rule::tag => {
                 let tagname = p.into_inner().next();
                 let tagname = match tagname.as_ref().map(|p| p.as_rule()) {
-                    Some(Rule::tagname) => tagname.unwrap().as_str(),
+                    Some(Rule::tagname) => tagname.unwrap().as_str().to_string(),
+                    Some(Rule::phrase) => unescape(tagname.unwrap().as_str()),
                     r => unreachable!("Rule::tag unexpected atom: {:?}", r),
                 };
                 // TODO: is there a need for querying only tasks in/out of backlog from text search?
-                db.tag_id(tagname)
+                db.tag_id(&tagname)
                     .map(|tag| Query::Tag { tag, backlog: None })
                     .unwrap_or_else(|| Query::Phrase(format!("tag:{tagname}")))
             }
             Rule::scheduled => parse_date_cmp(
                 p.into_inner(),
                 tz,
+                day_start,
                 Query::ScheduledForAfter,
                 Query::ScheduledForBefore,
             ),
             Rule::blocked => parse_date_cmp(
                 p.into_inner(),
                 tz,
+                day_start,
                 Query::BlockedUntilAtLeast,
                 Query::BlockedUntilAtMost,
             ),
-            Rule::search => parse_search(db, tz, p.into_inner()),
+            Rule::prio => parse_priority_cmp(p.into_inner()),
+            Rule::stale => {
+                let text = p.as_str();
+                parse_stale_cmp(p.into_inner(), tz, day_start)
+                    .unwrap_or_else(|| Query::Phrase(String::from(text)))
+            }
+            Rule::search => parse_search(db, tz, day_start, p.into_inner()),
             Rule::phrase => Query::Phrase(unescape(p.as_str())),
             Rule::word => Query::Phrase(p.as_str().to_string()),
             r => unreachable!("Search unexpected primary: {:?}", r),
@@ -285,6 +441,7 @@ fn parse_search(db: &DbDump, tz: &chrono_tz::Tz, pairs: Pairs<Rule>) -> Query {
 fn parse_date_cmp(
     mut reader: Pairs<Rule>,
     tz: &chrono_tz::Tz,
+    day_start: DayStart,
     date_after: impl Fn(TimeQuery) -> Query,
     date_before: impl Fn(TimeQuery) -> Query,
 ) -> Query {
@@ -293,12 +450,13 @@ fn parse_date_cmp(
     let timequery = match timequery.as_rule() {
         Rule::abstimeq => TimeQuery::Absolute(
             // TODO: for safety, see (currently open) https://github.com/chronotope/chrono/pull/927
-            midnight_on(
-                chrono::NaiveDate::parse_from_str(timequery.as_str(), "%Y-%m-%d")
-                    .expect("parsing date cmp with ill-formed absolute date"),
-                tz,
-            )
-            .with_timezone(&chrono::Utc),
+            day_start
+                .start_of(
+                    chrono::NaiveDate::parse_from_str(timequery.as_str(), "%Y-%m-%d")
+                        .expect("parsing date cmp with ill-formed absolute date"),
+                    tz,
+                )
+                .with_timezone(&chrono::Utc),
         ),
         Rule::reltimeq => {
             let mut reader = timequery.into_inner();
@@ -307,6 +465,7 @@ fn parse_date_cmp(
                 None => TimeQuery::DayRelative {
                     timezone: tz.clone(),
                     day_offset: 0,
+                    day_start,
                 },
                 Some(op) => {
                     let offset = reader
@@ -322,6 +481,7 @@ fn parse_date_cmp(
                     TimeQuery::DayRelative {
                         timezone: tz.clone(),
                         day_offset,
+                        day_start,
                     }
                 }
             }
@@ -329,19 +489,84 @@ fn parse_date_cmp(
         _ => unreachable!("got unexpected timequery type"),
     };
     match cmp.as_str() {
-        ">" => date_after(start_of_next_day(tz, timequery)),
-        "<=" => date_before(start_of_next_day(tz, timequery)),
+        ">" => date_after(start_of_next_day(tz, day_start, timequery)),
+        "<=" => date_before(start_of_next_day(tz, day_start, timequery)),
         "<" => date_before(timequery),
         ">=" => date_after(timequery),
         ":" => Query::All(vec![
             date_after(timequery.clone()),
-            date_before(start_of_next_day(tz, timequery)),
+            date_before(start_of_next_day(tz, day_start, timequery)),
         ]),
         _ => panic!("parsing date cmp with ill-formed cmp op"),
     }
 }
 
-fn start_of_next_day<Tz>(tz: &Tz, day: TimeQuery) -> TimeQuery
+fn parse_priority_cmp(mut reader: Pairs<Rule>) -> Query {
+    let mut priority = reader
+        .next()
+        .expect("parsing priority cmp without a priority");
+    let mut cmp = "";
+    if priority.as_rule() == Rule::numcmp {
+        cmp = priority.as_str();
+        priority = reader
+            .next()
+            .expect("parsing priority cmp without a priority");
+    }
+    let priority = Priority::parse(priority.as_str()).expect("parsing ill-formed priority");
+    // More urgent priorities are "greater", eg. `prio:>=p2` matches P1 and P2
+    let more_urgent = Priority::ALL.into_iter().rev().find(|p| *p < priority);
+    let less_urgent = Priority::ALL.into_iter().find(|p| *p > priority);
+    match cmp {
+        ">=" => Query::PriorityAtLeast(priority),
+        "<=" => Query::PriorityAtMost(priority),
+        ">" => more_urgent
+            .map(Query::PriorityAtLeast)
+            .unwrap_or_else(|| Query::Any(vec![])),
+        "<" => less_urgent
+            .map(Query::PriorityAtMost)
+            .unwrap_or_else(|| Query::Any(vec![])),
+        "" => Query::All(vec![
+            Query::PriorityAtLeast(priority),
+            Query::PriorityAtMost(priority),
+        ]),
+        _ => panic!("parsing priority cmp with ill-formed cmp op"),
+    }
+}
+
+/// Returns `None` if the number of days does not fit in an `i64`
+fn parse_stale_cmp(
+    mut reader: Pairs<Rule>,
+    tz: &chrono_tz::Tz,
+    day_start: DayStart,
+) -> Option<Query> {
+    let mut days = reader.next().expect("parsing stale cmp without a duration");
+    let mut cmp = "";
+    if days.as_rule() == Rule::numcmp {
+        cmp = days.as_str();
+        days = reader.next().expect("parsing stale cmp without a duration");
+    }
+    let days = i64::from_str(days.as_str()).ok()?;
+    // Being stale for more than N days means having had no event since the start of the day
+    // N days ago
+    let days_ago = |days: i64| TimeQuery::DayRelative {
+        timezone: tz.clone(),
+        day_offset: -days,
+        day_start,
+    };
+    Some(match cmp {
+        ">" => Query::LastEventBefore(days_ago(days)),
+        ">=" => Query::LastEventBefore(days_ago(days - 1)),
+        "<" => Query::LastEventAfter(days_ago(days - 1)),
+        "<=" => Query::LastEventAfter(days_ago(days)),
+        "" => Query::All(vec![
+            Query::LastEventAfter(days_ago(days)),
+            Query::LastEventBefore(days_ago(days - 1)),
+        ]),
+        _ => panic!("parsing stale cmp with ill-formed cmp op"),
+    })
+}
+
+fn start_of_next_day<Tz>(tz: &Tz, day_start: DayStart, day: TimeQuery) -> TimeQuery
 where
     Tz: Clone + std::fmt::Debug + chrono::TimeZone,
 {
@@ -349,32 +574,199 @@ where
         TimeQuery::DayRelative {
             timezone,
             day_offset,
+            day_start,
         } => TimeQuery::DayRelative {
             timezone,
             day_offset: day_offset + 1,
+            day_start,
         },
         TimeQuery::Absolute(t) => TimeQuery::Absolute(
-            midnight_on(
-                t.date_naive()
-                    .succ_opt()
-                    .expect("failed figuring out a date for day+1"),
-                tz,
-            )
-            .with_timezone(&chrono::Utc),
+            day_start
+                .start_of(
+                    day_start
+                        .day_of(&t, tz)
+                        .succ_opt()
+                        .expect("failed figuring out a date for day+1"),
+                    tz,
+                )
+                .with_timezone(&chrono::Utc),
         ),
     }
 }
 
+/// Normalizes the elements of an `All` or `Any`, splicing in the elements of its first element
+/// while it is of the same kind, like the parser does for its left-associative operators
+fn normalize_list(
+    queries: &[Query],
+    same_kind: impl Fn(&Query) -> Option<&Vec<Query>>,
+) -> Vec<Query> {
+    let mut res = queries.iter().map(|q| q.normalize()).collect::<Vec<_>>();
+    while let Some(first) = res.first().and_then(&same_kind).cloned() {
+        res.splice(0..1, first);
+    }
+    res
+}
+
+/// Writes normalized queries with the syntax of `query.pest`
+struct Printer<'a> {
+    tags: &'a im::HashMap<TagId, Tag>,
+    tz: &'a chrono_tz::Tz,
+    day_start: DayStart,
+}
+
+impl<'a> Printer<'a> {
+    fn search(&self, q: &Query) -> String {
+        if let Some(res) = self.primary(q) {
+            return res;
+        }
+        match q {
+            Query::All(q) => q
+                .iter()
+                .map(|q| self.operand(q))
+                .collect::<Vec<_>>()
+                .join(" "),
+            Query::Any(q) => q
+                .iter()
+                .map(|q| self.operand(q))
+                .collect::<Vec<_>>()
+                .join(" OR "),
+            _ => unreachable!("query {q:?} is neither a primary nor a list"),
+        }
+    }
+
+    /// Writes `q` so that it can be the operand of an infix or prefix operator
+    fn operand(&self, q: &Query) -> String {
+        self.primary(q)
+            .unwrap_or_else(|| format!("({})", self.search(q)))
+    }
+
+    /// Writes `q` as a single primary of the grammar, if possible
+    fn primary(&self, q: &Query) -> Option<String> {
+        Some(match q {
+            Query::All(q) if q.is_empty() => String::from("true"),
+            Query::Any(q) if q.is_empty() => String::from("false"),
+            Query::All(q) if q.len() == 2 => return self.pair(&q[0], &q[1]),
+            Query::Any(_) | Query::All(_) => return None,
+            Query::Not(q) => format!("-{}", self.operand(q)),
+            Query::Archived(b) => format!("archived:{b}"),
+            Query::Done(b) => format!("done:{b}"),
+            Query::Tag { tag, backlog: _ } => {
+                let name = self.tags.get(tag).map(|t| &t.name as &str);
+                format!("tag:{}", tag_name(name.unwrap_or("unknown")))
+            }
+            Query::Untagged(b) => format!("untagged:{b}"),
+            Query::TagArchived(b) => format!("tag-archived:{b}"),
+            Query::ScheduledForAfter(t) => format!("scheduled>={}", self.time(t)),
+            Query::ScheduledForBefore(t) => format!("scheduled<{}", self.time(t)),
+            Query::BlockedUntilAtLeast(t) => format!("blocked>={}", self.time(t)),
+            Query::BlockedUntilAtMost(t) => format!("blocked<{}", self.time(t)),
+            Query::LastEventBefore(t) => match days_ago(t) {
+                d if d >= 0 => format!("stale:>{d}d"),
+                _ => String::from("stale:>=0d"),
+            },
+            Query::LastEventAfter(t) => match days_ago(t) {
+                d if d >= 0 => format!("stale:<={d}d"),
+                _ => String::from("stale:<0d"),
+            },
+            Query::PriorityAtLeast(p) => format!("prio:>={}", p.name()),
+            Query::PriorityAtMost(p) => format!("prio:<={}", p.name()),
+            Query::Phrase(p) => phrase(p),
+        })
+    }
+
+    /// Writes the two-element `All`s that the parser generates for a single primary
+    fn pair(&self, a: &Query, b: &Query) -> Option<String> {
+        match (a, b) {
+            (Query::ScheduledForAfter(t), Query::ScheduledForBefore(u))
+                if self.next_day(t) == *u =>
+            {
+                Some(format!("scheduled:{}", self.time(t)))
+            }
+            (Query::BlockedUntilAtLeast(t), Query::BlockedUntilAtMost(u))
+                if self.next_day(t) == *u =>
+            {
+                Some(format!("blocked:{}", self.time(t)))
+            }
+            (Query::PriorityAtLeast(p), Query::PriorityAtMost(q)) if p == q => {
+                Some(format!("prio:{}", p.name()))
+            }
+            (
+                Query::LastEventAfter(t @ TimeQuery::DayRelative { .. }),
+                Query::LastEventBefore(u @ TimeQuery::DayRelative { .. }),
+            ) if days_ago(t) >= 0 && self.next_day(t) == *u => {
+                Some(format!("stale:{}d", days_ago(t)))
+            }
+            _ => None,
+        }
+    }
+
+    fn next_day(&self, t: &TimeQuery) -> TimeQuery {
+        start_of_next_day(self.tz, self.day_start, t.clone())
+    }
+
+    fn time(&self, t: &TimeQuery) -> String {
+        match t {
+            TimeQuery::Absolute(t) => self
+                .day_start
+                .day_of(t, self.tz)
+                .format("%Y-%m-%d")
+                .to_string(),
+            TimeQuery::DayRelative { day_offset: 0, .. } => String::from("today"),
+            TimeQuery::DayRelative { day_offset, .. } if *day_offset > 0 => {
+                format!("today+{day_offset}")
+            }
+            TimeQuery::DayRelative { day_offset, .. } => {
+                format!("today-{}", day_offset.unsigned_abs())
+            }
+        }
+    }
+}
+
+/// Number of days before today `t` is at, with absolute times approximated as today
+fn days_ago(t: &TimeQuery) -> i64 {
+    match t {
+        TimeQuery::Absolute(_) => 0,
+        TimeQuery::DayRelative { day_offset, .. } => -day_offset,
+    }
+}
+
+/// Writes `name` bare if the `tagname` rule accepts it, or quoted like a phrase
+fn tag_name(name: &str) -> String {
+    let is_bare = !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == ':');
+    match is_bare {
+        true => String::from(name),
+        false => quote(name),
+    }
+}
+
+/// Writes `p` as a bare word if it cannot be mistaken for anything else, or as a quoted phrase
+fn phrase(p: &str) -> String {
+    let lower = p.to_lowercase();
+    let is_word = !p.is_empty()
+        && p.chars().all(char::is_alphanumeric)
+        && !lower.starts_with("or")
+        && !lower.starts_with("and")
+        && lower != "true"
+        && lower != "false";
+    match is_word {
+        true => String::from(p),
+        false => quote(p),
+    }
+}
+
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::api::*;
-    use std::{collections::HashMap, sync::Arc};
 
     fn example_db() -> DbDump {
-        let mut tags = HashMap::new();
-        let mut perms = HashMap::new();
-        for t in ["foo", "bar", "baz"] {
+        let mut tags = im::HashMap::new();
+        let mut perms = im::HashMap::new();
+        for t in ["foo", "bar", "baz", "to-do"] {
             let id = TagId(Uuid::new_v4());
             tags.insert(
                 id,
@@ -383,17 +775,19 @@ mod tests {
                     owner_id: UserId::stub(),
                     name: String::from(t),
                     archived: false,
+                    sections: vec![],
                 },
             );
             perms.insert(id, AuthInfo::all());
         }
         DbDump {
             owner: UserId::stub(),
-            users: Arc::new(HashMap::new()),
-            tags: Arc::new(tags),
-            perms: Arc::new(perms),
-            searches: Arc::new(HashMap::new()),
-            tasks: Arc::new(HashMap::new()),
+            users: im::HashMap::new(),
+            tags,
+            perms,
+            searches: im::HashMap::new(),
+            tasks: im::HashMap::new(),
+            index: TaskIndex::default(),
         }
     }
 
@@ -410,11 +804,11 @@ mod tests {
         let db = example_db();
         let tz = example_tz();
         assert_eq!(
-            Query::from_search(&db, &tz, "archived:true"),
+            Query::from_search(&db, &tz, DayStart::MIDNIGHT, "archived:true"),
             Query::Archived(true),
         );
         assert_eq!(
-            Query::from_search(&db, &tz, "archived:false"),
+            Query::from_search(&db, &tz, DayStart::MIDNIGHT, "archived:false"),
             Query::Archived(false),
         );
     }
@@ -423,9 +817,12 @@ mod tests {
     fn primary_done() {
         let db = example_db();
         let tz = example_tz();
-        assert_eq!(Query::from_search(&db, &tz, "done:true"), Query::Done(true),);
         assert_eq!(
-            Query::from_search(&db, &tz, "done:false"),
+            Query::from_search(&db, &tz, DayStart::MIDNIGHT, "done:true"),
+            Query::Done(true),
+        );
+        assert_eq!(
+            Query::from_search(&db, &tz, DayStart::MIDNIGHT, "done:false"),
             Query::Done(false),
         );
     }
@@ -435,13 +832,99 @@ mod tests {
         let db = example_db();
         let tz = example_tz();
         assert_eq!(
-            Query::from_search(&db, &tz, "tag:foo"),
+            Query::from_search(&db, &tz, DayStart::MIDNIGHT, "tag:foo"),
             Query::tag(db.tag_id("foo").unwrap()),
         );
         assert_eq!(
-            Query::from_search(&db, &tz, "tag:bar"),
+            Query::from_search(&db, &tz, DayStart::MIDNIGHT, "tag:bar"),
             Query::tag(db.tag_id("bar").unwrap()),
         );
+        assert_eq!(
+            Query::from_search(&db, &tz, DayStart::MIDNIGHT, r#"tag:"to-do""#),
+            Query::tag(db.tag_id("to-do").unwrap()),
+        );
+    }
+
+    #[test]
+    fn primary_tag_archived() {
+        let db = example_db();
+        let tz = example_tz();
+        assert_eq!(
+            Query::from_search(&db, &tz, DayStart::MIDNIGHT, "tag-archived:true"),
+            Query::TagArchived(true),
+        );
+        assert_eq!(
+            Query::from_search(&db, &tz, DayStart::MIDNIGHT, "tag-archived:false"),
+            Query::TagArchived(false),
+        );
+    }
+
+    #[test]
+    fn tag_archived_follows_the_current_tags() {
+        let mut db = example_db();
+        let now = chrono::Utc::now();
+        let task_in = |tag: Option<TagId>| {
+            let mut task = crate::Task::from(crate::api::Task {
+                id: TaskId(Uuid::new_v4()),
+                owner_id: UserId::stub(),
+                date: now,
+                initial_title: String::new(),
+                top_comment_id: EventId(Uuid::new_v4()),
+            });
+            if let Some(tag) = tag {
+                task.current_tags.insert(
+                    tag,
+                    crate::TaskInTag {
+                        priority: 0,
+                        backlog: false,
+                        section: None,
+                    },
+                );
+            }
+            task
+        };
+        let baz = db.tag_id("baz").unwrap();
+        let in_baz = task_in(Some(baz));
+        let untagged = task_in(None);
+        let archived = |db: &DbDump, task: &crate::Task| {
+            let yes = Query::TagArchived(true)
+                .matches_at(task, &db.tags, &now)
+                .unwrap();
+            let no = Query::TagArchived(false)
+                .matches_at(task, &db.tags, &now)
+                .unwrap();
+            assert_ne!(yes, no);
+            yes
+        };
+        assert!(!archived(&db, &in_baz));
+        assert!(
+            !archived(&db, &untagged),
+            "untagged tasks are in no archived tag"
+        );
+        db.tags.get_mut(&baz).unwrap().archived = true;
+        assert!(
+            archived(&db, &in_baz),
+            "archiving the tag changes the result"
+        );
+        assert!(!archived(&db, &untagged));
+    }
+
+    #[test]
+    fn primary_constant() {
+        let db = example_db();
+        let tz = example_tz();
+        assert_eq!(
+            Query::from_search(&db, &tz, DayStart::MIDNIGHT, "true"),
+            Query::All(vec![]),
+        );
+        assert_eq!(
+            Query::from_search(&db, &tz, DayStart::MIDNIGHT, "-(FALSE)"),
+            Query::Not(Box::new(Query::Any(vec![]))),
+        );
+        assert_eq!(
+            Query::from_search(&db, &tz, DayStart::MIDNIGHT, "trueish"),
+            Query::Phrase(String::from("trueish")),
+        );
     }
 
     #[test]
@@ -449,11 +932,11 @@ mod tests {
         let db = example_db();
         let tz = example_tz();
         assert_eq!(
-            Query::from_search(&db, &tz, "untagged:true"),
+            Query::from_search(&db, &tz, DayStart::MIDNIGHT, "untagged:true"),
             Query::Untagged(true),
         );
         assert_eq!(
-            Query::from_search(&db, &tz, "untagged:false"),
+            Query::from_search(&db, &tz, DayStart::MIDNIGHT, "untagged:false"),
             Query::Untagged(false),
         );
     }
@@ -464,12 +947,24 @@ mod tests {
         let tz = example_tz();
 
         // Basic words (including tag name)
-        assert_eq!(Query::from_search(&db, &tz, "test"), phrase("test"),);
-        assert_eq!(Query::from_search(&db, &tz, "foo"), phrase("foo"),);
+        assert_eq!(
+            Query::from_search(&db, &tz, DayStart::MIDNIGHT, "test"),
+            phrase("test"),
+        );
+        assert_eq!(
+            Query::from_search(&db, &tz, DayStart::MIDNIGHT, "foo"),
+            phrase("foo"),
+        );
 
         // Words matching special query parameters
-        assert_eq!(Query::from_search(&db, &tz, "archived"), phrase("archived"),);
-        assert_eq!(Query::from_search(&db, &tz, "tag"), phrase("tag"),);
+        assert_eq!(
+            Query::from_search(&db, &tz, DayStart::MIDNIGHT, "archived"),
+            phrase("archived"),
+        );
+        assert_eq!(
+            Query::from_search(&db, &tz, DayStart::MIDNIGHT, "tag"),
+            phrase("tag"),
+        );
     }
 
     #[test]
@@ -478,30 +973,41 @@ mod tests {
         let tz = example_tz();
 
         // Basic usage
-        assert_eq!(Query::from_search(&db, &tz, r#""test""#), phrase("test"),);
         assert_eq!(
-            Query::from_search(&db, &tz, r#""foo bar""#),
+            Query::from_search(&db, &tz, DayStart::MIDNIGHT, r#""test""#),
+            phrase("test"),
+        );
+        assert_eq!(
+            Query::from_search(&db, &tz, DayStart::MIDNIGHT, r#""foo bar""#),
             phrase("foo bar"),
         );
 
         // Things that look like queries
         assert_eq!(
-            Query::from_search(&db, &tz, r#""(foo bar OR archived:false)""#),
+            Query::from_search(
+                &db,
+                &tz,
+                DayStart::MIDNIGHT,
+                r#""(foo bar OR archived:false)""#
+            ),
             phrase("(foo bar OR archived:false)"),
         );
-        assert_eq!(Query::from_search(&db, &tz, r#""(test""#), phrase("(test"),);
+        assert_eq!(
+            Query::from_search(&db, &tz, DayStart::MIDNIGHT, r#""(test""#),
+            phrase("(test"),
+        );
 
         // Escapes
         assert_eq!(
-            Query::from_search(&db, &tz, r#""foo\" bar""#),
+            Query::from_search(&db, &tz, DayStart::MIDNIGHT, r#""foo\" bar""#),
             phrase(r#"foo" bar"#),
         );
         assert_eq!(
-            Query::from_search(&db, &tz, r#""foo\\ bar""#),
+            Query::from_search(&db, &tz, DayStart::MIDNIGHT, r#""foo\\ bar""#),
             phrase(r#"foo\ bar"#),
         );
         assert_eq!(
-            Query::from_search(&db, &tz, r#""foo\\\" bar""#),
+            Query::from_search(&db, &tz, DayStart::MIDNIGHT, r#""foo\\\" bar""#),
             phrase(r#"foo\" bar"#),
         );
     }
@@ -513,23 +1019,23 @@ mod tests {
 
         // Nothing is and
         assert_eq!(
-            Query::from_search(&db, &tz, "foo bar"),
+            Query::from_search(&db, &tz, DayStart::MIDNIGHT, "foo bar"),
             Query::All(vec![phrase("foo"), phrase("bar")]),
         );
         assert_eq!(
-            Query::from_search(&db, &tz, r#""foo bar" "baz""#),
+            Query::from_search(&db, &tz, DayStart::MIDNIGHT, r#""foo bar" "baz""#),
             Query::All(vec![phrase("foo bar"), phrase("baz")]),
         );
 
         // Explicit and
         assert_eq!(
-            Query::from_search(&db, &tz, "foo AND archived:false"),
+            Query::from_search(&db, &tz, DayStart::MIDNIGHT, "foo AND archived:false"),
             Query::All(vec![phrase("foo"), Query::Archived(false)]),
         );
 
         // Explicit or
         assert_eq!(
-            Query::from_search(&db, &tz, "foo or archived:false"),
+            Query::from_search(&db, &tz, DayStart::MIDNIGHT, "foo or archived:false"),
             Query::Any(vec![phrase("foo"), Query::Archived(false)]),
         );
     }
@@ -539,29 +1045,293 @@ mod tests {
         let db = example_db();
         let tz = example_tz();
         assert_eq!(
-            Query::from_search(&db, &tz, "foo bar baz"),
+            Query::from_search(&db, &tz, DayStart::MIDNIGHT, "foo bar baz"),
             Query::All(vec![phrase("foo"), phrase("bar"), phrase("baz")]),
         );
         assert_eq!(
-            Query::from_search(&db, &tz, "foo bar or baz"),
+            Query::from_search(&db, &tz, DayStart::MIDNIGHT, "foo bar or baz"),
             Query::All(vec![
                 phrase("foo"),
                 Query::Any(vec![phrase("bar"), phrase("baz")])
             ]),
         );
         assert_eq!(
-            Query::from_search(&db, &tz, "(foo bar) or baz"),
+            Query::from_search(&db, &tz, DayStart::MIDNIGHT, "(foo bar) or baz"),
             Query::Any(vec![
                 Query::All(vec![phrase("foo"), phrase("bar")]),
                 phrase("baz")
             ]),
         );
         assert_eq!(
-            Query::from_search(&db, &tz, "(archived:true bar) or baz"),
+            Query::from_search(&db, &tz, DayStart::MIDNIGHT, "(archived:true bar) or baz"),
             Query::Any(vec![
                 Query::All(vec![Query::Archived(true), phrase("bar")]),
                 phrase("baz")
             ]),
         );
     }
+
+    #[test]
+    fn primary_prio() {
+        let db = example_db();
+        let tz = example_tz();
+        assert_eq!(
+            Query::from_search(&db, &tz, DayStart::MIDNIGHT, "prio:>=p2"),
+            Query::PriorityAtLeast(Priority::P2),
+        );
+        assert_eq!(
+            Query::from_search(&db, &tz, DayStart::MIDNIGHT, "prio:>P3"),
+            Query::PriorityAtLeast(Priority::P2),
+        );
+        assert_eq!(
+            Query::from_search(&db, &tz, DayStart::MIDNIGHT, "prio:<p4"),
+            Query::Any(vec![]),
+        );
+        assert_eq!(
+            Query::from_search(&db, &tz, DayStart::MIDNIGHT, "prio:p1"),
+            Query::All(vec![
+                Query::PriorityAtLeast(Priority::P1),
+                Query::PriorityAtMost(Priority::P1),
+            ]),
+        );
+        assert_eq!(
+            Query::from_search(&db, &tz, DayStart::MIDNIGHT, "prio:p5"),
+            phrase("prio:p5"),
+        );
+    }
+
+    #[test]
+    fn primary_stale() {
+        let db = example_db();
+        let tz = example_tz();
+        let days_ago = |days: i64| TimeQuery::DayRelative {
+            timezone: tz,
+            day_offset: -days,
+            day_start: DayStart::MIDNIGHT,
+        };
+        assert_eq!(
+            Query::from_search(&db, &tz, DayStart::MIDNIGHT, "stale:>30d"),
+            Query::LastEventBefore(days_ago(30)),
+        );
+        assert_eq!(
+            Query::from_search(&db, &tz, DayStart::MIDNIGHT, "stale:<=7d"),
+            Query::LastEventAfter(days_ago(7)),
+        );
+        assert_eq!(
+            Query::from_search(&db, &tz, DayStart::MIDNIGHT, "stale:1d"),
+            Query::All(vec![
+                Query::LastEventAfter(days_ago(1)),
+                Query::LastEventBefore(days_ago(0)),
+            ]),
+        );
+        assert_eq!(
+            Query::from_search(&db, &tz, DayStart::MIDNIGHT, "stale:30"),
+            phrase("stale:30"),
+        );
+        assert_eq!(
+            Query::from_search(&db, &tz, DayStart::MIDNIGHT, "stale:>99999999999999999999d"),
+            phrase("stale:>99999999999999999999d"),
+        );
+    }
+
+    #[test]
+    fn dates_across_dst() {
+        use chrono::TimeZone;
+        let db = example_db();
+        let tz = example_tz();
+        let utc = |m, d, h| {
+            TimeQuery::Absolute(chrono::Utc.with_ymd_and_hms(2023, m, d, h, 0, 0).unwrap())
+        };
+        // 2023-03-26 only lasts 23 hours in Paris
+        assert_eq!(
+            Query::from_search(&db, &tz, DayStart::MIDNIGHT, "scheduled:2023-03-26"),
+            Query::All(vec![
+                Query::ScheduledForAfter(utc(3, 25, 23)),
+                Query::ScheduledForBefore(utc(3, 26, 22)),
+            ]),
+        );
+        assert_eq!(
+            Query::from_search(
+                &db,
+                &tz,
+                DayStart { minutes: 3 * 60 },
+                "scheduled<=2023-03-26"
+            ),
+            Query::ScheduledForBefore(utc(3, 27, 1)),
+        );
+    }
+
+    #[test]
+    fn day_relative_queries_follow_the_clock() {
+        use chrono::TimeZone;
+        let clock = Clock::manual(chrono::Utc.with_ymd_and_hms(2023, 1, 9, 12, 0, 0).unwrap());
+        let mut task = crate::Task::from(crate::api::Task {
+            id: TaskId(Uuid::new_v4()),
+            owner_id: UserId::stub(),
+            date: clock.now(),
+            initial_title: String::new(),
+            top_comment_id: EventId(Uuid::new_v4()),
+        });
+        task.scheduled_for = Some(chrono::Utc.with_ymd_and_hms(2023, 1, 10, 10, 0, 0).unwrap());
+        // Scheduled for before the start of tomorrow
+        let today = Query::ScheduledForBefore(TimeQuery::DayRelative {
+            timezone: example_tz(),
+            day_offset: 1,
+            day_start: DayStart::MIDNIGHT,
+        });
+        let tags = im::HashMap::new();
+        assert!(!today.matches_at(&task, &tags, &clock.now()).unwrap());
+        clock.advance(chrono::Duration::hours(11));
+        assert!(!today.matches_at(&task, &tags, &clock.now()).unwrap());
+        // Midnight in Paris
+        clock.advance(chrono::Duration::hours(1));
+        assert!(today.matches_at(&task, &tags, &clock.now()).unwrap());
+    }
+
+    #[test]
+    fn printer_output() {
+        let db = example_db();
+        let tz = example_tz();
+        for search in [
+            "tag:foo done:false",
+            r#"tag:"to-do" -tag:bar"#,
+            "archived:false (prio:p1 OR scheduled<today+1)",
+            r#""foo bar" -baz"#,
+            "stale:>30d blocked:2023-03-26",
+            "true",
+            "done:true -false",
+        ] {
+            let q = Query::from_search(&db, &tz, DayStart::MIDNIGHT, search);
+            assert_eq!(q.to_search(&db.tags, &tz, DayStart::MIDNIGHT), search);
+        }
+    }
+
+    /// Replaces the leaves of `q` that the search syntax cannot express with close ones it can
+    fn expressible(q: &Query, db: &DbDump, tz: &chrono_tz::Tz) -> Query {
+        use chrono::Datelike;
+        let relative = |day_offset: i64| TimeQuery::DayRelative {
+            timezone: *tz,
+            day_offset: day_offset % 1000,
+            day_start: DayStart::MIDNIGHT,
+        };
+        let day = |t: &TimeQuery| match t {
+            TimeQuery::Absolute(t) => {
+                let date = t.date_naive();
+                let date = chrono::NaiveDate::from_ymd_opt(
+                    2000 + date.year().rem_euclid(100),
+                    date.month(),
+                    date.day().min(28),
+                )
+                .unwrap();
+                TimeQuery::Absolute(
+                    DayStart::MIDNIGHT
+                        .start_of(date, tz)
+                        .with_timezone(&chrono::Utc),
+                )
+            }
+            TimeQuery::DayRelative { day_offset, .. } => relative(*day_offset),
+        };
+        // Staleness can only be expressed relative to today, and up to tomorrow
+        let stale = |t: &TimeQuery| match t {
+            TimeQuery::Absolute(_) => relative(0),
+            TimeQuery::DayRelative { day_offset, .. } => relative((day_offset % 1000).min(1)),
+        };
+        let mut tags = db.tags.keys().copied().collect::<Vec<_>>();
+        tags.sort_unstable_by_key(|t| t.0);
+        match q {
+            Query::Any(q) => Query::Any(q.iter().map(|q| expressible(q, db, tz)).collect()),
+            Query::All(q) => Query::All(q.iter().map(|q| expressible(q, db, tz)).collect()),
+            Query::Not(q) => Query::Not(Box::new(expressible(q, db, tz))),
+            Query::Tag { tag, backlog } => Query::Tag {
+                tag: tags[(tag.0.as_u128() % tags.len() as u128) as usize],
+                backlog: *backlog,
+            },
+            Query::ScheduledForBefore(t) => Query::ScheduledForBefore(day(t)),
+            Query::ScheduledForAfter(t) => Query::ScheduledForAfter(day(t)),
+            Query::BlockedUntilAtMost(t) => Query::BlockedUntilAtMost(day(t)),
+            Query::BlockedUntilAtLeast(t) => Query::BlockedUntilAtLeast(day(t)),
+            Query::LastEventBefore(t) => Query::LastEventBefore(stale(t)),
+            Query::LastEventAfter(t) => Query::LastEventAfter(stale(t)),
+            q => q.clone(),
+        }
+    }
+
+    #[test]
+    fn printer_round_trips() {
+        let db = example_db();
+        let tz = example_tz();
+        bolero::check!().with_type::<Query>().for_each(|q| {
+            let q = expressible(q, &db, &tz);
+            let search = q.to_search(&db.tags, &tz, DayStart::MIDNIGHT);
+            assert_eq!(
+                Query::from_search(&db, &tz, DayStart::MIDNIGHT, &search),
+                q.normalize(),
+                "query {q:?} was printed as {search:?}",
+            );
+        });
+    }
+
+    #[test]
+    fn candidates_are_a_superset() {
+        let tag = |i: u8| TagId(Uuid::from_u128(u128::from(i % 2)));
+        let time = |secs: u8| {
+            chrono::DateTime::from_utc(
+                chrono::NaiveDateTime::from_timestamp_opt(i64::from(secs % 8), 0).unwrap(),
+                chrono::Utc,
+            )
+        };
+        let queries = [
+            Query::Archived(false),
+            Query::Done(true),
+            Query::tag(tag(0)),
+            Query::Untagged(true),
+            Query::ScheduledForBefore(TimeQuery::Absolute(time(3))),
+            Query::ScheduledForAfter(TimeQuery::Absolute(time(3))),
+            Query::All(vec![Query::Done(false), Query::tag(tag(1))]),
+            Query::All(vec![Query::Archived(true), phrase("foo")]),
+            Query::Any(vec![Query::Archived(true), Query::Untagged(true)]),
+            Query::Any(vec![Query::Done(true), phrase("foo")]),
+        ];
+        let now = chrono::Utc::now();
+        // Done, archived, tag and schedule of each task
+        bolero::check!()
+            .with_type::<Vec<(bool, bool, Option<u8>, Option<u8>)>>()
+            .for_each(|specs| {
+                let mut index = TaskIndex::default();
+                let mut tasks = Vec::new();
+                for (i, (done, archived, t, sched)) in specs.iter().enumerate() {
+                    let mut task = crate::Task::from(crate::api::Task {
+                        id: TaskId(Uuid::from_u128(i as u128)),
+                        owner_id: UserId::stub(),
+                        date: chrono::Utc::now(),
+                        initial_title: String::new(),
+                        top_comment_id: EventId(Uuid::from_u128(i as u128)),
+                    });
+                    task.is_done = *done;
+                    task.is_archived = *archived;
+                    if let Some(t) = t {
+                        task.current_tags.insert(
+                            tag(*t),
+                            crate::TaskInTag {
+                                priority: 0,
+                                backlog: false,
+                                section: None,
+                            },
+                        );
+                    }
+                    task.scheduled_for = sched.map(time);
+                    index.insert(&task);
+                    tasks.push(task);
+                }
+                for q in queries.iter() {
+                    if let Some(candidates) = q.candidates(&index, &now) {
+                        for t in tasks.iter() {
+                            if q.matches_at(t, &im::HashMap::new(), &now).unwrap() {
+                                assert!(candidates.contains(&t.id), "query {q:?} missed {t:?}");
+                            }
+                        }
+                    }
+                }
+            });
+    }
 }
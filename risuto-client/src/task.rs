@@ -1,10 +1,15 @@
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
 
 use crate::{
-    api::{self, Event, EventData, OrderId, TagId, TaskId, Time, UserId},
+    api::{self, Event, EventData, EventId, OrderId, Priority, TagId, TaskId, Time, UserId},
     Comment,
 };
 
+// TODO: make below chrono::Duration once https://github.com/chronotope/chrono/issues/309 fixed
+// Two title changes by different users closer than this are assumed to have been made without
+// seeing each other, unless the latter user marked the former change as read
+const TITLE_CONFLICT_WINDOW_SECS: i64 = 5 * 60;
+
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct TaskInTag {
     // higher is lower in the tag list
@@ -12,6 +17,9 @@ pub struct TaskInTag {
 
     /// if true, this task is in this tag's backlog
     pub backlog: bool,
+
+    /// section of the tag's list this task is in, if any
+    pub section: Option<String>,
 }
 
 // TODO: consider switching to the im crate for cheaply-clonable stuff here
@@ -24,12 +32,20 @@ pub struct Task {
     pub initial_title: Arc<String>,
     pub current_title: Arc<String>,
 
+    /// Title that got overridden by a concurrent title change, until the user picks one
+    pub title_conflict: Option<Arc<String>>,
+
+    /// Last title change by another user, unless the user marked it as seen, that their next
+    /// rename would be flagged as conflicting with
+    pub unseen_title_change: Option<EventId>,
+
     pub top_comment: Comment,
 
     pub is_done: bool,
     pub is_archived: bool,
     pub blocked_until: Option<Time>,
     pub scheduled_for: Option<Time>,
+    pub priority: Option<Priority>,
     pub current_tags: im::HashMap<TagId, TaskInTag>,
     pub orders: im::HashMap<OrderId, i64>,
 
@@ -37,6 +53,9 @@ pub struct Task {
     pub current_comments: im::OrdMap<Time, im::Vector<Comment>>,
 
     pub events: im::OrdMap<Time, im::Vector<Event>>,
+
+    /// Number of events added to this task, to cheaply know whether it changed
+    pub revision: u64,
 }
 
 impl From<api::Task> for Task {
@@ -48,6 +67,8 @@ impl From<api::Task> for Task {
             date: t.date,
             initial_title: initial_title.clone(),
             current_title: initial_title,
+            title_conflict: None,
+            unseen_title_change: None,
             top_comment: Comment {
                 creation_id: t.top_comment_id,
                 edits: im::OrdMap::new(),
@@ -58,10 +79,12 @@ impl From<api::Task> for Task {
             is_archived: false,
             blocked_until: None,
             scheduled_for: None,
+            priority: None,
             current_tags: im::HashMap::new(),
             orders: im::HashMap::new(),
             current_comments: im::OrdMap::new(),
             events: im::OrdMap::new(),
+            revision: 0,
         }
     }
 }
@@ -87,12 +110,85 @@ impl Task {
         let insert_into = self.events.entry(e.date).or_insert(im::Vector::new());
         if insert_into.iter().find(|evt| **evt == e).is_none() {
             insert_into.push_back(e);
+            self.revision += 1;
+        }
+    }
+
+    /// Flags the title as conflicted if `is_concurrent` with the previous title change
+    fn set_title(&mut self, title: &str, is_concurrent: bool) {
+        let resolves_conflict = self
+            .title_conflict
+            .as_ref()
+            .map(|t| **t == title || *self.current_title == title)
+            .unwrap_or(false);
+        self.title_conflict = match is_concurrent && !resolves_conflict {
+            true if *self.current_title != title => Some(self.current_title.clone()),
+            _ => None,
+        };
+        self.current_title = Arc::new(String::from(title));
+    }
+
+    /// Events renaming this task to `title` for `user`, who must be the user metadata got last
+    /// refreshed for
+    ///
+    /// The title change being overridden gets marked as read first, so that the rename does not
+    /// get flagged as conflicting with it.
+    pub fn rename_events(&self, user: UserId, date: Time, title: String) -> Vec<Event> {
+        let mut res = Vec::new();
+        if let Some(event_id) = self.unseen_title_change {
+            let data = EventData::SetEventRead {
+                event_id,
+                now_read: true,
+            };
+            res.push(Event::at(user, self.id, date, data));
+        }
+        res.push(Event::at(user, self.id, date, EventData::SetTitle(title)));
+        res
+    }
+
+    /// Lists the creation ids of the comments on this task that `user` did not read yet
+    pub fn unread_comments(&self, user: &UserId) -> Vec<EventId> {
+        let mut res = self.top_comment.unread_by(user);
+        for c in self.current_comments.values().flat_map(|v| v.iter()) {
+            res.extend(c.unread_by(user));
         }
+        res
     }
 
     pub fn refresh_metadata(&mut self, for_user: &UserId) {
+        // Everything is recomputed from the events, so that events received one by one (eg. from
+        // the feed) are folded in the same way as a full refresh would
         self.current_title = self.initial_title.clone();
-        for evts in self.events.values() {
+        self.title_conflict = None;
+        self.top_comment.edits = im::OrdMap::new();
+        self.top_comment.read = im::HashSet::new();
+        self.top_comment.children = im::OrdMap::new();
+        self.is_done = false;
+        self.is_archived = false;
+        self.blocked_until = None;
+        self.scheduled_for = None;
+        self.priority = None;
+        self.current_tags = im::HashMap::new();
+        self.orders = im::HashMap::new();
+        self.current_comments = im::OrdMap::new();
+        let mut last_title_change: Option<&Event> = None;
+        // Cheap clone of the events, so that going through them does not prevent updating self
+        let events = self.events.clone();
+        // When each user first marked each event as read, as a hint of what they had seen
+        //
+        // This is gathered beforehand, as clients mark the title change they override as read
+        // right before renaming, possibly at the same timestamp.
+        let mut seen = HashMap::new();
+        for e in events.values().flat_map(|evts| evts.iter()) {
+            if let EventData::SetEventRead {
+                event_id,
+                now_read: true,
+            } = &e.data
+            {
+                seen.entry((e.owner_id, *event_id)).or_insert(e.date);
+            }
+        }
+        for evts in events.values() {
             if evts.len() > 1 {
                 tracing::warn!(
                     num_evts = evts.len(),
@@ -101,7 +197,21 @@ impl Task {
             }
             for e in evts {
                 match &e.data {
-                    EventData::SetTitle(title) => self.current_title = Arc::new(title.clone()),
+                    EventData::SetTitle(title) => {
+                        let is_concurrent = last_title_change
+                            .map(|last| {
+                                last.owner_id != e.owner_id
+                                    && !seen
+                                        .get(&(e.owner_id, last.id))
+                                        .map(|read| *read <= e.date)
+                                        .unwrap_or(false)
+                                    && e.date - last.date
+                                        < chrono::Duration::seconds(TITLE_CONFLICT_WINDOW_SECS)
+                            })
+                            .unwrap_or(false);
+                        self.set_title(title, is_concurrent);
+                        last_title_change = Some(e);
+                    }
                     EventData::SetDone(now_done) => self.is_done = *now_done,
                     EventData::SetArchived(now_archived) => self.is_archived = *now_archived,
                     EventData::BlockedUntil(time) => self.blocked_until = *time,
@@ -110,20 +220,29 @@ impl Task {
                             self.scheduled_for = *time;
                         }
                     }
+                    EventData::SetPriority(priority) => self.priority = *priority,
                     EventData::SetOrder { order, prio } => {
                         if e.owner_id == *for_user {
                             self.orders.insert(order.clone(), *prio);
                         }
                     }
                     EventData::AddTag { tag, prio, backlog } => {
+                        // Moving a task within its tag re-adds it, which must not lose its section
+                        let section = self.current_tags.get(tag).and_then(|t| t.section.clone());
                         self.current_tags.insert(
                             *tag,
                             TaskInTag {
                                 priority: *prio,
                                 backlog: *backlog,
+                                section,
                             },
                         );
                     }
+                    EventData::SetSection { tag, section } => {
+                        if let Some(t) = self.current_tags.get_mut(tag) {
+                            t.section = section.clone();
+                        }
+                    }
                     EventData::RmTag(tag) => {
                         self.current_tags.remove(tag);
                     }
@@ -198,6 +317,15 @@ impl Task {
                             comment.read.insert(e.owner_id);
                         }
                     }
+                    EventData::SetEventRead { event_id, now_read }
+                        if *event_id == self.top_comment.creation_id =>
+                    {
+                        if *now_read {
+                            self.top_comment.read.insert(e.owner_id);
+                        } else {
+                            self.top_comment.read.remove(&e.owner_id);
+                        }
+                    }
                     EventData::SetEventRead { event_id, now_read } => {
                         if let Some(comment) =
                             Comment::find_in(&mut self.current_comments, event_id)
@@ -212,9 +340,126 @@ impl Task {
                 }
             }
         }
+        self.unseen_title_change = last_title_change
+            .filter(|e| e.owner_id != *for_user && !seen.contains_key(&(*for_user, e.id)))
+            .map(|e| e.id);
         assert!(
             !self.top_comment.edits.is_empty(),
             "task {self:?} has no top comment"
         );
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use super::*;
+    use crate::api::Uuid;
+
+    struct Example {
+        task: Task,
+        alice: UserId,
+        bob: UserId,
+        start: Time,
+    }
+
+    impl Example {
+        fn new() -> Example {
+            let alice = UserId(Uuid::new_v4());
+            let start = Utc.with_ymd_and_hms(2023, 1, 9, 12, 0, 0).unwrap();
+            let top_comment_id = EventId(Uuid::new_v4());
+            let mut task = Task::from(api::Task {
+                id: TaskId(Uuid::new_v4()),
+                owner_id: alice,
+                date: start,
+                initial_title: String::from("write report"),
+                top_comment_id,
+            });
+            task.add_event(Event {
+                id: top_comment_id,
+                owner_id: alice,
+                date: start,
+                task_id: task.id,
+                data: EventData::AddComment {
+                    text: String::new(),
+                    parent_id: None,
+                },
+            });
+            Example {
+                task,
+                alice,
+                bob: UserId(Uuid::new_v4()),
+                start,
+            }
+        }
+
+        /// Adds an event by `user`, `secs` seconds after the task got created
+        fn event(&mut self, user: UserId, secs: i64, data: EventData) -> EventId {
+            let date = self.start + chrono::Duration::seconds(secs);
+            let e = Event::at(user, self.task.id, date, data);
+            let id = e.id;
+            self.task.add_event(e);
+            self.task.refresh_metadata(&self.alice);
+            id
+        }
+
+        fn rename(&mut self, user: UserId, secs: i64, title: &str) -> EventId {
+            self.event(user, secs, EventData::SetTitle(String::from(title)))
+        }
+
+        fn conflict(&self) -> Option<&str> {
+            self.task.title_conflict.as_ref().map(|t| t.as_str())
+        }
+    }
+
+    #[test]
+    fn successive_renames_by_the_same_user_do_not_conflict() {
+        let mut ex = Example::new();
+        ex.rename(ex.alice, 10, "write the report");
+        ex.rename(ex.alice, 20, "write the quarterly report");
+        assert_eq!(*ex.task.current_title, "write the quarterly report");
+        assert_eq!(ex.conflict(), None);
+    }
+
+    #[test]
+    fn close_renames_by_different_users_conflict() {
+        let mut ex = Example::new();
+        ex.rename(ex.alice, 10, "write the report");
+        ex.rename(ex.bob, 20, "write the yearly report");
+        assert_eq!(*ex.task.current_title, "write the yearly report");
+        assert_eq!(ex.conflict(), Some("write the report"));
+
+        // Picking either title resolves the conflict
+        ex.rename(ex.alice, 30, "write the report");
+        assert_eq!(ex.conflict(), None);
+    }
+
+    #[test]
+    fn renames_after_seeing_the_previous_one_do_not_conflict() {
+        let mut ex = Example::new();
+        let first = ex.rename(ex.bob, 10, "write the yearly report");
+        assert_eq!(ex.task.unseen_title_change, Some(first));
+        let date = ex.start + chrono::Duration::seconds(20);
+        let title = String::from("write the report");
+        for e in ex.task.rename_events(ex.alice, date, title) {
+            ex.task.add_event(e);
+        }
+        ex.task.refresh_metadata(&ex.alice);
+        assert_eq!(*ex.task.current_title, "write the report");
+        assert_eq!(ex.conflict(), None);
+        assert_eq!(ex.task.unseen_title_change, None);
+    }
+
+    #[test]
+    fn distant_renames_do_not_conflict() {
+        let mut ex = Example::new();
+        ex.rename(ex.alice, 10, "write the report");
+        ex.rename(
+            ex.bob,
+            10 + TITLE_CONFLICT_WINDOW_SECS,
+            "write the yearly report",
+        );
+        assert_eq!(ex.conflict(), None);
+    }
+}
@@ -1,5 +1,5 @@
 use anyhow::Context;
-use risuto_api::{AuthToken, UserId, Uuid};
+use risuto_api::{AuthToken, Event, EventData, MigrationStatus, Query, Role, TagId, UserId, Uuid};
 
 #[derive(structopt::StructOpt)]
 struct Opt {
@@ -20,6 +20,95 @@ enum Command {
         /// Initial password
         initial_password: String,
     },
+
+    /// Make archived tasks of a tag automatically come back after some time
+    SetTickler {
+        /// Id of the tag
+        tag: Uuid,
+
+        /// Number of days after which archived tasks get unarchived and scheduled
+        #[structopt(long, required_unless = "disable")]
+        days: Option<i64>,
+
+        /// Disable the tickler for this tag
+        #[structopt(long, conflicts_with = "days")]
+        disable: bool,
+    },
+
+    /// Manage invite links
+    Invite(InviteCommand),
+
+    /// Put the server in read-only maintenance mode, or lift it
+    Maintenance(MaintenanceCommand),
+
+    /// Manage the database schema migrations
+    Migrate(MigrateCommand),
+
+    /// Replace some text in the titles and comments of all the tasks matching a full-text search
+    Replace {
+        /// Full-text search selecting the tasks to edit
+        #[structopt(long)]
+        query: String,
+
+        /// Text to look for, case-sensitively
+        #[structopt(long)]
+        find: String,
+
+        /// Text to replace it with
+        #[structopt(long)]
+        replace: String,
+
+        /// Replace the text in task titles
+        #[structopt(long)]
+        titles: bool,
+
+        /// Replace the text in comments
+        #[structopt(long)]
+        comments: bool,
+
+        /// Only list the changes, without making them
+        #[structopt(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(structopt::StructOpt)]
+enum InviteCommand {
+    /// Create an invite token, allowing someone to create an account
+    Create {
+        /// Id of a tag to share with the new user, can be repeated
+        #[structopt(long = "tag")]
+        tags: Vec<Uuid>,
+
+        /// Role the new user gets on the shared tags: viewer, commenter, editor or admin
+        #[structopt(long, default_value = "editor", parse(try_from_str = parse_role))]
+        role: Role,
+    },
+}
+
+#[derive(structopt::StructOpt)]
+enum MaintenanceCommand {
+    /// Reject all changes from users, while still serving reads and feeds
+    On,
+
+    /// Accept changes from users again
+    Off,
+}
+
+#[derive(structopt::StructOpt)]
+enum MigrateCommand {
+    /// List the migrations known to the server, and whether they were applied
+    Status,
+
+    /// Apply all pending migrations
+    Run,
+
+    /// Revert the latest applied migration
+    Revert,
+}
+
+fn parse_role(role: &str) -> anyhow::Result<Role> {
+    Role::parse(role).ok_or_else(|| anyhow::anyhow!("unknown role {role:?}"))
 }
 
 fn admin_token() -> anyhow::Result<AuthToken> {
@@ -52,6 +141,111 @@ async fn main() -> anyhow::Result<()> {
                 .await?
                 .error_for_status()?;
         }
+        Command::SetTickler { tag, days, .. } => {
+            client
+                .post(format!("{}/api/admin/set-tickler", opt.host))
+                .json(&risuto_api::Tickler {
+                    tag: TagId(tag),
+                    interval_secs: days.map(|d| d * 24 * 3600),
+                })
+                .bearer_auth(admin_token()?.0)
+                .send()
+                .await?
+                .error_for_status()?;
+        }
+        Command::Invite(InviteCommand::Create { tags, role }) => {
+            let token: Uuid = client
+                .post(format!("{}/api/admin/create-invite", opt.host))
+                .json(&risuto_api::NewInvite {
+                    tags: tags.into_iter().map(TagId).collect(),
+                    role,
+                })
+                .bearer_auth(admin_token()?.0)
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+            println!("{token}");
+        }
+        Command::Maintenance(cmd) => {
+            client
+                .post(format!("{}/api/admin/set-maintenance", opt.host))
+                .json(&matches!(cmd, MaintenanceCommand::On))
+                .bearer_auth(admin_token()?.0)
+                .send()
+                .await?
+                .error_for_status()?;
+        }
+        Command::Migrate(cmd) => {
+            let req = match cmd {
+                MigrateCommand::Status => client.get(format!("{}/api/admin/migrations", opt.host)),
+                MigrateCommand::Run => {
+                    client.post(format!("{}/api/admin/run-migrations", opt.host))
+                }
+                MigrateCommand::Revert => {
+                    client.post(format!("{}/api/admin/revert-migration", opt.host))
+                }
+            };
+            let migrations: Vec<MigrationStatus> = req
+                .bearer_auth(admin_token()?.0)
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+            for m in migrations.iter() {
+                let status = match m.applied {
+                    true => "applied",
+                    false => "pending",
+                };
+                println!("{} {status:>7} {}", m.version, m.description);
+            }
+        }
+        Command::Replace {
+            query,
+            find,
+            replace,
+            titles,
+            comments,
+            dry_run,
+        } => {
+            anyhow::ensure!(
+                titles || comments,
+                "at least one of --titles and --comments must be set"
+            );
+            let events: Vec<Event> = client
+                .post(format!("{}/api/admin/replace-text", opt.host))
+                .json(&risuto_api::TextReplacement {
+                    query: Query::Phrase(query),
+                    find,
+                    replace,
+                    titles,
+                    comments,
+                    dry_run,
+                })
+                .bearer_auth(admin_token()?.0)
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+            for e in events.iter() {
+                match &e.data {
+                    EventData::SetTitle(title) => {
+                        println!("task {}: title → {title:?}", e.task_id.0)
+                    }
+                    EventData::EditComment { text, comment_id } => {
+                        println!("task {}: comment {} → {text:?}", e.task_id.0, comment_id.0)
+                    }
+                    _ => (),
+                }
+            }
+            match dry_run {
+                true => println!("{} changes would be made", events.len()),
+                false => println!("{} changes made", events.len()),
+            }
+        }
     }
 
     Ok(())
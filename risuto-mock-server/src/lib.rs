@@ -1,4 +1,5 @@
 use std::{
+    cmp,
     collections::{btree_map, BTreeMap, HashMap},
     sync::Arc,
 };
@@ -6,13 +7,16 @@ use std::{
 use futures::channel::mpsc;
 use risuto_client::{
     api::{
-        self, Action, AuthInfo, AuthToken, Error, Event, NewSession, NewUser, Query, Search, Tag,
-        UserId, Uuid,
+        self, Action, ActivityPage, AuthInfo, AuthToken, Clock, Error, Event, EventData, EventId,
+        NewSession, NewUser, Query, Search, SearchResults, Tag, TagPermission, UserId, Uuid,
     },
-    DbDump, QueryExt,
+    DbDump, QueryExt, Task,
 };
 
-pub struct MockServer(BTreeMap<UserId, DbUser>);
+pub struct MockServer {
+    users: BTreeMap<UserId, DbUser>,
+    clock: Clock,
+}
 
 #[derive(Debug)]
 struct DbUser {
@@ -35,38 +39,148 @@ impl DbUser {
 #[derive(Debug)]
 struct Device(String);
 
+/// Serializable state of a `MockServer`, see `MockServer::snapshot`
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+pub struct MockSnapshot(Vec<UserSnapshot>);
+
+#[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
+struct UserSnapshot {
+    id: UserId,
+    name: String,
+    pass: String,
+    pass_hash: String,
+    sessions: Vec<(AuthToken, String)>,
+    users: Vec<api::User>,
+    tags: Vec<(Tag, AuthInfo)>,
+    searches: Vec<Search>,
+    tasks: Vec<api::Task>,
+    events: Vec<Event>,
+}
+
+fn api_task(t: &Task) -> api::Task {
+    api::Task {
+        id: t.id,
+        owner_id: t.owner_id,
+        date: t.date,
+        initial_title: String::clone(&t.initial_title),
+        top_comment_id: t.top_comment.creation_id,
+    }
+}
+
+fn task_events(t: &Task) -> impl '_ + Iterator<Item = &Event> {
+    t.events.values().flat_map(|e| e.iter())
+}
+
 impl MockServer {
     pub fn new() -> MockServer {
-        MockServer(BTreeMap::new())
+        MockServer::with_clock(Clock::system())
+    }
+
+    /// Creates a mock server that evaluates time-relative queries according to `clock`
+    pub fn with_clock(clock: Clock) -> MockServer {
+        MockServer {
+            users: BTreeMap::new(),
+            clock,
+        }
+    }
+
+    /// Captures the current state, so that it can later be restored with `restore`
+    ///
+    /// Open action feeds are not part of the snapshot.
+    pub fn snapshot(&self) -> MockSnapshot {
+        MockSnapshot(
+            self.users
+                .values()
+                .map(|u| UserSnapshot {
+                    id: u.db.owner,
+                    name: u.name.clone(),
+                    pass: u.pass.clone(),
+                    pass_hash: u.pass_hash.clone(),
+                    sessions: u
+                        .sessions
+                        .iter()
+                        .map(|(tok, dev)| (*tok, dev.0.clone()))
+                        .collect(),
+                    users: u.db.users.values().cloned().collect(),
+                    tags: u
+                        .db
+                        .tags
+                        .iter()
+                        .map(|(id, t)| (t.clone(), *u.db.perms.get(id).unwrap()))
+                        .collect(),
+                    searches: u.db.searches.values().cloned().collect(),
+                    tasks: u.db.tasks.values().map(|t| api_task(t)).collect(),
+                    events: u
+                        .db
+                        .tasks
+                        .values()
+                        .flat_map(|t| task_events(t))
+                        .cloned()
+                        .collect(),
+                })
+                .collect(),
+        )
+    }
+
+    /// Replaces the whole state with `snapshot`, closing all open action feeds
+    pub fn restore(&mut self, snapshot: MockSnapshot) {
+        self.users = snapshot
+            .0
+            .into_iter()
+            .map(|u| {
+                let mut db = DbDump {
+                    owner: u.id,
+                    ..DbDump::stub()
+                };
+                db.add_users(u.users);
+                db.add_tags(u.tags);
+                db.add_searches(u.searches);
+                db.add_tasks(u.tasks);
+                db.add_events_and_refresh_all(u.events);
+                let user = DbUser {
+                    name: u.name,
+                    pass: u.pass,
+                    pass_hash: u.pass_hash,
+                    sessions: u
+                        .sessions
+                        .into_iter()
+                        .map(|(tok, dev)| (tok, Device(dev)))
+                        .collect(),
+                    feeds: Vec::new(),
+                    db,
+                };
+                (u.id, user)
+            })
+            .collect();
     }
 
     /// Return name & pass for user number `id`
     pub fn test_get_user_info(&self, id: usize) -> (&str, &str) {
         let u = self
-            .0
+            .users
             .values()
             .skip(id)
             .next()
-            .unwrap_or_else(|| panic!("getting user {id} among {}", self.0.len()));
+            .unwrap_or_else(|| panic!("getting user {id} among {}", self.users.len()));
         (&u.name, &u.pass)
     }
 
     /// Return the current number of users
     pub fn test_num_users(&self) -> usize {
-        self.0.len()
+        self.users.len()
     }
 
     pub async fn admin_create_user(&mut self, u: NewUser, password: String) -> Result<(), Error> {
         u.validate()?;
 
-        if self.0.values().any(|db| db.db.owner == u.id) {
+        if self.users.values().any(|db| db.db.owner == u.id) {
             return Err(Error::UuidAlreadyUsed(u.id.0));
         }
-        if self.0.values().any(|db| db.name == u.name) {
+        if self.users.values().any(|db| db.name == u.name) {
             return Err(Error::NameAlreadyUsed(u.name));
         }
 
-        match self.0.entry(u.id) {
+        match self.users.entry(u.id) {
             btree_map::Entry::Occupied(_) => Err(Error::UuidAlreadyUsed(u.id.0)),
             btree_map::Entry::Vacant(entry) => {
                 entry.insert(DbUser {
@@ -77,24 +191,22 @@ impl MockServer {
                     feeds: Vec::new(),
                     db: DbDump {
                         owner: u.id,
-                        users: Arc::new(HashMap::new()),
-                        tags: Arc::new(HashMap::new()),
-                        searches: Arc::new(HashMap::new()),
-                        perms: Arc::new(HashMap::new()),
-                        tasks: Arc::new(HashMap::new()),
+                        ..DbDump::stub()
                     },
                 });
-                for db in self.0.values_mut() {
+                for db in self.users.values_mut() {
                     db.db.add_users(vec![api::User {
                         id: u.id,
                         name: u.name.clone(),
+                        avatar_hash: None,
                     }]);
                 }
-                for existing_user in self.0.values_mut() {
+                for existing_user in self.users.values_mut() {
                     existing_user
                         .relay_action(Action::NewUser(api::User {
                             id: u.id,
                             name: u.name.clone(),
+                            avatar_hash: None,
                         }))
                         .await;
                 }
@@ -105,7 +217,7 @@ impl MockServer {
 
     pub fn auth(&mut self, s: NewSession) -> Result<AuthToken, Error> {
         s.validate_except_pow()?;
-        for u in self.0.values_mut() {
+        for u in self.users.values_mut() {
             if u.name == s.user {
                 // tests (of which mock-server is a part of) don't actually use bcrypt
                 if s.password != u.pass_hash {
@@ -121,7 +233,7 @@ impl MockServer {
     }
 
     fn resolve(&self, tok: AuthToken) -> Result<&DbUser, Error> {
-        for u in self.0.values() {
+        for u in self.users.values() {
             if u.sessions.contains_key(&tok) {
                 return Ok(u);
             }
@@ -130,7 +242,7 @@ impl MockServer {
     }
 
     fn resolve_mut(&mut self, tok: AuthToken) -> Result<&mut DbUser, Error> {
-        for u in self.0.values_mut() {
+        for u in self.users.values_mut() {
             if u.sessions.contains_key(&tok) {
                 return Ok(u);
             }
@@ -152,11 +264,12 @@ impl MockServer {
     pub fn fetch_users(&self, tok: AuthToken) -> Result<Vec<api::User>, Error> {
         let _u = self.resolve(tok)?;
         Ok(self
-            .0
+            .users
             .values()
             .map(|u| api::User {
                 id: u.db.owner,
                 name: u.name.clone(),
+                avatar_hash: None,
             })
             .collect())
     }
@@ -170,36 +283,157 @@ impl MockServer {
             .collect())
     }
 
+    /// Shares or unshares a tag owned by the session's user, closing the action feeds of the
+    /// affected user like the server does, for their clients to fetch everything again
+    pub fn set_tag_permission(&mut self, tok: AuthToken, perm: TagPermission) -> Result<(), Error> {
+        let owner = self.resolve(tok)?;
+        let tag = owner
+            .db
+            .tags
+            .get(&perm.tag)
+            .filter(|t| t.owner_id == owner.db.owner)
+            .cloned();
+        let tasks = owner
+            .db
+            .tasks
+            .values()
+            .filter(|t| t.current_tags.contains_key(&perm.tag))
+            .cloned()
+            .collect::<Vec<_>>();
+        let (tag, u) = match (tag, self.users.get_mut(&perm.user), perm.role) {
+            (Some(tag), Some(u), _) => (tag, u),
+            (_, _, None) => return Ok(()),
+            (_, _, Some(_)) => return Err(Error::PermissionDenied),
+        };
+        match perm.role {
+            Some(role) => {
+                u.db.add_tags(vec![(tag, role.auth_info())]);
+                for t in tasks {
+                    let mut t = Task::clone(&t);
+                    t.refresh_metadata(&u.db.owner);
+                    u.db.insert_task(Arc::new(t));
+                }
+            }
+            None => {
+                if u.db.perms.remove(&perm.tag).is_none() {
+                    // The user could not see the tag already
+                    return Ok(());
+                }
+                u.db.tags.remove(&perm.tag);
+                for t in tasks {
+                    let still_visible = t.owner_id == u.db.owner
+                        || t.current_tags.keys().any(|tag| u.db.tags.contains_key(tag));
+                    if !still_visible {
+                        u.db.index.remove(&t);
+                        u.db.tasks.remove(&t.id);
+                    }
+                }
+            }
+        }
+        u.feeds.clear();
+        Ok(())
+    }
+
     pub fn fetch_searches(&self, tok: AuthToken) -> Result<Vec<Search>, Error> {
         let u = self.resolve(tok)?;
         Ok(u.db.searches.values().cloned().collect())
     }
 
-    pub fn search_tasks(
-        &self,
-        tok: AuthToken,
-        q: Query,
-    ) -> Result<(Vec<api::Task>, Vec<Event>), Error> {
+    pub fn search_tasks(&self, tok: AuthToken, q: Query) -> Result<SearchResults, Error> {
         let u = self.resolve(tok)?;
-        q.validate_now()?;
+        let now = self.clock.now();
+        q.validate_at(&now)?;
         let mut tasks = Vec::new();
         let mut evts = Vec::new();
-        for t in u.db.search(&Search::stub_for_query(q))? {
-            tasks.push(api::Task {
-                id: t.id,
-                owner_id: t.owner_id,
-                date: t.date,
-                initial_title: t.initial_title.clone(),
-                top_comment_id: t.top_comment.creation_id,
-            });
-            evts.extend(t.events.values().flat_map(|e| e.iter()).cloned());
+        for t in u.db.search_at(&Search::stub_for_query(q), &now)? {
+            tasks.push(api_task(&t));
+            evts.extend(task_events(&t).cloned());
         }
-        Ok((tasks, evts))
+        Ok(SearchResults::capped(tasks, evts))
     }
 
-    pub async fn submit_action(&mut self, tok: AuthToken, a: Action) -> Result<(), Error> {
-        let u = self.resolve_mut(tok)?;
+    /// Checks that the task `e` is on and the event it refers to, if any, are visible in `db`
+    ///
+    /// Missing ones are reported like the ones the user cannot see, like the server does.
+    fn check_visible_references(db: &DbDump, e: &Event) -> Result<(), Error> {
+        if !db.tasks.contains_key(&e.task_id) {
+            return Err(Error::PermissionDenied);
+        }
+        match e.data {
+            EventData::AddComment {
+                parent_id: Some(event),
+                ..
+            }
+            | EventData::EditComment {
+                comment_id: event, ..
+            }
+            | EventData::SetEventRead {
+                event_id: event, ..
+            } => {
+                if db.event(&event).is_none() {
+                    return Err(match e.data {
+                        EventData::AddComment { .. } => Error::ParentCommentNotFound(event),
+                        _ => Error::EventNotFound(event),
+                    });
+                }
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+
+    /// Checks that the tag `e` refers to, if any, exists for any user
+    fn check_tag_reference(&self, e: &Event) -> Result<(), Error> {
+        match e.data {
+            EventData::AddTag { tag, .. }
+            | EventData::RmTag(tag)
+            | EventData::SetSection { tag, .. } => {
+                if !self.users.values().any(|u| u.db.tags.contains_key(&tag)) {
+                    return Err(Error::TagNotFound(tag));
+                }
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+
+    /// Checks that the ids of `t` are not used by anything else, like the server does
+    ///
+    /// Returns whether `t` already got submitted, submitting it again being a no-op.
+    fn check_task(&self, t: &api::Task, top_comm: &str) -> Result<bool, Error> {
+        let dbs = || self.users.values().map(|u| &u.db);
+        let already_present = match dbs().find_map(|db| db.tasks.get(&t.id)) {
+            None => false,
+            Some(p)
+                if p.owner_id == t.owner_id
+                    && p.date == t.date
+                    && *p.initial_title == t.initial_title =>
+            {
+                true
+            }
+            Some(_) => return Err(Error::UuidAlreadyUsed(t.id.0)),
+        };
+        if let Some(e) = dbs().find_map(|db| db.event(&t.top_comment_id)) {
+            let same = e.owner_id == t.owner_id
+                && e.date == t.date
+                && e.task_id == t.id
+                && matches!(&e.data, EventData::AddComment { text, .. } if text == top_comm);
+            if !same {
+                return Err(Error::UuidAlreadyUsed(t.top_comment_id.0));
+            }
+        }
+        Ok(already_present)
+    }
+
+    pub async fn validate_action(&self, tok: AuthToken, a: Action) -> Result<(), Error> {
+        let u = self.resolve(tok)?;
         a.validate()?;
+        if let Action::NewEvent(e) = &a {
+            if e.owner_id != u.db.owner {
+                return Err(Error::PermissionDenied);
+            }
+            Self::check_visible_references(&u.db, e)?;
+        }
         if !a
             .is_authorized(&mut &u.db)
             .await
@@ -207,28 +441,36 @@ impl MockServer {
         {
             return Err(Error::PermissionDenied);
         }
+        match &a {
+            Action::NewUser(_) | Action::DeletedUser(_) => (),
+            Action::NewTask(t, top_comm) => {
+                self.check_task(t, top_comm)?;
+            }
+            Action::NewEvent(e) => self.check_tag_reference(e)?,
+        }
+        Ok(())
+    }
+
+    pub async fn submit_action(&mut self, tok: AuthToken, a: Action) -> Result<(), Error> {
+        self.validate_action(tok, a.clone()).await?;
+        let already_present = match &a {
+            Action::NewTask(t, top_comm) => self.check_task(t, top_comm)?,
+            _ => false,
+        };
+        let u = self.resolve_mut(tok)?;
         match a {
-            Action::NewUser(_) => unreachable!(),
+            Action::NewUser(_) | Action::DeletedUser(_) => unreachable!(),
             Action::NewTask(t, top_comm) => {
-                u.db.add_tasks(vec![t.clone()]);
-                u.db.add_events_and_refresh_all(vec![api::Event {
-                    id: t.top_comment_id,
-                    owner_id: t.owner_id,
-                    date: t.date,
-                    task_id: t.id,
-                    data: api::EventData::AddComment {
-                        text: top_comm.clone(),
-                        parent_id: None,
-                    },
-                }]);
+                // Submitting a task again is a no-op, that the server still relays
+                if !already_present {
+                    u.db.apply_action(Action::NewTask(t.clone(), top_comm.clone()));
+                }
                 u.relay_action(Action::NewTask(t, top_comm)).await;
             }
             Action::NewEvent(e) => {
-                for u in self.0.values_mut() {
+                for u in self.users.values_mut() {
                     // TODO: perms handling
-                    if u.db.tasks.contains_key(&e.task_id) {
-                        u.db.add_events_and_refresh_all(vec![e.clone()]);
-                    }
+                    u.db.apply_action(Action::NewEvent(e.clone()));
                     u.relay_action(Action::NewEvent(e.clone())).await;
                 }
             }
@@ -236,6 +478,42 @@ impl MockServer {
         Ok(())
     }
 
+    pub fn committed_events(
+        &self,
+        tok: AuthToken,
+        events: Vec<EventId>,
+    ) -> Result<Vec<EventId>, Error> {
+        let u = self.resolve(tok)?;
+        Ok(events
+            .into_iter()
+            .filter(|e| matches!(u.db.event(e), Some(evt) if evt.owner_id == u.db.owner))
+            .collect())
+    }
+
+    pub fn fetch_activity(&self, tok: AuthToken, page: ActivityPage) -> Result<Vec<Event>, Error> {
+        let u = self.resolve(tok)?;
+        page.validate()?;
+        let mut evts =
+            u.db.tasks
+                .values()
+                .flat_map(|t| t.events.values().flat_map(|e| e.iter()))
+                .cloned()
+                .collect::<Vec<_>>();
+        evts.sort_unstable_by_key(|e| cmp::Reverse((e.date, e.id.0)));
+        let skip = match page.before {
+            None => 0,
+            Some(before) => match evts.iter().position(|e| e.id == before) {
+                Some(pos) => pos + 1,
+                None => evts.len(),
+            },
+        };
+        Ok(evts
+            .into_iter()
+            .skip(skip)
+            .take(page.limit as usize)
+            .collect())
+    }
+
     pub async fn action_feed(
         &mut self,
         tok: AuthToken,
@@ -0,0 +1,10 @@
+CREATE TABLE ticklers (
+    tag_id UUID PRIMARY KEY NOT NULL,
+    -- delay after which tasks archived in this tag get unarchived and scheduled again
+    interval_secs BIGINT NOT NULL,
+
+    CHECK (interval_secs > 0),
+
+    FOREIGN KEY (tag_id) REFERENCES tags (id)
+        ON DELETE CASCADE
+);
@@ -0,0 +1,3 @@
+ALTER TABLE events
+    DROP COLUMN app_version,
+    DROP COLUMN session_id;
@@ -0,0 +1,6 @@
+ALTER TABLE events
+    -- session and client version that submitted the event, NULL if unknown or for server-generated events
+    ADD COLUMN session_id UUID,
+    ADD COLUMN app_version VARCHAR,
+    ADD FOREIGN KEY (session_id) REFERENCES sessions (id)
+        ON DELETE SET NULL;
@@ -0,0 +1,2 @@
+DROP INDEX avatars_by_hash;
+DROP TABLE avatars;
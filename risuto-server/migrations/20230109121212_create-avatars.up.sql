@@ -0,0 +1,12 @@
+CREATE TABLE avatars (
+    user_id UUID PRIMARY KEY NOT NULL,
+    hash VARCHAR NOT NULL, -- hex-encoded sha256 of data, used as a cache-busting identifier
+    content_type VARCHAR NOT NULL,
+    data BYTEA NOT NULL,
+
+    FOREIGN KEY (user_id) REFERENCES users (id)
+        ON DELETE CASCADE
+);
+
+CREATE INDEX avatars_by_hash
+ON avatars (hash);
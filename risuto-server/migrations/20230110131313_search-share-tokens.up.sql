@@ -0,0 +1,3 @@
+-- Token allowing unauthenticated read-only access to a search's aggregates, eg. for badges
+ALTER TABLE searches
+ADD COLUMN share_token UUID UNIQUE;
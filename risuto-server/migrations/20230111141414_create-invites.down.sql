@@ -0,0 +1,3 @@
+DROP TABLE invite_tags;
+DROP TABLE invites;
+DROP TYPE tag_role;
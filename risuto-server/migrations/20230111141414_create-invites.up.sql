@@ -0,0 +1,27 @@
+CREATE TYPE tag_role AS ENUM (
+    'viewer',
+    'commenter',
+    'editor',
+    'admin'
+);
+
+CREATE TABLE invites (
+    token UUID PRIMARY KEY NOT NULL,
+    creator_id UUID, -- NULL for invites created through the admin interface
+    role tag_role NOT NULL,
+
+    FOREIGN KEY (creator_id) REFERENCES users (id)
+        ON DELETE CASCADE
+);
+
+-- Tags shared with the new user upon signup
+CREATE TABLE invite_tags (
+    token UUID NOT NULL,
+    tag_id UUID NOT NULL,
+
+    PRIMARY KEY (token, tag_id),
+    FOREIGN KEY (token) REFERENCES invites (token)
+        ON DELETE CASCADE,
+    FOREIGN KEY (tag_id) REFERENCES tags (id)
+        ON DELETE CASCADE
+);
@@ -0,0 +1,3 @@
+-- Lock the accounts of LDAP users, as they have no password to fall back to
+UPDATE users SET password = '*' WHERE password IS NULL;
+ALTER TABLE users ALTER COLUMN password SET NOT NULL;
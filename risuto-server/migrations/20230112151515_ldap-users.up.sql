@@ -0,0 +1,2 @@
+-- NULL for users authenticated through LDAP
+ALTER TABLE users ALTER COLUMN password DROP NOT NULL;
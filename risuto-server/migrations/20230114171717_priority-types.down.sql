@@ -0,0 +1,2 @@
+-- Postgres cannot remove values from an enum, and they are harmless once nothing uses them
+UPDATE searches SET order_type = 'last_event_date_desc' WHERE order_type = 'priority';
@@ -0,0 +1,3 @@
+-- Kept separate from the migration using them, as postgres cannot use new enum values in the transaction adding them
+ALTER TYPE event_type ADD VALUE 'set_priority';
+ALTER TYPE search_order_type ADD VALUE 'priority';
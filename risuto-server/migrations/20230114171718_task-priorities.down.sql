@@ -0,0 +1,38 @@
+DROP VIEW v_tasks_priority;
+
+DELETE FROM events WHERE d_type = 'set_priority';
+ALTER TABLE events DROP CONSTRAINT event_is_valid;
+ALTER TABLE events ADD CONSTRAINT event_is_valid CHECK (
+    (d_type = 'set_title' AND
+        d_text IS NOT NULL AND -- the new title
+        d_bool IS NULL AND d_int IS NULL AND d_time IS NULL AND d_tag_id IS NULL AND d_parent_id IS NULL AND d_order_id IS NULL) OR
+    ((d_type = 'set_done' OR d_type = 'set_archived') AND
+        d_bool IS NOT NULL AND -- the new state
+        d_text IS NULL AND d_int IS NULL AND d_time IS NULL AND d_tag_id IS NULL AND d_parent_id IS NULL AND d_order_id IS NULL) OR
+    ((d_type = 'blocked_until' OR d_type = 'schedule_for') AND
+        -- time is the date at which the task state will change, can be null to unset
+        d_text IS NULL AND d_bool IS NULL AND d_int IS NULL AND d_tag_id IS NULL AND d_parent_id IS NULL AND d_order_id IS NULL) OR
+    (d_type = 'set_order' AND
+        d_order_id IS NOT NULL AND d_int IS NOT NULL AND
+        d_text IS NULL AND d_bool IS NULL AND d_time IS NULL AND d_tag_id IS NULL AND d_parent_id IS NULL) OR
+    (d_type = 'add_tag' AND
+        d_bool IS NOT NULL AND -- whether the task is in this tag's backlog
+        d_int IS NOT NULL AND -- the priority of the task within this tag (lower is higher in the list)
+        d_tag_id IS NOT NULL AND -- the tag added
+        d_text IS NULL AND d_time IS NULL AND d_parent_id IS NULL AND d_order_id IS NULL) OR
+    (d_type = 'remove_tag' AND
+        d_tag_id IS NOT NULL AND -- the tag removed
+        d_text IS NULL AND d_bool IS NULL AND d_int IS NULL AND d_time IS NULL AND d_parent_id IS NULL AND d_order_id IS NULL) OR
+    (d_type = 'add_comment' AND
+        d_text IS NOT NULL AND -- comment text
+        -- parent_id can be either null or not-null depending on whether the comment is a reply to another comment
+        d_bool IS NULL AND d_int IS NULL AND d_time IS NULL AND d_tag_id IS NULL AND d_order_id IS NULL) OR
+    (d_type = 'edit_comment' AND
+        d_text IS NOT NULL AND -- comment text
+        d_parent_id IS NOT NULL AND -- edited comment
+        d_bool IS NULL AND d_int IS NULL AND d_time IS NULL AND d_tag_id IS NULL AND d_order_id IS NULL) OR
+    (d_type = 'set_event_read' AND
+        d_parent_id IS NOT NULL AND -- the comment or comment edit marked as (un)read
+        d_bool IS NOT NULL AND -- true iff the comment (edit) is now read
+        d_text IS NULL AND d_int IS NULL AND d_time IS NULL AND d_tag_id IS NULL AND d_order_id IS NULL)
+);
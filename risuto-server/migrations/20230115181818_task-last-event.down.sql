@@ -0,0 +1 @@
+DROP VIEW v_tasks_last_event;
@@ -0,0 +1,6 @@
+CREATE VIEW v_tasks_last_event AS
+SELECT
+    task_id,
+    MAX(date) AS date -- the top comment is an event too, so this is never before the task creation
+FROM events
+GROUP BY task_id;
@@ -0,0 +1 @@
+-- Postgres cannot remove values from an enum, and they are harmless once nothing uses them
@@ -0,0 +1,2 @@
+-- Kept separate from the migration using them, as postgres cannot use new enum values in the transaction adding them
+ALTER TYPE event_type ADD VALUE 'set_section';
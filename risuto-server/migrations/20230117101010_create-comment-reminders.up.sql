@@ -0,0 +1,8 @@
+CREATE TABLE comment_reminders (
+    comment_id UUID PRIMARY KEY NOT NULL,
+    -- time after which the comment's author gets reminded of it, unless someone else replied
+    remind_at TIMESTAMP NOT NULL,
+
+    FOREIGN KEY (comment_id) REFERENCES events (id)
+        ON DELETE CASCADE
+);
@@ -0,0 +1,13 @@
+CREATE TABLE duplicate_candidates (
+    task_id UUID NOT NULL,
+    other_id UUID NOT NULL,
+    -- between 0 and 1, see `duplicates::similarity`
+    similarity REAL NOT NULL,
+
+    PRIMARY KEY (task_id, other_id),
+    CHECK (task_id < other_id),
+    FOREIGN KEY (task_id) REFERENCES tasks (id)
+        ON DELETE CASCADE,
+    FOREIGN KEY (other_id) REFERENCES tasks (id)
+        ON DELETE CASCADE
+);
@@ -0,0 +1,12 @@
+CREATE OR REPLACE VIEW v_tasks_last_event AS
+SELECT
+    task_id,
+    MAX(date) AS date -- the top comment is an event too, so this is never before the task creation
+FROM events
+GROUP BY task_id;
+
+INSERT INTO events (id, owner_id, date, task_id, d_type, d_bool, d_parent_id)
+SELECT id, owner_id, date, task_id, 'set_event_read', now_read, event_id
+FROM event_reads;
+
+DROP TABLE event_reads;
@@ -0,0 +1,28 @@
+-- `set_event_read` events folded by the retention job, only the latest one of each user for
+-- each comment (edit) being kept. They are served to clients as if they still were events.
+CREATE TABLE event_reads (
+    id UUID PRIMARY KEY NOT NULL, -- id of the event this row was folded from
+    owner_id UUID NOT NULL,
+    date TIMESTAMP NOT NULL,
+    task_id UUID NOT NULL,
+    event_id UUID NOT NULL, -- the comment or comment edit marked as (un)read
+    now_read BOOLEAN NOT NULL,
+
+    UNIQUE (owner_id, event_id),
+    FOREIGN KEY (owner_id) REFERENCES users (id),
+    FOREIGN KEY (task_id) REFERENCES tasks (id)
+        ON DELETE CASCADE,
+    FOREIGN KEY (event_id, task_id) REFERENCES events (id, task_id)
+        ON DELETE CASCADE
+);
+
+CREATE OR REPLACE VIEW v_tasks_last_event AS
+SELECT
+    task_id,
+    MAX(date) AS date -- the top comment is an event too, so this is never before the task creation
+FROM (
+    SELECT task_id, date FROM events
+    UNION ALL
+    SELECT task_id, date FROM event_reads
+) AS the_table_because_postgres_needs_this_name
+GROUP BY task_id;
@@ -0,0 +1,5 @@
+-- Versions of risuto-server that ran on this database, for users to be told what changed
+CREATE TABLE server_versions (
+    version TEXT PRIMARY KEY NOT NULL,
+    installed_at TIMESTAMP NOT NULL -- first start of this version
+);
@@ -0,0 +1,16 @@
+-- Automations of users, applied by the server to the actions that trigger them
+CREATE TABLE rules (
+    id UUID PRIMARY KEY NOT NULL,
+    owner_id UUID NOT NULL,
+    name TEXT NOT NULL,
+    enabled BOOLEAN NOT NULL,
+    trigger JSON NOT NULL,
+    filter JSON NOT NULL,
+    action JSON NOT NULL,
+
+    FOREIGN KEY (owner_id) REFERENCES users (id)
+        ON DELETE CASCADE
+);
+
+CREATE INDEX rules_by_owner
+ON rules (owner_id);
@@ -0,0 +1,2 @@
+ALTER TABLE searches
+DROP COLUMN feed_token;
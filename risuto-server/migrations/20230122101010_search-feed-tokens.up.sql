@@ -0,0 +1,3 @@
+-- Token allowing unauthenticated read-only access to a search's results as an Atom feed
+ALTER TABLE searches
+ADD COLUMN feed_token UUID UNIQUE;
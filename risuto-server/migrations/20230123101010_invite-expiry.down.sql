@@ -0,0 +1,2 @@
+ALTER TABLE invites
+DROP COLUMN expires_at;
@@ -0,0 +1,6 @@
+-- Invites stop being usable after this date, existing ones get the default validity from now on
+ALTER TABLE invites
+ADD COLUMN expires_at TIMESTAMP NOT NULL DEFAULT (now() AT TIME ZONE 'utc') + interval '7 days';
+
+ALTER TABLE invites
+ALTER COLUMN expires_at DROP DEFAULT;
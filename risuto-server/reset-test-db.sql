@@ -1 +1 @@
-TRUNCATE users, sessions, perms, tags, events, tasks, searches
+TRUNCATE users, sessions, perms, tags, events, tasks, searches, ticklers, avatars, invites, comment_reminders
@@ -0,0 +1,200 @@
+use std::collections::HashMap;
+
+use risuto_api::{Event, EventData, Search, Task, TaskId, Time};
+
+/// Maximum number of entries in a feed, the most recently updated tasks being kept
+const MAX_ENTRIES: usize = 100;
+
+struct Entry<'a> {
+    task: &'a Task,
+    title: &'a str,
+    top_comment: &'a str,
+    updated: Time,
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Renders the result of a saved search as an Atom feed, with the tasks last updated first
+///
+/// `now` is used as the feed's update time when it has no entries.
+pub fn render(search: &Search, tasks: &[Task], events: &[Event], now: Time) -> String {
+    let mut entries = tasks
+        .iter()
+        .map(|t| {
+            (
+                t.id,
+                Entry {
+                    task: t,
+                    title: t.initial_title.as_str(),
+                    top_comment: "",
+                    updated: t.date,
+                },
+            )
+        })
+        .collect::<HashMap<TaskId, Entry>>();
+    let mut events = events.iter().collect::<Vec<_>>();
+    events.sort_unstable_by_key(|e| e.date);
+    for e in events {
+        let entry = match entries.get_mut(&e.task_id) {
+            Some(entry) => entry,
+            None => continue,
+        };
+        entry.updated = std::cmp::max(entry.updated, e.date);
+        match &e.data {
+            EventData::SetTitle(title) => entry.title = title.as_str(),
+            EventData::AddComment { text, .. } if e.id == entry.task.top_comment_id => {
+                entry.top_comment = text.as_str()
+            }
+            EventData::EditComment { text, comment_id }
+                if *comment_id == entry.task.top_comment_id =>
+            {
+                entry.top_comment = text.as_str()
+            }
+            _ => (),
+        }
+    }
+    let mut entries = entries.into_values().collect::<Vec<_>>();
+    entries.sort_unstable_by_key(|e| std::cmp::Reverse(e.updated));
+    entries.truncate(MAX_ENTRIES);
+
+    let updated = entries.first().map(|e| e.updated).unwrap_or(now);
+    let mut res = format!(
+        r#"<?xml version="1.0" encoding="utf-8"?>
+<feed xmlns="http://www.w3.org/2005/Atom">
+<id>urn:uuid:{}</id>
+<title>{}</title>
+<updated>{}</updated>
+"#,
+        search.id.0,
+        escape(&search.name),
+        updated.to_rfc3339(),
+    );
+    for e in entries {
+        res += &format!(
+            r#"<entry>
+<id>urn:uuid:{}</id>
+<title>{}</title>
+<published>{}</published>
+<updated>{}</updated>
+<content type="text">{}</content>
+</entry>
+"#,
+            e.task.id.0,
+            escape(e.title),
+            e.task.date.to_rfc3339(),
+            e.updated.to_rfc3339(),
+            escape(e.top_comment),
+        );
+    }
+    res += "</feed>\n";
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, TimeZone, Utc};
+    use risuto_api::{EventId, Order, OrderType, Query, SearchId, UserId, Uuid};
+
+    fn search() -> Search {
+        Search {
+            id: SearchId(Uuid::new_v4()),
+            name: String::from("Work & <stuff>"),
+            filter: Query::All(vec![]),
+            order: Order::CreationDate(OrderType::Asc),
+            priority: 0,
+        }
+    }
+
+    fn task(title: &str, date: Time) -> Task {
+        Task {
+            id: TaskId(Uuid::new_v4()),
+            owner_id: UserId::stub(),
+            date,
+            initial_title: String::from(title),
+            top_comment_id: EventId(Uuid::new_v4()),
+        }
+    }
+
+    fn event(task: &Task, date: Time, data: EventData) -> Event {
+        Event {
+            id: EventId(Uuid::new_v4()),
+            owner_id: task.owner_id,
+            date,
+            task_id: task.id,
+            data,
+        }
+    }
+
+    #[test]
+    fn empty_feed_is_updated_at_the_current_time() {
+        let now = Utc.with_ymd_and_hms(2023, 1, 9, 12, 0, 0).unwrap();
+        let feed = render(&search(), &[], &[], now);
+        assert!(feed.contains(&format!("<updated>{}</updated>", now.to_rfc3339())));
+    }
+
+    #[test]
+    fn escapes_user_text() {
+        let now = Utc.with_ymd_and_hms(2023, 1, 9, 12, 0, 0).unwrap();
+        let t = task("<script>\"hi\"</script>", now);
+        let feed = render(&search(), &[t], &[], now);
+        assert!(feed.contains("<title>Work &amp; &lt;stuff&gt;</title>"));
+        assert!(feed.contains("<title>&lt;script&gt;&quot;hi&quot;&lt;/script&gt;</title>"));
+        assert!(!feed.contains("<script>"));
+    }
+
+    #[test]
+    fn entries_show_the_latest_title_and_top_comment() {
+        let now = Utc.with_ymd_and_hms(2023, 1, 9, 12, 0, 0).unwrap();
+        let t = task("initial", now);
+        let later = now + Duration::hours(1);
+        let events = [
+            event(&t, later, EventData::SetTitle(String::from("renamed"))),
+            Event {
+                id: t.top_comment_id,
+                ..event(
+                    &t,
+                    now,
+                    EventData::AddComment {
+                        text: String::from("first draft"),
+                        parent_id: None,
+                    },
+                )
+            },
+            event(
+                &t,
+                later,
+                EventData::EditComment {
+                    text: String::from("final text"),
+                    comment_id: t.top_comment_id,
+                },
+            ),
+        ];
+        let feed = render(&search(), &[t], &events, now);
+        assert!(feed.contains("<title>renamed</title>"));
+        assert!(!feed.contains("initial"));
+        assert!(feed.contains(r#"<content type="text">final text</content>"#));
+        assert!(feed.contains(&format!("<updated>{}</updated>", later.to_rfc3339())));
+    }
+
+    #[test]
+    fn most_recently_updated_tasks_come_first_and_are_capped() {
+        let start = Utc.with_ymd_and_hms(2023, 1, 9, 12, 0, 0).unwrap();
+        let tasks = (0..MAX_ENTRIES + 10)
+            .map(|i| task(&format!("task {i}"), start + Duration::minutes(i as i64)))
+            .collect::<Vec<_>>();
+        let feed = render(&search(), &tasks, &[], start);
+        assert_eq!(feed.matches("<entry>").count(), MAX_ENTRIES);
+        let newest = feed.find("task 109").expect("newest task is missing");
+        let older = feed
+            .find("task 108")
+            .expect("second newest task is missing");
+        assert!(newest < older);
+        assert!(!feed.contains("<title>task 9</title>"));
+    }
+}
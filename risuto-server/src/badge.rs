@@ -0,0 +1,41 @@
+use crate::db::SearchCounts;
+
+/// Approximate width of a character in the 11px sans-serif font used by the badge
+const CHAR_WIDTH: usize = 7;
+
+/// Padding on each side of each half of the badge
+const PADDING: usize = 6;
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Renders a shields.io-like badge, with the search name on the left and its counts on the right
+pub fn render(label: &str, counts: &SearchCounts) -> String {
+    let value = format!("{} open / {} overdue", counts.open, counts.overdue);
+    let color = match counts.overdue {
+        0 => "#4c1",
+        _ => "#e05d44",
+    };
+    let label_width = label.chars().count() * CHAR_WIDTH + 2 * PADDING;
+    let value_width = value.chars().count() * CHAR_WIDTH + 2 * PADDING;
+    let width = label_width + value_width;
+    let label_x = label_width / 2;
+    let value_x = label_width + value_width / 2;
+    let label = escape(label);
+    format!(
+        r##"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="20" role="img" aria-label="{label}: {value}">
+<title>{label}: {value}</title>
+<rect width="{label_width}" height="20" fill="#555"/>
+<rect x="{label_width}" width="{value_width}" height="20" fill="{color}"/>
+<g fill="#fff" text-anchor="middle" font-family="Verdana,Geneva,DejaVu Sans,sans-serif" font-size="11">
+<text x="{label_x}" y="14">{label}</text>
+<text x="{value_x}" y="14">{value}</text>
+</g>
+</svg>
+"##
+    )
+}
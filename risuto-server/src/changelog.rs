@@ -0,0 +1,38 @@
+use risuto_api::{ChangelogEntry, Time};
+
+/// Version of this server, recorded in the database on startup
+pub const CURRENT_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// What changed for users in each version of the server, most recent first
+///
+/// Each user gets shown the notes of the versions the instance got upgraded to since they last
+/// acknowledged them, so only list changes users can notice.
+const RELEASE_NOTES: &[(&str, &[&str])] = &[(
+    "0.1.0",
+    &[
+        "Task lists can be grouped by tag, scheduled day or owner, from the button next to the \
+         search bar.",
+        "Custom searches can be exported from the sidebar, and imported by other users.",
+        "The scheduled and blocked dates of a task can be pushed back from a menu on their pill.",
+        "Tags can be shared by searching for users by name.",
+    ],
+)];
+
+/// Changelog of the versions that got installed, given as `(version, installed_at)` pairs
+///
+/// Versions without release notes are left out. Entries come most recently installed first.
+pub fn entries(installed: Vec<(String, Time)>) -> Vec<ChangelogEntry> {
+    let mut res = installed
+        .into_iter()
+        .filter_map(|(version, installed_at)| {
+            let (_, notes) = RELEASE_NOTES.iter().find(|(v, _)| *v == version)?;
+            Some(ChangelogEntry {
+                version,
+                installed_at,
+                notes: notes.iter().map(|n| String::from(*n)).collect(),
+            })
+        })
+        .collect::<Vec<_>>();
+    res.sort_unstable_by(|a, b| b.installed_at.cmp(&a.installed_at));
+    res
+}
@@ -0,0 +1,99 @@
+use std::path::Path;
+
+use anyhow::Context;
+use risuto_api::InstanceInfo;
+
+use crate::{
+    duplicates::DuplicatesConfig, extractors::ReadReplicaConfig, ldap::LdapConfig,
+    mqtt::MqttConfig, retention::RetentionConfig, scim::ScimConfig,
+    search_cache::SearchCacheConfig, telemetry::TelemetryConfig,
+};
+
+/// Contents of the configuration file passed with `--config`, all sections being optional
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Publicly served at `/api/instance-info`
+    pub instance: InstanceInfo,
+
+    /// Serves the read-only endpoints from a replica of the database when set
+    pub read_replica: Option<ReadReplicaConfig>,
+
+    /// Allows users to log in with their LDAP password when set
+    pub ldap: Option<LdapConfig>,
+
+    /// Enables user provisioning through SCIM at `/scim/v2` when set
+    pub scim: Option<ScimConfig>,
+
+    /// Runs the duplicate task detection job when set
+    pub duplicates: Option<DuplicatesConfig>,
+
+    /// Runs the event compaction job when set
+    pub retention: Option<RetentionConfig>,
+
+    /// Publishes what happens to tasks to an MQTT broker when set
+    pub mqtt: Option<MqttConfig>,
+
+    /// Caches the counts of the shared searches that are slow to compute when set
+    pub search_cache: Option<SearchCacheConfig>,
+
+    /// Sends anonymous usage counters to a third-party endpoint when set
+    pub telemetry: Option<TelemetryConfig>,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> anyhow::Result<Config> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("reading config file {path:?}"))?;
+        let config: Config =
+            toml::from_str(&contents).with_context(|| format!("parsing config file {path:?}"))?;
+        config
+            .validate()
+            .with_context(|| format!("validating config file {path:?}"))?;
+        Ok(config)
+    }
+
+    /// Rejects the values the server cannot run with, rather than failing once running
+    fn validate(&self) -> anyhow::Result<()> {
+        let intervals = [
+            (
+                "duplicates",
+                self.duplicates.as_ref().map(|c| c.interval_secs),
+            ),
+            (
+                "retention",
+                self.retention.as_ref().map(|c| c.interval_secs),
+            ),
+            (
+                "search_cache",
+                self.search_cache.as_ref().map(|c| c.interval_secs),
+            ),
+            (
+                "telemetry",
+                self.telemetry.as_ref().map(|c| c.interval_secs),
+            ),
+        ];
+        for (section, interval_secs) in intervals {
+            anyhow::ensure!(
+                interval_secs != Some(0),
+                "interval_secs of [{section}] must be positive"
+            );
+        }
+        let mqtt_events = self.mqtt.as_ref().and_then(|c| c.events.as_ref());
+        for event in mqtt_events.into_iter().flatten() {
+            anyhow::ensure!(
+                crate::mqtt::EVENT_NAMES.contains(&event.as_str()),
+                "unknown event {event:?} in [mqtt], known ones are {:?}",
+                crate::mqtt::EVENT_NAMES
+            );
+        }
+        if let Some(duplicates) = &self.duplicates {
+            anyhow::ensure!(
+                duplicates.threshold > 0. && duplicates.threshold <= 1.,
+                "threshold of [duplicates] must be more than 0 and at most 1, got {}",
+                duplicates.threshold
+            );
+        }
+        Ok(())
+    }
+}
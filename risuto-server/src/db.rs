@@ -3,11 +3,18 @@ use axum::async_trait;
 use chrono::Utc;
 use futures::{Future, Stream, StreamExt, TryStreamExt};
 use risuto_api::{
-    AuthInfo, AuthToken, Event, EventData, EventId, NewSession, NewUser, Order, OrderId, OrderType,
-    Query, Search, SearchId, Tag, TagId, Task, TaskId, Time, User, UserId, Uuid,
+    ActivityPage, AuthInfo, AuthToken, CommentReminder, Db, DuplicateCandidate, Event, EventData,
+    EventId, EventProvenance, MigrationStatus, NewInvite, NewUser, Order, OrderId, OrderType,
+    Priority, Query, Registration, Role, Rule, RuleAction, RuleId, RuleTrigger, Search,
+    SearchExport, SearchId, SearchResults, Tag, TagId, TagPermission, TagSections, Task, TaskId,
+    TextReplacement, Tickler, Time, User, UserId, Uuid, MAX_RULES_PER_USER, MAX_SEARCHES_PER_USER,
+    MAX_SEARCH_TASKS,
+};
+use sqlx::{migrate::Migrate, Connection};
+use std::{
+    collections::{HashMap, HashSet},
+    pin::Pin,
 };
-use sqlx::Connection;
-use std::pin::Pin;
 
 use crate::{query, Error};
 
@@ -16,6 +23,13 @@ pub struct PostgresDb<'a> {
     pub user: UserId,
 }
 
+/// Where an action was submitted from, recorded alongside the events it creates
+#[derive(Clone, Debug, Default)]
+pub struct Provenance {
+    pub session: Option<AuthToken>,
+    pub app_version: Option<String>,
+}
+
 #[derive(Debug, Eq, PartialEq, sqlx::Type)]
 #[sqlx(type_name = "event_type", rename_all = "snake_case")]
 enum DbType {
@@ -24,16 +38,18 @@ enum DbType {
     SetArchived,
     BlockedUntil,
     ScheduleFor,
+    SetPriority,
     SetOrder,
     AddTag,
     RemoveTag,
+    SetSection,
     AddComment,
     EditComment,
     SetEventRead,
 }
 
 #[derive(Debug, Eq, PartialEq, sqlx::Type)]
-#[sqlx(type_name = "order_type", rename_all = "snake_case")]
+#[sqlx(type_name = "search_order_type", rename_all = "snake_case")]
 enum DbOrderType {
     Custom,
     Tag,
@@ -45,9 +61,27 @@ enum DbOrderType {
     ScheduledForDesc,
     BlockedUntilAsc,
     BlockedUntilDesc,
+    Priority,
 }
 
 impl DbOrderType {
+    /// Also returns the tag the order is that of, if any
+    fn from_api(o: &Order) -> (DbOrderType, Option<Uuid>) {
+        match o {
+            Order::Custom(_) => (DbOrderType::Custom, None),
+            Order::Tag(tag) => (DbOrderType::Tag, Some(tag.0)),
+            Order::CreationDate(OrderType::Asc) => (DbOrderType::CreationDateAsc, None),
+            Order::CreationDate(OrderType::Desc) => (DbOrderType::CreationDateDesc, None),
+            Order::LastEventDate(OrderType::Asc) => (DbOrderType::LastEventDateAsc, None),
+            Order::LastEventDate(OrderType::Desc) => (DbOrderType::LastEventDateDesc, None),
+            Order::ScheduledFor(OrderType::Asc) => (DbOrderType::ScheduledForAsc, None),
+            Order::ScheduledFor(OrderType::Desc) => (DbOrderType::ScheduledForDesc, None),
+            Order::BlockedUntil(OrderType::Asc) => (DbOrderType::BlockedUntilAsc, None),
+            Order::BlockedUntil(OrderType::Desc) => (DbOrderType::BlockedUntilDesc, None),
+            Order::Priority => (DbOrderType::Priority, None),
+        }
+    }
+
     fn into_api(self, id: Uuid, tag_id: Option<Uuid>) -> Order {
         match self {
             DbOrderType::Custom => Order::Custom(OrderId(id)),
@@ -60,6 +94,36 @@ impl DbOrderType {
             DbOrderType::ScheduledForDesc => Order::ScheduledFor(OrderType::Desc),
             DbOrderType::BlockedUntilAsc => Order::BlockedUntil(OrderType::Asc),
             DbOrderType::BlockedUntilDesc => Order::BlockedUntil(OrderType::Desc),
+            DbOrderType::Priority => Order::Priority,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, sqlx::Type)]
+#[sqlx(type_name = "tag_role", rename_all = "snake_case")]
+enum DbRole {
+    Viewer,
+    Commenter,
+    Editor,
+    Admin,
+}
+
+impl DbRole {
+    fn from_api(r: Role) -> DbRole {
+        match r {
+            Role::Viewer => DbRole::Viewer,
+            Role::Commenter => DbRole::Commenter,
+            Role::Editor => DbRole::Editor,
+            Role::Admin => DbRole::Admin,
+        }
+    }
+
+    fn into_api(self) -> Role {
+        match self {
+            DbRole::Viewer => Role::Viewer,
+            DbRole::Commenter => Role::Commenter,
+            DbRole::Editor => Role::Editor,
+            DbRole::Admin => Role::Admin,
         }
     }
 }
@@ -124,6 +188,10 @@ impl DbEvent {
         self.d_text = Some(t);
         self
     }
+    fn d_text_opt(mut self, t: Option<String>) -> DbEvent {
+        self.d_text = t;
+        self
+    }
     fn d_bool(mut self, b: bool) -> DbEvent {
         self.d_bool = Some(b);
         self
@@ -140,6 +208,10 @@ impl DbEvent {
         self.d_int = Some(i);
         self
     }
+    fn d_priority(mut self, p: Option<Priority>) -> DbEvent {
+        self.d_int = p.map(|p| p.level());
+        self
+    }
     fn d_parent_id(mut self, p: Option<EventId>) -> DbEvent {
         self.d_parent_id = p.map(|p| p.0);
         self
@@ -173,6 +245,7 @@ impl From<Event> for DbEvent {
             SetArchived(b) => res.d_type(DbType::SetArchived).d_bool(b),
             BlockedUntil(t) => res.d_type(DbType::BlockedUntil).d_time(t),
             ScheduleFor(t) => res.d_type(DbType::ScheduleFor).d_time(t),
+            SetPriority(p) => res.d_type(DbType::SetPriority).d_priority(p),
             SetOrder { order, prio } => res.d_order_id(order).d_int(prio),
             AddTag { tag, prio, backlog } => res
                 .d_type(DbType::AddTag)
@@ -180,6 +253,10 @@ impl From<Event> for DbEvent {
                 .d_int(prio)
                 .d_bool(backlog),
             RmTag(t) => res.d_type(DbType::RemoveTag).d_tag_id(t),
+            SetSection { tag, section } => res
+                .d_type(DbType::SetSection)
+                .d_tag_id(tag)
+                .d_text_opt(section),
             AddComment { text, parent_id } => res
                 .d_type(DbType::AddComment)
                 .d_text(text)
@@ -219,6 +296,9 @@ impl From<DbEvent> for Event {
                 DbType::ScheduleFor => EventData::ScheduleFor(
                     e.d_time.map(|t| t.and_local_timezone(chrono::Utc).unwrap()),
                 ),
+                DbType::SetPriority => EventData::SetPriority(e.d_int.map(|p| {
+                    Priority::from_level(p).expect("set_priority event with invalid priority")
+                })),
                 DbType::SetOrder => EventData::SetOrder {
                     order: OrderId(e.d_order_id.expect("set_order event without order_id")),
                     prio: e.d_int.expect("set_order event without prio"),
@@ -231,6 +311,10 @@ impl From<DbEvent> for Event {
                 DbType::RemoveTag => {
                     EventData::RmTag(TagId(e.d_tag_id.expect("remove_tag event without tag_id")))
                 }
+                DbType::SetSection => EventData::SetSection {
+                    tag: TagId(e.d_tag_id.expect("set_section event without tag_id")),
+                    section: e.d_text,
+                },
                 DbType::AddComment => EventData::AddComment {
                     text: e.d_text.expect("add_comment event without text"),
                     parent_id: e.d_parent_id.map(EventId),
@@ -373,38 +457,62 @@ impl<'a> risuto_api::Db for PostgresDb<'a> {
     }
 }
 
-pub async fn login_user(
+/// How a user proves their identity upon login
+pub enum Credentials {
+    /// Local user, with their password hash
+    Password(UserId, String),
+
+    /// User authenticated through LDAP
+    Ldap(UserId),
+
+    /// User who can no longer log in
+    Deactivated,
+}
+
+pub async fn fetch_credentials(
     db: &mut sqlx::PgConnection,
-    s: &NewSession,
-) -> anyhow::Result<Option<AuthToken>> {
-    let user = sqlx::query!("SELECT id, password FROM users WHERE name = $1", s.user)
-        .fetch_optional(&mut *db)
-        .await
-        .with_context(|| format!("authenticating user {:?}", s.user))?;
-    let user = match user {
-        Some(user) => user,
-        None => return Ok(None),
-    };
+    name: &str,
+) -> anyhow::Result<Option<Credentials>> {
+    let user = sqlx::query!(
+        "SELECT id, password, active FROM users WHERE name = $1",
+        name
+    )
+    .fetch_optional(&mut *db)
+    .await
+    .with_context(|| format!("fetching credentials of user {name:?}"))?;
+    Ok(user.map(|u| match (u.active, u.password) {
+        (false, _) => Credentials::Deactivated,
+        (true, Some(hash)) => Credentials::Password(UserId(u.id), hash),
+        (true, None) => Credentials::Ldap(UserId(u.id)),
+    }))
+}
+
+pub fn verify_password(password: &str, hash: &str) -> anyhow::Result<bool> {
+    // in test setup, passwords are stored in plaintext
     #[cfg(test)]
-    if s.password != user.password {
-        return Ok(None);
-    }
+    let valid = password == hash;
     #[cfg(not(test))]
-    if !bcrypt::verify(&s.password, &user.password).context("verifying password hash")? {
-        return Ok(None);
-    }
+    let valid = bcrypt::verify(password, hash).context("verifying password hash")?;
+    Ok(valid)
+}
+
+pub async fn create_session(
+    db: &mut sqlx::PgConnection,
+    user: UserId,
+    device: &str,
+    now: Time,
+) -> anyhow::Result<Option<AuthToken>> {
     let session_id = Uuid::new_v4();
-    let now = Utc::now();
     let rows_inserted = sqlx::query!(
         "INSERT INTO sessions VALUES ($1, $2, $3, $4, $4)",
         session_id,
-        user.id,
-        s.device,
+        user.0,
+        device,
         now.naive_utc(),
     )
     .execute(db)
     .await
-    .with_context(|| format!("creating session for user {:?}", s.user))?
+    .with_context(|| format!("creating session for user {user:?}"))?
     .rows_affected();
     assert!(
         rows_inserted <= 1,
@@ -438,6 +546,7 @@ pub async fn logout_user(db: &mut sqlx::PgConnection, user: &AuthToken) -> anyho
 pub async fn recover_session(
     db: &mut sqlx::PgConnection,
     token: AuthToken,
+    now: Time,
 ) -> Result<UserId, Error> {
     let res = sqlx::query!(
         "
@@ -446,7 +555,7 @@ pub async fn recover_session(
             WHERE id=$2
             RETURNING user_id
         ",
-        Utc::now().naive_utc(),
+        now.naive_utc(),
         token.0,
     )
     .fetch_all(db)
@@ -480,6 +589,64 @@ pub fn users_interested_by<'conn>(
     .map(|r| r.map(|u| UserId(u.user_id)).map_err(anyhow::Error::from))
 }
 
+/// Returns the current title of each of `tasks`
+pub async fn fetch_task_titles(
+    conn: &mut sqlx::PgConnection,
+    tasks: &[Uuid],
+) -> anyhow::Result<HashMap<TaskId, String>> {
+    Ok(sqlx::query!(
+        r#"
+            SELECT task_id AS "task_id!", title AS "title!"
+            FROM v_tasks_title
+            WHERE task_id = ANY($1)
+        "#,
+        tasks
+    )
+    .fetch(conn)
+    .map_ok(|t| (TaskId(t.task_id), t.title))
+    .try_collect()
+    .await
+    .context("querying task titles")?)
+}
+
+/// Returns the name of each of `users` that is still active
+pub async fn fetch_user_names(
+    conn: &mut sqlx::PgConnection,
+    users: &[Uuid],
+) -> anyhow::Result<HashMap<UserId, String>> {
+    Ok(sqlx::query!(
+        "SELECT id, name FROM users WHERE id = ANY($1) AND active",
+        users
+    )
+    .fetch(conn)
+    .map_ok(|u| (UserId(u.id), u.name))
+    .try_collect()
+    .await
+    .context("querying user names")?)
+}
+
+/// Returns the subset of `events` that were already committed by `owner`
+pub async fn committed_events(
+    conn: &mut sqlx::PgConnection,
+    owner: UserId,
+    events: &[EventId],
+) -> anyhow::Result<Vec<EventId>> {
+    let ids = events.iter().map(|e| e.0).collect::<Vec<Uuid>>();
+    Ok(sqlx::query!(
+        r#"
+            SELECT id AS "id!" FROM events WHERE id = ANY($1) AND owner_id = $2
+            UNION ALL
+            SELECT id FROM event_reads WHERE id = ANY($1) AND owner_id = $2
+        "#,
+        &ids,
+        owner.0,
+    )
+    .map(|r| EventId(r.id))
+    .fetch_all(conn)
+    .await
+    .with_context(|| format!("checking which of {} events are committed", events.len()))?)
+}
+
 async fn with_tmp_tasks_table<R, F>(conn: &mut sqlx::PgConnection, f: F) -> Result<R, Error>
 where
     F: for<'a> FnOnce(
@@ -505,15 +672,150 @@ where
 }
 
 pub async fn fetch_users(conn: &mut sqlx::PgConnection) -> anyhow::Result<Vec<User>> {
-    Ok(sqlx::query!("SELECT id, name FROM users")
-        .fetch(conn)
-        .map_ok(|u| User {
+    Ok(sqlx::query!(
+        r#"
+            SELECT u.id, u.name, a.hash AS "avatar_hash?"
+                FROM users u
+            LEFT JOIN avatars a
+                ON a.user_id = u.id
+            WHERE u.active
+        "#
+    )
+    .fetch(conn)
+    .map_ok(|u| User {
+        id: UserId(u.id),
+        name: u.name,
+        avatar_hash: u.avatar_hash,
+    })
+    .try_collect()
+    .await
+    .context("querying users table")?)
+}
+
+/// Returns the users matching `id` and `name` when set, along with whether they are active
+///
+/// Contrary to `fetch_users`, this also returns deactivated users.
+pub async fn fetch_users_with_status(
+    conn: &mut sqlx::PgConnection,
+    id: Option<UserId>,
+    name: Option<&str>,
+) -> anyhow::Result<Vec<(User, bool)>> {
+    Ok(sqlx::query!(
+        r#"
+            SELECT u.id, u.name, u.active, a.hash AS "avatar_hash?"
+                FROM users u
+            LEFT JOIN avatars a
+                ON a.user_id = u.id
+            WHERE ($1::UUID IS NULL OR u.id = $1)
+            AND ($2::VARCHAR IS NULL OR u.name = $2)
+            ORDER BY u.name
+        "#,
+        id.map(|u| u.0),
+        name,
+    )
+    .fetch(conn)
+    .map_ok(|u| {
+        let user = User {
             id: UserId(u.id),
             name: u.name,
-        })
-        .try_collect()
+            avatar_hash: u.avatar_hash,
+        };
+        (user, u.active)
+    })
+    .try_collect()
+    .await
+    .with_context(|| format!("querying users table for user {id:?} named {name:?}"))?)
+}
+
+/// Returns false iff the user does not exist
+pub async fn rename_user(
+    conn: &mut sqlx::PgConnection,
+    user: UserId,
+    name: String,
+) -> Result<bool, Error> {
+    let res = sqlx::query!("UPDATE users SET name = $2 WHERE id = $1", user.0, name)
+        .execute(&mut *conn)
+        .await
+        .risuto_db_err()
+        .with_context(|| format!("renaming user {user:?} to {name:?}"))?;
+    match res {
+        Ok(res) => Ok(res.rows_affected() == 1),
+        Err(err) => match err.constraint() {
+            Some("users_name_key") => Err(Error::name_already_used(name)),
+            constraint => Err(Error::Anyhow(anyhow!(
+                "unknown user renaming conflict on constraint {constraint:?} while renaming {user:?} to {name:?}"
+            ))),
+        },
+    }
+}
+
+/// Deactivating a user also logs them out of all their sessions
+///
+/// Returns false iff the user does not exist
+pub async fn set_user_active(
+    conn: &mut sqlx::PgConnection,
+    user: UserId,
+    active: bool,
+) -> anyhow::Result<bool> {
+    let mut transaction = conn
+        .begin()
+        .await
+        .context("creating user activation transaction")?;
+    let res = sqlx::query!("UPDATE users SET active = $2 WHERE id = $1", user.0, active)
+        .execute(&mut transaction)
         .await
-        .context("querying users table")?)
+        .with_context(|| format!("setting activation of user {user:?} to {active}"))?;
+    if !active {
+        sqlx::query!("DELETE FROM sessions WHERE user_id = $1", user.0)
+            .execute(&mut transaction)
+            .await
+            .with_context(|| format!("logging deactivated user {user:?} out"))?;
+    }
+    transaction
+        .commit()
+        .await
+        .context("committing user activation transaction")?;
+    Ok(res.rows_affected() == 1)
+}
+
+/// Sets the avatar of `user`, returning its new hash
+pub async fn set_avatar(
+    conn: &mut sqlx::PgConnection,
+    user: UserId,
+    content_type: &str,
+    data: &[u8],
+) -> anyhow::Result<String> {
+    Ok(sqlx::query!(
+        "
+            INSERT INTO avatars
+            VALUES ($1, encode(sha256($3), 'hex'), $2, $3)
+            ON CONFLICT (user_id) DO UPDATE
+            SET hash = EXCLUDED.hash, content_type = EXCLUDED.content_type, data = EXCLUDED.data
+            RETURNING hash
+        ",
+        user.0,
+        content_type,
+        data,
+    )
+    .fetch_one(conn)
+    .await
+    .with_context(|| format!("setting avatar for user {user:?}"))?
+    .hash)
+}
+
+/// Returns the content type and data of the avatar with hash `hash`
+pub async fn fetch_avatar(
+    conn: &mut sqlx::PgConnection,
+    hash: &str,
+) -> anyhow::Result<Option<(String, Vec<u8>)>> {
+    Ok(sqlx::query!(
+        "SELECT content_type, data FROM avatars WHERE hash = $1 LIMIT 1",
+        hash
+    )
+    .fetch_optional(conn)
+    .await
+    .with_context(|| format!("fetching avatar {hash:?}"))?
+    .map(|a| (a.content_type, a.data)))
 }
 
 pub async fn fetch_tags_for_user(
@@ -527,6 +829,7 @@ pub async fn fetch_tags_for_user(
                 t.owner_id,
                 t.name,
                 t.archived,
+                t.sections,
                 u.name AS owner_name,
                 vtu.can_edit AS "can_edit!",
                 vtu.can_triage AS "can_triage!",
@@ -554,6 +857,7 @@ pub async fn fetch_tags_for_user(
                     format!("{}:{}", t.owner_name, t.name)
                 },
                 archived: t.archived,
+                sections: t.sections,
             },
             AuthInfo {
                 can_read: true,
@@ -601,42 +905,134 @@ pub async fn fetch_searches_for_user(
     .context("querying tags table")?)
 }
 
+/// Checks that `search` is valid and that `user` can see all the tags it refers to
+pub async fn check_search_export(
+    conn: &mut sqlx::PgConnection,
+    user: UserId,
+    search: &SearchExport,
+) -> Result<(), Error> {
+    search.validate()?;
+    check_tags_visible(&mut *conn, user, &search.tags()).await
+}
+
+async fn check_tags_visible(
+    conn: &mut sqlx::PgConnection,
+    user: UserId,
+    tags: &[TagId],
+) -> Result<(), Error> {
+    let visible = fetch_tags_for_user(&mut *conn, &user)
+        .await
+        .with_context(|| format!("fetching tag list for {user:?}"))?;
+    for tag in tags {
+        if !visible.iter().any(|(t, _)| t.id == *tag) {
+            return Err(Error::tag_not_found(*tag));
+        }
+    }
+    Ok(())
+}
+
+/// Saves `search` as a new search of `owner`, listed after their other searches
+///
+/// Fails if `owner` already has `MAX_SEARCHES_PER_USER` searches.
+pub async fn create_search(
+    conn: &mut sqlx::PgConnection,
+    owner: UserId,
+    search: &SearchExport,
+) -> Result<Search, Error> {
+    let count = sqlx::query_scalar!(
+        r#"SELECT COUNT(*) AS "count!" FROM searches WHERE owner_id = $1"#,
+        owner.0
+    )
+    .fetch_one(&mut *conn)
+    .await
+    .with_context(|| format!("counting the searches of {owner:?}"))?;
+    if count >= MAX_SEARCHES_PER_USER as i64 {
+        return Err(Error::integer_out_of_range(count));
+    }
+    let id = Uuid::new_v4();
+    let (order_type, tag_id) = DbOrderType::from_api(&search.order);
+    let priority = sqlx::query_scalar!(
+        r#"
+            INSERT INTO searches (id, owner_id, name, filter, order_type, priority, tag_id)
+            SELECT $1, $2, $3, $4, $5, COALESCE(MAX(priority) + 1, 0), $6
+                FROM searches WHERE owner_id = $2
+            RETURNING priority
+        "#,
+        id,
+        owner.0,
+        search.name,
+        sqlx::types::Json(&search.filter) as _,
+        order_type as DbOrderType,
+        tag_id,
+    )
+    .fetch_one(&mut *conn)
+    .await
+    .with_context(|| format!("creating search {search:?}"))?;
+    Ok(Search {
+        id: SearchId(id),
+        name: search.name.clone(),
+        filter: search.filter.clone(),
+        order: match &search.order {
+            // The search id doubles as the id of its custom order
+            Order::Custom(_) => Order::Custom(OrderId(id)),
+            o => o.clone(),
+        },
+        priority,
+    })
+}
+
+/// Tables joined for `query::to_postgres` where clauses to apply, binding the user to `$1`
+const SEARCH_TASKS_FROM: &str = "
+    tasks t
+    LEFT JOIN v_tasks_users vtu
+        ON vtu.task_id = t.id
+    LEFT JOIN v_tasks_archived vta
+        ON vta.task_id = t.id
+    LEFT JOIN v_tasks_done vtd
+        ON vtd.task_id = t.id
+    LEFT JOIN v_tasks_tags vtt
+        ON vtt.task_id = t.id
+    LEFT JOIN v_tasks_is_tagged vtit
+        ON vtit.task_id = t.id
+    LEFT JOIN v_tasks_scheduled vts
+        ON vts.task_id = t.id AND vts.owner_id = $1
+    LEFT JOIN v_tasks_blocked vtb
+        ON vtb.task_id = t.id
+    LEFT JOIN v_tasks_priority vtp
+        ON vtp.task_id = t.id
+    LEFT JOIN v_tasks_last_event vtle
+        ON vtle.task_id = t.id
+    LEFT JOIN v_tasks_comments vtc
+        ON vtc.task_id = t.id
+    LEFT JOIN v_tasks_text vtx
+        ON vtx.task_id = t.id
+";
+
 pub async fn search_tasks_for_user(
     conn: &mut sqlx::PgConnection,
     owner: UserId,
     query: &Query,
-) -> Result<(Vec<Task>, Vec<Event>), Error> {
+    now: Time,
+) -> Result<SearchResults, Error> {
     let query::Sql {
         where_clause,
         binds,
-    } = query::to_postgres(&query, 2)?;
+    } = query::to_postgres(&query, &now, 2)?;
     with_tmp_tasks_table(&mut *conn, |conn| {
         Box::pin(async move {
+            // Fetching one more task than allowed lets `SearchResults::capped` notice truncation
+            let limit = MAX_SEARCH_TASKS + 1;
             let query = format!(
                 "
                     INSERT INTO tmp_tasks
-                    SELECT DISTINCT t.id
-                        FROM tasks t
-                    LEFT JOIN v_tasks_users vtu
-                        ON vtu.task_id = t.id
-                    LEFT JOIN v_tasks_archived vta
-                        ON vta.task_id = t.id
-                    LEFT JOIN v_tasks_done vtd
-                        ON vtd.task_id = t.id
-                    LEFT JOIN v_tasks_tags vtt
-                        ON vtt.task_id = t.id
-                    LEFT JOIN v_tasks_is_tagged vtit
-                        ON vtit.task_id = t.id
-                    LEFT JOIN v_tasks_scheduled vts
-                        ON vts.task_id = t.id AND vts.owner_id = $1
-                    LEFT JOIN v_tasks_blocked vtb
-                        ON vtb.task_id = t.id
-                    LEFT JOIN v_tasks_comments vtc
-                        ON vtc.task_id = t.id
-                    LEFT JOIN v_tasks_text vtx
-                        ON vtx.task_id = t.id
-                    WHERE vtu.user_id = $1
-                    AND {where_clause}
+                    SELECT id FROM (
+                        SELECT DISTINCT t.id, t.date
+                            FROM {SEARCH_TASKS_FROM}
+                        WHERE vtu.user_id = $1
+                        AND {where_clause}
+                    ) matching
+                    ORDER BY date DESC, id
+                    LIMIT {limit}
                 "
             );
             let mut q = sqlx::query(&query).bind(owner.0);
@@ -646,18 +1042,257 @@ pub async fn search_tasks_for_user(
                     query::Bind::Uuid(u) => q = q.bind(u),
                     query::Bind::String(s) => q = q.bind(s),
                     query::Bind::Time(t) => q = q.bind(t.naive_utc()),
+                    query::Bind::Int(i) => q = q.bind(i),
+                };
+            }
+            q.execute(&mut *conn)
+                .await
+                .context("filling temp table with interesting task ids")?;
+
+            let (tasks, events) = fetch_tasks_from_tmp_tasks_table(&mut *conn).await?;
+            Ok(SearchResults::capped(tasks, events))
+        })
+    })
+    .await
+}
+
+/// Computes the events that replace `r.find` in the tasks matching `r.query`, across all users
+pub async fn text_replacement_events(
+    conn: &mut sqlx::PgConnection,
+    r: &TextReplacement,
+    now: Time,
+) -> Result<Vec<Event>, Error> {
+    let query::Sql {
+        where_clause,
+        binds,
+    } = query::to_postgres(&r.query, &now, 2)?;
+    let r = r.clone();
+    with_tmp_tasks_table(&mut *conn, |conn| {
+        Box::pin(async move {
+            let query = format!(
+                "
+                    INSERT INTO tmp_tasks
+                    SELECT DISTINCT t.id
+                        FROM {SEARCH_TASKS_FROM}
+                    WHERE {where_clause}
+                "
+            );
+            // Admins have no schedule of their own, so the stub user makes scheduling conditions
+            // match no task
+            let mut q = sqlx::query(&query).bind(UserId::stub().0);
+            for b in binds {
+                match b {
+                    query::Bind::Bool(b) => q = q.bind(b),
+                    query::Bind::Uuid(u) => q = q.bind(u),
+                    query::Bind::String(s) => q = q.bind(s),
+                    query::Bind::Time(t) => q = q.bind(t.naive_utc()),
+                    query::Bind::Int(i) => q = q.bind(i),
                 };
             }
             q.execute(&mut *conn)
                 .await
                 .context("filling temp table with interesting task ids")?;
 
-            fetch_tasks_from_tmp_tasks_table(&mut *conn).await
+            let mut res = Vec::new();
+            if r.titles {
+                let titles = sqlx::query_as::<_, (Uuid, Uuid, String)>(
+                    "
+                        SELECT t.id, t.owner_id, vtt.title
+                        FROM tmp_tasks tmp
+                        INNER JOIN tasks t
+                            ON t.id = tmp.id
+                        INNER JOIN v_tasks_title vtt
+                            ON vtt.task_id = t.id
+                        WHERE strpos(vtt.title, $1) > 0
+                    ",
+                )
+                .bind(&r.find)
+                .fetch_all(&mut *conn)
+                .await
+                .context("fetching task titles to replace")?;
+                res.extend(titles.into_iter().map(|(task, owner, title)| {
+                    Event::at(
+                        UserId(owner),
+                        TaskId(task),
+                        now,
+                        EventData::SetTitle(title.replace(&r.find, &r.replace)),
+                    )
+                }));
+            }
+            if r.comments {
+                let comments = sqlx::query_as::<_, (Uuid, Uuid, Uuid, String)>(
+                    "
+                        SELECT vtc.task_id, vtc.comment_id, e.owner_id, vtc.text
+                        FROM tmp_tasks tmp
+                        INNER JOIN v_tasks_comments vtc
+                            ON vtc.task_id = tmp.id
+                        INNER JOIN events e
+                            ON e.id = vtc.comment_id
+                        WHERE strpos(vtc.text, $1) > 0
+                    ",
+                )
+                .bind(&r.find)
+                .fetch_all(&mut *conn)
+                .await
+                .context("fetching comments to replace")?;
+                res.extend(comments.into_iter().map(|(task, comment, owner, text)| {
+                    Event::at(
+                        UserId(owner),
+                        TaskId(task),
+                        now,
+                        EventData::EditComment {
+                            text: text.replace(&r.find, &r.replace),
+                            comment_id: EventId(comment),
+                        },
+                    )
+                }));
+            }
+            Ok(res)
         })
     })
     .await
 }
 
+/// Aggregate counts over the results of a search
+#[derive(Clone, Copy, Debug, sqlx::FromRow)]
+pub struct SearchCounts {
+    /// Tasks neither done nor archived
+    pub open: i64,
+
+    /// Open tasks that were scheduled for before now
+    pub overdue: i64,
+}
+
+pub async fn count_search_results(
+    conn: &mut sqlx::PgConnection,
+    owner: UserId,
+    query: &Query,
+    now: Time,
+) -> Result<SearchCounts, Error> {
+    let query::Sql {
+        where_clause,
+        binds,
+    } = query::to_postgres(&query, &now, 2)?;
+    let now_idx = 2 + binds.len();
+    let query = format!(
+        "
+            SELECT
+                COUNT(*) AS open,
+                COUNT(*) FILTER (WHERE overdue) AS overdue
+            FROM (
+                SELECT DISTINCT t.id, COALESCE(vts.time < ${now_idx}, false) AS overdue
+                    FROM {SEARCH_TASKS_FROM}
+                WHERE vtu.user_id = $1
+                AND (vtd.done = false OR vtd.done IS NULL)
+                AND (vta.archived = false OR vta.archived IS NULL)
+                AND {where_clause}
+            ) AS matching_tasks
+        "
+    );
+    let mut q = sqlx::query_as::<_, SearchCounts>(&query).bind(owner.0);
+    for b in binds {
+        match b {
+            query::Bind::Bool(b) => q = q.bind(b),
+            query::Bind::Uuid(u) => q = q.bind(u),
+            query::Bind::String(s) => q = q.bind(s),
+            query::Bind::Time(t) => q = q.bind(t.naive_utc()),
+            query::Bind::Int(i) => q = q.bind(i),
+        };
+    }
+    Ok(q.bind(now.naive_utc())
+        .fetch_one(&mut *conn)
+        .await
+        .with_context(|| format!("counting results of search {query:?}"))?)
+}
+
+/// Sets the token with which a search can be accessed without authentication, `None` to revoke
+pub async fn set_search_share_token(
+    conn: &mut sqlx::PgConnection,
+    owner: UserId,
+    search: SearchId,
+    token: Option<Uuid>,
+) -> Result<(), Error> {
+    let res = sqlx::query!(
+        "UPDATE searches SET share_token = $3 WHERE id = $1 AND owner_id = $2",
+        search.0,
+        owner.0,
+        token,
+    )
+    .execute(&mut *conn)
+    .await
+    .with_context(|| format!("setting share token of search {search:?}"))?;
+    match res.rows_affected() {
+        0 => Err(Error::permission_denied()),
+        _ => Ok(()),
+    }
+}
+
+/// Sets the token with which a search's results can be read as a feed, `None` to revoke it
+pub async fn set_search_feed_token(
+    conn: &mut sqlx::PgConnection,
+    owner: UserId,
+    search: SearchId,
+    token: Option<Uuid>,
+) -> Result<(), Error> {
+    let res = sqlx::query!(
+        "UPDATE searches SET feed_token = $3 WHERE id = $1 AND owner_id = $2",
+        search.0,
+        owner.0,
+        token,
+    )
+    .execute(&mut *conn)
+    .await
+    .with_context(|| format!("setting feed token of search {search:?}"))?;
+    match res.rows_affected() {
+        0 => Err(Error::permission_denied()),
+        _ => Ok(()),
+    }
+}
+
+/// Returns the owner and the search whose feed is readable with `token`
+pub async fn fetch_feed_search(
+    conn: &mut sqlx::PgConnection,
+    token: Uuid,
+) -> Result<(UserId, Search), Error> {
+    let feed = sqlx::query!(
+        "SELECT id, owner_id FROM searches WHERE feed_token = $1",
+        token
+    )
+    .fetch_optional(&mut *conn)
+    .await
+    .context("fetching search feed")?
+    .ok_or(Error::permission_denied())?;
+    let owner = UserId(feed.owner_id);
+    let search = fetch_searches_for_user(&mut *conn, &owner)
+        .await?
+        .into_iter()
+        .find(|s| s.id.0 == feed.id)
+        .ok_or(Error::permission_denied())?;
+    Ok((owner, search))
+}
+
+/// Returns the owner and the search shared with `token`
+pub async fn fetch_shared_search(
+    conn: &mut sqlx::PgConnection,
+    token: Uuid,
+) -> Result<(UserId, Search), Error> {
+    let shared = sqlx::query!(
+        "SELECT id, owner_id FROM searches WHERE share_token = $1",
+        token
+    )
+    .fetch_optional(&mut *conn)
+    .await
+    .context("fetching shared search")?
+    .ok_or(Error::permission_denied())?;
+    let owner = UserId(shared.owner_id);
+    let search = fetch_searches_for_user(&mut *conn, &owner)
+        .await?
+        .into_iter()
+        .find(|s| s.id.0 == shared.id)
+        .ok_or(Error::permission_denied())?;
+    Ok((owner, search))
+}
+
 async fn fetch_tasks_from_tmp_tasks_table(
     conn: &mut sqlx::PgConnection,
 ) -> Result<(Vec<Task>, Vec<Event>), Error> {
@@ -675,12 +1310,20 @@ async fn fetch_tasks_from_tmp_tasks_table(
     .await
     .context("fetching relevant tasks")?;
 
+    // Folded read events are served as if they were still in the events table
     let events = sqlx::query_as::<_, DbEvent>(
         "
-            SELECT e.*
+            SELECT e.id, e.owner_id, e.date, e.task_id, e.d_type, e.d_text, e.d_bool, e.d_int,
+                e.d_time, e.d_tag_id, e.d_parent_id, e.d_order_id
             FROM tmp_tasks t
             INNER JOIN events e
             ON t.id = e.task_id
+            UNION ALL
+            SELECT r.id, r.owner_id, r.date, r.task_id, 'set_event_read', NULL, r.now_read, NULL,
+                NULL, NULL, r.event_id, NULL
+            FROM tmp_tasks t
+            INNER JOIN event_reads r
+            ON t.id = r.task_id
         ",
     )
     .fetch(&mut *conn)
@@ -692,33 +1335,159 @@ async fn fetch_tasks_from_tmp_tasks_table(
     Ok((tasks, events))
 }
 
-pub async fn submit_event(db: &mut PostgresDb<'_>, e: Event) -> Result<(), Error> {
-    let event_id = e.id;
+pub async fn fetch_activity_for_user(
+    conn: &mut sqlx::PgConnection,
+    owner: UserId,
+    page: &ActivityPage,
+) -> anyhow::Result<Vec<Event>> {
+    Ok(sqlx::query_as::<_, DbEvent>(
+        "
+            SELECT e.*
+                FROM events e
+            INNER JOIN v_tasks_users vtu
+                ON vtu.task_id = e.task_id
+            WHERE vtu.user_id = $1
+            AND ($2::UUID IS NULL OR (e.date, e.id) < (
+                SELECT be.date, be.id
+                    FROM events be
+                INNER JOIN v_tasks_users bvtu
+                    ON bvtu.task_id = be.task_id
+                WHERE be.id = $2 AND bvtu.user_id = $1
+            ))
+            ORDER BY e.date DESC, e.id DESC
+            LIMIT $3
+        ",
+    )
+    .bind(owner.0)
+    .bind(page.before.map(|e| e.0))
+    .bind(page.limit)
+    .fetch(&mut *conn)
+    .map_ok(Event::from)
+    .try_collect()
+    .await
+    .with_context(|| format!("fetching activity page {page:?}"))?)
+}
 
-    // Check authorization
-    let auth = e
-        .is_authorized(&mut *db)
+/// Checks that the user of `db` can see the task `e` is on and the event it refers to, if any,
+/// which authorizing `e` relies on
+///
+/// Missing ones are reported like the ones the user cannot see, so that whether they exist does
+/// not leak.
+async fn check_visible_references(db: &mut PostgresDb<'_>, e: &Event) -> Result<(), Error> {
+    let auth = db
+        .auth_info_for(e.task_id)
         .await
-        .with_context(|| format!("checking if user is authorized to add event {:?}", event_id))?;
-    if !auth {
-        tracing::info!("rejected permission for event {:?}", e);
+        .with_context(|| format!("checking whether task {:?} is visible", e.task_id))?;
+    if !auth.can_read {
         return Err(Error::permission_denied());
     }
 
-    let e = DbEvent::from(e);
-    let res = sqlx::query!(
-        "INSERT INTO events VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)",
-        &e.id,
-        &e.owner_id,
-        &e.date,
-        &e.task_id,
-        &e.d_type as &DbType,
+    let event = match e.data {
+        EventData::AddComment {
+            parent_id: Some(event),
+            ..
+        }
+        | EventData::EditComment {
+            comment_id: event, ..
+        }
+        | EventData::SetEventRead {
+            event_id: event, ..
+        } => event,
+        _ => return Ok(()),
+    };
+    let event_task = sqlx::query!("SELECT task_id FROM events WHERE id = $1", event.0)
+        .fetch_optional(&mut *db.conn)
+        .await
+        .with_context(|| format!("checking whether event {event:?} exists"))?;
+    let event_visible = match event_task {
+        None => false,
+        Some(t) => {
+            db.auth_info_for(TaskId(t.task_id))
+                .await
+                .with_context(|| format!("checking whether event {event:?} is visible"))?
+                .can_read
+        }
+    };
+    if !event_visible {
+        return Err(match e.data {
+            EventData::AddComment { .. } => Error::parent_comment_not_found(event),
+            _ => Error::event_not_found(event),
+        });
+    }
+    Ok(())
+}
+
+/// Checks that the tag `e` refers to, if any, exists
+///
+/// This only happens once `e` is known to be authorized, for it not to tell whether tags exist.
+async fn check_tag_reference(conn: &mut sqlx::PgConnection, e: &Event) -> Result<(), Error> {
+    match e.data {
+        EventData::AddTag { tag, .. }
+        | EventData::RmTag(tag)
+        | EventData::SetSection { tag, .. } => {
+            let tag_exists = sqlx::query!("SELECT id FROM tags WHERE id = $1", tag.0)
+                .fetch_optional(&mut *conn)
+                .await
+                .with_context(|| format!("checking whether tag {tag:?} exists"))?
+                .is_some();
+            if !tag_exists {
+                return Err(Error::tag_not_found(tag));
+            }
+        }
+        _ => (),
+    }
+    Ok(())
+}
+
+/// Checks that `e` can be submitted by the user of `db`, without submitting it
+pub async fn check_event(db: &mut PostgresDb<'_>, e: &Event) -> Result<(), Error> {
+    if e.owner_id != db.user {
+        return Err(Error::permission_denied());
+    }
+    check_visible_references(&mut *db, e).await?;
+
+    // Check authorization
+    let auth = e
+        .is_authorized(&mut *db)
+        .await
+        .with_context(|| format!("checking if user is authorized to add event {:?}", e.id))?;
+    if !auth {
+        tracing::info!("rejected permission for event {:?}", e);
+        return Err(Error::permission_denied());
+    }
+
+    check_tag_reference(&mut *db.conn, e).await
+}
+
+pub async fn submit_event(
+    db: &mut PostgresDb<'_>,
+    e: Event,
+    provenance: &Provenance,
+) -> Result<(), Error> {
+    let event_id = e.id;
+
+    check_event(&mut *db, &e).await?;
+
+    let e = DbEvent::from(e);
+    let res = sqlx::query!(
+        "
+            INSERT INTO events
+                (id, owner_id, date, task_id, d_type, d_text, d_bool, d_int, d_time, d_tag_id, d_parent_id, session_id, app_version)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)
+        ",
+        &e.id,
+        &e.owner_id,
+        &e.date,
+        &e.task_id,
+        &e.d_type as &DbType,
         e.d_text.as_ref(),
         e.d_bool.as_ref(),
         e.d_int.as_ref(),
         e.d_time.as_ref(),
         e.d_tag_id.as_ref(),
         e.d_parent_id.as_ref(),
+        provenance.session.map(|s| s.0),
+        provenance.app_version.as_ref(),
     )
     .execute(&mut *db.conn)
     .await
@@ -744,13 +1513,90 @@ pub async fn submit_event(db: &mut PostgresDb<'_>, e: Event) -> Result<(), Error
     }
 }
 
-pub async fn submit_task(db: &mut PostgresDb<'_>, t: Task, top_comm: String) -> Result<(), Error> {
-    let task_id = t.id.0;
+/// Submits all of `events`, each on behalf of its owner, so that either all or none get committed
+pub async fn submit_events_as_owners(
+    conn: &mut sqlx::PgConnection,
+    events: &[Event],
+    provenance: &Provenance,
+) -> Result<(), Error> {
+    let mut transaction = conn
+        .begin()
+        .await
+        .context("creating events submission transaction")?;
+    for e in events {
+        let mut db = PostgresDb {
+            conn: &mut *transaction,
+            user: e.owner_id,
+        };
+        submit_event(&mut db, e.clone(), provenance).await?;
+    }
+    transaction
+        .commit()
+        .await
+        .context("committing events submission transaction")?;
+    Ok(())
+}
 
+/// Checks that `t` can be submitted by the user of `db`, without submitting it
+///
+/// Returns whether `t` already got submitted, submitting it again being a no-op.
+pub async fn check_task(db: &mut PostgresDb<'_>, t: &Task, top_comm: &str) -> Result<bool, Error> {
     if t.owner_id != db.user {
         return Err(Error::permission_denied());
     }
 
+    let task = sqlx::query!("SELECT * FROM tasks WHERE id=$1", t.id.0)
+        .fetch_optional(&mut *db.conn)
+        .await
+        .with_context(|| format!("checking whether task {:?} already exists", t.id))?;
+    let already_present = match task {
+        None => false,
+        Some(p)
+            if p.owner_id == t.owner_id.0
+                && p.date == t.date.naive_utc()
+                && p.initial_title == t.initial_title =>
+        {
+            true
+        }
+        Some(p) => return Err(Error::uuid_already_used(p.id)),
+    };
+
+    let top_comment = sqlx::query_as::<_, DbEvent>("SELECT * FROM events WHERE id=$1")
+        .bind(&t.top_comment_id.0)
+        .fetch_optional(&mut *db.conn)
+        .await
+        .with_context(|| {
+            format!(
+                "checking whether event {:?} already exists",
+                t.top_comment_id
+            )
+        })?;
+    if let Some(p) = top_comment {
+        let same = p.owner_id == t.owner_id.0
+            && p.date == t.date.naive_utc()
+            && p.task_id == t.id.0
+            && p.d_type == DbType::AddComment
+            && p.d_text.as_deref() == Some(top_comm);
+        if !same {
+            return Err(Error::uuid_already_used(p.id));
+        }
+    }
+
+    Ok(already_present)
+}
+
+pub async fn submit_task(
+    db: &mut PostgresDb<'_>,
+    t: Task,
+    top_comm: String,
+    provenance: &Provenance,
+) -> Result<(), Error> {
+    let task_id = t.id.0;
+
+    if check_task(&mut *db, &t, &top_comm).await? {
+        return Ok(());
+    }
+
     let mut transaction = db
         .conn
         .begin()
@@ -791,16 +1637,27 @@ pub async fn submit_task(db: &mut PostgresDb<'_>, t: Task, top_comm: String) ->
     }?;
 
     let res = sqlx::query!(
-        "INSERT INTO events VALUES ($1, $2, $3, $4, 'add_comment', $5, NULL, NULL, NULL, NULL, NULL, NULL)",
+        "
+            INSERT INTO events
+                (id, owner_id, date, task_id, d_type, d_text, session_id, app_version)
+            VALUES ($1, $2, $3, $4, 'add_comment', $5, $6, $7)
+        ",
         &t.top_comment_id.0,
         &t.owner_id.0,
         &t.date.naive_utc(),
         &t.id.0,
         &top_comm,
+        provenance.session.map(|s| s.0),
+        provenance.app_version.as_ref(),
     )
     .execute(&mut transaction)
     .await
-    .with_context(|| format!("creating top-comment {:?} for task {:?}", t.top_comment_id, t.id))?;
+    .with_context(|| {
+        format!(
+            "creating top-comment {:?} for task {:?}",
+            t.top_comment_id, t.id
+        )
+    })?;
 
     match res.rows_affected() {
         1 => Ok(()),
@@ -833,32 +1690,947 @@ pub async fn submit_task(db: &mut PostgresDb<'_>, t: Task, top_comm: String) ->
 }
 
 pub async fn create_user(conn: &mut sqlx::PgConnection, user: NewUser) -> Result<(), Error> {
+    insert_user(conn, user.id, user.name, Some(user.initial_password_hash)).await
+}
+
+/// Creates a user without a local password, who logs in through LDAP
+pub async fn create_ldap_user(
+    conn: &mut sqlx::PgConnection,
+    id: UserId,
+    name: String,
+) -> Result<(), Error> {
+    insert_user(conn, id, name, None).await
+}
+
+async fn insert_user(
+    conn: &mut sqlx::PgConnection,
+    id: UserId,
+    name: String,
+    password_hash: Option<String>,
+) -> Result<(), Error> {
+    // Savepoint when called from within a transaction, so that conflicts can be looked into
+    let mut transaction = conn
+        .begin()
+        .await
+        .context("creating user insertion transaction")?;
     let res = sqlx::query!(
         "INSERT INTO users VALUES ($1, $2, $3)",
-        user.id.0,
-        user.name,
-        user.initial_password_hash
+        id.0,
+        name,
+        password_hash
     )
-    .execute(&mut *conn)
+    .execute(&mut transaction)
     .await
     .risuto_should_affect_rows(1)?
     .risuto_db_err()
-    .with_context(|| format!("inserting into database user {user:?}"))?;
-    match res {
-        Ok(_) => Ok(()),
-        Err(err) => match err.constraint() {
-            Some("users_pkey") => {
-                let already_present = sqlx::query!("SELECT * FROM users WHERE id=$1", user.id.0)
-                    .fetch_optional(&mut *conn)
-                    .await
-                    .context("sanity-checking the already-present user")?;
-                match already_present {
-                    Some(p) if p.id == user.id.0 => Err(Error::uuid_already_used(p.id)),
-                    _ => Err(Error::Anyhow(anyhow!("unknown user creation conflict on users_pkey: trying to insert {user:?}, already had {already_present:?}"))),
-                }
+    .with_context(|| format!("inserting into database user {id:?} named {name:?}"))?;
+    let err = match res {
+        Ok(_) => {
+            transaction
+                .commit()
+                .await
+                .context("committing user insertion transaction")?;
+            return Ok(());
+        }
+        Err(err) => err,
+    };
+    transaction
+        .rollback()
+        .await
+        .context("rolling back user insertion transaction")?;
+    match err.constraint() {
+        Some("users_pkey") => {
+            let already_present = sqlx::query!("SELECT * FROM users WHERE id=$1", id.0)
+                .fetch_optional(&mut *conn)
+                .await
+                .context("sanity-checking the already-present user")?;
+            match already_present {
+                Some(p) if p.id == id.0 => Err(Error::uuid_already_used(p.id)),
+                _ => Err(Error::Anyhow(anyhow!("unknown user creation conflict on users_pkey: trying to insert {id:?} named {name:?}, already had {already_present:?}"))),
             }
-            Some("users_name_key") => Err(Error::name_already_used(user.name)),
-            constraint => Err(Error::Anyhow(anyhow!("unknown user creation conflict on constraint {constraint:?} while trying to insert {user:?}"))),
         }
+        Some("users_name_key") => Err(Error::name_already_used(name)),
+        constraint => Err(Error::Anyhow(anyhow!("unknown user creation conflict on constraint {constraint:?} while trying to insert {id:?} named {name:?}"))),
+    }
+}
+
+/// Creates an invite token valid until `expires_at`, `creator` being `None` for invites created by
+/// the administrator
+///
+/// Users can only invite people to tags they own.
+pub async fn create_invite(
+    conn: &mut sqlx::PgConnection,
+    creator: Option<UserId>,
+    invite: NewInvite,
+    expires_at: Time,
+) -> Result<Uuid, Error> {
+    let token = Uuid::new_v4();
+    let mut tags = invite.tags.iter().map(|t| t.0).collect::<Vec<_>>();
+    tags.sort_unstable();
+    tags.dedup();
+
+    let mut transaction = conn
+        .begin()
+        .await
+        .context("creating invite creation transaction")?;
+    if let Some(creator) = creator {
+        let owned = sqlx::query!(
+            r#"SELECT COUNT(*) AS "count!" FROM tags WHERE id = ANY($1) AND owner_id = $2"#,
+            &tags,
+            creator.0
+        )
+        .fetch_one(&mut transaction)
+        .await
+        .with_context(|| format!("checking ownership of invite tags {tags:?}"))?;
+        if owned.count != tags.len() as i64 {
+            return Err(Error::permission_denied());
+        }
+    }
+    sqlx::query!(
+        "INSERT INTO invites VALUES ($1, $2, $3, $4)",
+        token,
+        creator.map(|c| c.0),
+        DbRole::from_api(invite.role) as DbRole,
+        expires_at.naive_utc(),
+    )
+    .execute(&mut transaction)
+    .await
+    .context("inserting invite")?;
+    let res = sqlx::query!(
+        "INSERT INTO invite_tags SELECT $1, id FROM tags WHERE id = ANY($2)",
+        token,
+        &tags
+    )
+    .execute(&mut transaction)
+    .await
+    .context("inserting invite tags")?;
+    if res.rows_affected() != tags.len() as u64 {
+        return Err(Error::permission_denied()); // some tag does not exist
+    }
+    transaction
+        .commit()
+        .await
+        .context("committing invite creation transaction")?;
+
+    Ok(token)
+}
+
+/// Creates the user and shares the invite's tags with them, consuming the invite, all at once
+pub async fn register(
+    conn: &mut sqlx::PgConnection,
+    reg: Registration,
+    now: Time,
+) -> Result<(), Error> {
+    let mut transaction = conn
+        .begin()
+        .await
+        .context("creating registration transaction")?;
+    // Locking the invite, so that concurrent registrations wait for this one and then see it gone
+    let invite = sqlx::query!(
+        r#"
+            SELECT role AS "role: DbRole"
+            FROM invites
+            WHERE token = $1 AND expires_at > $2
+            FOR UPDATE
+        "#,
+        reg.invite,
+        now.naive_utc(),
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("fetching invite")?
+    .ok_or(Error::permission_denied())?;
+    create_user(&mut *transaction, reg.user.clone()).await?;
+    let auth = invite.role.into_api().auth_info();
+    sqlx::query!(
+        "
+            INSERT INTO perms
+            SELECT tag_id, $2, $3, $4, $5, $6, $7
+                FROM invite_tags
+            WHERE token = $1
+        ",
+        reg.invite,
+        reg.user.id.0,
+        auth.can_edit,
+        auth.can_triage,
+        auth.can_relabel_to_any,
+        auth.can_comment,
+        auth.can_archive,
+    )
+    .execute(&mut transaction)
+    .await
+    .context("sharing invite tags with the new user")?;
+    sqlx::query!("DELETE FROM invites WHERE token = $1", reg.invite)
+        .execute(&mut transaction)
+        .await
+        .context("consuming invite")?;
+    transaction
+        .commit()
+        .await
+        .context("committing registration transaction")?;
+    Ok(())
+}
+
+/// Lists the migrations known to this server, in the order they apply
+pub async fn migration_status(
+    conn: &mut sqlx::PgConnection,
+) -> Result<Vec<MigrationStatus>, Error> {
+    conn.ensure_migrations_table()
+        .await
+        .context("creating migrations table")?;
+    if let Some(version) = conn
+        .dirty_version()
+        .await
+        .context("checking for partially applied migrations")?
+    {
+        return Err(Error::Anyhow(anyhow!(
+            "migration {version} was partially applied, the database needs manual fixing"
+        )));
+    }
+    let applied = conn
+        .list_applied_migrations()
+        .await
+        .context("listing applied migrations")?
+        .into_iter()
+        .map(|m| m.version)
+        .collect::<HashSet<_>>();
+    Ok(crate::MIGRATOR
+        .iter()
+        .filter(|m| !m.migration_type.is_down_migration())
+        .map(|m| MigrationStatus {
+            version: m.version,
+            description: m.description.to_string(),
+            applied: applied.contains(&m.version),
+        })
+        .collect())
+}
+
+pub async fn run_migrations(conn: &mut sqlx::PgConnection) -> Result<Vec<MigrationStatus>, Error> {
+    crate::MIGRATOR
+        .run(&mut *conn)
+        .await
+        .context("running pending migrations")?;
+    migration_status(conn).await
+}
+
+/// Reverts the latest applied migration, if any
+pub async fn revert_migration(
+    conn: &mut sqlx::PgConnection,
+) -> Result<Vec<MigrationStatus>, Error> {
+    let applied = migration_status(&mut *conn)
+        .await?
+        .into_iter()
+        .filter(|m| m.applied)
+        .map(|m| m.version)
+        .collect::<Vec<_>>();
+    if let Some((_, rest)) = applied.split_last() {
+        // `undo` reverts all the migrations newer than the target
+        let target = rest.last().copied().unwrap_or(0);
+        crate::MIGRATOR
+            .undo(&mut *conn, target)
+            .await
+            .with_context(|| format!("reverting migrations newer than {target}"))?;
+    }
+    migration_status(conn).await
+}
+
+/// Records that `version` of the server started, unless it already did on this database
+pub async fn record_server_version(
+    conn: &mut sqlx::PgConnection,
+    version: &str,
+    now: Time,
+) -> anyhow::Result<()> {
+    sqlx::query!(
+        "INSERT INTO server_versions VALUES ($1, $2) ON CONFLICT (version) DO NOTHING",
+        version,
+        now.naive_utc(),
+    )
+    .execute(&mut *conn)
+    .await
+    .with_context(|| format!("recording server version {version:?}"))?;
+    Ok(())
+}
+
+/// Lists the versions of the server that ran on this database, along with their first start
+pub async fn fetch_server_versions(
+    conn: &mut sqlx::PgConnection,
+) -> anyhow::Result<Vec<(String, Time)>> {
+    Ok(
+        sqlx::query!("SELECT version, installed_at FROM server_versions")
+            .fetch(&mut *conn)
+            .map_ok(|v| {
+                let installed_at = v.installed_at.and_local_timezone(chrono::Utc).unwrap();
+                (v.version, installed_at)
+            })
+            .try_collect()
+            .await
+            .context("querying server versions table")?,
+    )
+}
+
+pub async fn fetch_rules_for_user(
+    conn: &mut sqlx::PgConnection,
+    user: UserId,
+) -> anyhow::Result<Vec<Rule>> {
+    Ok(sqlx::query!(
+        r#"
+            SELECT
+                id,
+                owner_id,
+                name,
+                enabled,
+                trigger AS "trigger: sqlx::types::Json<RuleTrigger>",
+                filter AS "filter: sqlx::types::Json<Query>",
+                action AS "action: sqlx::types::Json<RuleAction>"
+            FROM rules
+            WHERE owner_id = $1
+        "#,
+        user.0
+    )
+    .fetch(conn)
+    .map_ok(|r| Rule {
+        id: RuleId(r.id),
+        owner_id: UserId(r.owner_id),
+        name: r.name,
+        enabled: r.enabled,
+        trigger: r.trigger.0,
+        filter: r.filter.0,
+        action: r.action.0,
+    })
+    .try_collect()
+    .await
+    .with_context(|| format!("querying rules of {user:?}"))?)
+}
+
+/// Returns the enabled rules of all the users who can see `task`
+pub async fn fetch_rules_for_task(
+    conn: &mut sqlx::PgConnection,
+    task: TaskId,
+) -> anyhow::Result<Vec<Rule>> {
+    Ok(sqlx::query!(
+        r#"
+            SELECT
+                r.id,
+                r.owner_id,
+                r.name,
+                r.enabled,
+                r.trigger AS "trigger: sqlx::types::Json<RuleTrigger>",
+                r.filter AS "filter: sqlx::types::Json<Query>",
+                r.action AS "action: sqlx::types::Json<RuleAction>"
+            FROM rules r
+            INNER JOIN v_tasks_users vtu
+                ON vtu.user_id = r.owner_id
+            WHERE vtu.task_id = $1
+            AND r.enabled
+        "#,
+        task.0
+    )
+    .fetch(conn)
+    .map_ok(|r| Rule {
+        id: RuleId(r.id),
+        owner_id: UserId(r.owner_id),
+        name: r.name,
+        enabled: r.enabled,
+        trigger: r.trigger.0,
+        filter: r.filter.0,
+        action: r.action.0,
+    })
+    .try_collect()
+    .await
+    .with_context(|| format!("querying rules applying to {task:?}"))?)
+}
+
+/// Checks that `rule` is valid, belongs to `user` and only refers to tags `user` can see
+///
+/// New rules are also rejected once `user` has `MAX_RULES_PER_USER` rules.
+pub async fn check_rule(
+    conn: &mut sqlx::PgConnection,
+    user: UserId,
+    rule: &Rule,
+) -> Result<(), Error> {
+    if rule.owner_id != user {
+        return Err(Error::permission_denied());
+    }
+    rule.validate()?;
+    let others = sqlx::query_scalar!(
+        r#"SELECT COUNT(*) AS "count!" FROM rules WHERE owner_id = $1 AND id != $2"#,
+        user.0,
+        rule.id.0
+    )
+    .fetch_one(&mut *conn)
+    .await
+    .with_context(|| format!("counting the rules of {user:?}"))?;
+    if others >= MAX_RULES_PER_USER as i64 {
+        return Err(Error::integer_out_of_range(others));
+    }
+    check_tags_visible(&mut *conn, user, &rule.tags()).await
+}
+
+/// Creates or updates `rule`, assuming it passed `check_rule`
+pub async fn set_rule(conn: &mut sqlx::PgConnection, rule: &Rule) -> Result<(), Error> {
+    let res = sqlx::query!(
+        "
+            INSERT INTO rules (id, owner_id, name, enabled, trigger, filter, action)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            ON CONFLICT (id) DO UPDATE SET
+                name = EXCLUDED.name,
+                enabled = EXCLUDED.enabled,
+                trigger = EXCLUDED.trigger,
+                filter = EXCLUDED.filter,
+                action = EXCLUDED.action
+            WHERE rules.owner_id = EXCLUDED.owner_id
+        ",
+        rule.id.0,
+        rule.owner_id.0,
+        rule.name,
+        rule.enabled,
+        sqlx::types::Json(&rule.trigger) as _,
+        sqlx::types::Json(&rule.filter) as _,
+        sqlx::types::Json(&rule.action) as _,
+    )
+    .execute(&mut *conn)
+    .await
+    .with_context(|| format!("saving rule {rule:?}"))?;
+    match res.rows_affected() {
+        // The rule id is already used by another user
+        0 => Err(Error::permission_denied()),
+        _ => Ok(()),
+    }
+}
+
+pub async fn delete_rule(
+    conn: &mut sqlx::PgConnection,
+    owner: UserId,
+    rule: RuleId,
+) -> Result<(), Error> {
+    let res = sqlx::query!(
+        "DELETE FROM rules WHERE id = $1 AND owner_id = $2",
+        rule.0,
+        owner.0,
+    )
+    .execute(&mut *conn)
+    .await
+    .with_context(|| format!("deleting rule {rule:?}"))?;
+    match res.rows_affected() {
+        0 => Err(Error::permission_denied()),
+        _ => Ok(()),
+    }
+}
+
+/// Whether `user` can see `task` and it matches `query`
+pub async fn task_matches_query(
+    conn: &mut sqlx::PgConnection,
+    user: UserId,
+    task: TaskId,
+    query: &Query,
+    now: Time,
+) -> Result<bool, Error> {
+    let query::Sql {
+        where_clause,
+        binds,
+    } = query::to_postgres(query, &now, 3)?;
+    let query = format!(
+        "
+            SELECT EXISTS (
+                SELECT 1
+                    FROM {SEARCH_TASKS_FROM}
+                WHERE vtu.user_id = $1
+                AND t.id = $2
+                AND {where_clause}
+            )
+        "
+    );
+    let mut q = sqlx::query_scalar::<_, bool>(&query)
+        .bind(user.0)
+        .bind(task.0);
+    for b in binds {
+        match b {
+            query::Bind::Bool(b) => q = q.bind(b),
+            query::Bind::Uuid(u) => q = q.bind(u),
+            query::Bind::String(s) => q = q.bind(s),
+            query::Bind::Time(t) => q = q.bind(t.naive_utc()),
+            query::Bind::Int(i) => q = q.bind(i),
+        };
+    }
+    Ok(q.fetch_one(&mut *conn)
+        .await
+        .with_context(|| format!("checking whether {task:?} matches {query:?}"))?)
+}
+
+/// Priority putting a task above all the others of `tag`
+pub async fn top_prio_in_tag(conn: &mut sqlx::PgConnection, tag: TagId) -> anyhow::Result<i64> {
+    Ok(sqlx::query_scalar!(
+        r#"
+            SELECT COALESCE(MIN(d_int) - 1, 0) AS "prio!"
+                FROM events
+            WHERE d_type = 'add_tag' AND d_tag_id = $1
+        "#,
+        tag.0
+    )
+    .fetch_one(&mut *conn)
+    .await
+    .with_context(|| format!("fetching top priority of {tag:?}"))?)
+}
+
+pub async fn set_tickler(conn: &mut sqlx::PgConnection, tickler: Tickler) -> Result<(), Error> {
+    let res = match tickler.interval_secs {
+        Some(interval_secs) => sqlx::query!(
+            "
+                INSERT INTO ticklers
+                SELECT id, $2 FROM tags WHERE id = $1
+                ON CONFLICT (tag_id) DO UPDATE SET interval_secs = EXCLUDED.interval_secs
+            ",
+            tickler.tag.0,
+            interval_secs,
+        )
+        .execute(&mut *conn)
+        .await
+        .with_context(|| format!("setting tickler {tickler:?}"))?,
+        None => sqlx::query!("DELETE FROM ticklers WHERE tag_id = $1", tickler.tag.0)
+            .execute(&mut *conn)
+            .await
+            .with_context(|| format!("removing tickler for tag {:?}", tickler.tag))?,
+    };
+    match (tickler.interval_secs, res.rows_affected()) {
+        (Some(_), 0) => Err(Error::permission_denied()), // the tag does not exist
+        _ => Ok(()),
+    }
+}
+
+/// Creates a tag, there is no API for it yet so this only serves to seed developer sandboxes
+#[cfg(feature = "dev-sandbox")]
+pub async fn create_tag(conn: &mut sqlx::PgConnection, tag: Tag) -> Result<(), Error> {
+    sqlx::query!(
+        "INSERT INTO tags (id, owner_id, name, archived, sections) VALUES ($1, $2, $3, $4, $5)",
+        tag.id.0,
+        tag.owner_id.0,
+        tag.name,
+        tag.archived,
+        &tag.sections[..],
+    )
+    .execute(&mut *conn)
+    .await
+    .with_context(|| format!("creating tag {tag:?}"))?;
+    Ok(())
+}
+
+/// Returns whether the permissions of `perm.user` actually changed
+pub async fn set_tag_permission(
+    conn: &mut sqlx::PgConnection,
+    owner: UserId,
+    perm: TagPermission,
+) -> Result<bool, Error> {
+    let res = match perm.role {
+        Some(role) => {
+            // Roles are stored expanded, so that permissions set before roles existed keep working
+            let auth = role.auth_info();
+            sqlx::query!(
+                "
+                    INSERT INTO perms
+                    SELECT id, $3, $4, $5, $6, $7, $8 FROM tags WHERE id = $1 AND owner_id = $2
+                    ON CONFLICT (tag_id, user_id) DO UPDATE SET
+                        can_edit = EXCLUDED.can_edit,
+                        can_triage = EXCLUDED.can_triage,
+                        can_relabel_to_any = EXCLUDED.can_relabel_to_any,
+                        can_comment = EXCLUDED.can_comment,
+                        can_archive = EXCLUDED.can_archive
+                ",
+                perm.tag.0,
+                owner.0,
+                perm.user.0,
+                auth.can_edit,
+                auth.can_triage,
+                auth.can_relabel_to_any,
+                auth.can_comment,
+                auth.can_archive,
+            )
+            .execute(&mut *conn)
+            .await
+            .with_context(|| format!("setting tag permission {perm:?}"))?
+        }
+        None => sqlx::query!(
+            "
+                DELETE FROM perms p
+                USING tags t
+                WHERE p.tag_id = t.id AND t.id = $1 AND t.owner_id = $2 AND p.user_id = $3
+            ",
+            perm.tag.0,
+            owner.0,
+            perm.user.0,
+        )
+        .execute(&mut *conn)
+        .await
+        .with_context(|| format!("revoking tag permission {perm:?}"))?,
+    };
+    match (perm.role, res.rows_affected()) {
+        (Some(_), 0) => Err(Error::permission_denied()), // the tag does not exist or is not owned
+        (_, rows) => Ok(rows > 0),
+    }
+}
+
+pub async fn set_tag_sections(
+    conn: &mut sqlx::PgConnection,
+    owner: UserId,
+    sections: TagSections,
+) -> Result<(), Error> {
+    let res = sqlx::query!(
+        "UPDATE tags SET sections = $3 WHERE id = $1 AND owner_id = $2",
+        sections.tag.0,
+        owner.0,
+        &sections.sections[..],
+    )
+    .execute(&mut *conn)
+    .await
+    .with_context(|| format!("setting tag sections {sections:?}"))?;
+    match res.rows_affected() {
+        0 => Err(Error::permission_denied()), // the tag does not exist or is not owned
+        _ => Ok(()),
+    }
+}
+
+/// Lists the users the tag is shared with, only allowed to the tag's owner
+pub async fn fetch_tag_permissions(
+    conn: &mut sqlx::PgConnection,
+    owner: UserId,
+    tag: TagId,
+) -> Result<Vec<(UserId, AuthInfo)>, Error> {
+    sqlx::query!(
+        "SELECT id FROM tags WHERE id = $1 AND owner_id = $2",
+        tag.0,
+        owner.0
+    )
+    .fetch_optional(&mut *conn)
+    .await
+    .with_context(|| format!("checking ownership of tag {tag:?}"))?
+    .ok_or(Error::permission_denied())?;
+    Ok(sqlx::query!(
+        "
+            SELECT user_id, can_edit, can_triage, can_relabel_to_any, can_comment, can_archive
+            FROM perms
+            WHERE tag_id = $1
+        ",
+        tag.0
+    )
+    .fetch(&mut *conn)
+    .map_ok(|p| {
+        (
+            UserId(p.user_id),
+            AuthInfo {
+                can_read: true,
+                can_edit: p.can_edit,
+                can_triage: p.can_triage,
+                can_relabel_to_any: p.can_relabel_to_any,
+                can_comment: p.can_comment,
+                can_archive: p.can_archive,
+            },
+        )
+    })
+    .try_collect()
+    .await
+    .with_context(|| format!("fetching permissions of tag {tag:?}"))?)
+}
+
+/// Returns the (tag owner, task) pairs for tasks whose tickler is due, ie. that have
+/// been archived within a tickler tag for longer than its interval
+pub async fn due_ticklers(
+    conn: &mut sqlx::PgConnection,
+    now: Time,
+) -> anyhow::Result<Vec<(UserId, TaskId)>> {
+    Ok(sqlx::query!(
+        r#"
+            SELECT DISTINCT ON (a.task_id)
+                tg.owner_id AS "owner_id!",
+                a.task_id AS "task_id!"
+            FROM ticklers tk
+            INNER JOIN tags tg
+                ON tg.id = tk.tag_id
+            INNER JOIN v_tasks_tags vtt
+                ON vtt.tag_id = tk.tag_id AND vtt.is_in
+            INNER JOIN (
+                SELECT DISTINCT ON (task_id) task_id, date, d_bool
+                FROM events
+                WHERE d_type = 'set_archived'
+                ORDER BY task_id, date DESC
+            ) a
+                ON a.task_id = vtt.task_id
+            WHERE a.d_bool AND a.date + tk.interval_secs * INTERVAL '1 second' <= $1
+        "#,
+        now.naive_utc(),
+    )
+    .fetch(conn)
+    .map_ok(|r| (UserId(r.owner_id), TaskId(r.task_id)))
+    .try_collect()
+    .await
+    .context("querying due ticklers")?)
+}
+
+pub async fn set_comment_reminder(
+    conn: &mut sqlx::PgConnection,
+    owner: UserId,
+    reminder: CommentReminder,
+) -> Result<(), Error> {
+    let res = match reminder.remind_at {
+        Some(remind_at) => sqlx::query!(
+            "
+                INSERT INTO comment_reminders
+                SELECT id, $3 FROM events WHERE id = $1 AND owner_id = $2 AND d_type = 'add_comment'
+                ON CONFLICT (comment_id) DO UPDATE SET remind_at = EXCLUDED.remind_at
+            ",
+            reminder.comment.0,
+            owner.0,
+            remind_at.naive_utc(),
+        )
+        .execute(&mut *conn)
+        .await
+        .with_context(|| format!("setting comment reminder {reminder:?}"))?,
+        None => sqlx::query!(
+            "
+                DELETE FROM comment_reminders r
+                USING events e
+                WHERE r.comment_id = e.id AND e.id = $1 AND e.owner_id = $2
+            ",
+            reminder.comment.0,
+            owner.0,
+        )
+        .execute(&mut *conn)
+        .await
+        .with_context(|| format!("removing comment reminder {reminder:?}"))?,
+    };
+    match (reminder.remind_at, res.rows_affected()) {
+        (Some(_), 0) => Err(Error::permission_denied()), // the comment does not exist or is not owned
+        _ => Ok(()),
     }
 }
+
+/// Returns the (comment, author, task, got a reply) tuples for the comment reminders that are due
+///
+/// A comment got a reply if someone other than its author commented on the same task after it.
+pub async fn due_comment_reminders(
+    conn: &mut sqlx::PgConnection,
+    now: Time,
+) -> anyhow::Result<Vec<(EventId, UserId, TaskId, bool)>> {
+    Ok(sqlx::query!(
+        r#"
+            SELECT
+                e.id,
+                e.owner_id,
+                e.task_id,
+                EXISTS (
+                    SELECT 1
+                    FROM events c
+                    WHERE c.task_id = e.task_id
+                    AND c.d_type = 'add_comment'
+                    AND c.owner_id != e.owner_id
+                    AND c.date > e.date
+                ) AS "replied!"
+            FROM comment_reminders r
+            INNER JOIN events e
+                ON e.id = r.comment_id
+            WHERE r.remind_at <= $1
+        "#,
+        now.naive_utc(),
+    )
+    .fetch(conn)
+    .map_ok(|r| {
+        (
+            EventId(r.id),
+            UserId(r.owner_id),
+            TaskId(r.task_id),
+            r.replied,
+        )
+    })
+    .try_collect()
+    .await
+    .context("querying due comment reminders")?)
+}
+
+pub async fn remove_comment_reminder(
+    conn: &mut sqlx::PgConnection,
+    comment: EventId,
+) -> anyhow::Result<()> {
+    sqlx::query!(
+        "DELETE FROM comment_reminders WHERE comment_id = $1",
+        comment.0
+    )
+    .execute(conn)
+    .await
+    .with_context(|| format!("removing comment reminder for {comment:?}"))?;
+    Ok(())
+}
+
+/// Returns `(user, task, title)` for each open task and each user who can see it
+pub async fn open_task_titles(
+    conn: &mut sqlx::PgConnection,
+) -> anyhow::Result<Vec<(UserId, TaskId, String)>> {
+    Ok(sqlx::query!(
+        r#"
+            SELECT vtu.user_id AS "user_id!", t.id, vtt.title AS "title!"
+                FROM tasks t
+            INNER JOIN v_tasks_users vtu
+                ON vtu.task_id = t.id
+            INNER JOIN v_tasks_title vtt
+                ON vtt.task_id = t.id
+            LEFT JOIN v_tasks_archived vta
+                ON vta.task_id = t.id
+            LEFT JOIN v_tasks_done vtd
+                ON vtd.task_id = t.id
+            WHERE (vtd.done = false OR vtd.done IS NULL)
+            AND (vta.archived = false OR vta.archived IS NULL)
+        "#,
+    )
+    .fetch(conn)
+    .map_ok(|r| (UserId(r.user_id), TaskId(r.id), r.title))
+    .try_collect()
+    .await
+    .context("querying open task titles")?)
+}
+
+/// Folds the `set_event_read` events dated before `before` into the `event_reads` table
+///
+/// Only the latest of these events of each user for each comment is kept, which is enough for
+/// clients to compute the same read state: an event can only be overridden by a later one of
+/// the same user for the same comment, or by a later edit of the comment.
+pub async fn compact_read_events(
+    conn: &mut sqlx::PgConnection,
+    before: Time,
+) -> anyhow::Result<()> {
+    // Deleting and inserting in a single statement, so that no concurrently submitted event
+    // gets deleted without having been folded
+    sqlx::query!(
+        "
+            WITH deleted AS (
+                DELETE FROM events
+                WHERE d_type = 'set_event_read' AND date < $1
+                RETURNING id, owner_id, date, task_id, d_parent_id, d_bool
+            )
+            INSERT INTO event_reads (id, owner_id, date, task_id, event_id, now_read)
+            SELECT DISTINCT ON (owner_id, d_parent_id)
+                id, owner_id, date, task_id, d_parent_id, d_bool
+            FROM deleted
+            WHERE id NOT IN (SELECT id FROM event_reads)
+            ORDER BY owner_id, d_parent_id, date DESC, id DESC
+            ON CONFLICT (owner_id, event_id) DO UPDATE SET
+                id = EXCLUDED.id,
+                date = EXCLUDED.date,
+                now_read = EXCLUDED.now_read
+            WHERE EXCLUDED.date > event_reads.date
+        ",
+        before.naive_utc(),
+    )
+    .execute(&mut *conn)
+    .await
+    .with_context(|| format!("compacting read events older than {before:?}"))?;
+    Ok(())
+}
+
+/// Logs out all the sessions that were last used before `before`
+pub async fn delete_idle_sessions(
+    conn: &mut sqlx::PgConnection,
+    before: Time,
+) -> anyhow::Result<()> {
+    sqlx::query!(
+        "DELETE FROM sessions WHERE last_active < $1",
+        before.naive_utc(),
+    )
+    .execute(&mut *conn)
+    .await
+    .with_context(|| format!("deleting sessions idle since {before:?}"))?;
+    Ok(())
+}
+
+/// Replaces all the candidate duplicate pairs with `pairs`, each `(task, other, similarity)`
+/// having `task < other`
+pub async fn set_duplicate_candidates(
+    conn: &mut sqlx::PgConnection,
+    pairs: &[(TaskId, TaskId, f32)],
+) -> anyhow::Result<()> {
+    let tasks = pairs.iter().map(|p| p.0 .0).collect::<Vec<_>>();
+    let others = pairs.iter().map(|p| p.1 .0).collect::<Vec<_>>();
+    let similarities = pairs.iter().map(|p| p.2).collect::<Vec<_>>();
+    let mut transaction = conn
+        .begin()
+        .await
+        .context("creating duplicate candidates transaction")?;
+    sqlx::query!("DELETE FROM duplicate_candidates")
+        .execute(&mut transaction)
+        .await
+        .context("clearing duplicate candidates")?;
+    sqlx::query!(
+        "
+            INSERT INTO duplicate_candidates (task_id, other_id, similarity)
+            SELECT * FROM UNNEST($1::UUID[], $2::UUID[], $3::REAL[])
+        ",
+        &tasks,
+        &others,
+        &similarities,
+    )
+    .execute(&mut transaction)
+    .await
+    .context("inserting duplicate candidates")?;
+    transaction
+        .commit()
+        .await
+        .context("committing duplicate candidates transaction")?;
+    Ok(())
+}
+
+/// Returns the candidate duplicate pairs of which `user` can see both tasks, most similar first
+pub async fn fetch_duplicate_candidates(
+    conn: &mut sqlx::PgConnection,
+    user: UserId,
+) -> anyhow::Result<Vec<DuplicateCandidate>> {
+    Ok(sqlx::query!(
+        "
+            SELECT d.task_id, d.other_id, d.similarity
+                FROM duplicate_candidates d
+            INNER JOIN v_tasks_users vtu
+                ON vtu.task_id = d.task_id
+            INNER JOIN v_tasks_users ovtu
+                ON ovtu.task_id = d.other_id
+            WHERE vtu.user_id = $1
+            AND ovtu.user_id = $1
+            ORDER BY d.similarity DESC, d.task_id, d.other_id
+        ",
+        user.0,
+    )
+    .fetch(conn)
+    .map_ok(|r| DuplicateCandidate {
+        task: TaskId(r.task_id),
+        other: TaskId(r.other_id),
+        similarity: r.similarity,
+    })
+    .try_collect()
+    .await
+    .with_context(|| format!("fetching duplicate candidates for {user:?}"))?)
+}
+
+pub async fn fetch_event_provenance(
+    conn: &mut sqlx::PgConnection,
+    user: UserId,
+    events: &[EventId],
+) -> anyhow::Result<Vec<EventProvenance>> {
+    let event_ids = events.iter().map(|e| e.0).collect::<Vec<_>>();
+    Ok(sqlx::query!(
+        r#"
+            SELECT e.id, s.name AS "device?", e.app_version
+                FROM events e
+            INNER JOIN v_tasks_users vtu
+                ON vtu.task_id = e.task_id
+            LEFT JOIN sessions s
+                ON s.id = e.session_id
+            WHERE e.id = ANY($1)
+            AND vtu.user_id = $2
+        "#,
+        &event_ids,
+        user.0,
+    )
+    .fetch(conn)
+    .map_ok(|e| EventProvenance {
+        event: EventId(e.id),
+        device: e.device,
+        app_version: e.app_version,
+    })
+    .try_collect()
+    .await
+    .context("querying event provenance")?)
+}
@@ -0,0 +1,139 @@
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
+
+use anyhow::Context;
+use risuto_api::{TaskId, UserId};
+
+use crate::{
+    db,
+    extractors::{Maintenance, PgPool},
+};
+
+fn default_threshold() -> f32 {
+    0.6
+}
+
+fn default_interval_secs() -> u64 {
+    60 * 60
+}
+
+fn default_max_tasks_per_user() -> usize {
+    2000
+}
+
+/// Set in the `[duplicates]` section of the config file to periodically look for open tasks
+/// with similar titles, that users then get hinted at as possible duplicates
+#[derive(Clone, Debug, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DuplicatesConfig {
+    /// Minimum similarity between two titles, from 0 to 1, for them to be reported
+    #[serde(default = "default_threshold")]
+    pub threshold: f32,
+
+    /// Time between two runs of the detection job
+    #[serde(default = "default_interval_secs")]
+    pub interval_secs: u64,
+
+    /// Maximum number of open tasks of a user that get compared with one another, as comparing
+    /// them takes a time quadratic in their number. Users who see more only get some of them
+    /// compared.
+    #[serde(default = "default_max_tasks_per_user")]
+    pub max_tasks_per_user: usize,
+}
+
+/// Periodically recomputes the candidate duplicate pairs among open tasks, except in maintenance
+/// mode where the last computed ones stay around
+pub async fn run(db: PgPool, maintenance: Maintenance, config: DuplicatesConfig) {
+    let mut interval = tokio::time::interval(Duration::from_secs(config.interval_secs));
+    loop {
+        interval.tick().await;
+        if maintenance.is_read_only() {
+            continue;
+        }
+        if let Err(err) = run_once(&db, &config).await {
+            tracing::error!(?err, "error while looking for duplicate tasks");
+        }
+    }
+}
+
+pub(crate) async fn run_once(db: &PgPool, config: &DuplicatesConfig) -> anyhow::Result<()> {
+    let mut conn = db.acquire().await.context("acquiring db connection")?;
+    let tasks = db::open_task_titles(&mut *conn).await?;
+    let (threshold, max_tasks) = (config.threshold, config.max_tasks_per_user);
+    // This is CPU-bound, so keep it off the threads serving requests
+    let pairs = tokio::task::spawn_blocking(move || candidate_pairs(&tasks, threshold, max_tasks))
+        .await
+        .context("computing duplicate candidates")?;
+    tracing::debug!(n = pairs.len(), "found duplicate candidates");
+    db::set_duplicate_candidates(&mut *conn, &pairs).await
+}
+
+/// Trigrams of the lowercased words of `s`, each word being padded like `pg_trgm` does
+fn trigrams(s: &str) -> HashSet<[char; 3]> {
+    let mut res = HashSet::new();
+    for word in s.split(|c: char| !c.is_alphanumeric()) {
+        if word.is_empty() {
+            continue;
+        }
+        let chars = ["  ", &word.to_lowercase(), " "]
+            .concat()
+            .chars()
+            .collect::<Vec<_>>();
+        res.extend(chars.windows(3).map(|w| [w[0], w[1], w[2]]));
+    }
+    res
+}
+
+/// Jaccard index of the trigrams of two titles
+fn similarity(a: &HashSet<[char; 3]>, b: &HashSet<[char; 3]>) -> f32 {
+    let union = a.union(b).count();
+    if union == 0 {
+        return 0.;
+    }
+    a.intersection(b).count() as f32 / union as f32
+}
+
+/// Returns the `(task, other, similarity)` triples, with `task < other`, of the tasks seen by a
+/// same user whose titles are at least `threshold` similar
+///
+/// `tasks` lists the open tasks each user can see, as `(user, task, title)` triples. Only the
+/// first `max_tasks` tasks by id of each user get compared.
+fn candidate_pairs(
+    tasks: &[(UserId, TaskId, String)],
+    threshold: f32,
+    max_tasks: usize,
+) -> Vec<(TaskId, TaskId, f32)> {
+    let mut grams = HashMap::new();
+    let mut by_user = HashMap::<UserId, Vec<TaskId>>::new();
+    for (user, task, title) in tasks {
+        grams.entry(*task).or_insert_with(|| trigrams(title));
+        by_user.entry(*user).or_default().push(*task);
+    }
+    let mut res = HashMap::new();
+    for (user, tasks) in by_user.iter_mut() {
+        tasks.sort_unstable();
+        tasks.dedup();
+        if tasks.len() > max_tasks {
+            tracing::warn!(
+                ?user,
+                n = tasks.len(),
+                "too many open tasks to compare them all, only looking at some"
+            );
+            tasks.truncate(max_tasks);
+        }
+        for (i, a) in tasks.iter().enumerate() {
+            for b in &tasks[i + 1..] {
+                if res.contains_key(&(*a, *b)) {
+                    continue;
+                }
+                let s = similarity(&grams[a], &grams[b]);
+                if s >= threshold {
+                    res.insert((*a, *b), s);
+                }
+            }
+        }
+    }
+    res.into_iter().map(|((a, b), s)| (a, b, s)).collect()
+}
@@ -1,4 +1,4 @@
-use risuto_api::{Error as ApiError, Uuid};
+use risuto_api::{Error as ApiError, EventId, TagId, Uuid};
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
@@ -26,9 +26,29 @@ impl Error {
         Error::Api(ApiError::InvalidPow)
     }
 
+    pub fn read_only() -> Error {
+        Error::Api(ApiError::ReadOnly)
+    }
+
     pub fn integer_out_of_range(i: i64) -> Error {
         Error::Api(ApiError::IntegerOutOfRange(i))
     }
+
+    pub fn invalid_file(reason: impl Into<String>) -> Error {
+        Error::Api(ApiError::InvalidFile(reason.into()))
+    }
+
+    pub fn tag_not_found(tag: TagId) -> Error {
+        Error::Api(ApiError::TagNotFound(tag))
+    }
+
+    pub fn parent_comment_not_found(event: EventId) -> Error {
+        Error::Api(ApiError::ParentCommentNotFound(event))
+    }
+
+    pub fn event_not_found(event: EventId) -> Error {
+        Error::Api(ApiError::EventNotFound(event))
+    }
 }
 
 impl axum::response::IntoResponse for Error {
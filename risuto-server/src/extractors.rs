@@ -1,37 +1,191 @@
-use std::ops::{Deref, DerefMut};
+use std::{
+    collections::HashMap,
+    ops::{Deref, DerefMut},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+    time::{Duration, Instant},
+};
 
 use anyhow::Context;
 use axum::{
     async_trait,
-    extract::FromRequestParts,
-    http::{self, request},
+    extract::{FromRequestParts, State},
+    http::{self, request, Request},
+    middleware::Next,
+    response::Response,
 };
-use risuto_api::{AuthToken, UserId, Uuid};
+use risuto_api::{AuthToken, Clock, InstanceInfo, UserId, Uuid, APP_VERSION_HEADER};
 
-use crate::{db, Error, UserFeeds};
+use crate::{
+    db, ldap::LdapConfig, scanner::UploadScanner, scim::ScimConfig, search_cache::SearchCache,
+    telemetry::Telemetry, Error, UserFeeds,
+};
 
 #[derive(Clone, axum::extract::FromRef)]
 pub struct AppState {
     pub db: PgPool,
     pub feeds: UserFeeds,
+    pub search_cache: SearchCache,
+    pub telemetry: Telemetry,
     pub admin_token: Option<AuthToken>,
+    pub upload_scanner: Option<UploadScanner>,
+    pub instance: Arc<InstanceInfo>,
+    pub ldap: Option<Arc<LdapConfig>>,
+    pub scim: Option<Arc<ScimConfig>>,
+    pub maintenance: Maintenance,
+    pub clock: Clock,
+}
+
+impl AppState {
+    /// State with all optional features disabled, that get enabled by setting their fields
+    pub fn new(db: PgPool, feeds: UserFeeds, clock: Clock) -> AppState {
+        AppState {
+            db,
+            search_cache: feeds.search_cache().clone(),
+            feeds,
+            telemetry: Telemetry::default(),
+            admin_token: None,
+            upload_scanner: None,
+            instance: Arc::new(InstanceInfo::default()),
+            ldap: None,
+            scim: None,
+            maintenance: Maintenance::default(),
+            clock,
+        }
+    }
+}
+
+/// Whether the server is in maintenance mode, only serving reads and feeds
+#[derive(Clone, Default)]
+pub struct Maintenance(Arc<AtomicBool>);
+
+impl Maintenance {
+    pub fn new(read_only: bool) -> Maintenance {
+        Maintenance(Arc::new(AtomicBool::new(read_only)))
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    pub fn set_read_only(&self, read_only: bool) {
+        self.0.store(read_only, Ordering::Relaxed)
+    }
 }
+
+fn default_replica_max_connections() -> u32 {
+    8
+}
+
+fn default_max_lag_secs() -> u64 {
+    5
+}
+
+/// Set in the `[read_replica]` section of the config file to serve the read-only endpoints,
+/// like `fetch-*` and `search-tasks`, from a replica of the database
+#[derive(Clone, Debug, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ReadReplicaConfig {
+    /// Connection URL of the replica, like `DATABASE_URL` is for the primary
+    pub url: String,
+
+    #[serde(default = "default_replica_max_connections")]
+    pub max_connections: u32,
+
+    /// Time during which the reads of a session keep going to the primary after it wrote, for
+    /// it to see its own changes. This should be more than the usual replication lag.
+    #[serde(default = "default_max_lag_secs")]
+    pub max_lag_secs: u64,
+}
+
 #[derive(Clone)]
-pub struct PgPool(sqlx::PgPool);
+struct Replica {
+    pool: sqlx::PgPool,
+    max_lag: Duration,
+    /// Sessions that wrote within `max_lag`, with the time of their last write
+    recent_writes: Arc<Mutex<HashMap<AuthToken, Instant>>>,
+}
+
+impl Replica {
+    fn recent_writes(&self) -> MutexGuard<'_, HashMap<AuthToken, Instant>> {
+        self.recent_writes
+            .lock()
+            .expect("recent writes mutex got poisoned")
+    }
+}
+
+#[derive(Clone)]
+pub struct PgPool {
+    primary: sqlx::PgPool,
+    replica: Option<Replica>,
+}
 
 impl PgPool {
     pub fn new(pool: sqlx::PgPool) -> PgPool {
-        PgPool(pool)
+        PgPool {
+            primary: pool,
+            replica: None,
+        }
     }
 
+    /// Routes the reads that go through `acquire_read` to `replica`
+    pub fn with_replica(self, replica: sqlx::PgPool, max_lag: Duration) -> PgPool {
+        PgPool {
+            primary: self.primary,
+            replica: Some(Replica {
+                pool: replica,
+                max_lag,
+                recent_writes: Arc::new(Mutex::new(HashMap::new())),
+            }),
+        }
+    }
+
+    /// Returns a connection to the primary, that all writes must go through
     pub async fn acquire(&self) -> Result<PgConn, Error> {
         Ok(PgConn(
-            self.0.acquire().await.context("acquiring db connection")?,
+            self.primary
+                .acquire()
+                .await
+                .context("acquiring db connection")?,
+        ))
+    }
+
+    /// Returns a connection for read-only queries, to the replica unless `session` just wrote
+    pub async fn acquire_read(&self, session: Option<AuthToken>) -> Result<PgConn, Error> {
+        let replica = match &self.replica {
+            Some(replica) => replica,
+            None => return self.acquire().await,
+        };
+        let wrote_recently = session
+            .and_then(|s| replica.recent_writes().get(&s).copied())
+            .map(|t| t.elapsed() < replica.max_lag)
+            .unwrap_or(false);
+        if wrote_recently {
+            return self.acquire().await;
+        }
+        Ok(PgConn(
+            replica
+                .pool
+                .acquire()
+                .await
+                .context("acquiring read replica connection")?,
         ))
     }
 
+    /// Records that `session` is writing, so that its reads go to the primary for a while
+    pub fn note_write(&self, session: AuthToken) {
+        if let Some(replica) = &self.replica {
+            let now = Instant::now();
+            let mut recent_writes = replica.recent_writes();
+            recent_writes.retain(|_, t| now.duration_since(*t) < replica.max_lag);
+            recent_writes.insert(session, now);
+        }
+    }
+
     pub fn num_idle(&self) -> usize {
-        self.0.num_idle()
+        self.primary.num_idle()
     }
 }
 
@@ -63,6 +217,38 @@ impl DerefMut for PgConn {
     }
 }
 
+/// Connection for handlers that only read, see `PgPool::acquire_read`
+pub struct PgReadConn(PgConn);
+
+#[async_trait]
+impl FromRequestParts<AppState> for PgReadConn {
+    type Rejection = Error;
+
+    async fn from_request_parts(
+        req: &mut request::Parts,
+        state: &AppState,
+    ) -> Result<PgReadConn, Error> {
+        let session = PreAuth::from_request_parts(req, state).await.ok();
+        Ok(PgReadConn(
+            state.db.acquire_read(session.map(|s| s.0)).await?,
+        ))
+    }
+}
+
+impl Deref for PgReadConn {
+    type Target = sqlx::PgConnection;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl DerefMut for PgReadConn {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.0
+    }
+}
+
 pub struct PreAuth(pub AuthToken);
 
 #[async_trait]
@@ -93,6 +279,26 @@ impl<S: Sync> FromRequestParts<S> for PreAuth {
     }
 }
 
+/// Version of the client app that sent the request, if it reported it
+pub struct AppVersion(pub Option<String>);
+
+// Long enough for any sane version string, while not letting clients store arbitrary data
+const MAX_APP_VERSION_LEN: usize = 64;
+
+#[async_trait]
+impl<S: Sync> FromRequestParts<S> for AppVersion {
+    type Rejection = Error;
+
+    async fn from_request_parts(req: &mut request::Parts, _state: &S) -> Result<AppVersion, Error> {
+        Ok(AppVersion(
+            req.headers
+                .get(APP_VERSION_HEADER)
+                .and_then(|v| v.to_str().ok())
+                .map(|v| v.chars().take(MAX_APP_VERSION_LEN).collect()),
+        ))
+    }
+}
+
 pub struct Auth(pub UserId);
 
 #[async_trait]
@@ -102,7 +308,11 @@ impl FromRequestParts<AppState> for Auth {
     async fn from_request_parts(req: &mut request::Parts, state: &AppState) -> Result<Auth, Error> {
         let token = PreAuth::from_request_parts(req, state).await?.0;
         let mut conn = PgConn::from_request_parts(req, state).await?;
-        Ok(Auth(db::recover_session(&mut *conn, token).await?))
+        let user = db::recover_session(&mut *conn, token, state.clock.now()).await?;
+        if let Some(writes) = req.extensions.get::<WriteTracker>() {
+            writes.lock().session = Some(token);
+        }
+        Ok(Auth(user))
     }
 }
 
@@ -124,3 +334,84 @@ impl FromRequestParts<AppState> for AdminAuth {
         }
     }
 }
+
+/// Rejects the request if the server is in maintenance mode
+///
+/// Otherwise, if the request succeeds and its session got verified by `Auth`, `note_writes`
+/// sends the session's next reads to the primary database, for it to see its changes even if
+/// the read replica lags behind.
+pub struct Writable;
+
+#[async_trait]
+impl FromRequestParts<AppState> for Writable {
+    type Rejection = Error;
+
+    async fn from_request_parts(
+        req: &mut request::Parts,
+        state: &AppState,
+    ) -> Result<Writable, Error> {
+        if state.maintenance.is_read_only() {
+            return Err(Error::read_only());
+        }
+        if let Some(writes) = req.extensions.get::<WriteTracker>() {
+            writes.lock().writes = true;
+        }
+        Ok(Writable)
+    }
+}
+
+/// What the extractors of a request tell `note_writes`
+#[derive(Default)]
+struct RequestWrites {
+    /// Session verified by `Auth`, so that arbitrary tokens cannot fill `recent_writes`
+    session: Option<AuthToken>,
+
+    /// Whether the handler extracted `Writable`
+    writes: bool,
+}
+
+#[derive(Clone, Default)]
+struct WriteTracker(Arc<Mutex<RequestWrites>>);
+
+impl WriteTracker {
+    fn lock(&self) -> MutexGuard<'_, RequestWrites> {
+        self.0.lock().expect("write tracker mutex got poisoned")
+    }
+}
+
+/// Middleware recording the sessions whose `Writable` requests succeeded
+pub async fn note_writes<B>(
+    State(db): State<PgPool>,
+    mut req: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let tracker = WriteTracker::default();
+    req.extensions_mut().insert(tracker.clone());
+    let res = next.run(req).await;
+    let writes = tracker.lock();
+    if writes.writes && res.status().is_success() {
+        if let Some(session) = writes.session {
+            db.note_write(session);
+        }
+    }
+    drop(writes);
+    res
+}
+
+pub struct ScimAuth;
+
+#[async_trait]
+impl FromRequestParts<AppState> for ScimAuth {
+    type Rejection = Error;
+
+    async fn from_request_parts(
+        req: &mut request::Parts,
+        state: &AppState,
+    ) -> Result<ScimAuth, Error> {
+        let token = PreAuth::from_request_parts(req, state).await?.0;
+        match &state.scim {
+            Some(scim) if token.0 == scim.token => Ok(ScimAuth),
+            _ => Err(Error::permission_denied()),
+        }
+    }
+}
@@ -1,20 +1,42 @@
 use std::{collections::HashMap, iter, pin::Pin, sync::Arc};
 
-use axum::extract::ws::Message;
+use axum::extract::ws::{CloseFrame, Message};
 use futures::{channel::mpsc, select, stream, SinkExt, Stream, StreamExt};
-use risuto_api::{Action, FeedMessage, UserId, Uuid};
+use risuto_api::{
+    Action, FeedMessage, UserId, Uuid, FEED_CLOSE_PROTOCOL_ERROR, FEED_CLOSE_RESYNC, FEED_PING,
+};
 use tokio::sync::RwLock;
 
-use crate::db;
+use crate::{db, mqtt::MqttPublisher, search_cache::SearchCache};
 
 #[derive(Clone, Debug)]
 pub struct UserFeeds(
     Arc<RwLock<HashMap<UserId, HashMap<Uuid, mpsc::UnboundedSender<FeedMessage>>>>>,
+    Option<Arc<MqttPublisher>>,
+    SearchCache,
 );
 
 impl UserFeeds {
     pub fn new() -> UserFeeds {
-        UserFeeds(Arc::new(RwLock::new(HashMap::new())))
+        UserFeeds(
+            Arc::new(RwLock::new(HashMap::new())),
+            None,
+            SearchCache::default(),
+        )
+    }
+
+    /// Also publishes the relayed actions to `mqtt`
+    pub fn with_mqtt(self, mqtt: MqttPublisher) -> UserFeeds {
+        UserFeeds(self.0, Some(Arc::new(mqtt)), self.2)
+    }
+
+    /// Also marks the cached counts of the users the actions get relayed to as out of date
+    pub fn with_search_cache(self, cache: SearchCache) -> UserFeeds {
+        UserFeeds(self.0, self.1, cache)
+    }
+
+    pub fn search_cache(&self) -> &SearchCache {
+        &self.2
     }
 
     pub async fn add_for_user<W, R>(self, user: UserId, mut write: W, read: R)
@@ -53,6 +75,17 @@ impl UserFeeds {
                     return;
                 }};
             }
+            macro_rules! protocol_error {
+                () => {{
+                    let _ = write
+                        .send(Message::Close(Some(CloseFrame {
+                            code: FEED_CLOSE_PROTOCOL_ERROR,
+                            reason: "unexpected message".into(),
+                        })))
+                        .await;
+                    remove_self!();
+                }};
+            }
             macro_rules! send_message {
                 ( $msg:expr ) => {{
                     let msg: FeedMessage = $msg;
@@ -72,7 +105,16 @@ impl UserFeeds {
             loop {
                 select! {
                     msg = receiver.next() => match msg {
-                        None => remove_self!(),
+                        // Closed by `resync_user`
+                        None => {
+                            let _ = write
+                                .send(Message::Close(Some(CloseFrame {
+                                    code: FEED_CLOSE_RESYNC,
+                                    reason: "resync needed".into(),
+                                })))
+                                .await;
+                            remove_self!();
+                        }
                         Some(msg) => send_message!(msg),
                     },
                     msg = read.next() => match msg {
@@ -80,15 +122,15 @@ impl UserFeeds {
                         Some(Ok(Message::Close(_))) => remove_self!(),
                         Some(Ok(Message::Text(msg))) => {
                             // TODO: remove the client if did not receive a ping from there for a while
-                            if msg != "ping" {
+                            if msg != FEED_PING {
                                 tracing::warn!("received unexpected message from client: {msg:?}");
-                                remove_self!();
+                                protocol_error!();
                             }
                             send_message!(FeedMessage::Pong);
                         }
                         Some(msg) => {
                             tracing::warn!("received unexpected message from client: {msg:?}");
-                            remove_self!();
+                            protocol_error!();
                         }
                     },
                 }
@@ -96,35 +138,60 @@ impl UserFeeds {
         });
     }
 
-    pub async fn relay_action(&self, conn: &mut sqlx::PgConnection, a: Action) {
-        match &a {
-            Action::NewUser(_) => match db::fetch_users(conn).await {
-                Err(e) => Box::pin(stream::iter(iter::once(Err(e))))
-                    as Pin<Box<dyn Send + Stream<Item = anyhow::Result<UserId>>>>,
-                Ok(u) => Box::pin(stream::iter(u.into_iter().map(|u| Ok(u.id)))),
-            },
-            Action::NewTask(t, _) => Box::pin(stream::iter(iter::once(Ok(t.owner_id)))),
-            Action::NewEvent(e) => Box::pin(db::users_interested_by(conn, &[e.task_id.0])),
-            // TODO: make sure we actually send the whole task if a user gets access to this task it didn't have before
+    /// Closes the feeds of `user`, for their clients to fetch everything again, eg. after
+    /// they got access to a tag
+    pub async fn resync_user(&self, user: UserId) {
+        if let Some(socks) = self.0.read().await.get(&user) {
+            for s in socks.values() {
+                s.close_channel();
+            }
         }
-        // TODO: magic numbers below should be at least explained
-        .for_each_concurrent(Some(16), |u| {
-            let a = a.clone();
-            async move {
+    }
+
+    pub async fn relay_action(&self, conn: &mut sqlx::PgConnection, a: Action) {
+        self.relay_actions(conn, vec![a]).await
+    }
+
+    /// Relays `actions` to all interested users, as a single message per socket
+    pub async fn relay_actions(&self, conn: &mut sqlx::PgConnection, actions: Vec<Action>) {
+        let mut per_user = HashMap::<UserId, Vec<Action>>::new();
+        for a in actions {
+            let users = match &a {
+                Action::NewUser(_) | Action::DeletedUser(_) => match db::fetch_users(conn).await {
+                    Err(e) => Box::pin(stream::iter(iter::once(Err(e))))
+                        as Pin<Box<dyn Send + Stream<Item = anyhow::Result<UserId>>>>,
+                    Ok(u) => Box::pin(stream::iter(u.into_iter().map(|u| Ok(u.id)))),
+                },
+                Action::NewTask(t, _) => Box::pin(stream::iter(iter::once(Ok(t.owner_id)))),
+                Action::NewEvent(e) => Box::pin(db::users_interested_by(conn, &[e.task_id.0])),
+                // TODO: make sure we actually send the whole task if a user gets access to this task it didn't have before
+            }
+            .collect::<Vec<_>>()
+            .await;
+            for u in users {
                 match u {
                     Err(err) => {
                         tracing::error!(?err, "error occurred while listing interested users");
                     }
-                    Ok(u) => {
-                        if let Some(socks) = self.0.read().await.get(&u) {
-                            for s in socks.values() {
-                                let _ = s.unbounded_send(FeedMessage::Action(a.clone()));
-                            }
-                        }
-                    }
+                    Ok(u) => per_user.entry(u).or_default().push(a.clone()),
                 }
             }
-        })
-        .await;
+        }
+        self.2.invalidate(per_user.keys());
+        if let Some(mqtt) = &self.1 {
+            mqtt.publish(conn, &per_user).await;
+        }
+        let feeds = self.0.read().await;
+        for (u, mut actions) in per_user {
+            if let Some(socks) = feeds.get(&u) {
+                let msg = match actions.len() {
+                    1 => FeedMessage::Action(actions.pop().expect("checked length")),
+                    _ => FeedMessage::Actions(actions),
+                };
+                for s in socks.values() {
+                    let _ = s.unbounded_send(msg.clone());
+                }
+            }
+        }
     }
 }
@@ -7,8 +7,10 @@ use axum::{
 };
 use futures::{channel::mpsc, StreamExt};
 use risuto_api::{
-    Action, Error as ApiError, FeedMessage, NewSession, NewUser, Query, User, UserId,
+    Action, ActivityPage, AuthToken, Clock, Error as ApiError, EventId, FeedMessage, NewSession,
+    NewUser, Query, Role, SearchResults, TagId, TagPermission, User, UserId, FEED_CLOSE_RESYNC,
 };
+use risuto_client::DbDump;
 use risuto_mock_server::MockServer;
 use std::{
     cmp, collections::VecDeque, fmt::Debug, ops::RangeTo, panic::AssertUnwindSafe, path::Path,
@@ -37,7 +39,7 @@ macro_rules! do_tokio_test {
     };
 }
 
-fn build_pg_cluster(data: &Path) -> postgresfixture::cluster::Cluster {
+pub(crate) fn build_pg_cluster(data: &Path) -> postgresfixture::cluster::Cluster {
     let mut runtime = None;
     let mut best_version = None;
     for r in postgresfixture::runtime::Runtime::find_on_path() {
@@ -181,6 +183,13 @@ enum FuzzOp {
     FetchTags {
         sid: usize,
     },
+    SetTagPermission {
+        sid: usize,
+        tag: TagId,
+        uid: usize,
+        /// Index in `Role::ALL`, `None` to revoke access
+        role: Option<u8>,
+    },
     FetchSearches {
         sid: usize,
     },
@@ -188,10 +197,23 @@ enum FuzzOp {
         sid: usize,
         query: risuto_api::Query,
     },
+    ValidateAction {
+        sid: usize,
+        evt: risuto_api::Action,
+    },
     SubmitAction {
         sid: usize,
         evt: risuto_api::Action,
     },
+    CommittedEvents {
+        sid: usize,
+        events: Vec<EventId>,
+    },
+    FetchActivity {
+        sid: usize,
+        before: Option<EventId>,
+        limit: u8,
+    },
     OpenActionFeed {
         sid: usize,
     },
@@ -271,7 +293,7 @@ where
     }))
 }
 
-async fn run_on_app<Req, Resp>(
+pub(crate) async fn run_on_app<Req, Resp>(
     app: &mut Router,
     method: &str,
     uri: &str,
@@ -362,6 +384,7 @@ struct ComparativeFuzzer {
     mock: MockServer,
     app_db: PgPool,
     app_feeds: UserFeeds,
+    app_clock: Clock,
     sessions: Vec<Session>,
     feeds: Vec<Option<Feed>>,
 }
@@ -370,13 +393,22 @@ impl ComparativeFuzzer {
     async fn new(pool: PgPool) -> ComparativeFuzzer {
         let admin_token = Uuid::new_v4();
         let feeds = UserFeeds::new();
-        let app = app(pool.clone(), feeds.clone(), Some(AuthToken(admin_token))).await;
+        let clock = Clock::system();
+        let app = app(
+            AppState {
+                admin_token: Some(AuthToken(admin_token)),
+                ..AppState::new(pool.clone(), feeds.clone(), clock.clone())
+            },
+            "",
+        )
+        .await;
         ComparativeFuzzer {
             admin_token,
             app,
-            mock: MockServer::new(),
+            mock: MockServer::with_clock(clock.clone()),
             app_db: pool,
             app_feeds: feeds.clone(),
+            app_clock: clock,
             sessions: Vec::new(),
             feeds: Vec::new(),
         }
@@ -487,6 +519,40 @@ impl ComparativeFuzzer {
                     self.mock.fetch_tags(sess.mock),
                 );
             }
+            FuzzOp::SetTagPermission {
+                sid,
+                tag,
+                uid,
+                role,
+            } => {
+                let sess = self.get_session(sid).await;
+                let mut users = match self.mock.fetch_users(sess.mock) {
+                    Ok(users) => users,
+                    Err(_) => return,
+                };
+                users.sort_by_key(|u| u.id);
+                let user = match resize_int(uid, ..users.len()) {
+                    Some(uid) => users[uid].id,
+                    None => return,
+                };
+                let perm = TagPermission {
+                    tag,
+                    user,
+                    role: role.map(|r| Role::ALL[usize::from(r) % Role::ALL.len()]),
+                };
+                compare(
+                    "SetTagPermission",
+                    run_on_app(
+                        &mut self.app,
+                        "POST",
+                        "/api/set-tag-permission",
+                        Some(sess.app.0),
+                        &perm,
+                    )
+                    .await,
+                    self.mock.set_tag_permission(sess.mock, perm),
+                );
+            }
             FuzzOp::FetchSearches { sid } => {
                 let sess = self.get_session(sid).await;
                 compare(
@@ -519,6 +585,23 @@ impl ComparativeFuzzer {
                     );
                 }
             }
+            FuzzOp::ValidateAction { sid, evt } => {
+                let sess = self.get_session(sid).await;
+                if let Some(evt) = sanitize_action(evt) {
+                    compare(
+                        "ValidateAction",
+                        run_on_app(
+                            &mut self.app,
+                            "POST",
+                            "/api/validate-action",
+                            Some(sess.app.0),
+                            &evt,
+                        )
+                        .await,
+                        self.mock.validate_action(sess.mock, evt).await,
+                    );
+                }
+            }
             FuzzOp::SubmitAction { sid, evt } => {
                 let sess = self.get_session(sid).await;
                 if let Some(evt) = sanitize_action(evt) {
@@ -536,6 +619,45 @@ impl ComparativeFuzzer {
                     );
                 }
             }
+            FuzzOp::CommittedEvents { sid, events } => {
+                let sess = self.get_session(sid).await;
+                let mut app_res: Result<Vec<EventId>, _> = run_on_app(
+                    &mut self.app,
+                    "POST",
+                    "/api/committed-events",
+                    Some(sess.app.0),
+                    &events,
+                )
+                .await;
+                let mut mock_res = self.mock.committed_events(sess.mock, events);
+                // the app does not preserve order or duplicates
+                for res in [&mut app_res, &mut mock_res] {
+                    let _ = res.as_mut().map(|v| {
+                        v.sort_by_key(|e| e.0);
+                        v.dedup();
+                    });
+                }
+                compare("CommittedEvents", app_res, mock_res);
+            }
+            FuzzOp::FetchActivity { sid, before, limit } => {
+                let sess = self.get_session(sid).await;
+                let page = ActivityPage {
+                    before,
+                    limit: i64::from(limit),
+                };
+                compare(
+                    "FetchActivity",
+                    run_on_app(
+                        &mut self.app,
+                        "POST",
+                        "/api/fetch-activity",
+                        Some(sess.app.0),
+                        &page,
+                    )
+                    .await,
+                    self.mock.fetch_activity(sess.mock, page),
+                );
+            }
             FuzzOp::OpenActionFeed { sid } => {
                 let sess = self.get_session(sid).await;
                 let (app_sender, serv_receiver) = mpsc::unbounded();
@@ -547,6 +669,7 @@ impl ComparativeFuzzer {
                             serv_receiver,
                             self.app_db.clone(),
                             self.app_feeds.clone(),
+                            self.app_clock.clone(),
                         )
                         .await;
                     },
@@ -614,11 +737,76 @@ impl ComparativeFuzzer {
         }
     }
 
+    /// Builds the dump a client connecting to the app with session `tok` would start from
+    async fn app_dump(&mut self, tok: AuthToken) -> Result<DbDump, ApiError> {
+        let mut db = DbDump::stub();
+        db.owner = run_on_app(&mut self.app, "GET", "/api/whoami", Some(tok.0), &()).await?;
+        db.add_tags(run_on_app(&mut self.app, "GET", "/api/fetch-tags", Some(tok.0), &()).await?);
+        db.add_searches(
+            run_on_app(
+                &mut self.app,
+                "GET",
+                "/api/fetch-searches",
+                Some(tok.0),
+                &(),
+            )
+            .await?,
+        );
+        let res: SearchResults = run_on_app(
+            &mut self.app,
+            "POST",
+            "/api/search-tasks",
+            Some(tok.0),
+            &Query::Archived(false),
+        )
+        .await?;
+        db.add_tasks(res.tasks);
+        db.add_events_and_refresh_all(res.events);
+        Ok(db)
+    }
+
+    /// Same as `app_dump`, but on the mock
+    fn mock_dump(&self, tok: AuthToken) -> Result<DbDump, ApiError> {
+        let mut db = DbDump::stub();
+        db.owner = self.mock.whoami(tok)?;
+        db.add_tags(self.mock.fetch_tags(tok)?);
+        db.add_searches(self.mock.fetch_searches(tok)?);
+        let res = self.mock.search_tasks(tok, Query::Archived(false))?;
+        db.add_tasks(res.tasks);
+        db.add_events_and_refresh_all(res.events);
+        Ok(db)
+    }
+
+    /// Checks that each session would see the same data on the app and on the mock
+    async fn check_convergence(&mut self) {
+        for sess in self.sessions.clone() {
+            match (self.app_dump(sess.app).await, self.mock_dump(sess.mock)) {
+                (Ok(app), Ok(mock)) => {
+                    let diff = mock.diff(&app);
+                    assert!(diff.is_empty(), "app diverged from mock:\n{diff}");
+                }
+                (app, mock) => compare("Convergence", app.map(|_| ()), mock.map(|_| ())),
+            }
+        }
+    }
+
     async fn check_feeds(&mut self) {
-        for f in self.feeds.iter_mut().flat_map(|f| f.iter_mut()) {
+        for feed in self.feeds.iter_mut() {
+            let f = match feed {
+                Some(f) => f,
+                None => continue,
+            };
             let mut expected = VecDeque::new();
-            while let Ok(Some(a)) = f.mock_receiver.try_next() {
-                expected.push_back(a);
+            let mut mock_closed = false;
+            loop {
+                match f.mock_receiver.try_next() {
+                    Ok(Some(a)) => expected.push_back(a),
+                    Ok(None) => {
+                        mock_closed = true;
+                        break;
+                    }
+                    Err(_) => break,
+                }
             }
             'next_action: while !expected.is_empty() {
                 for _attempt in 0..1000 {
@@ -635,6 +823,13 @@ impl ComparativeFuzzer {
                                             expected.pop_front();
                                             continue 'next_action;
                                         }
+                                        FeedMessage::Actions(actions) => {
+                                            for a in actions {
+                                                assert_eq!(Some(&a), expected.front(), "got unexpected action in feed message:\n---\n{a:#?}\n---\nExpected messages:\n---\n{expected:#?}\n---");
+                                                expected.pop_front();
+                                            }
+                                            continue 'next_action;
+                                        }
                                         m => panic!("unexpected FeedMessage: {m:?}"),
                                     }
                                 }
@@ -645,6 +840,26 @@ impl ComparativeFuzzer {
                 }
                 panic!("did not receive expected message within allocated time. Expected message:\n---\n{:#?}\n---", expected[0]);
             }
+            if mock_closed {
+                // The mock closed the feed for the client to resync, the app must have too
+                let mut app_closed = false;
+                for _attempt in 0..1000 {
+                    match f.app_receiver.try_next() {
+                        Err(_) => tokio::task::yield_now().await, // waiting for data
+                        Ok(Some(Message::Close(Some(c)))) if c.code == FEED_CLOSE_RESYNC => {
+                            app_closed = true;
+                            break;
+                        }
+                        m => panic!("expected the feed to be closed for resync, but got {m:?}"),
+                    }
+                }
+                assert!(
+                    app_closed,
+                    "app did not close the feed within allocated time"
+                );
+                *feed = None;
+                continue;
+            }
             match f.app_receiver.try_next() {
                 Ok(Some(m)) => {
                     if let Message::Binary(m) = &m {
@@ -671,5 +886,6 @@ do_sqlx_test!(
             fuzzer.execute_fuzz_op(op).await;
             fuzzer.check_feeds().await;
         }
+        fuzzer.check_convergence().await;
     }
 );
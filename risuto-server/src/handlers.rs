@@ -1,14 +1,30 @@
 use anyhow::Context;
 use axum::{
-    extract::{ws::Message, State, WebSocketUpgrade},
+    body::Bytes,
+    extract::{
+        ws::{CloseFrame, Message},
+        Path, State, WebSocketUpgrade,
+    },
+    http::header,
+    response::{IntoResponse, Response},
     Json,
 };
 use futures::{SinkExt, StreamExt};
 use risuto_api::{
-    Action, AuthInfo, AuthToken, Event, NewSession, NewUser, Search, Tag, Task, User, UserId, Uuid,
+    validate_user_name, Action, ActivityPage, AuthInfo, AuthToken, BadgeCounts, ChangelogEntry,
+    Clock, CommentReminder, DuplicateCandidate, Event, EventId, EventProvenance, FeedProtocol,
+    InstanceInfo, MigrationStatus, NewInvite, NewSession, NewUser, Registration, Rule, RuleId,
+    Search, SearchExport, SearchId, SearchResults, Tag, TagId, TagPermission, TagSections,
+    TelemetryReport, TextReplacement, Tickler, User, UserId, Uuid, COUNTS_FRESH_HEADER,
+    FEED_AUTH_DENIED, FEED_AUTH_OK, FEED_CLOSE_PERMISSION_DENIED, INVITE_VALIDITY_DAYS,
 };
 
-use crate::{db, extractors::*, Error, UserFeeds};
+use std::sync::Arc;
+
+use crate::{
+    atom, badge, changelog, db, extractors::*, ldap::LdapConfig, rules, scanner::UploadScanner,
+    search_cache::SearchCache, telemetry::Telemetry, Error, UserFeeds,
+};
 
 pub async fn admin_create_user(
     AdminAuth: AdminAuth,
@@ -24,13 +40,224 @@ pub async fn admin_create_user(
             Action::NewUser(User {
                 id: data.id,
                 name: data.name,
+                avatar_hash: None,
+            }),
+        )
+        .await;
+    Ok(())
+}
+
+pub async fn admin_create_invite(
+    AdminAuth: AdminAuth,
+    State(clock): State<Clock>,
+    mut conn: PgConn,
+    Json(data): Json<NewInvite>,
+) -> Result<Json<Uuid>, Error> {
+    let expires_at = clock.now() + chrono::Duration::days(INVITE_VALIDITY_DAYS);
+    Ok(Json(
+        db::create_invite(&mut *conn, None, data, expires_at).await?,
+    ))
+}
+
+pub async fn create_invite(
+    Auth(user): Auth,
+    Writable: Writable,
+    State(clock): State<Clock>,
+    mut conn: PgConn,
+    Json(data): Json<NewInvite>,
+) -> Result<Json<Uuid>, Error> {
+    let expires_at = clock.now() + chrono::Duration::days(INVITE_VALIDITY_DAYS);
+    Ok(Json(
+        db::create_invite(&mut *conn, Some(user), data, expires_at).await?,
+    ))
+}
+
+pub async fn register(
+    Writable: Writable,
+    State(clock): State<Clock>,
+    State(feeds): State<UserFeeds>,
+    mut conn: PgConn,
+    Json(data): Json<Registration>,
+) -> Result<(), Error> {
+    data.user.validate()?;
+    db::register(&mut *conn, data.clone(), clock.now()).await?;
+    // The new user cannot have any feed open yet, so they will get the tags shared with them and
+    // their tasks along with everything else when their client first fetches the database
+    feeds
+        .relay_action(
+            &mut *conn,
+            Action::NewUser(User {
+                id: data.user.id,
+                name: data.user.name,
+                avatar_hash: None,
             }),
         )
         .await;
     Ok(())
 }
 
+pub async fn admin_set_tickler(
+    AdminAuth: AdminAuth,
+    mut conn: PgConn,
+    Json(data): Json<Tickler>,
+) -> Result<(), Error> {
+    data.validate()?;
+    db::set_tickler(&mut *conn, data).await
+}
+
+pub async fn admin_replace_text(
+    AdminAuth: AdminAuth,
+    State(clock): State<Clock>,
+    State(feeds): State<UserFeeds>,
+    State(pool): State<PgPool>,
+    mut conn: PgConn,
+    Json(data): Json<TextReplacement>,
+) -> Result<Json<Vec<Event>>, Error> {
+    data.validate()?;
+    if data.find.is_empty() {
+        return Ok(Json(Vec::new()));
+    }
+    let events = db::text_replacement_events(&mut *conn, &data, clock.now()).await?;
+    // Check all the events beforehand, so that a dry run reports what would make the replacement
+    // fail, and so that it never gets half-applied
+    for e in events.iter() {
+        e.validate()?;
+        let mut db = db::PostgresDb {
+            conn: &mut *conn,
+            user: e.owner_id,
+        };
+        db::check_event(&mut db, e).await?;
+    }
+    if !data.dry_run {
+        db::submit_events_as_owners(&mut *conn, &events, &db::Provenance::default()).await?;
+        let actions: Vec<_> = events.iter().cloned().map(Action::NewEvent).collect();
+        feeds.relay_actions(&mut *conn, actions.clone()).await;
+        rules::spawn_apply(pool, feeds, clock.now(), actions);
+    }
+    Ok(Json(events))
+}
+
+pub async fn admin_migration_status(
+    AdminAuth: AdminAuth,
+    mut conn: PgConn,
+) -> Result<Json<Vec<MigrationStatus>>, Error> {
+    Ok(Json(db::migration_status(&mut *conn).await?))
+}
+
+pub async fn admin_run_migrations(
+    AdminAuth: AdminAuth,
+    mut conn: PgConn,
+) -> Result<Json<Vec<MigrationStatus>>, Error> {
+    Ok(Json(db::run_migrations(&mut *conn).await?))
+}
+
+pub async fn admin_revert_migration(
+    AdminAuth: AdminAuth,
+    mut conn: PgConn,
+) -> Result<Json<Vec<MigrationStatus>>, Error> {
+    Ok(Json(db::revert_migration(&mut *conn).await?))
+}
+
+/// Shows the counters that will be sent to the telemetry endpoint, `null` if it is not configured
+pub async fn admin_telemetry(
+    AdminAuth: AdminAuth,
+    State(telemetry): State<Telemetry>,
+) -> Json<Option<TelemetryReport>> {
+    Json(telemetry.pending())
+}
+
+/// Admin endpoints stay available in maintenance mode, eg. to fix data before lifting it
+pub async fn admin_set_maintenance(
+    AdminAuth: AdminAuth,
+    State(maintenance): State<Maintenance>,
+    Json(read_only): Json<bool>,
+) {
+    tracing::info!(read_only, "setting maintenance mode");
+    maintenance.set_read_only(read_only);
+}
+
+pub async fn set_tag_permission(
+    Auth(user): Auth,
+    Writable: Writable,
+    State(feeds): State<UserFeeds>,
+    mut conn: PgConn,
+    Json(data): Json<TagPermission>,
+) -> Result<(), Error> {
+    let affected = data.user;
+    if db::set_tag_permission(&mut *conn, user, data).await? {
+        // The tag and its tasks appeared or disappeared for the affected user, which actions
+        // cannot convey
+        feeds.resync_user(affected).await;
+    }
+    Ok(())
+}
+
+pub async fn set_tag_sections(
+    Auth(user): Auth,
+    Writable: Writable,
+    State(feeds): State<UserFeeds>,
+    mut conn: PgConn,
+    Json(data): Json<TagSections>,
+) -> Result<(), Error> {
+    data.validate()?;
+    let tag = data.tag;
+    db::set_tag_sections(&mut *conn, user, data).await?;
+    // Tags are not actions, so everyone seeing the tag needs to fetch it again
+    feeds.resync_user(user).await;
+    for (shared_with, _) in db::fetch_tag_permissions(&mut *conn, user, tag).await? {
+        feeds.resync_user(shared_with).await;
+    }
+    Ok(())
+}
+
+pub async fn set_comment_reminder(
+    Auth(user): Auth,
+    Writable: Writable,
+    mut conn: PgConn,
+    Json(data): Json<CommentReminder>,
+) -> Result<(), Error> {
+    data.validate()?;
+    db::set_comment_reminder(&mut *conn, user, data).await
+}
+
+pub async fn fetch_duplicates(
+    Auth(user): Auth,
+    mut conn: PgReadConn,
+) -> Result<Json<Vec<DuplicateCandidate>>, Error> {
+    Ok(Json(
+        db::fetch_duplicate_candidates(&mut *conn, user).await?,
+    ))
+}
+
+/// Adds the feature counters of a client whose user opted in, dropping them if the instance does
+/// not collect any
+pub async fn submit_telemetry(
+    Auth(_): Auth,
+    Writable: Writable,
+    State(telemetry): State<Telemetry>,
+    State(clock): State<Clock>,
+    Json(report): Json<TelemetryReport>,
+) -> Result<(), Error> {
+    report.validate(clock.now().date_naive())?;
+    telemetry.merge(report);
+    Ok(())
+}
+
+pub async fn fetch_tag_permissions(
+    Auth(user): Auth,
+    mut conn: PgReadConn,
+    Json(tag): Json<TagId>,
+) -> Result<Json<Vec<(UserId, AuthInfo)>>, Error> {
+    Ok(Json(
+        db::fetch_tag_permissions(&mut *conn, user, tag).await?,
+    ))
+}
+
 pub async fn auth(
+    State(feeds): State<UserFeeds>,
+    State(ldap): State<Option<Arc<LdapConfig>>>,
+    State(maintenance): State<Maintenance>,
+    State(clock): State<Clock>,
     mut conn: PgConn,
     Json(data): Json<NewSession>,
 ) -> Result<Json<AuthToken>, Error> {
@@ -44,8 +271,42 @@ pub async fn auth(
     if !data.verify_pow() {
         return Err(Error::invalid_pow());
     }
+    let credentials = db::fetch_credentials(&mut *conn, &data.user)
+        .await
+        .context("logging user in")?;
+    let user = match (credentials, ldap) {
+        (Some(db::Credentials::Password(user, hash)), _) => {
+            db::verify_password(&data.password, &hash)?.then_some(user)
+        }
+        (Some(db::Credentials::Ldap(user)), Some(ldap)) => ldap
+            .authenticate(&data.user, &data.password)
+            .await?
+            .then_some(user),
+        (None, Some(ldap)) if ldap.authenticate(&data.user, &data.password).await? => {
+            // First login of this LDAP user, provision their account
+            if maintenance.is_read_only() {
+                return Err(Error::read_only());
+            }
+            validate_user_name(&data.user)?;
+            let user = UserId(Uuid::new_v4());
+            db::create_ldap_user(&mut *conn, user, data.user.clone()).await?;
+            feeds
+                .relay_action(
+                    &mut *conn,
+                    Action::NewUser(User {
+                        id: user,
+                        name: data.user.clone(),
+                        avatar_hash: None,
+                    }),
+                )
+                .await;
+            Some(user)
+        }
+        _ => None,
+    };
+    let user = user.ok_or(Error::permission_denied())?;
     Ok(Json(
-        db::login_user(&mut *conn, &data)
+        db::create_session(&mut *conn, user, &data.device, clock.now())
             .await
             .context("logging user in")?
             .ok_or(Error::permission_denied())?,
@@ -64,15 +325,113 @@ pub async fn whoami(Auth(user): Auth) -> Json<UserId> {
     Json(user)
 }
 
-pub async fn fetch_users(Auth(user): Auth, mut conn: PgConn) -> Result<Json<Vec<User>>, Error> {
+pub async fn fetch_users(Auth(user): Auth, mut conn: PgReadConn) -> Result<Json<Vec<User>>, Error> {
     Ok(Json(db::fetch_users(&mut *conn).await.with_context(
         || format!("fetching user list for {:?}", user),
     )?))
 }
 
-pub async fn fetch_tags(
+pub async fn instance_info(
+    State(instance): State<Arc<InstanceInfo>>,
+    State(maintenance): State<Maintenance>,
+    State(telemetry): State<Telemetry>,
+) -> Json<InstanceInfo> {
+    Json(InstanceInfo {
+        read_only: maintenance.is_read_only(),
+        telemetry: telemetry.is_enabled(),
+        ..InstanceInfo::clone(&instance)
+    })
+}
+
+pub async fn changelog(mut conn: PgReadConn) -> Result<Json<Vec<ChangelogEntry>>, Error> {
+    let installed = db::fetch_server_versions(&mut *conn).await?;
+    Ok(Json(changelog::entries(installed)))
+}
+
+pub async fn feed_protocol() -> Json<FeedProtocol> {
+    Json(FeedProtocol::current())
+}
+
+pub async fn set_avatar(
     Auth(user): Auth,
+    Writable: Writable,
+    State(instance): State<Arc<InstanceInfo>>,
+    State(feeds): State<UserFeeds>,
+    State(scanner): State<Option<UploadScanner>>,
+    mut conn: PgConn,
+    data: Bytes,
+) -> Result<Json<String>, Error> {
+    // The client-provided content type cannot be trusted, eg. SVG images can embed scripts
+    let content_type = sniff_image_type(&data)
+        .ok_or_else(|| Error::invalid_file("avatars must be PNG, JPEG, WebP or GIF images"))?;
+    if data.len() > instance.max_upload_size {
+        return Err(Error::invalid_file(format!(
+            "avatars must be at most {} bytes",
+            instance.max_upload_size
+        )));
+    }
+    if let Some(scanner) = scanner {
+        scanner.scan(content_type, &data).await?;
+    }
+    let hash = db::set_avatar(&mut *conn, user, content_type, &data).await?;
+    // Let the other clients know about the new avatar
+    let user_info = db::fetch_users(&mut *conn)
+        .await
+        .context("fetching user list after avatar change")?
+        .into_iter()
+        .find(|u| u.id == user);
+    if let Some(user_info) = user_info {
+        feeds
+            .relay_action(&mut *conn, Action::NewUser(user_info))
+            .await;
+    }
+    Ok(Json(hash))
+}
+
+/// Returns the content type of `data` if it is one of the image formats allowed for avatars
+fn sniff_image_type(data: &[u8]) -> Option<&'static str> {
+    if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        Some("image/png")
+    } else if data.starts_with(b"\xff\xd8\xff") {
+        Some("image/jpeg")
+    } else if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+        Some("image/gif")
+    } else if data.len() >= 12 && data.starts_with(b"RIFF") && &data[8..12] == b"WEBP" {
+        Some("image/webp")
+    } else {
+        None
+    }
+}
+
+pub async fn fetch_avatar(
+    Path(hash): Path<String>,
     mut conn: PgConn,
+) -> Result<impl IntoResponse, Error> {
+    let (content_type, data) = db::fetch_avatar(&mut *conn, &hash)
+        .await?
+        .ok_or(Error::permission_denied())?;
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type),
+            // Never let the browser interpret the avatar as anything else than an image
+            (header::X_CONTENT_TYPE_OPTIONS, String::from("nosniff")),
+            (
+                header::CONTENT_SECURITY_POLICY,
+                String::from("default-src 'none'"),
+            ),
+            // avatars are content-addressed, so they never change
+            (
+                header::CACHE_CONTROL,
+                String::from("public, max-age=31536000, immutable"),
+            ),
+        ],
+        data,
+    ))
+}
+
+pub async fn fetch_tags(
+    Auth(user): Auth,
+    mut conn: PgReadConn,
 ) -> Result<Json<Vec<(Tag, AuthInfo)>>, Error> {
     Ok(Json(
         db::fetch_tags_for_user(&mut *conn, &user)
@@ -83,7 +442,7 @@ pub async fn fetch_tags(
 
 pub async fn fetch_searches(
     Auth(user): Auth,
-    mut conn: PgConn,
+    mut conn: PgReadConn,
 ) -> Result<Json<Vec<Search>>, Error> {
     Ok(Json(
         db::fetch_searches_for_user(&mut *conn, &user)
@@ -94,56 +453,279 @@ pub async fn fetch_searches(
 
 pub async fn search_tasks(
     Auth(user): Auth,
-    mut conn: PgConn,
+    State(clock): State<Clock>,
+    mut conn: PgReadConn,
     Json(q): Json<risuto_api::Query>,
-) -> Result<Json<(Vec<Task>, Vec<Event>)>, Error> {
+) -> Result<Json<SearchResults>, Error> {
     q.validate()?;
-    Ok(Json(db::search_tasks_for_user(&mut *conn, user, &q).await?))
+    Ok(Json(
+        db::search_tasks_for_user(&mut *conn, user, &q, clock.now()).await?,
+    ))
 }
 
-pub async fn submit_action(
+pub async fn fetch_activity(
+    Auth(user): Auth,
+    mut conn: PgReadConn,
+    Json(page): Json<ActivityPage>,
+) -> Result<Json<Vec<Event>>, Error> {
+    page.validate()?;
+    Ok(Json(
+        db::fetch_activity_for_user(&mut *conn, user, &page).await?,
+    ))
+}
+
+/// Atom feed of the tasks matching a saved search, served at `/api/feeds/<feed token>.atom`
+///
+/// Feed readers usually cannot set headers, so the path holds a token that only grants reading
+/// this feed, and never a session token.
+pub async fn search_feed(
+    Path(file): Path<String>,
+    State(clock): State<Clock>,
+    mut conn: PgConn,
+) -> Result<impl IntoResponse, Error> {
+    let token = file
+        .strip_suffix(".atom")
+        .and_then(|t| Uuid::try_parse(t).ok())
+        .ok_or(Error::permission_denied())?;
+    let (user, search) = db::fetch_feed_search(&mut *conn, token).await?;
+    let res = db::search_tasks_for_user(&mut *conn, user, &search.filter, clock.now()).await?;
+    Ok((
+        [(header::CONTENT_TYPE, "application/atom+xml")],
+        atom::render(&search, &res.tasks, &res.events, clock.now()),
+    ))
+}
+
+/// Creates a new share token for the search, invalidating any previous one
+pub async fn share_search(
+    Auth(user): Auth,
+    Writable: Writable,
+    mut conn: PgConn,
+    Json(search): Json<SearchId>,
+) -> Result<Json<Uuid>, Error> {
+    let token = Uuid::new_v4();
+    db::set_search_share_token(&mut *conn, user, search, Some(token)).await?;
+    Ok(Json(token))
+}
+
+/// Creates a new feed token for the search, invalidating any previous one
+pub async fn create_search_feed(
     Auth(user): Auth,
+    Writable: Writable,
+    mut conn: PgConn,
+    Json(search): Json<SearchId>,
+) -> Result<Json<Uuid>, Error> {
+    let token = Uuid::new_v4();
+    db::set_search_feed_token(&mut *conn, user, search, Some(token)).await?;
+    Ok(Json(token))
+}
+
+pub async fn revoke_search_feed(
+    Auth(user): Auth,
+    Writable: Writable,
+    mut conn: PgConn,
+    Json(search): Json<SearchId>,
+) -> Result<(), Error> {
+    db::set_search_feed_token(&mut *conn, user, search, None).await
+}
+
+pub async fn unshare_search(
+    Auth(user): Auth,
+    Writable: Writable,
+    mut conn: PgConn,
+    Json(search): Json<SearchId>,
+) -> Result<(), Error> {
+    db::set_search_share_token(&mut *conn, user, search, None).await
+}
+
+/// Checks that a search exported by another user can be imported, without importing it
+pub async fn validate_search(
+    Auth(user): Auth,
+    mut conn: PgReadConn,
+    Json(search): Json<SearchExport>,
+) -> Result<(), Error> {
+    db::check_search_export(&mut *conn, user, &search).await
+}
+
+pub async fn import_search(
+    Auth(user): Auth,
+    Writable: Writable,
     State(feeds): State<UserFeeds>,
     mut conn: PgConn,
-    Json(a): Json<Action>,
+    Json(search): Json<SearchExport>,
+) -> Result<Json<Search>, Error> {
+    db::check_search_export(&mut *conn, user, &search).await?;
+    let search = db::create_search(&mut *conn, user, &search).await?;
+    // Searches are not actions, so the user's other sessions need to fetch them again
+    feeds.resync_user(user).await;
+    Ok(Json(search))
+}
+
+pub async fn fetch_rules(Auth(user): Auth, mut conn: PgReadConn) -> Result<Json<Vec<Rule>>, Error> {
+    Ok(Json(db::fetch_rules_for_user(&mut *conn, user).await?))
+}
+
+pub async fn set_rule(
+    Auth(user): Auth,
+    Writable: Writable,
+    mut conn: PgConn,
+    Json(rule): Json<Rule>,
+) -> Result<(), Error> {
+    db::check_rule(&mut *conn, user, &rule).await?;
+    db::set_rule(&mut *conn, &rule).await
+}
+
+pub async fn delete_rule(
+    Auth(user): Auth,
+    Writable: Writable,
+    mut conn: PgConn,
+    Json(rule): Json<RuleId>,
 ) -> Result<(), Error> {
+    db::delete_rule(&mut *conn, user, rule).await
+}
+
+/// Serves the counts of a shared search, as an svg badge or as json for `.json` paths
+pub async fn search_badge(
+    Path(file): Path<String>,
+    State(clock): State<Clock>,
+    State(search_cache): State<SearchCache>,
+    mut conn: PgConn,
+) -> Result<Response, Error> {
+    let (token, json) = match (file.strip_suffix(".svg"), file.strip_suffix(".json")) {
+        (Some(token), _) => (token, false),
+        (None, Some(token)) => (token, true),
+        (None, None) => return Err(Error::permission_denied()),
+    };
+    let token = Uuid::try_parse(token).map_err(|_| Error::permission_denied())?;
+    let (owner, search) = db::fetch_shared_search(&mut *conn, token).await?;
+    let cached = search_cache
+        .counts(&mut *conn, owner, &search, clock.now())
+        .await?;
+    if json {
+        return Ok(Json(BadgeCounts {
+            open: cached.counts.open,
+            overdue: cached.counts.overdue,
+            computed_at: cached.computed_at,
+            fresh: cached.fresh,
+        })
+        .into_response());
+    }
+    Ok((
+        [
+            (header::CONTENT_TYPE, "image/svg+xml"),
+            // badges get embedded in third-party pages, which should not keep them for long
+            (header::CACHE_CONTROL, "no-cache"),
+            (
+                header::HeaderName::from_static(COUNTS_FRESH_HEADER),
+                if cached.fresh { "true" } else { "false" },
+            ),
+        ],
+        badge::render(&search.name, &cached.counts),
+    )
+        .into_response())
+}
+
+/// Checks the parts of `a` that do not need the database, common to submission and validation
+fn check_action(user: UserId, a: &Action) -> Result<(), Error> {
     a.validate()?;
+    let owner = match a {
+        Action::NewUser(_) | Action::DeletedUser(_) => return Err(Error::permission_denied()),
+        Action::NewTask(t, _) => t.owner_id,
+        Action::NewEvent(e) => e.owner_id,
+    };
+    if user != owner {
+        return Err(Error::permission_denied());
+    }
+    Ok(())
+}
+
+/// Runs all the checks `submit_action` would, without actually submitting the action
+pub async fn validate_action(
+    Auth(user): Auth,
+    mut conn: PgConn,
+    Json(a): Json<Action>,
+) -> Result<(), Error> {
+    check_action(user, &a)?;
+    let mut db = db::PostgresDb {
+        conn: &mut *conn,
+        user,
+    };
+    match &a {
+        Action::NewUser(_) | Action::DeletedUser(_) => Err(Error::permission_denied()),
+        Action::NewTask(t, top_comm) => db::check_task(&mut db, t, top_comm).await.map(|_| ()),
+        Action::NewEvent(e) => db::check_event(&mut db, e).await,
+    }
+}
+
+pub async fn submit_action(
+    Auth(user): Auth,
+    Writable: Writable,
+    PreAuth(session): PreAuth,
+    AppVersion(app_version): AppVersion,
+    State(state): State<AppState>,
+    mut conn: PgConn,
+    Json(a): Json<Action>,
+) -> Result<(), Error> {
+    check_action(user, &a)?;
     let mut db = db::PostgresDb {
         conn: &mut *conn,
         user,
     };
+    let provenance = db::Provenance {
+        session: Some(session),
+        app_version,
+    };
     match &a {
-        Action::NewUser(_) => return Err(Error::permission_denied()),
+        Action::NewUser(_) | Action::DeletedUser(_) => return Err(Error::permission_denied()),
         Action::NewTask(t, top_comm) => {
-            if user != t.owner_id {
-                return Err(Error::permission_denied());
-            }
-            db::submit_task(&mut db, t.clone(), top_comm.clone()).await?;
+            db::submit_task(&mut db, t.clone(), top_comm.clone(), &provenance).await?;
         }
         Action::NewEvent(e) => {
-            if user != e.owner_id {
-                return Err(Error::permission_denied());
-            }
-            db::submit_event(&mut db, e.clone()).await?;
+            db::submit_event(&mut db, e.clone(), &provenance).await?;
         }
     }
-    feeds.relay_action(&mut db.conn, a).await;
+    state.telemetry.record_action(&a, state.clock.now());
+    state.feeds.relay_action(&mut db.conn, a.clone()).await;
+    rules::spawn_apply(state.db, state.feeds, state.clock.now(), vec![a]);
     Ok(())
 }
 
+pub async fn committed_events(
+    Auth(user): Auth,
+    mut conn: PgConn,
+    Json(events): Json<Vec<EventId>>,
+) -> Result<Json<Vec<EventId>>, Error> {
+    Ok(Json(db::committed_events(&mut *conn, user, &events).await?))
+}
+
+pub async fn event_provenance(
+    Auth(user): Auth,
+    mut conn: PgConn,
+    Json(events): Json<Vec<EventId>>,
+) -> Result<Json<Vec<EventProvenance>>, Error> {
+    Ok(Json(
+        db::fetch_event_provenance(&mut *conn, user, &events).await?,
+    ))
+}
+
 pub async fn action_feed(
     ws: WebSocketUpgrade,
     State(db): State<PgPool>,
     State(feeds): State<UserFeeds>,
+    State(clock): State<Clock>,
 ) -> Result<axum::response::Response, Error> {
     Ok(ws.on_upgrade(move |sock| {
         let (write, read) = sock.split();
-        action_feed_impl(write, read, db, feeds)
+        action_feed_impl(write, read, db, feeds, clock)
     }))
 }
 
-pub async fn action_feed_impl<W, R>(mut write: W, mut read: R, db: PgPool, feeds: UserFeeds)
-where
+pub async fn action_feed_impl<W, R>(
+    mut write: W,
+    mut read: R,
+    db: PgPool,
+    feeds: UserFeeds,
+    clock: Clock,
+) where
     W: 'static + Send + Unpin + futures::Sink<Message>,
     <W as futures::Sink<Message>>::Error: Send,
     R: 'static + Send + Unpin + futures::Stream<Item = Result<Message, axum::Error>>,
@@ -154,8 +736,10 @@ where
     if let Some(Ok(Message::Text(token))) = read.next().await {
         if let Ok(token) = Uuid::try_from(&token as &str) {
             if let Ok(mut conn) = db.acquire().await {
-                if let Ok(user) = db::recover_session(&mut *conn, AuthToken(token)).await {
-                    if let Ok(_) = write.send(Message::Text(String::from("ok"))).await {
+                if let Ok(user) =
+                    db::recover_session(&mut *conn, AuthToken(token), clock.now()).await
+                {
+                    if let Ok(_) = write.send(Message::Text(String::from(FEED_AUTH_OK))).await {
                         tracing::debug!(?user, "event feed websocket auth success");
                         feeds.add_for_user(user, write, read).await;
                         return;
@@ -165,7 +749,13 @@ where
         }
         tracing::debug!(?token, "event feed websocket auth failure");
         let _ = write
-            .send(Message::Text(String::from("permission denied")))
+            .send(Message::Text(String::from(FEED_AUTH_DENIED)))
+            .await;
+        let _ = write
+            .send(Message::Close(Some(CloseFrame {
+                code: FEED_CLOSE_PERMISSION_DENIED,
+                reason: FEED_AUTH_DENIED.into(),
+            })))
             .await;
     }
 }
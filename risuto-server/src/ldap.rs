@@ -0,0 +1,52 @@
+use anyhow::Context;
+use ldap3::{LdapConnAsync, LdapConnSettings};
+
+/// LDAP error code returned when the password does not match the DN
+const INVALID_CREDENTIALS: u32 = 49;
+
+/// LDAP (or Active Directory) server users can authenticate against, set in the `[ldap]`
+/// section of the config file
+///
+/// Local users keep logging in with their password, so a local user shadows any LDAP user
+/// with the same name. LDAP users get a local account without password upon first login.
+#[derive(Clone, Debug, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LdapConfig {
+    /// `ldap://` or `ldaps://` URL of the server
+    pub url: String,
+
+    /// DN to bind as, with `{user}` replaced by the escaped user name, eg.
+    /// `uid={user},ou=people,dc=example,dc=org` or `{user}@example.org` for Active Directory
+    pub bind_dn: String,
+
+    /// Upgrade `ldap://` connections with StartTLS
+    #[serde(default)]
+    pub starttls: bool,
+}
+
+impl LdapConfig {
+    /// Returns whether `password` is the LDAP password of `user`
+    pub async fn authenticate(&self, user: &str, password: &str) -> anyhow::Result<bool> {
+        // An empty password would be an unauthenticated bind, that most servers accept
+        if password.is_empty() {
+            return Ok(false);
+        }
+        let dn = self.bind_dn.replace("{user}", &ldap3::dn_escape(user));
+        let settings = LdapConnSettings::new().set_starttls(self.starttls);
+        let (conn, mut ldap) = LdapConnAsync::with_settings(settings, &self.url)
+            .await
+            .with_context(|| format!("connecting to LDAP server {:?}", self.url))?;
+        ldap3::drive!(conn);
+        let res = ldap
+            .simple_bind(&dn, password)
+            .await
+            .with_context(|| format!("binding to LDAP server as {dn:?}"))?;
+        let _ = ldap.unbind().await;
+        match res.rc {
+            0 => Ok(true),
+            INVALID_CREDENTIALS => Ok(false),
+            _ => Err(res)
+                .with_context(|| format!("unexpected answer when binding to LDAP as {dn:?}")),
+        }
+    }
+}
@@ -1,23 +1,44 @@
 use anyhow::Context;
 use axum::{
+    middleware,
     routing::{get, post},
     Router,
 };
-use risuto_api::{AuthToken, Uuid};
-use std::net::SocketAddr;
+use risuto_api::{AuthToken, Clock, Uuid, FEED_PATH};
+use std::{net::SocketAddr, sync::Arc, time::Duration};
 use tower_http::trace::TraceLayer;
 
+mod atom;
+mod badge;
+mod changelog;
+mod config;
 mod db;
+mod duplicates;
 mod error;
 mod extractors;
 mod feeds;
 mod fuzz;
 mod handlers;
+mod ldap;
+mod mqtt;
 mod query;
+mod reminders;
+mod retention;
+mod rules;
+mod sandbox;
+mod scanner;
+mod scenarios;
+mod scim;
+mod search_cache;
+mod telemetry;
+mod tickler;
 
 use crate::extractors::PgPool;
 use crate::feeds::UserFeeds;
-use crate::{error::Error, extractors::AppState};
+use crate::{
+    error::Error,
+    extractors::{note_writes, AppState},
+};
 
 #[derive(Debug, structopt::StructOpt)]
 struct Opt {
@@ -26,6 +47,26 @@ struct Opt {
     /// Note that the admin token changes on each server start.
     #[structopt(long)]
     enable_admin: bool,
+
+    /// Path under which all routes are served, eg. `/risuto` when behind a reverse proxy that
+    /// forwards `https://example.org/risuto/` to risuto-server.
+    #[structopt(long, default_value = "")]
+    base_path: String,
+
+    /// Validate uploaded files before storing them, eg. with an antivirus. This is either an
+    /// `http(s)://` URL the files get POSTed to, or a shell command that gets them on stdin and
+    /// their content type as `$1`. A success HTTP status or exit code accepts the file, and the
+    /// response body or stdout is shown to the user upon rejection.
+    #[structopt(long)]
+    upload_scanner: Option<scanner::UploadScanner>,
+
+    /// Path to a TOML configuration file, see `risuto-server/config.example.toml`
+    #[structopt(long, parse(from_os_str))]
+    config: Option<std::path::PathBuf>,
+
+    /// Do not apply pending migrations on startup, leaving it to `risuto-ctl migrate run`
+    #[structopt(long)]
+    no_migrate: bool,
 }
 
 static MIGRATOR: sqlx::migrate::Migrator = sqlx::migrate!();
@@ -36,17 +77,49 @@ async fn main() -> anyhow::Result<()> {
 
     tracing_subscriber::fmt::init();
 
+    let config = match &opt.config {
+        Some(path) => config::Config::load(path)?,
+        None => config::Config::default(),
+    };
+
     let db_url = std::env::var("DATABASE_URL").context("DATABASE_URL must be set")?;
     let db = create_sqlx_pool(&db_url).await?;
-    MIGRATOR
-        .run(
-            &mut *db
-                .acquire()
+    if !opt.no_migrate {
+        MIGRATOR
+            .run(
+                &mut *db
+                    .acquire()
+                    .await
+                    .context("acquiring conn for migration running")?,
+            )
+            .await
+            .context("running pending migrations")?;
+    }
+    let clock = Clock::system();
+    let res = db::record_server_version(
+        &mut *db
+            .acquire()
+            .await
+            .context("acquiring conn for version recording")?,
+        changelog::CURRENT_VERSION,
+        clock.now(),
+    )
+    .await;
+    if let Err(err) = res {
+        // eg. with `--no-migrate` before the migrations got run, only the changelog is affected
+        tracing::warn!(?err, "failed recording the server version");
+    }
+    let db = match &config.read_replica {
+        None => db,
+        Some(replica) => db.with_replica(
+            sqlx::postgres::PgPoolOptions::new()
+                .max_connections(replica.max_connections)
+                .connect(&replica.url)
                 .await
-                .context("acquiring conn for migration running")?,
-        )
-        .await
-        .context("running pending migrations")?;
+                .with_context(|| format!("Error opening read replica {:?}", replica.url))?,
+            Duration::from_secs(replica.max_lag_secs),
+        ),
+    };
 
     let admin_token = match opt.enable_admin {
         false => None,
@@ -58,8 +131,66 @@ async fn main() -> anyhow::Result<()> {
         }
     };
 
-    let feeds = UserFeeds::new();
-    let app = app(db, feeds, admin_token).await;
+    let base_path = opt.base_path.trim_end_matches('/');
+    anyhow::ensure!(
+        base_path.is_empty() || base_path.starts_with('/'),
+        "base path {base_path:?} must start with a '/'"
+    );
+
+    let feeds = match config.mqtt {
+        Some(mqtt) => UserFeeds::new().with_mqtt(mqtt::MqttPublisher::start(mqtt)),
+        None => UserFeeds::new(),
+    };
+    let feeds = match config.search_cache {
+        Some(search_cache) => feeds.with_search_cache(search_cache::SearchCache::new(search_cache)),
+        None => feeds,
+    };
+    let maintenance = extractors::Maintenance::new(config.instance.read_only);
+    tokio::spawn(tickler::run(
+        db.clone(),
+        feeds.clone(),
+        clock.clone(),
+        maintenance.clone(),
+    ));
+    tokio::spawn(reminders::run(
+        db.clone(),
+        feeds.clone(),
+        clock.clone(),
+        maintenance.clone(),
+    ));
+    if let Some(duplicates) = config.duplicates {
+        tokio::spawn(duplicates::run(db.clone(), maintenance.clone(), duplicates));
+    }
+    if let Some(retention) = config.retention {
+        tokio::spawn(retention::run(
+            db.clone(),
+            clock.clone(),
+            maintenance.clone(),
+            retention,
+        ));
+    }
+    tokio::spawn(search_cache::run(
+        db.clone(),
+        feeds.search_cache().clone(),
+        clock.clone(),
+        maintenance.clone(),
+    ));
+    let telemetry = config
+        .telemetry
+        .map(telemetry::Telemetry::new)
+        .unwrap_or_default();
+    tokio::spawn(telemetry::run(telemetry.clone(), clock.clone()));
+    let state = AppState {
+        admin_token,
+        upload_scanner: opt.upload_scanner.clone(),
+        instance: Arc::new(config.instance),
+        maintenance,
+        ldap: config.ldap.map(Arc::new),
+        scim: config.scim.map(Arc::new),
+        telemetry,
+        ..AppState::new(db, feeds, clock)
+    };
+    let app = app(state, base_path).await;
 
     let addr = SocketAddr::from(([127, 0, 0, 1], 3000));
     tracing::info!("listening on {}", addr);
@@ -79,26 +210,79 @@ async fn create_sqlx_pool(db_url: &str) -> anyhow::Result<PgPool> {
     ))
 }
 
-async fn app(db: PgPool, feeds: UserFeeds, admin_token: Option<AuthToken>) -> Router {
+async fn app(state: AppState, base_path: &str) -> Router {
     use handlers::*;
 
-    let state = AppState {
-        db,
-        feeds,
-        admin_token,
-    };
-
-    Router::new()
+    let router = Router::new()
         .route("/api/admin/create-user", post(admin_create_user))
+        .route("/api/admin/set-tickler", post(admin_set_tickler))
+        .route("/api/admin/create-invite", post(admin_create_invite))
+        .route("/api/admin/replace-text", post(admin_replace_text))
+        .route("/api/admin/set-maintenance", post(admin_set_maintenance))
+        .route("/api/admin/migrations", get(admin_migration_status))
+        .route("/api/admin/run-migrations", post(admin_run_migrations))
+        .route("/api/admin/revert-migration", post(admin_revert_migration))
+        .route("/api/admin/telemetry", get(admin_telemetry))
+        .route("/api/instance-info", get(instance_info))
+        .route("/api/feed-protocol", get(feed_protocol))
+        .route("/api/changelog", get(changelog))
+        .route("/api/create-invite", post(create_invite))
+        .route("/api/register", post(register))
         .route("/api/auth", post(auth))
         .route("/api/unauth", post(unauth))
         .route("/api/whoami", get(whoami))
         .route("/api/fetch-users", get(fetch_users))
+        .route("/api/set-avatar", post(set_avatar))
+        .route("/api/avatars/:hash", get(fetch_avatar))
         .route("/api/fetch-tags", get(fetch_tags))
+        .route("/api/set-tag-permission", post(set_tag_permission))
+        .route("/api/fetch-tag-permissions", post(fetch_tag_permissions))
+        .route("/api/set-tag-sections", post(set_tag_sections))
         .route("/api/fetch-searches", get(fetch_searches))
         .route("/api/search-tasks", post(search_tasks))
-        .route("/ws/action-feed", get(action_feed))
+        .route("/api/fetch-activity", post(fetch_activity))
+        .route("/api/feeds/:token", get(search_feed))
+        .route("/api/create-search-feed", post(create_search_feed))
+        .route("/api/revoke-search-feed", post(revoke_search_feed))
+        .route("/api/share-search", post(share_search))
+        .route("/api/unshare-search", post(unshare_search))
+        .route("/api/validate-search", post(validate_search))
+        .route("/api/import-search", post(import_search))
+        .route("/api/badge/:token", get(search_badge))
+        .route("/api/fetch-rules", get(fetch_rules))
+        .route("/api/set-rule", post(set_rule))
+        .route("/api/delete-rule", post(delete_rule))
+        .route(FEED_PATH, get(action_feed))
+        .route("/api/validate-action", post(validate_action))
         .route("/api/submit-action", post(submit_action))
+        .route("/api/committed-events", post(committed_events))
+        .route("/api/event-provenance", post(event_provenance))
+        .route("/api/set-comment-reminder", post(set_comment_reminder))
+        .route("/api/fetch-duplicates", get(fetch_duplicates))
+        .route("/api/submit-telemetry", post(submit_telemetry))
+        .route(
+            "/scim/v2/Users",
+            get(scim::list_users).post(scim::create_user),
+        )
+        .route(
+            "/scim/v2/Users/:id",
+            get(scim::get_user)
+                .put(scim::replace_user)
+                .patch(scim::patch_user)
+                .delete(scim::delete_user),
+        );
+    #[cfg(feature = "dev-sandbox")]
+    let router = router.route("/api/admin/seed-sandbox", post(sandbox::seed_sandbox));
+    let router = router
+        .layer(middleware::from_fn_with_state(
+            state.db.clone(),
+            note_writes,
+        ))
         .layer(TraceLayer::new_for_http())
-        .with_state(state)
+        .with_state(state);
+
+    match base_path {
+        "" => router,
+        base_path => Router::new().nest(base_path, router),
+    }
 }
@@ -0,0 +1,221 @@
+use std::{collections::HashMap, time::Duration};
+
+use risuto_api::{Action, EventData, TaskId, Time, UserId};
+use rumqttc::{AsyncClient, MqttOptions, QoS, Transport};
+
+use crate::db;
+
+/// Time to wait before reconnecting to the broker after a connection error
+const RECONNECT_DELAY: Duration = Duration::from_secs(10);
+
+/// Number of messages that can wait for the broker before new ones get dropped
+const QUEUE_CAPACITY: usize = 256;
+
+fn default_port() -> u16 {
+    1883
+}
+
+fn default_client_id() -> String {
+    String::from("risuto-server")
+}
+
+fn default_topic() -> String {
+    String::from("risuto/{user}/{event}")
+}
+
+/// Set in the `[mqtt]` section of the config file to publish a summary of what happens to
+/// tasks, eg. for home automation setups to react to tasks getting done
+#[derive(Clone, Debug, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MqttConfig {
+    pub host: String,
+
+    #[serde(default = "default_port")]
+    pub port: u16,
+
+    #[serde(default = "default_client_id")]
+    pub client_id: String,
+
+    pub username: Option<String>,
+    pub password: Option<String>,
+
+    /// Connect over TLS, checking the broker's certificate against the system roots
+    #[serde(default)]
+    pub tls: bool,
+
+    /// Topic of the messages, where `{user}` is replaced by the name of the user who can see
+    /// the task and `{event}` by the name of the event, eg. `task-done`
+    #[serde(default = "default_topic")]
+    pub topic: String,
+
+    /// Names of the events to publish, all of them if unset
+    pub events: Option<Vec<String>>,
+}
+
+/// Names of all the events that can get published
+pub const EVENT_NAMES: [&str; 6] = [
+    "task-created",
+    "task-done",
+    "task-reopened",
+    "task-archived",
+    "task-unarchived",
+    "comment-added",
+];
+
+/// Payload of the published messages
+#[derive(Debug, serde::Serialize)]
+struct Summary<'a> {
+    event: &'static str,
+    task: TaskId,
+    title: &'a str,
+    by: &'a str,
+    date: Time,
+}
+
+/// Name of the event `a` gets summarized as, if it is worth publishing
+fn event_name(a: &Action) -> Option<&'static str> {
+    match a {
+        Action::NewTask(..) => Some("task-created"),
+        Action::NewEvent(e) => match e.data {
+            EventData::SetDone(true) => Some("task-done"),
+            EventData::SetDone(false) => Some("task-reopened"),
+            EventData::SetArchived(true) => Some("task-archived"),
+            EventData::SetArchived(false) => Some("task-unarchived"),
+            EventData::AddComment { .. } => Some("comment-added"),
+            _ => None,
+        },
+        Action::NewUser(_) | Action::DeletedUser(_) => None,
+    }
+}
+
+/// Replaces the characters that have a meaning in MQTT topics
+fn topic_part(s: &str) -> String {
+    s.replace(['/', '+', '#'], "_")
+}
+
+#[derive(Debug)]
+pub struct MqttPublisher {
+    client: AsyncClient,
+    config: MqttConfig,
+}
+
+impl MqttPublisher {
+    /// Starts connecting to the broker, in a task that keeps reconnecting upon errors
+    pub fn start(config: MqttConfig) -> MqttPublisher {
+        let mut options = MqttOptions::new(&config.client_id, &config.host, config.port);
+        options.set_keep_alive(Duration::from_secs(30));
+        if let Some(username) = &config.username {
+            options.set_credentials(username, config.password.as_deref().unwrap_or(""));
+        }
+        if config.tls {
+            options.set_transport(Transport::tls_with_default_config());
+        }
+        let (client, mut eventloop) = AsyncClient::new(options, QUEUE_CAPACITY);
+        tokio::spawn(async move {
+            loop {
+                if let Err(err) = eventloop.poll().await {
+                    tracing::warn!(?err, "mqtt connection error, reconnecting");
+                    tokio::time::sleep(RECONNECT_DELAY).await;
+                }
+            }
+        });
+        MqttPublisher { client, config }
+    }
+
+    /// Publishes a summary of each of the actions, for each of the users they are relayed to
+    ///
+    /// Messages are dropped rather than waited for when the broker cannot keep up, so that
+    /// action submission never blocks on it.
+    pub async fn publish(
+        &self,
+        conn: &mut sqlx::PgConnection,
+        per_user: &HashMap<UserId, Vec<Action>>,
+    ) {
+        let enabled = |name: &str| {
+            self.config
+                .events
+                .as_ref()
+                .map(|events| events.iter().any(|e| e == name))
+                .unwrap_or(true)
+        };
+        let to_publish = per_user
+            .iter()
+            .flat_map(|(u, actions)| actions.iter().map(move |a| (u, a)))
+            .filter_map(|(u, a)| event_name(a).map(|name| (u, a, name)))
+            .filter(|(_, _, name)| enabled(name))
+            .collect::<Vec<_>>();
+        if to_publish.is_empty() {
+            return;
+        }
+
+        let mut user_ids = Vec::new();
+        for (user, a, _) in to_publish.iter() {
+            user_ids.push(user.0);
+            match a {
+                Action::NewTask(t, _) => user_ids.push(t.owner_id.0),
+                Action::NewEvent(e) => user_ids.push(e.owner_id.0),
+                Action::NewUser(_) | Action::DeletedUser(_) => (),
+            }
+        }
+        user_ids.sort_unstable();
+        user_ids.dedup();
+        let users = match db::fetch_user_names(&mut *conn, &user_ids).await {
+            Ok(users) => users,
+            Err(err) => {
+                tracing::error!(?err, "failed listing users for mqtt publishing");
+                return;
+            }
+        };
+        let tasks = to_publish
+            .iter()
+            .filter_map(|(_, a, _)| match a {
+                Action::NewEvent(e) => Some(e.task_id.0),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        let titles = match db::fetch_task_titles(&mut *conn, &tasks).await {
+            Ok(titles) => titles,
+            Err(err) => {
+                tracing::error!(?err, "failed fetching task titles for mqtt publishing");
+                return;
+            }
+        };
+
+        for (user, a, event) in to_publish {
+            let (task, title, by, date) = match a {
+                Action::NewTask(t, _) => (t.id, Some(&t.initial_title), t.owner_id, t.date),
+                Action::NewEvent(e) => (e.task_id, titles.get(&e.task_id), e.owner_id, e.date),
+                Action::NewUser(_) | Action::DeletedUser(_) => continue,
+            };
+            let (user, by) = match (users.get(user), users.get(&by)) {
+                (Some(user), Some(by)) => (user, by),
+                _ => continue,
+            };
+            let summary = Summary {
+                event,
+                task,
+                title: title.map(String::as_str).unwrap_or(""),
+                by,
+                date,
+            };
+            let payload = match serde_json::to_vec(&summary) {
+                Ok(payload) => payload,
+                Err(err) => {
+                    tracing::error!(?err, ?summary, "failed serializing mqtt message");
+                    continue;
+                }
+            };
+            let topic = self
+                .config
+                .topic
+                .replace("{user}", &topic_part(user))
+                .replace("{event}", event);
+            if let Err(err) = self
+                .client
+                .try_publish(topic, QoS::AtLeastOnce, false, payload)
+            {
+                tracing::warn!(?err, "dropping mqtt message");
+            }
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use risuto_api::{midnight_on, Query, Time, TimeQuery, Uuid};
+use risuto_api::{Query, Time, TimeQuery, Uuid};
 
 use crate::error::Error;
 
@@ -7,6 +7,7 @@ pub enum Bind {
     Uuid(Uuid),
     String(String),
     Time(Time),
+    Int(i64),
 }
 
 #[derive(Default)]
@@ -24,22 +25,30 @@ impl Sql {
     }
 }
 
-/// Assumes tables vta (v_tasks_archived), vtd(v_tasks_done), vtt (v_tasks_tags),
-/// vtit (v_tasks_is_tagged), vts (v_tasks_scheduled), vtb (v_tasks_blocked)
-/// and vtx (v_tasks_text) are available
-pub fn to_postgres(q: &Query, first_bind_idx: usize) -> Result<Sql, Error> {
+/// Assumes tables t (tasks), vta (v_tasks_archived), vtd(v_tasks_done), vtt (v_tasks_tags),
+/// vtit (v_tasks_is_tagged), vts (v_tasks_scheduled), vtb (v_tasks_blocked),
+/// vtp (v_tasks_priority), vtle (v_tasks_last_event) and vtx (v_tasks_text) are available,
+/// and that the first bind is the id of the user running the query
+///
+/// Time-relative conditions are evaluated as if the current time were `now`.
+pub fn to_postgres(q: &Query, now: &Time, first_bind_idx: usize) -> Result<Sql, Error> {
     let mut res = Default::default();
-    add_to_postgres(q, first_bind_idx, &mut res)?;
+    add_to_postgres(q, now, first_bind_idx, &mut res)?;
     Ok(res)
 }
 
-fn add_to_postgres(q: &Query, first_bind_idx: usize, res: &mut Sql) -> Result<(), Error> {
+fn add_to_postgres(
+    q: &Query,
+    now: &Time,
+    first_bind_idx: usize,
+    res: &mut Sql,
+) -> Result<(), Error> {
     match q {
         Query::Any(queries) => {
             res.where_clause.push_str("(false");
             for q in queries {
                 res.where_clause.push_str(" OR ");
-                add_to_postgres(q, first_bind_idx, &mut *res)?;
+                add_to_postgres(q, now, first_bind_idx, &mut *res)?;
             }
             res.where_clause.push(')');
         }
@@ -47,13 +56,13 @@ fn add_to_postgres(q: &Query, first_bind_idx: usize, res: &mut Sql) -> Result<()
             res.where_clause.push_str("(true");
             for q in queries {
                 res.where_clause.push_str(" AND ");
-                add_to_postgres(q, first_bind_idx, &mut *res)?;
+                add_to_postgres(q, now, first_bind_idx, &mut *res)?;
             }
             res.where_clause.push(')');
         }
         Query::Not(q) => {
             res.where_clause.push_str("NOT ");
-            add_to_postgres(q, first_bind_idx, &mut *res)?;
+            add_to_postgres(q, now, first_bind_idx, &mut *res)?;
         }
         Query::Archived(true) => {
             res.where_clause.push_str("vta.archived = true");
@@ -87,22 +96,57 @@ fn add_to_postgres(q: &Query, first_bind_idx: usize, res: &mut Sql) -> Result<()
             res.where_clause
                 .push_str("(vtit.has_tag = false OR vtit.has_tag IS NULL)");
         }
+        Query::TagArchived(archived) => {
+            let idx = res.add_bind(first_bind_idx, Bind::Bool(*archived));
+            res.where_clause.push_str(&format!(
+                "(EXISTS (
+                    SELECT 1
+                    FROM v_tasks_tags vtta
+                    INNER JOIN tags ta
+                        ON ta.id = vtta.tag_id
+                    INNER JOIN v_tags_users vtua
+                        ON vtua.tag_id = vtta.tag_id
+                    WHERE vtta.task_id = t.id
+                    AND vtta.is_in = true
+                    AND ta.archived = true
+                    AND vtua.user_id = $1
+                ) = ${idx})"
+            ));
+        }
         Query::ScheduledForBefore(date) => {
-            let idx = res.add_bind(first_bind_idx, timeq_to_bind(date)?);
+            let idx = res.add_bind(first_bind_idx, timeq_to_bind(date, now)?);
             res.where_clause.push_str(&format!("(vts.time <= ${idx})"));
         }
         Query::ScheduledForAfter(date) => {
-            let idx = res.add_bind(first_bind_idx, timeq_to_bind(date)?);
+            let idx = res.add_bind(first_bind_idx, timeq_to_bind(date, now)?);
             res.where_clause.push_str(&format!("(vts.time >= ${idx})"));
         }
         Query::BlockedUntilAtMost(date) => {
-            let idx = res.add_bind(first_bind_idx, timeq_to_bind(date)?);
+            let idx = res.add_bind(first_bind_idx, timeq_to_bind(date, now)?);
             res.where_clause.push_str(&format!("(vtb.time <= ${idx})"));
         }
         Query::BlockedUntilAtLeast(date) => {
-            let idx = res.add_bind(first_bind_idx, timeq_to_bind(date)?);
+            let idx = res.add_bind(first_bind_idx, timeq_to_bind(date, now)?);
             res.where_clause.push_str(&format!("(vtb.time >= ${idx})"));
         }
+        Query::LastEventBefore(date) => {
+            let idx = res.add_bind(first_bind_idx, timeq_to_bind(date, now)?);
+            res.where_clause.push_str(&format!("(vtle.date < ${idx})"));
+        }
+        Query::LastEventAfter(date) => {
+            let idx = res.add_bind(first_bind_idx, timeq_to_bind(date, now)?);
+            res.where_clause.push_str(&format!("(vtle.date >= ${idx})"));
+        }
+        Query::PriorityAtLeast(p) => {
+            let idx = res.add_bind(first_bind_idx, Bind::Int(p.level()));
+            res.where_clause
+                .push_str(&format!("(vtp.priority <= ${idx})"));
+        }
+        Query::PriorityAtMost(p) => {
+            let idx = res.add_bind(first_bind_idx, Bind::Int(p.level()));
+            res.where_clause
+                .push_str(&format!("(vtp.priority >= ${idx})"));
+        }
         Query::Phrase(t) => {
             let idx = res.add_bind(first_bind_idx, Bind::String(t.clone()));
             res.where_clause
@@ -112,24 +156,6 @@ fn add_to_postgres(q: &Query, first_bind_idx: usize, res: &mut Sql) -> Result<()
     Ok(())
 }
 
-fn timeq_to_bind(q: &TimeQuery) -> Result<Bind, Error> {
-    Ok(Bind::Time(match q {
-        TimeQuery::Absolute(t) => *t,
-        TimeQuery::DayRelative {
-            timezone,
-            day_offset,
-        } => {
-            let now = chrono::Utc::now().with_timezone(timezone);
-            let date = now.date_naive();
-            let date = match *day_offset > 0 {
-                true => date
-                    .checked_add_days(chrono::naive::Days::new(*day_offset as u64))
-                    .ok_or_else(|| Error::integer_out_of_range(*day_offset))?,
-                false => date
-                    .checked_sub_days(chrono::naive::Days::new((-day_offset) as u64))
-                    .ok_or_else(|| Error::integer_out_of_range(*day_offset))?,
-            };
-            midnight_on(date, timezone).with_timezone(&chrono::Utc)
-        }
-    }))
+fn timeq_to_bind(q: &TimeQuery, now: &Time) -> Result<Bind, Error> {
+    Ok(Bind::Time(q.eval_at(now)?))
 }
@@ -0,0 +1,80 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use risuto_api::{Action, Clock, Event, EventData, TaskId, Time, UserId};
+
+use crate::{
+    db::{self, PostgresDb, Provenance},
+    extractors::{Maintenance, PgPool},
+    rules, UserFeeds,
+};
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Periodically brings back the tasks whose comment reminders are due and got no reply, by
+/// scheduling them for now for the comment's author
+///
+/// Reminders that come due in maintenance mode wait for it to be lifted.
+pub async fn run(db: PgPool, feeds: UserFeeds, clock: Clock, maintenance: Maintenance) {
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+    loop {
+        interval.tick().await;
+        if maintenance.is_read_only() {
+            continue;
+        }
+        if let Err(err) = run_once(&db, &feeds, &clock).await {
+            tracing::error!(?err, "error while running comment reminders");
+        }
+    }
+}
+
+/// Handles each due reminder separately, so that a failing one does not block the others
+pub(crate) async fn run_once(db: &PgPool, feeds: &UserFeeds, clock: &Clock) -> anyhow::Result<()> {
+    let mut conn = db.acquire().await.context("acquiring db connection")?;
+    let now = clock.now();
+    for (comment, owner, task, replied) in db::due_comment_reminders(&mut *conn, now).await? {
+        if !replied {
+            tracing::debug!(
+                ?comment,
+                ?task,
+                "comment got no reply, reminding its author"
+            );
+            if let Err(err) = remind(&mut *conn, feeds, now, owner, task).await {
+                tracing::error!(?err, ?comment, "failed reminding comment author");
+            }
+        }
+        // Failed reminders are dropped too, lest they fail again on each run
+        if let Err(err) = db::remove_comment_reminder(&mut *conn, comment).await {
+            tracing::error!(?err, ?comment, "failed removing comment reminder");
+        }
+    }
+    Ok(())
+}
+
+/// Schedules `task` for now for `owner`
+async fn remind(
+    conn: &mut sqlx::PgConnection,
+    feeds: &UserFeeds,
+    now: Time,
+    owner: UserId,
+    task: TaskId,
+) -> anyhow::Result<()> {
+    let e = Event::at(owner, task, now, EventData::ScheduleFor(Some(now)));
+    let mut pg = PostgresDb {
+        conn: &mut *conn,
+        user: owner,
+    };
+    // The author may have lost access to the task since commenting
+    if e.is_authorized(&mut pg).await? {
+        db::submit_event(&mut pg, e.clone(), &Provenance::default())
+            .await
+            .with_context(|| format!("submitting reminder event {e:?}"))?;
+        let a = Action::NewEvent(e);
+        feeds.relay_action(&mut *conn, a.clone()).await;
+        // The reminder got sent anyway, so failing rules only get logged
+        if let Err(err) = rules::apply(&mut *conn, feeds, now, vec![a]).await {
+            tracing::error!(?err, ?task, "error while applying rules after reminder");
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,65 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use risuto_api::Clock;
+
+use crate::{
+    db,
+    extractors::{Maintenance, PgPool},
+};
+
+fn default_read_events_days() -> u64 {
+    30
+}
+
+fn default_interval_secs() -> u64 {
+    24 * 60 * 60
+}
+
+/// Set in the `[retention]` section of the config file to periodically compact the events that
+/// can be derived again from a smaller state, like the ones marking comments as read, and to
+/// expire idle sessions
+#[derive(Clone, Debug, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RetentionConfig {
+    /// Age after which the events marking comments as (un)read get folded into the read state
+    #[serde(default = "default_read_events_days")]
+    pub read_events_days: u64,
+
+    /// Time without any request after which a session gets logged out, never if unset
+    #[serde(default)]
+    pub idle_sessions_days: Option<u64>,
+
+    /// Time between two runs of the compaction job
+    #[serde(default = "default_interval_secs")]
+    pub interval_secs: u64,
+}
+
+/// Periodically applies the retention policy of `config`, except in maintenance mode
+pub async fn run(db: PgPool, clock: Clock, maintenance: Maintenance, config: RetentionConfig) {
+    let mut interval = tokio::time::interval(Duration::from_secs(config.interval_secs));
+    loop {
+        interval.tick().await;
+        if maintenance.is_read_only() {
+            continue;
+        }
+        if let Err(err) = run_once(&db, &clock, &config).await {
+            tracing::error!(?err, "error while applying the retention policy");
+        }
+    }
+}
+
+pub(crate) async fn run_once(
+    db: &PgPool,
+    clock: &Clock,
+    config: &RetentionConfig,
+) -> anyhow::Result<()> {
+    let mut conn = db.acquire().await.context("acquiring db connection")?;
+    let before = clock.now() - chrono::Duration::days(config.read_events_days as i64);
+    db::compact_read_events(&mut *conn, before).await?;
+    if let Some(days) = config.idle_sessions_days {
+        let before = clock.now() - chrono::Duration::days(days as i64);
+        db::delete_idle_sessions(&mut *conn, before).await?;
+    }
+    Ok(())
+}
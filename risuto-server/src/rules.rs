@@ -0,0 +1,115 @@
+use std::collections::HashSet;
+
+use anyhow::Context;
+use risuto_api::{
+    Action, Event, EventData, Query, Rule, RuleAction, RuleTrigger, TaskId, Time,
+    MAX_RULE_CHAIN_DEPTH,
+};
+
+use crate::{
+    db::{self, PostgresDb, Provenance},
+    extractors::PgPool,
+    UserFeeds,
+};
+
+/// Runs `apply` in the background, for requests not to wait on the rules their actions trigger
+pub fn spawn_apply(db: PgPool, feeds: UserFeeds, now: Time, actions: Vec<Action>) {
+    tokio::spawn(async move {
+        let res = async {
+            let mut conn = db.acquire().await.context("acquiring db connection")?;
+            apply(&mut *conn, &feeds, now, actions).await
+        };
+        // The actions got committed anyway, so failing rules only get logged
+        if let Err(err) = res.await {
+            tracing::error!(?err, "error while applying rules");
+        }
+    });
+}
+
+/// Applies the rules triggered by `actions`, that just got committed, then the rules triggered
+/// by what these rules did, up to `MAX_RULE_CHAIN_DEPTH` times
+///
+/// Each rule applies at most once per task over the whole chain, so that rules triggering one
+/// another cannot loop.
+pub async fn apply(
+    conn: &mut sqlx::PgConnection,
+    feeds: &UserFeeds,
+    now: Time,
+    actions: Vec<Action>,
+) -> anyhow::Result<()> {
+    let mut applied = HashSet::new();
+    let mut pending = actions;
+    for _ in 0..MAX_RULE_CHAIN_DEPTH {
+        let mut produced = Vec::new();
+        for a in pending {
+            let (trigger, task) = match RuleTrigger::of(&a) {
+                Some(t) => t,
+                None => continue,
+            };
+            for rule in db::fetch_rules_for_task(&mut *conn, task).await? {
+                if rule.trigger != trigger || !applied.insert((rule.id, task)) {
+                    continue;
+                }
+                if let Some(e) = apply_rule(&mut *conn, &rule, task, now)
+                    .await
+                    .with_context(|| format!("applying rule {rule:?} to {task:?}"))?
+                {
+                    let a = Action::NewEvent(e);
+                    feeds.relay_action(&mut *conn, a.clone()).await;
+                    produced.push(a);
+                }
+            }
+        }
+        pending = produced;
+    }
+    if !pending.is_empty() {
+        tracing::warn!(
+            ?pending,
+            "stopped applying rules that kept triggering each other"
+        );
+    }
+    Ok(())
+}
+
+/// Submits the event `rule` results in, if `task` matches it and its owner is allowed to
+async fn apply_rule(
+    conn: &mut sqlx::PgConnection,
+    rule: &Rule,
+    task: TaskId,
+    now: Time,
+) -> anyhow::Result<Option<Event>> {
+    let (filter, data) = match &rule.action {
+        // Tasks already in the tag keep their place in it
+        RuleAction::AddTag(tag) => (
+            Query::All(vec![
+                rule.filter.clone(),
+                Query::Not(Box::new(Query::tag(*tag))),
+            ]),
+            EventData::AddTag {
+                tag: *tag,
+                prio: db::top_prio_in_tag(&mut *conn, *tag).await?,
+                backlog: false,
+            },
+        ),
+        RuleAction::ScheduleFor(t) => (
+            rule.filter.clone(),
+            EventData::ScheduleFor(Some(t.eval_at(&now)?)),
+        ),
+        RuleAction::Notify => (rule.filter.clone(), EventData::ScheduleFor(Some(now))),
+    };
+    if !db::task_matches_query(&mut *conn, rule.owner_id, task, &filter, now).await? {
+        return Ok(None);
+    }
+    let e = Event::at(rule.owner_id, task, now, data);
+    let mut pg = PostgresDb {
+        conn: &mut *conn,
+        user: rule.owner_id,
+    };
+    // The owner may have lost the permissions the rule needs since creating it
+    if !e.is_authorized(&mut pg).await? {
+        return Ok(None);
+    }
+    tracing::debug!(rule = ?rule.id, ?task, "applying rule");
+    db::submit_event(&mut pg, e.clone(), &Provenance::default()).await?;
+    Ok(Some(e))
+}
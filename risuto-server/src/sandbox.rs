@@ -0,0 +1,269 @@
+#![cfg(feature = "dev-sandbox")]
+
+//! Seeding of a realistic dataset into a fresh server, to demo the web app reproducibly
+//!
+//! This is adapted from `tests/src/bin/generate-test-data.rs`, but instead of writing SQL it
+//! goes through the same code paths as the API, so that the permission checks and feeds behave
+//! exactly like they would for real users.
+
+use axum::{extract::State, Json};
+use chrono::Duration;
+use lipsum::lipsum_words_from_seed;
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+use risuto_api::{
+    Action, Clock, Event, EventData, EventId, NewUser, Priority, Role, Tag, TagId, TagPermission,
+    Task, TaskId, Time, User, UserId, Uuid,
+};
+
+use crate::{db, extractors::*, Error, UserFeeds};
+
+const USER_NAMES: [&str; 3] = ["alice", "bob", "carol"];
+const TAG_NAMES: [&str; 8] = [
+    "work", "home", "errands", "reading", "garden", "travel", "finances", "health",
+];
+const TAGS_PER_USER: usize = 3;
+const TASKS_PER_USER: usize = 20;
+const EVENTS_PER_TASK: usize = 5;
+
+const TASK_TITLE_LEN: usize = 6;
+const COMMENT_WORD_COUNT: usize = 10;
+const SECTIONS: [&str; 3] = ["Doing", "Next", "Later"];
+
+#[derive(Debug, serde::Deserialize)]
+pub struct SeedSandbox {
+    /// Seed of the generated data, the same seed always generating the same dataset
+    #[serde(default)]
+    pub seed: u64,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct SandboxUser {
+    pub name: String,
+    pub password: String,
+}
+
+struct SeededTag {
+    id: TagId,
+    owner: UserId,
+    shared_with: Option<UserId>,
+    has_sections: bool,
+    next_prio: i64,
+}
+
+struct SeededTask {
+    id: TaskId,
+    owner: UserId,
+    /// Index of the tag the task was filed into
+    tag: usize,
+    comments: Vec<(EventId, UserId)>,
+    date: Time,
+}
+
+fn gen_uuid(rng: &mut StdRng) -> Uuid {
+    Uuid::from_u128(rng.gen())
+}
+
+fn gen_password(rng: &mut StdRng) -> String {
+    let mut res = lipsum_words_from_seed(2, rng.gen());
+    res.retain(|c| c.is_ascii_alphanumeric());
+    res.to_lowercase()
+}
+
+fn gen_task_title(rng: &mut StdRng) -> String {
+    let mut res = lipsum_words_from_seed(TASK_TITLE_LEN, rng.gen());
+    res.pop(); // remove the ending '.'
+    res
+}
+
+fn gen_comment_text(rng: &mut StdRng) -> String {
+    lipsum_words_from_seed(COMMENT_WORD_COUNT, rng.gen())
+}
+
+/// Seeds users, tags, tasks and their history, returning the credentials of the created users
+///
+/// Seeding the same server twice fails, as the users already exist.
+pub async fn seed_sandbox(
+    AdminAuth: AdminAuth,
+    State(clock): State<Clock>,
+    State(feeds): State<UserFeeds>,
+    mut conn: PgConn,
+    Json(data): Json<SeedSandbox>,
+) -> Result<Json<Vec<SandboxUser>>, Error> {
+    let mut rng = StdRng::seed_from_u64(data.seed);
+    let now = clock.now();
+    let provenance = db::Provenance::default();
+
+    // Generate users
+    let mut users = Vec::new();
+    let mut credentials = Vec::new();
+    for name in USER_NAMES {
+        let id = UserId(gen_uuid(&mut rng));
+        let password = gen_password(&mut rng);
+        let user = NewUser::new(id, String::from(name), password.clone());
+        user.validate()?;
+        db::create_user(&mut *conn, user).await?;
+        feeds
+            .relay_action(
+                &mut *conn,
+                Action::NewUser(User {
+                    id,
+                    name: String::from(name),
+                    avatar_hash: None,
+                }),
+            )
+            .await;
+        users.push(id);
+        credentials.push(SandboxUser {
+            name: String::from(name),
+            password,
+        });
+    }
+
+    // Generate tags, each user sharing their first tag with the next user
+    let mut tags = Vec::new();
+    for (i, &owner) in users.iter().enumerate() {
+        let names = TAG_NAMES.choose_multiple(&mut rng, TAGS_PER_USER);
+        for (j, name) in names.enumerate() {
+            let has_sections = rng.gen::<bool>();
+            let tag = Tag {
+                id: TagId(gen_uuid(&mut rng)),
+                owner_id: owner,
+                name: String::from(*name),
+                archived: j == TAGS_PER_USER - 1 && rng.gen(),
+                sections: match has_sections {
+                    true => SECTIONS.iter().map(|s| String::from(*s)).collect(),
+                    false => Vec::new(),
+                },
+            };
+            db::create_tag(&mut *conn, tag.clone()).await?;
+            let shared_with = (j == 0).then(|| users[(i + 1) % users.len()]);
+            if let Some(user) = shared_with {
+                let perm = TagPermission {
+                    tag: tag.id,
+                    user,
+                    role: Some(Role::Editor),
+                };
+                db::set_tag_permission(&mut *conn, owner, perm).await?;
+            }
+            tags.push(SeededTag {
+                id: tag.id,
+                owner,
+                shared_with,
+                has_sections,
+                next_prio: 0,
+            });
+        }
+    }
+
+    // Generate tasks, each starting in one of their owner's tags
+    let mut actions = Vec::new();
+    let mut tasks = Vec::new();
+    for &owner in users.iter() {
+        let owned_tags = (0..tags.len())
+            .filter(|&t| tags[t].owner == owner)
+            .collect::<Vec<_>>();
+        for _ in 0..TASKS_PER_USER {
+            let date = now - Duration::minutes(rng.gen_range(7 * 24 * 60..60 * 24 * 60));
+            let task = Task {
+                id: TaskId(gen_uuid(&mut rng)),
+                owner_id: owner,
+                date,
+                initial_title: gen_task_title(&mut rng),
+                top_comment_id: EventId(gen_uuid(&mut rng)),
+            };
+            let top_comm = gen_comment_text(&mut rng);
+            let mut pg = db::PostgresDb {
+                conn: &mut *conn,
+                user: owner,
+            };
+            db::submit_task(&mut pg, task.clone(), top_comm.clone(), &provenance).await?;
+            actions.push(Action::NewTask(task.clone(), top_comm));
+            tasks.push(SeededTask {
+                id: task.id,
+                owner,
+                tag: *owned_tags.choose(&mut rng).unwrap(),
+                comments: vec![(task.top_comment_id, owner)],
+                date,
+            });
+        }
+    }
+
+    // Finally, generate the history of each task, in chronological order
+    for task in tasks.iter_mut() {
+        let mut date = task.date;
+        for i in 0..=EVENTS_PER_TASK {
+            // Events of a task are at most a few days apart, so that they all are in the past
+            date += Duration::minutes(rng.gen_range(1..24 * 60));
+            let mut owner = task.owner;
+            let data = match (i, rng.gen_range(0..10)) {
+                (0, _) => {
+                    let tag = &mut tags[task.tag];
+                    tag.next_prio += 1;
+                    EventData::AddTag {
+                        tag: tag.id,
+                        prio: tag.next_prio,
+                        backlog: rng.gen_ratio(1, 4),
+                    }
+                }
+                (_, 0) => EventData::SetTitle(gen_task_title(&mut rng)),
+                (_, 1) => EventData::SetDone(rng.gen()),
+                (_, 2) => EventData::SetPriority(Priority::ALL.choose(&mut rng).copied()),
+                (_, 3) => EventData::ScheduleFor(Some(now + Duration::days(rng.gen_range(-3..14)))),
+                (_, 4) => EventData::BlockedUntil(Some(now + Duration::days(rng.gen_range(1..7)))),
+                (_, 5) => {
+                    let tag = &tags[task.tag];
+                    match tag.has_sections {
+                        true => EventData::SetSection {
+                            tag: tag.id,
+                            section: SECTIONS.choose(&mut rng).map(|s| String::from(*s)),
+                        },
+                        false => EventData::SetPriority(Priority::ALL.choose(&mut rng).copied()),
+                    }
+                }
+                (_, 6) => {
+                    let (comment_id, comment_owner) = *task.comments.choose(&mut rng).unwrap();
+                    owner = comment_owner;
+                    EventData::EditComment {
+                        text: gen_comment_text(&mut rng),
+                        comment_id,
+                    }
+                }
+                _ => {
+                    // Users the tag is shared with chime in on the discussion too
+                    if let Some(user) = tags[task.tag].shared_with {
+                        if rng.gen() {
+                            owner = user;
+                        }
+                    }
+                    let parent_id = match rng.gen_ratio(1, 3) {
+                        true => task.comments.choose(&mut rng).map(|c| c.0),
+                        false => None,
+                    };
+                    EventData::AddComment {
+                        text: gen_comment_text(&mut rng),
+                        parent_id,
+                    }
+                }
+            };
+            let e = Event {
+                id: EventId(gen_uuid(&mut rng)),
+                owner_id: owner,
+                date,
+                task_id: task.id,
+                data,
+            };
+            let mut pg = db::PostgresDb {
+                conn: &mut *conn,
+                user: owner,
+            };
+            db::submit_event(&mut pg, e.clone(), &provenance).await?;
+            if let EventData::AddComment { .. } = e.data {
+                task.comments.push((e.id, owner));
+            }
+            actions.push(Action::NewEvent(e));
+        }
+    }
+    feeds.relay_actions(&mut *conn, actions).await;
+
+    Ok(Json(credentials))
+}
@@ -0,0 +1,99 @@
+use std::{process::Stdio, str::FromStr, sync::Arc, time::Duration};
+
+use anyhow::Context;
+use tokio::io::AsyncWriteExt;
+
+use crate::Error;
+
+/// Time the scanner has to give its verdict, after which the upload fails
+const SCAN_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// External validator (eg. an antivirus) that uploaded files go through before being stored
+#[derive(Clone, Debug)]
+pub enum UploadScanner {
+    /// Command run through `sh -c` with the file on stdin and its content type as `$1`.
+    /// It accepts the file by exiting successfully, and otherwise explains why on stdout.
+    Command(Arc<String>),
+
+    /// URL the file is POSTed to. It accepts the file by answering with a success status,
+    /// and otherwise explains why in the response body.
+    Http(Arc<String>),
+}
+
+impl FromStr for UploadScanner {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<UploadScanner, Self::Err> {
+        Ok(
+            match s.starts_with("http://") || s.starts_with("https://") {
+                true => UploadScanner::Http(Arc::new(String::from(s))),
+                false => UploadScanner::Command(Arc::new(String::from(s))),
+            },
+        )
+    }
+}
+
+impl UploadScanner {
+    /// Returns `Error::InvalidFile` if the scanner rejected the file
+    pub async fn scan(&self, content_type: &str, data: &[u8]) -> Result<(), Error> {
+        let (accepted, reason) = match self {
+            UploadScanner::Command(cmd) => {
+                let mut child = tokio::process::Command::new("sh")
+                    .arg("-c")
+                    .arg(&**cmd)
+                    .arg("risuto-upload-scanner")
+                    .arg(content_type)
+                    .stdin(Stdio::piped())
+                    .stdout(Stdio::piped())
+                    .kill_on_drop(true)
+                    .spawn()
+                    .with_context(|| format!("spawning upload scanner {cmd:?}"))?;
+                let mut stdin = child.stdin.take().expect("stdin was piped");
+                let write = async move {
+                    // The scanner may reject the file without reading it all, so ignore write
+                    // errors. Dropping stdin then lets it know the file is complete.
+                    let _ = stdin.write_all(data).await;
+                };
+                // Read stdout while writing, lest the scanner blocks on a full stdout pipe
+                let (output, ()) = tokio::time::timeout(SCAN_TIMEOUT, async {
+                    tokio::join!(child.wait_with_output(), write)
+                })
+                .await
+                .with_context(|| format!("upload scanner {cmd:?} timed out"))?;
+                let output =
+                    output.with_context(|| format!("waiting for upload scanner {cmd:?}"))?;
+                (
+                    output.status.success(),
+                    String::from_utf8_lossy(&output.stdout).into_owned(),
+                )
+            }
+            UploadScanner::Http(url) => {
+                let resp = reqwest::Client::new()
+                    .post(&**url)
+                    .header(reqwest::header::CONTENT_TYPE, content_type)
+                    .body(data.to_vec())
+                    .timeout(SCAN_TIMEOUT)
+                    .send()
+                    .await
+                    .with_context(|| format!("sending file to upload scanner {url:?}"))?;
+                let accepted = resp.status().is_success();
+                let reason = resp
+                    .text()
+                    .await
+                    .with_context(|| format!("reading answer of upload scanner {url:?}"))?;
+                (accepted, reason)
+            }
+        };
+        match accepted {
+            true => Ok(()),
+            false => {
+                tracing::info!(?self, ?reason, "upload scanner rejected a file");
+                let reason = reason.trim();
+                Err(Error::invalid_file(match reason.is_empty() {
+                    true => "rejected by the upload scanner",
+                    false => reason,
+                }))
+            }
+        }
+    }
+}
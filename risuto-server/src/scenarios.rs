@@ -0,0 +1,1286 @@
+#![cfg(test)]
+
+//! Scripted end-to-end scenarios, with explicit assertions on what each user can see and do
+//!
+//! Contrary to the comparative fuzzer, these do not rely on the mock server being right, and
+//! each scenario runs exactly once, deterministically.
+
+use axum::extract::ws::Message;
+use chrono::{Duration, TimeZone, Utc};
+use futures::{channel::mpsc, Future, StreamExt};
+use risuto_api::{
+    Action, AuthInfo, ChangelogEntry, Clock, CommentReminder, DayStart, DuplicateCandidate,
+    Error as ApiError, Event, EventData, EventId, FeedMessage, NewInvite, NewSession, NewUser,
+    Order, OrderId, OrderType, Query, Registration, Role, Rule, RuleAction, RuleId, RuleTrigger,
+    Search, SearchExport, SearchResults, Tag, TagId, TagPermission, TagSections, Task, TaskId,
+    TimeQuery, UserId, Uuid, FEED_CLOSE_RESYNC, INVITE_VALIDITY_DAYS, MAX_SEARCHES_PER_USER,
+};
+use std::panic::AssertUnwindSafe;
+
+use crate::{
+    extractors::*,
+    fuzz::{build_pg_cluster, run_on_app},
+    *,
+};
+
+/// Runs `scenario` against the app, backed by a freshly migrated scratch database
+fn run_scenario<F, Fut>(scenario: F)
+where
+    F: FnOnce(Harness) -> Fut,
+    Fut: Future<Output = ()>,
+{
+    if std::env::var("RUST_LOG").is_ok() {
+        let _ = tracing_subscriber::fmt::try_init();
+    }
+    let tmpdir = tempfile::Builder::new()
+        .prefix("risuto-scenario-db")
+        .tempdir()
+        .expect("creating tempdir");
+    let lockfile =
+        std::fs::File::create(tmpdir.path().join("lockfile")).expect("creating lockfile");
+    let datadir = tmpdir.path().join("db");
+    let cluster = build_pg_cluster(&datadir);
+    let datadir_path: &str = datadir.to_str().expect("tempdir is not valid utf8");
+    let scenario = AssertUnwindSafe(scenario);
+    postgresfixture::coordinate::run_and_destroy(&cluster, lockfile.into(), || {
+        cluster
+            .createdb("test_db")
+            .expect("creating test_db database");
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("failed initializing tokio runtime");
+        let AssertUnwindSafe(scenario) = scenario;
+        runtime.block_on(async move {
+            let pool = create_sqlx_pool(&format!(
+                "postgresql://?host={}&dbname=test_db",
+                datadir_path
+            ))
+            .await
+            .expect("creating sqlx pool");
+            MIGRATOR
+                .run(&mut *pool.acquire().await.expect("getting migrator connection"))
+                .await
+                .expect("failed applying migrations");
+            scenario(Harness::new(pool).await).await;
+        })
+    })
+    .expect("coordinating spinup and shutdown of the pg cluster");
+}
+
+struct Harness {
+    admin_token: Uuid,
+    app: Router,
+    db: PgPool,
+    feeds: UserFeeds,
+    /// Manual clock shared with the app, only moved by the scenario itself
+    clock: Clock,
+}
+
+/// A logged-in user
+#[derive(Clone, Copy)]
+struct User {
+    id: UserId,
+    session: Uuid,
+}
+
+/// An action feed, along with the channel keeping it open
+struct Feed {
+    _sender: mpsc::UnboundedSender<Result<Message, axum::Error>>,
+    receiver: mpsc::UnboundedReceiver<Message>,
+}
+
+impl Harness {
+    async fn new(db: PgPool) -> Harness {
+        let admin_token = Uuid::new_v4();
+        let feeds = UserFeeds::new();
+        let clock = Clock::manual(Utc.with_ymd_and_hms(2023, 1, 9, 12, 0, 0).unwrap());
+        let app = app(
+            AppState {
+                admin_token: Some(AuthToken(admin_token)),
+                ..AppState::new(db.clone(), feeds.clone(), clock.clone())
+            },
+            "",
+        )
+        .await;
+        Harness {
+            admin_token,
+            app,
+            db,
+            feeds,
+            clock,
+        }
+    }
+
+    async fn create_user(&mut self, name: &str) -> User {
+        let id = UserId(Uuid::new_v4());
+        let user = NewUser {
+            id,
+            name: String::from(name),
+            // no hashing for tests
+            initial_password_hash: String::from("password"),
+        };
+        let () = run_on_app(
+            &mut self.app,
+            "POST",
+            "/api/admin/create-user",
+            Some(self.admin_token),
+            &user,
+        )
+        .await
+        .expect("creating user");
+        let session = NewSession {
+            user: String::from(name),
+            password: String::from("password"),
+            device: String::from("scenario"),
+            pow: String::new(),
+        };
+        let token: AuthToken = run_on_app(&mut self.app, "POST", "/api/auth", None, &session)
+            .await
+            .expect("logging in");
+        User {
+            id,
+            session: token.0,
+        }
+    }
+
+    /// Tags cannot be created through the API yet, so insert them directly
+    async fn create_tag(&mut self, owner: User, name: &str) -> TagId {
+        let id = TagId(Uuid::new_v4());
+        let mut conn = self.db.acquire().await.expect("acquiring db connection");
+        sqlx::query("INSERT INTO tags (id, owner_id, name, archived) VALUES ($1, $2, $3, false)")
+            .bind(id.0)
+            .bind(owner.id.0)
+            .bind(name)
+            .execute(&mut *conn)
+            .await
+            .expect("inserting tag");
+        id
+    }
+
+    async fn submit(&mut self, user: User, action: Action) -> Result<(), ApiError> {
+        run_on_app(
+            &mut self.app,
+            "POST",
+            "/api/submit-action",
+            Some(user.session),
+            &action,
+        )
+        .await
+    }
+
+    async fn event(&mut self, user: User, task: TaskId, data: EventData) -> Result<(), ApiError> {
+        let e = Event::at(user.id, task, self.clock.now(), data);
+        self.submit(user, Action::NewEvent(e)).await
+    }
+
+    async fn create_task(&mut self, user: User, title: &str, tag: TagId, prio: i64) -> TaskId {
+        let task = Task {
+            id: TaskId(Uuid::new_v4()),
+            owner_id: user.id,
+            date: self.clock.now(),
+            initial_title: String::from(title),
+            top_comment_id: EventId(Uuid::new_v4()),
+        };
+        let id = task.id;
+        self.submit(user, Action::NewTask(task, String::new()))
+            .await
+            .expect("creating task");
+        self.event(
+            user,
+            id,
+            EventData::AddTag {
+                tag,
+                prio,
+                backlog: false,
+            },
+        )
+        .await
+        .expect("tagging task");
+        id
+    }
+
+    /// Creates an invite sharing `tag` with the invitee as a viewer
+    async fn create_invite(&mut self, owner: User, tag: TagId) -> Uuid {
+        let invite = NewInvite {
+            tags: vec![tag],
+            role: Role::Viewer,
+        };
+        run_on_app(
+            &mut self.app,
+            "POST",
+            "/api/create-invite",
+            Some(owner.session),
+            &invite,
+        )
+        .await
+        .expect("creating invite")
+    }
+
+    async fn register(&mut self, invite: Uuid, name: &str) -> Result<(), ApiError> {
+        let registration = Registration {
+            invite,
+            user: NewUser {
+                id: UserId(Uuid::new_v4()),
+                name: String::from(name),
+                // no hashing for tests
+                initial_password_hash: String::from("password"),
+            },
+        };
+        run_on_app(&mut self.app, "POST", "/api/register", None, &registration).await
+    }
+
+    async fn set_role(&mut self, owner: User, tag: TagId, user: User, role: Option<Role>) {
+        let perm = TagPermission {
+            tag,
+            user: user.id,
+            role,
+        };
+        let () = run_on_app(
+            &mut self.app,
+            "POST",
+            "/api/set-tag-permission",
+            Some(owner.session),
+            &perm,
+        )
+        .await
+        .expect("setting tag permission");
+    }
+
+    /// Adds a comment on `task`, returning its id
+    async fn comment(&mut self, user: User, task: TaskId, text: &str) -> EventId {
+        let e = Event::at(
+            user.id,
+            task,
+            self.clock.now(),
+            EventData::AddComment {
+                text: String::from(text),
+                parent_id: None,
+            },
+        );
+        let id = e.id;
+        self.submit(user, Action::NewEvent(e))
+            .await
+            .expect("adding comment");
+        id
+    }
+
+    async fn set_comment_reminder(&mut self, user: User, comment: EventId, after: Duration) {
+        let reminder = CommentReminder {
+            comment,
+            remind_at: Some(self.clock.now() + after),
+        };
+        let () = run_on_app(
+            &mut self.app,
+            "POST",
+            "/api/set-comment-reminder",
+            Some(user.session),
+            &reminder,
+        )
+        .await
+        .expect("setting comment reminder");
+    }
+
+    async fn run_reminders(&mut self) {
+        crate::reminders::run_once(&self.db, &self.feeds, &self.clock)
+            .await
+            .expect("running comment reminders");
+    }
+
+    async fn run_duplicates(&mut self) {
+        let config = crate::duplicates::DuplicatesConfig {
+            threshold: 0.6,
+            interval_secs: 3600,
+            max_tasks_per_user: 2000,
+        };
+        crate::duplicates::run_once(&self.db, &config)
+            .await
+            .expect("looking for duplicate tasks");
+    }
+
+    async fn run_retention(&mut self, read_events_days: u64, idle_sessions_days: Option<u64>) {
+        let config = crate::retention::RetentionConfig {
+            read_events_days,
+            idle_sessions_days,
+            interval_secs: 3600,
+        };
+        crate::retention::run_once(&self.db, &self.clock, &config)
+            .await
+            .expect("applying retention policy");
+    }
+
+    async fn duplicates(&mut self, user: User) -> Vec<(TaskId, TaskId)> {
+        let res: Vec<DuplicateCandidate> = run_on_app(
+            &mut self.app,
+            "GET",
+            "/api/fetch-duplicates",
+            Some(user.session),
+            &(),
+        )
+        .await
+        .expect("fetching duplicates");
+        res.into_iter().map(|d| (d.task, d.other)).collect()
+    }
+
+    async fn search(&mut self, user: User, query: Query) -> SearchResults {
+        run_on_app(
+            &mut self.app,
+            "POST",
+            "/api/search-tasks",
+            Some(user.session),
+            &query,
+        )
+        .await
+        .expect("searching tasks")
+    }
+
+    async fn changelog(&mut self, user: User) -> Vec<ChangelogEntry> {
+        run_on_app(
+            &mut self.app,
+            "GET",
+            "/api/changelog",
+            Some(user.session),
+            &(),
+        )
+        .await
+        .expect("fetching changelog")
+    }
+
+    async fn set_rule(&mut self, user: User, rule: &Rule) -> Result<(), ApiError> {
+        run_on_app(
+            &mut self.app,
+            "POST",
+            "/api/set-rule",
+            Some(user.session),
+            rule,
+        )
+        .await
+    }
+
+    async fn visible_tasks(&mut self, user: User, tag: TagId) -> Vec<TaskId> {
+        self.matching_tasks(user, Query::tag(tag)).await
+    }
+
+    async fn matching_tasks(&mut self, user: User, query: Query) -> Vec<TaskId> {
+        let res = self.search(user, query).await;
+        let mut tasks = res.tasks.into_iter().map(|t| t.id).collect::<Vec<_>>();
+        tasks.sort_unstable_by_key(|t| t.0);
+        tasks
+    }
+
+    async fn open_feed(&mut self, user: User) -> Feed {
+        let (sender, serv_receiver) = mpsc::unbounded();
+        let (serv_sender, mut receiver) = mpsc::unbounded();
+        sender
+            .unbounded_send(Ok(Message::Text(user.session.to_string())))
+            .expect("sending auth token to feed");
+        crate::handlers::action_feed_impl(
+            serv_sender,
+            serv_receiver,
+            self.db.clone(),
+            self.feeds.clone(),
+            self.clock.clone(),
+        )
+        .await;
+        match receiver.next().await {
+            Some(Message::Text(t)) if t == "ok" => (),
+            o => panic!("unexpected reply to auth request {o:?}"),
+        }
+        Feed {
+            _sender: sender,
+            receiver,
+        }
+    }
+}
+
+impl Feed {
+    /// Waits for the next `n` relayed actions
+    async fn expect_actions(&mut self, n: usize) -> Vec<Action> {
+        let mut res = Vec::new();
+        for _attempt in 0..1000 {
+            if res.len() >= n {
+                break;
+            }
+            match self.receiver.try_next() {
+                Err(_) => tokio::task::yield_now().await, // waiting for data
+                Ok(None) => panic!("feed closed while still expecting messages"),
+                Ok(Some(Message::Binary(m))) => {
+                    match serde_json::from_slice(&m).expect("failed deserializing feed message") {
+                        FeedMessage::Action(a) => res.push(a),
+                        FeedMessage::Actions(a) => res.extend(a),
+                        FeedMessage::Pong => (),
+                    }
+                }
+                Ok(Some(m)) => panic!("unexpected ws::Message: {m:?}"),
+            }
+        }
+        assert_eq!(
+            res.len(),
+            n,
+            "did not receive the expected actions: {res:#?}"
+        );
+        res
+    }
+
+    /// Waits for the next `n` relayed actions, leaving time for the server to produce them in the
+    /// background, like it does when applying rules
+    async fn wait_for_actions(&mut self, n: usize) -> Vec<Action> {
+        let mut res = Vec::new();
+        while res.len() < n {
+            let m = tokio::time::timeout(std::time::Duration::from_secs(10), self.receiver.next())
+                .await
+                .unwrap_or_else(|_| panic!("did not receive the expected actions: {res:#?}"));
+            match m {
+                None => panic!("feed closed while still expecting messages"),
+                Some(Message::Binary(m)) => {
+                    match serde_json::from_slice(&m).expect("failed deserializing feed message") {
+                        FeedMessage::Action(a) => res.push(a),
+                        FeedMessage::Actions(a) => res.extend(a),
+                        FeedMessage::Pong => (),
+                    }
+                }
+                Some(m) => panic!("unexpected ws::Message: {m:?}"),
+            }
+        }
+        assert_eq!(
+            res.len(),
+            n,
+            "did not receive the expected actions: {res:#?}"
+        );
+        res
+    }
+
+    /// Waits for the server to close the feed, for the client to fetch everything again
+    async fn expect_resync(&mut self) {
+        for _attempt in 0..1000 {
+            match self.receiver.try_next() {
+                Err(_) => tokio::task::yield_now().await, // waiting for data
+                Ok(Some(Message::Close(Some(c)))) if c.code == FEED_CLOSE_RESYNC => return,
+                m => panic!("expected the feed to be closed for resync, but got {m:?}"),
+            }
+        }
+        panic!("feed did not get closed within allocated time");
+    }
+
+    async fn expect_nothing(&mut self) {
+        for _attempt in 0..100 {
+            tokio::task::yield_now().await;
+        }
+        if let Ok(Some(m)) = self.receiver.try_next() {
+            panic!("expected no more messages, but got:\n---\n{m:#?}\n---");
+        }
+    }
+}
+
+fn event_data(a: &Action) -> Option<&EventData> {
+    match a {
+        Action::NewEvent(e) => Some(&e.data),
+        _ => None,
+    }
+}
+
+#[test]
+fn sharing_a_tag_shares_its_tasks() {
+    run_scenario(|mut h| async move {
+        let alice = h.create_user("alice").await;
+        let bob = h.create_user("bob").await;
+        let work = h.create_tag(alice, "work").await;
+        let task = h.create_task(alice, "write report", work, 0).await;
+        assert_eq!(h.visible_tasks(alice, work).await, vec![task]);
+        assert_eq!(h.visible_tasks(bob, work).await, vec![]);
+
+        h.set_role(alice, work, bob, Some(Role::Viewer)).await;
+        assert_eq!(h.visible_tasks(bob, work).await, vec![task]);
+        assert_eq!(
+            h.event(bob, task, EventData::SetTitle(String::from("skip it")))
+                .await,
+            Err(ApiError::PermissionDenied),
+            "viewers cannot edit tasks",
+        );
+
+        h.set_role(alice, work, bob, Some(Role::Editor)).await;
+        h.event(
+            bob,
+            task,
+            EventData::SetTitle(String::from("write the report")),
+        )
+        .await
+        .expect("editors can edit tasks");
+        let res = h.search(alice, Query::tag(work)).await;
+        assert!(
+            res.events.iter().any(|e| e.owner_id == bob.id
+                && e.data == EventData::SetTitle(String::from("write the report"))),
+            "the owner sees the changes made by editors",
+        );
+    })
+}
+
+#[test]
+fn reordering_is_seen_by_all_users() {
+    run_scenario(|mut h| async move {
+        let alice = h.create_user("alice").await;
+        let bob = h.create_user("bob").await;
+        let work = h.create_tag(alice, "work").await;
+        h.set_role(alice, work, bob, Some(Role::Editor)).await;
+        let a = h.create_task(alice, "a", work, 0).await;
+        let b = h.create_task(alice, "b", work, 1).await;
+        let c = h.create_task(alice, "c", work, 2).await;
+
+        // Move c to the top of the list
+        h.event(
+            bob,
+            c,
+            EventData::AddTag {
+                tag: work,
+                prio: -1,
+                backlog: false,
+            },
+        )
+        .await
+        .expect("reordering task");
+
+        for user in [alice, bob] {
+            let res = h.search(user, Query::tag(work)).await;
+            let mut order = [a, b, c]
+                .into_iter()
+                .map(|t| {
+                    let prio = res
+                        .events
+                        .iter()
+                        .filter(|e| e.task_id == t)
+                        .filter_map(|e| match e.data {
+                            EventData::AddTag { tag, prio, .. } if tag == work => {
+                                Some((e.date, prio))
+                            }
+                            _ => None,
+                        })
+                        .max()
+                        .expect("task has no AddTag event")
+                        .1;
+                    (prio, t)
+                })
+                .collect::<Vec<_>>();
+            order.sort_unstable();
+            let order = order.into_iter().map(|(_, t)| t).collect::<Vec<_>>();
+            assert_eq!(order, vec![c, a, b]);
+        }
+    })
+}
+
+#[test]
+fn feeds_relay_changes_to_interested_users_only() {
+    run_scenario(|mut h| async move {
+        let alice = h.create_user("alice").await;
+        let bob = h.create_user("bob").await;
+        let carol = h.create_user("carol").await;
+        let work = h.create_tag(alice, "work").await;
+        h.set_role(alice, work, bob, Some(Role::Editor)).await;
+        let task = h.create_task(alice, "write report", work, 0).await;
+
+        let mut alice_feed = h.open_feed(alice).await;
+        let mut bob_feed = h.open_feed(bob).await;
+        let mut carol_feed = h.open_feed(carol).await;
+
+        h.event(bob, task, EventData::SetDone(true))
+            .await
+            .expect("marking task done");
+        for feed in [&mut alice_feed, &mut bob_feed] {
+            let actions = feed.expect_actions(1).await;
+            assert_eq!(event_data(&actions[0]), Some(&EventData::SetDone(true)));
+        }
+        carol_feed.expect_nothing().await;
+    })
+}
+
+#[test]
+fn role_changes_resync_the_affected_user_only() {
+    run_scenario(|mut h| async move {
+        let alice = h.create_user("alice").await;
+        let bob = h.create_user("bob").await;
+        let work = h.create_tag(alice, "work").await;
+        let mut alice_feed = h.open_feed(alice).await;
+        let mut bob_feed = h.open_feed(bob).await;
+
+        h.set_role(alice, work, bob, Some(Role::Viewer)).await;
+        bob_feed.expect_resync().await;
+        alice_feed.expect_nothing().await;
+
+        let mut bob_feed = h.open_feed(bob).await;
+        h.set_role(alice, work, bob, Some(Role::Editor)).await;
+        bob_feed.expect_resync().await;
+
+        let mut bob_feed = h.open_feed(bob).await;
+        h.set_role(alice, work, bob, None).await;
+        bob_feed.expect_resync().await;
+
+        // Nothing changes when revoking access again
+        let mut bob_feed = h.open_feed(bob).await;
+        h.set_role(alice, work, bob, None).await;
+        bob_feed.expect_nothing().await;
+    })
+}
+
+#[test]
+fn section_changes_resync_the_users_seeing_the_tag() {
+    run_scenario(|mut h| async move {
+        let alice = h.create_user("alice").await;
+        let bob = h.create_user("bob").await;
+        let carol = h.create_user("carol").await;
+        let work = h.create_tag(alice, "work").await;
+        h.set_role(alice, work, bob, Some(Role::Viewer)).await;
+        let mut bob_feed = h.open_feed(bob).await;
+        let mut carol_feed = h.open_feed(carol).await;
+
+        let sections = TagSections {
+            tag: work,
+            sections: vec![String::from("Doing"), String::from("Later")],
+        };
+        let res: Result<(), ApiError> = run_on_app(
+            &mut h.app,
+            "POST",
+            "/api/set-tag-sections",
+            Some(bob.session),
+            &sections,
+        )
+        .await;
+        assert_eq!(
+            res,
+            Err(ApiError::PermissionDenied),
+            "only the owner can change sections",
+        );
+        let () = run_on_app(
+            &mut h.app,
+            "POST",
+            "/api/set-tag-sections",
+            Some(alice.session),
+            &sections,
+        )
+        .await
+        .expect("setting tag sections");
+        bob_feed.expect_resync().await;
+        carol_feed.expect_nothing().await;
+
+        let tags: Vec<(Tag, AuthInfo)> =
+            run_on_app(&mut h.app, "GET", "/api/fetch-tags", Some(bob.session), &())
+                .await
+                .expect("fetching tags");
+        let tag = tags
+            .iter()
+            .find(|(t, _)| t.id == work)
+            .expect("tag is shared");
+        assert_eq!(tag.0.sections, sections.sections);
+    })
+}
+
+#[test]
+fn revoking_access_stops_edits_and_feeds() {
+    run_scenario(|mut h| async move {
+        let alice = h.create_user("alice").await;
+        let bob = h.create_user("bob").await;
+        let work = h.create_tag(alice, "work").await;
+        h.set_role(alice, work, bob, Some(Role::Editor)).await;
+        let task = h.create_task(alice, "write report", work, 0).await;
+        let mut bob_feed = h.open_feed(bob).await;
+
+        h.set_role(alice, work, bob, None).await;
+        bob_feed.expect_resync().await;
+        let mut bob_feed = h.open_feed(bob).await;
+        assert_eq!(h.visible_tasks(bob, work).await, vec![]);
+        assert_eq!(
+            h.event(bob, task, EventData::SetDone(true)).await,
+            Err(ApiError::PermissionDenied),
+        );
+
+        h.event(alice, task, EventData::SetDone(true))
+            .await
+            .expect("marking task done");
+        bob_feed.expect_nothing().await;
+    })
+}
+
+#[test]
+fn events_referring_to_missing_data_are_rejected() {
+    run_scenario(|mut h| async move {
+        let alice = h.create_user("alice").await;
+        let work = h.create_tag(alice, "work").await;
+        let task = h.create_task(alice, "write report", work, 0).await;
+        let bob = h.create_user("bob").await;
+        let home = h.create_tag(bob, "home").await;
+        let bob_task = h.create_task(bob, "fix the sink", home, 0).await;
+        let bob_comment = h.comment(bob, bob_task, "call a plumber").await;
+
+        // Data alice cannot see gets reported like missing data, for her not to learn it exists
+        let missing_task = TaskId(Uuid::new_v4());
+        for t in [missing_task, bob_task] {
+            assert_eq!(
+                h.event(alice, t, EventData::SetDone(true)).await,
+                Err(ApiError::PermissionDenied),
+            );
+        }
+        for parent in [EventId(Uuid::new_v4()), bob_comment] {
+            assert_eq!(
+                h.event(
+                    alice,
+                    task,
+                    EventData::AddComment {
+                        text: String::from("answer"),
+                        parent_id: Some(parent),
+                    },
+                )
+                .await,
+                Err(ApiError::ParentCommentNotFound(parent)),
+            );
+        }
+        let missing_tag = TagId(Uuid::new_v4());
+        assert_eq!(
+            h.event(alice, task, EventData::RmTag(missing_tag)).await,
+            Err(ApiError::TagNotFound(missing_tag)),
+        );
+        let missing_event = EventId(Uuid::new_v4());
+        assert_eq!(
+            h.event(
+                alice,
+                task,
+                EventData::SetEventRead {
+                    event_id: missing_event,
+                    now_read: true,
+                },
+            )
+            .await,
+            Err(ApiError::EventNotFound(missing_event)),
+        );
+    })
+}
+
+#[test]
+fn unanswered_comments_come_back_when_the_reminder_is_due() {
+    run_scenario(|mut h| async move {
+        let alice = h.create_user("alice").await;
+        let work = h.create_tag(alice, "work").await;
+        let task = h.create_task(alice, "ask for review", work, 0).await;
+        let comment = h.comment(alice, task, "could you review this?").await;
+        h.set_comment_reminder(alice, comment, Duration::days(2))
+            .await;
+        let mut alice_feed = h.open_feed(alice).await;
+
+        h.clock.advance(Duration::days(1));
+        h.run_reminders().await;
+        alice_feed.expect_nothing().await;
+
+        h.clock.advance(Duration::days(1));
+        h.run_reminders().await;
+        let actions = alice_feed.expect_actions(1).await;
+        assert_eq!(
+            event_data(&actions[0]),
+            Some(&EventData::ScheduleFor(Some(h.clock.now()))),
+        );
+
+        h.clock.advance(Duration::days(1));
+        h.run_reminders().await;
+        alice_feed.expect_nothing().await;
+    })
+}
+
+#[test]
+fn similar_open_tasks_are_reported_as_duplicates() {
+    run_scenario(|mut h| async move {
+        let alice = h.create_user("alice").await;
+        let bob = h.create_user("bob").await;
+        let work = h.create_tag(alice, "work").await;
+        let a = h
+            .create_task(alice, "Buy milk for the party", work, 0)
+            .await;
+        let b = h.create_task(alice, "buy milk for party!", work, 1).await;
+        h.create_task(alice, "write the quarterly report", work, 2)
+            .await;
+        let pair = match a < b {
+            true => (a, b),
+            false => (b, a),
+        };
+
+        h.run_duplicates().await;
+        assert_eq!(h.duplicates(alice).await, vec![pair]);
+        assert_eq!(
+            h.duplicates(bob).await,
+            vec![],
+            "duplicates are only reported to users who can see both tasks",
+        );
+
+        h.set_role(alice, work, bob, Some(Role::Viewer)).await;
+        assert_eq!(h.duplicates(bob).await, vec![pair]);
+
+        h.event(alice, b, EventData::SetDone(true))
+            .await
+            .expect("marking task done");
+        h.run_duplicates().await;
+        assert_eq!(
+            h.duplicates(alice).await,
+            vec![],
+            "done tasks are no longer reported",
+        );
+    })
+}
+
+#[test]
+fn old_read_events_are_folded_into_the_latest_one() {
+    run_scenario(|mut h| async move {
+        let alice = h.create_user("alice").await;
+        let bob = h.create_user("bob").await;
+        let work = h.create_tag(alice, "work").await;
+        h.set_role(alice, work, bob, Some(Role::Viewer)).await;
+        let task = h.create_task(alice, "Plan the offsite", work, 0).await;
+        let comment = h.comment(alice, task, "Where should we go?").await;
+
+        let mut reads = Vec::new();
+        for now_read in [true, false, true] {
+            h.clock.advance(Duration::hours(1));
+            let e = Event::at(
+                bob.id,
+                task,
+                h.clock.now(),
+                EventData::SetEventRead {
+                    event_id: comment,
+                    now_read,
+                },
+            );
+            reads.push(e.clone());
+            h.submit(bob, Action::NewEvent(e))
+                .await
+                .expect("marking comment as read");
+        }
+        let read_events = |res: SearchResults| {
+            res.events
+                .into_iter()
+                .filter(|e| matches!(e.data, EventData::SetEventRead { .. }))
+                .collect::<Vec<_>>()
+        };
+
+        h.run_retention(30, None).await;
+        assert_eq!(
+            read_events(h.search(bob, Query::tag(work)).await).len(),
+            3,
+            "recent read events are kept as is",
+        );
+
+        h.clock.advance(Duration::days(31));
+        h.run_retention(30, None).await;
+        assert_eq!(
+            read_events(h.search(bob, Query::tag(work)).await),
+            vec![reads[2].clone()],
+            "only the latest read event is kept, and still served as an event",
+        );
+        let committed: Vec<EventId> = run_on_app(
+            &mut h.app,
+            "POST",
+            "/api/committed-events",
+            Some(bob.session),
+            &vec![reads[2].id],
+        )
+        .await
+        .expect("checking committed events");
+        assert_eq!(committed, vec![reads[2].id]);
+
+        h.run_retention(30, None).await;
+        assert_eq!(
+            read_events(h.search(bob, Query::tag(work)).await),
+            vec![reads[2].clone()],
+            "compacting again changes nothing",
+        );
+    })
+}
+
+#[test]
+fn imported_searches_need_their_tags_to_be_visible() {
+    run_scenario(|mut h| async move {
+        let alice = h.create_user("alice").await;
+        let bob = h.create_user("bob").await;
+        let work = h.create_tag(alice, "work").await;
+        let export = SearchExport {
+            name: String::from("Urgent work"),
+            filter: Query::All(vec![Query::tag(work), Query::Done(false)]),
+            order: Order::Custom(OrderId(Uuid::new_v4())),
+        };
+
+        let res: Result<(), ApiError> = run_on_app(
+            &mut h.app,
+            "POST",
+            "/api/validate-search",
+            Some(bob.session),
+            &export,
+        )
+        .await;
+        assert_eq!(
+            res,
+            Err(ApiError::TagNotFound(work)),
+            "searches on tags one cannot see are rejected",
+        );
+
+        h.set_role(alice, work, bob, Some(Role::Viewer)).await;
+        let () = run_on_app(
+            &mut h.app,
+            "POST",
+            "/api/validate-search",
+            Some(bob.session),
+            &export,
+        )
+        .await
+        .expect("validating search");
+        let imported: Search = run_on_app(
+            &mut h.app,
+            "POST",
+            "/api/import-search",
+            Some(bob.session),
+            &export,
+        )
+        .await
+        .expect("importing search");
+        assert_eq!(imported.filter, export.filter);
+        assert_eq!(
+            imported.order,
+            Order::Custom(OrderId(imported.id.0)),
+            "the custom order becomes the imported search's own",
+        );
+        let searches: Vec<Search> = run_on_app(
+            &mut h.app,
+            "GET",
+            "/api/fetch-searches",
+            Some(bob.session),
+            &(),
+        )
+        .await
+        .expect("fetching searches");
+        assert_eq!(searches, vec![imported]);
+    })
+}
+
+#[test]
+fn users_can_only_have_so_many_searches() {
+    run_scenario(|mut h| async move {
+        let alice = h.create_user("alice").await;
+        let export = SearchExport {
+            name: String::from("Everything"),
+            filter: Query::Done(false),
+            order: Order::CreationDate(OrderType::Asc),
+        };
+        for _ in 0..MAX_SEARCHES_PER_USER {
+            let _: Search = run_on_app(
+                &mut h.app,
+                "POST",
+                "/api/import-search",
+                Some(alice.session),
+                &export,
+            )
+            .await
+            .expect("importing search");
+        }
+        let res: Result<Search, ApiError> = run_on_app(
+            &mut h.app,
+            "POST",
+            "/api/import-search",
+            Some(alice.session),
+            &export,
+        )
+        .await;
+        assert_eq!(
+            res,
+            Err(ApiError::IntegerOutOfRange(MAX_SEARCHES_PER_USER as i64)),
+            "searches past the limit are rejected",
+        );
+    })
+}
+
+#[test]
+fn invites_can_only_be_used_once_before_expiring() {
+    run_scenario(|mut h| async move {
+        let alice = h.create_user("alice").await;
+        let work = h.create_tag(alice, "work").await;
+        let task = h.create_task(alice, "write report", work, 0).await;
+
+        let invite = h.create_invite(alice, work).await;
+        h.register(invite, "bob").await.expect("registering bob");
+        assert_eq!(
+            h.register(invite, "carol").await,
+            Err(ApiError::PermissionDenied),
+            "invites can only be used once",
+        );
+        let session = NewSession {
+            user: String::from("bob"),
+            password: String::from("password"),
+            device: String::from("scenario"),
+            pow: String::new(),
+        };
+        let token: AuthToken = run_on_app(&mut h.app, "POST", "/api/auth", None, &session)
+            .await
+            .expect("logging in");
+        let id = run_on_app(&mut h.app, "GET", "/api/whoami", Some(token.0), &())
+            .await
+            .expect("fetching user id");
+        let bob = User {
+            id,
+            session: token.0,
+        };
+        assert_eq!(h.visible_tasks(bob, work).await, vec![task]);
+
+        let invite = h.create_invite(alice, work).await;
+        h.clock
+            .advance(Duration::days(INVITE_VALIDITY_DAYS) + Duration::seconds(1));
+        assert_eq!(
+            h.register(invite, "carol").await,
+            Err(ApiError::PermissionDenied),
+            "invites expire",
+        );
+    })
+}
+
+#[test]
+fn the_changelog_lists_installed_versions_only() {
+    run_scenario(|mut h| async move {
+        let alice = h.create_user("alice").await;
+        assert_eq!(
+            h.changelog(alice).await,
+            vec![],
+            "no version got recorded yet",
+        );
+
+        let mut conn = h.db.acquire().await.expect("acquiring db connection");
+        for version in [
+            "0.0.1",
+            changelog::CURRENT_VERSION,
+            changelog::CURRENT_VERSION,
+        ] {
+            db::record_server_version(&mut *conn, version, h.clock.now())
+                .await
+                .expect("recording server version");
+        }
+        std::mem::drop(conn);
+        let entries = h.changelog(alice).await;
+        assert_eq!(
+            entries
+                .iter()
+                .map(|e| &e.version as &str)
+                .collect::<Vec<_>>(),
+            vec![changelog::CURRENT_VERSION],
+            "versions without notes are left out, and restarts are not new versions",
+        );
+        assert_eq!(entries[0].installed_at, h.clock.now());
+    })
+}
+
+#[test]
+fn rules_apply_once_per_task_on_behalf_of_their_owner() {
+    run_scenario(|mut h| async move {
+        let alice = h.create_user("alice").await;
+        let bob = h.create_user("bob").await;
+        let work = h.create_tag(alice, "work").await;
+        let triage = h.create_tag(alice, "triage").await;
+        h.set_role(alice, work, bob, Some(Role::Editor)).await;
+        let rule = |name: &str, trigger, filter, action| Rule {
+            id: RuleId(Uuid::new_v4()),
+            owner_id: alice.id,
+            name: String::from(name),
+            enabled: true,
+            trigger,
+            filter,
+            action,
+        };
+
+        let to_triage = rule(
+            "Triage new work",
+            RuleTrigger::AddTag,
+            Query::tag(work),
+            RuleAction::AddTag(triage),
+        );
+        assert_eq!(
+            h.set_rule(bob, &to_triage).await,
+            Err(ApiError::PermissionDenied),
+            "rules cannot be created for other users",
+        );
+        let mut bobs = to_triage.clone();
+        bobs.owner_id = bob.id;
+        assert_eq!(
+            h.set_rule(bob, &bobs).await,
+            Err(ApiError::TagNotFound(triage)),
+            "rules cannot refer to tags their owner cannot see",
+        );
+        h.set_rule(alice, &to_triage).await.expect("setting rule");
+        let mut feed = h.open_feed(alice).await;
+        let task = h.create_task(bob, "fix the build", work, 0).await;
+        let actions = feed.wait_for_actions(2).await;
+        let triaged = matches!(
+            event_data(&actions[1]),
+            Some(EventData::AddTag { tag, .. }) if *tag == triage
+        );
+        assert!(triaged, "rules get applied after responding: {actions:#?}");
+        assert_eq!(
+            h.visible_tasks(alice, triage).await,
+            vec![task],
+            "bob's action triggered alice's rule",
+        );
+
+        // Scheduling triggers this rule, that schedules again
+        let follow_up = rule(
+            "Follow up on scheduled work",
+            RuleTrigger::ScheduleFor,
+            Query::tag(work),
+            RuleAction::Notify,
+        );
+        h.set_rule(alice, &follow_up).await.expect("setting rule");
+        let tomorrow = h.clock.now() + Duration::days(1);
+        h.event(alice, task, EventData::ScheduleFor(Some(tomorrow)))
+            .await
+            .expect("scheduling task");
+        feed.wait_for_actions(2).await;
+        let res = h.search(alice, Query::tag(work)).await;
+        let schedules = res
+            .events
+            .iter()
+            .filter(|e| matches!(e.data, EventData::ScheduleFor(_)))
+            .count();
+        assert_eq!(schedules, 2, "the rule did not trigger itself again");
+
+        // Actions of the server itself trigger rules too
+        let comment = h.comment(alice, task, "any news?").await;
+        h.set_comment_reminder(alice, comment, Duration::hours(1))
+            .await;
+        feed.wait_for_actions(1).await;
+        h.clock.advance(Duration::hours(1));
+        h.run_reminders().await;
+        feed.expect_actions(2).await;
+        let res = h.search(alice, Query::tag(work)).await;
+        let schedules = res
+            .events
+            .iter()
+            .filter(|e| matches!(e.data, EventData::ScheduleFor(_)))
+            .count();
+        assert_eq!(schedules, 4, "the reminder triggered the rule");
+    })
+}
+
+#[test]
+fn tag_archived_follows_the_tags_current_state() {
+    run_scenario(|mut h| async move {
+        let alice = h.create_user("alice").await;
+        let work = h.create_tag(alice, "work").await;
+        let old = h.create_tag(alice, "old").await;
+        let current = h.create_task(alice, "current task", work, 0).await;
+        let past = h.create_task(alice, "past task", old, 0).await;
+        let loose = Task {
+            id: TaskId(Uuid::new_v4()),
+            owner_id: alice.id,
+            date: h.clock.now(),
+            initial_title: String::from("loose task"),
+            top_comment_id: EventId(Uuid::new_v4()),
+        };
+        let loose_id = loose.id;
+        h.submit(alice, Action::NewTask(loose, String::new()))
+            .await
+            .expect("creating untagged task");
+        let mut all = vec![current, past, loose_id];
+        all.sort_unstable_by_key(|t| t.0);
+        assert_eq!(
+            h.matching_tasks(alice, Query::TagArchived(true)).await,
+            vec![],
+        );
+        assert_eq!(
+            h.matching_tasks(alice, Query::TagArchived(false)).await,
+            all,
+            "untagged tasks are in no archived tag",
+        );
+
+        // Like creating them, archiving tags is not possible through the API yet
+        let mut conn = h.db.acquire().await.expect("acquiring db connection");
+        sqlx::query("UPDATE tags SET archived = true WHERE id = $1")
+            .bind(old.0)
+            .execute(&mut *conn)
+            .await
+            .expect("archiving tag");
+        drop(conn);
+        all.retain(|t| *t != past);
+        assert_eq!(
+            h.matching_tasks(alice, Query::TagArchived(true)).await,
+            vec![past],
+            "archiving the tag changes the results of the same query",
+        );
+        assert_eq!(
+            h.matching_tasks(alice, Query::TagArchived(false)).await,
+            all,
+        );
+    })
+}
+
+#[test]
+fn relative_queries_follow_the_clock() {
+    run_scenario(|mut h| async move {
+        let alice = h.create_user("alice").await;
+        let work = h.create_tag(alice, "work").await;
+        let report = h.create_task(alice, "write report", work, 0).await;
+        let review = h.create_task(alice, "review report", work, 1).await;
+        let tomorrow = h.clock.now() + Duration::days(1);
+        h.event(alice, report, EventData::ScheduleFor(Some(tomorrow)))
+            .await
+            .expect("scheduling task");
+        h.event(alice, review, EventData::BlockedUntil(Some(tomorrow)))
+            .await
+            .expect("blocking task");
+        let day = |day_offset| TimeQuery::DayRelative {
+            timezone: chrono_tz::UTC,
+            day_offset,
+            day_start: DayStart::MIDNIGHT,
+        };
+        let today = Search::today(chrono_tz::UTC, DayStart::MIDNIGHT).filter;
+        let blocked = Query::BlockedUntilAtLeast(day(1));
+        let unblocked = Query::BlockedUntilAtMost(day(1));
+        let tasks = |res: SearchResults| res.tasks.into_iter().map(|t| t.id).collect::<Vec<_>>();
+
+        assert_eq!(tasks(h.search(alice, today.clone()).await), vec![]);
+        assert_eq!(tasks(h.search(alice, blocked.clone()).await), vec![review]);
+        assert_eq!(tasks(h.search(alice, unblocked.clone()).await), vec![]);
+
+        h.clock.advance(Duration::days(1));
+        assert_eq!(
+            tasks(h.search(alice, today).await),
+            vec![report],
+            "the task scheduled for tomorrow is now due today",
+        );
+        assert_eq!(tasks(h.search(alice, blocked).await), vec![]);
+        assert_eq!(
+            tasks(h.search(alice, unblocked).await),
+            vec![review],
+            "the task blocked until tomorrow gets unblocked today",
+        );
+    })
+}
+
+#[test]
+fn idle_sessions_get_logged_out() {
+    run_scenario(|mut h| async move {
+        let alice = h.create_user("alice").await;
+        let bob = h.create_user("bob").await;
+        let work = h.create_tag(alice, "work").await;
+
+        h.clock.advance(Duration::days(60));
+        h.visible_tasks(bob, work).await;
+        h.clock.advance(Duration::days(40));
+        h.run_retention(30, None).await;
+        h.visible_tasks(alice, work).await;
+
+        h.clock.advance(Duration::days(100));
+        h.visible_tasks(bob, work).await;
+        h.clock.advance(Duration::days(40));
+        h.run_retention(30, Some(90)).await;
+        h.visible_tasks(bob, work).await;
+        let res: Result<SearchResults, ApiError> = run_on_app(
+            &mut h.app,
+            "POST",
+            "/api/search-tasks",
+            Some(alice.session),
+            &Query::tag(work),
+        )
+        .await;
+        assert_eq!(
+            res,
+            Err(ApiError::PermissionDenied),
+            "alice was idle for 140 days",
+        );
+    })
+}
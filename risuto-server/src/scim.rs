@@ -0,0 +1,345 @@
+//! Minimal SCIM 2.0 (RFC 7643 and 7644) user provisioning, for identity providers to create,
+//! rename and deactivate users
+//!
+//! Deleting a user through SCIM only deactivates it, so that its history is kept.
+
+use axum::{
+    extract::{Path, Query as UrlQuery, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use risuto_api::{validate_string, validate_user_name, Action, NewUser, User, UserId, Uuid};
+use serde_json::json;
+
+use crate::{db, extractors::*, Error, UserFeeds};
+
+const USER_SCHEMA: &str = "urn:ietf:params:scim:schemas:core:2.0:User";
+const LIST_SCHEMA: &str = "urn:ietf:params:scim:api:messages:2.0:ListResponse";
+const ERROR_SCHEMA: &str = "urn:ietf:params:scim:api:messages:2.0:Error";
+const CONTENT_TYPE: &str = "application/scim+json";
+
+/// Set in the `[scim]` section of the config file to enable the SCIM endpoints
+#[derive(Clone, Debug, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScimConfig {
+    /// Bearer token the identity provider authenticates with, eg. generated with `uuidgen`
+    pub token: Uuid,
+}
+
+pub enum ScimError {
+    Server(Error),
+    NotFound,
+    BadRequest(String),
+}
+
+impl From<Error> for ScimError {
+    fn from(e: Error) -> ScimError {
+        ScimError::Server(e)
+    }
+}
+
+impl From<anyhow::Error> for ScimError {
+    fn from(e: anyhow::Error) -> ScimError {
+        ScimError::Server(Error::Anyhow(e))
+    }
+}
+
+impl From<risuto_api::Error> for ScimError {
+    fn from(e: risuto_api::Error) -> ScimError {
+        ScimError::Server(Error::Api(e))
+    }
+}
+
+impl IntoResponse for ScimError {
+    fn into_response(self) -> Response {
+        let (status, detail) = match self {
+            ScimError::Server(Error::Anyhow(err)) => {
+                tracing::error!(?err, "internal server error");
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    String::from("Internal server error, see logs for details"),
+                )
+            }
+            ScimError::Server(Error::Api(err)) => (err.status_code(), err.to_string()),
+            ScimError::NotFound => (StatusCode::NOT_FOUND, String::from("User not found")),
+            ScimError::BadRequest(detail) => (StatusCode::BAD_REQUEST, detail),
+        };
+        scim_response(
+            status,
+            json!({
+                "schemas": [ERROR_SCHEMA],
+                "status": status.as_u16().to_string(),
+                "detail": detail,
+            }),
+        )
+    }
+}
+
+fn scim_response(status: StatusCode, body: serde_json::Value) -> Response {
+    (status, [(header::CONTENT_TYPE, CONTENT_TYPE)], Json(body)).into_response()
+}
+
+fn user_resource(user: &User, active: bool) -> serde_json::Value {
+    json!({
+        "schemas": [USER_SCHEMA],
+        "id": user.id.0,
+        "userName": user.name,
+        "active": active,
+        "meta": {
+            "resourceType": "User",
+        },
+    })
+}
+
+/// Identity providers disagree on whether booleans are sent as JSON booleans or strings
+fn parse_bool(v: &serde_json::Value) -> Result<bool, ScimError> {
+    match v {
+        serde_json::Value::Bool(b) => Ok(*b),
+        serde_json::Value::String(s) if s.eq_ignore_ascii_case("true") => Ok(true),
+        serde_json::Value::String(s) if s.eq_ignore_ascii_case("false") => Ok(false),
+        _ => Err(ScimError::BadRequest(format!(
+            "expected a boolean, got {v}"
+        ))),
+    }
+}
+
+fn parse_user_name(v: &serde_json::Value) -> Result<String, ScimError> {
+    let name = v
+        .as_str()
+        .ok_or_else(|| ScimError::BadRequest(format!("expected a user name, got {v}")))?;
+    validate_user_name(name)?;
+    Ok(String::from(name))
+}
+
+async fn fetch_user(conn: &mut PgConn, id: Uuid) -> Result<(User, bool), ScimError> {
+    db::fetch_users_with_status(&mut **conn, Some(UserId(id)), None)
+        .await?
+        .pop()
+        .ok_or(ScimError::NotFound)
+}
+
+/// Applies the changes to the user, relaying them to the other users
+async fn update_user(
+    feeds: &UserFeeds,
+    conn: &mut PgConn,
+    id: Uuid,
+    name: Option<String>,
+    active: Option<bool>,
+) -> Result<Response, ScimError> {
+    let (mut user, was_active) = fetch_user(conn, id).await?;
+    if let Some(name) = name.filter(|n| *n != user.name) {
+        if !db::rename_user(&mut **conn, user.id, name.clone()).await? {
+            return Err(ScimError::NotFound);
+        }
+        user.name = name;
+    }
+    let active = active.unwrap_or(was_active);
+    if active != was_active && !db::set_user_active(&mut **conn, user.id, active).await? {
+        return Err(ScimError::NotFound);
+    }
+    match (was_active, active) {
+        (true, false) => {
+            feeds
+                .relay_action(&mut **conn, Action::DeletedUser(user.id))
+                .await
+        }
+        (_, true) => {
+            feeds
+                .relay_action(&mut **conn, Action::NewUser(user.clone()))
+                .await
+        }
+        (false, false) => (),
+    }
+    Ok(scim_response(StatusCode::OK, user_resource(&user, active)))
+}
+
+#[derive(serde::Deserialize)]
+pub struct ListParams {
+    filter: Option<String>,
+}
+
+/// Only supports the `userName eq "..."` filter, that identity providers use to find out
+/// whether a user already exists
+pub async fn list_users(
+    ScimAuth: ScimAuth,
+    mut conn: PgConn,
+    UrlQuery(params): UrlQuery<ListParams>,
+) -> Result<Response, ScimError> {
+    let name = match &params.filter {
+        None => None,
+        Some(filter) => {
+            let mut parts = filter.splitn(3, ' ');
+            match (parts.next(), parts.next(), parts.next()) {
+                (Some(attr), Some(op), Some(value))
+                    if attr.eq_ignore_ascii_case("userName") && op.eq_ignore_ascii_case("eq") =>
+                {
+                    Some(value.trim_matches('"'))
+                }
+                _ => {
+                    return Err(ScimError::BadRequest(format!(
+                        "unsupported filter {filter:?}"
+                    )))
+                }
+            }
+        }
+    };
+    let users = db::fetch_users_with_status(&mut *conn, None, name).await?;
+    Ok(scim_response(
+        StatusCode::OK,
+        json!({
+            "schemas": [LIST_SCHEMA],
+            "totalResults": users.len(),
+            "startIndex": 1,
+            "itemsPerPage": users.len(),
+            "Resources": users
+                .iter()
+                .map(|(u, active)| user_resource(u, *active))
+                .collect::<Vec<_>>(),
+        }),
+    ))
+}
+
+pub async fn get_user(
+    ScimAuth: ScimAuth,
+    mut conn: PgConn,
+    Path(id): Path<Uuid>,
+) -> Result<Response, ScimError> {
+    let (user, active) = fetch_user(&mut conn, id).await?;
+    Ok(scim_response(StatusCode::OK, user_resource(&user, active)))
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScimUser {
+    user_name: serde_json::Value,
+    active: Option<serde_json::Value>,
+    password: Option<String>,
+}
+
+/// Users created without a password can only log in through LDAP
+pub async fn create_user(
+    ScimAuth: ScimAuth,
+    Writable: Writable,
+    State(feeds): State<UserFeeds>,
+    mut conn: PgConn,
+    Json(data): Json<ScimUser>,
+) -> Result<Response, ScimError> {
+    let name = parse_user_name(&data.user_name)?;
+    let active = data.active.as_ref().map(parse_bool).transpose()?;
+    let id = UserId(Uuid::new_v4());
+    match data.password {
+        Some(password) => {
+            validate_string(&password)?;
+            db::create_user(&mut *conn, NewUser::new(id, name.clone(), password)).await?;
+        }
+        None => db::create_ldap_user(&mut *conn, id, name.clone()).await?,
+    }
+    let user = User {
+        id,
+        name,
+        avatar_hash: None,
+    };
+    match active {
+        Some(false) => {
+            db::set_user_active(&mut *conn, id, false).await?;
+        }
+        _ => {
+            feeds
+                .relay_action(&mut *conn, Action::NewUser(user.clone()))
+                .await
+        }
+    }
+    Ok(scim_response(
+        StatusCode::CREATED,
+        user_resource(&user, active.unwrap_or(true)),
+    ))
+}
+
+pub async fn replace_user(
+    ScimAuth: ScimAuth,
+    Writable: Writable,
+    State(feeds): State<UserFeeds>,
+    mut conn: PgConn,
+    Path(id): Path<Uuid>,
+    Json(data): Json<ScimUser>,
+) -> Result<Response, ScimError> {
+    let name = parse_user_name(&data.user_name)?;
+    let active = data.active.as_ref().map(parse_bool).transpose()?;
+    update_user(
+        &feeds,
+        &mut conn,
+        id,
+        Some(name),
+        Some(active.unwrap_or(true)),
+    )
+    .await
+}
+
+#[derive(serde::Deserialize)]
+pub struct PatchRequest {
+    #[serde(rename = "Operations")]
+    operations: Vec<PatchOperation>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct PatchOperation {
+    op: String,
+    path: Option<String>,
+    value: serde_json::Value,
+}
+
+/// Only supports replacing `userName` and `active`
+pub async fn patch_user(
+    ScimAuth: ScimAuth,
+    Writable: Writable,
+    State(feeds): State<UserFeeds>,
+    mut conn: PgConn,
+    Path(id): Path<Uuid>,
+    Json(data): Json<PatchRequest>,
+) -> Result<Response, ScimError> {
+    let mut name = None;
+    let mut active = None;
+    for op in data.operations {
+        if !op.op.eq_ignore_ascii_case("replace") && !op.op.eq_ignore_ascii_case("add") {
+            return Err(ScimError::BadRequest(format!(
+                "unsupported operation {:?}",
+                op.op
+            )));
+        }
+        let values = match op.path {
+            Some(path) => vec![(path, op.value)],
+            None => match op.value {
+                serde_json::Value::Object(values) => values.into_iter().collect(),
+                value => {
+                    return Err(ScimError::BadRequest(format!(
+                        "expected an object, got {value}"
+                    )))
+                }
+            },
+        };
+        for (path, value) in values {
+            match &path as &str {
+                p if p.eq_ignore_ascii_case("userName") => name = Some(parse_user_name(&value)?),
+                p if p.eq_ignore_ascii_case("active") => active = Some(parse_bool(&value)?),
+                _ => {
+                    return Err(ScimError::BadRequest(format!(
+                        "unsupported attribute {path:?}"
+                    )))
+                }
+            }
+        }
+    }
+    update_user(&feeds, &mut conn, id, name, active).await
+}
+
+pub async fn delete_user(
+    ScimAuth: ScimAuth,
+    Writable: Writable,
+    State(feeds): State<UserFeeds>,
+    mut conn: PgConn,
+    Path(id): Path<Uuid>,
+) -> Result<StatusCode, ScimError> {
+    update_user(&feeds, &mut conn, id, None, Some(false)).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,241 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
+
+use anyhow::Context;
+use risuto_api::{Clock, Query, Search, SearchId, Time, UserId};
+
+use crate::{
+    db::{self, SearchCounts},
+    extractors::{Maintenance, PgPool},
+    Error,
+};
+
+fn default_min_query_ms() -> u64 {
+    500
+}
+
+fn default_max_age_secs() -> u64 {
+    15 * 60
+}
+
+fn default_interval_secs() -> u64 {
+    60
+}
+
+/// Set in the `[search_cache]` section of the config file to keep the counts of the shared
+/// searches that are slow to compute, rather than computing them on each badge request
+#[derive(Clone, Debug, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SearchCacheConfig {
+    /// Searches whose counts take at least this long to compute get cached
+    #[serde(default = "default_min_query_ms")]
+    pub min_query_ms: u64,
+
+    /// Age after which cached counts get computed again even if no action touched the search's
+    /// owner's tasks, eg. for tasks to become overdue. Counts not requested for that long get
+    /// dropped from the cache.
+    #[serde(default = "default_max_age_secs")]
+    pub max_age_secs: u64,
+
+    /// Time between two refreshes of the out-of-date counts
+    #[serde(default = "default_interval_secs")]
+    pub interval_secs: u64,
+}
+
+#[derive(Debug)]
+struct Entry {
+    /// Filter the counts were computed for, in case the search got edited since
+    filter: Query,
+    counts: SearchCounts,
+    computed_at: Time,
+    requested_at: Time,
+    /// Set when an action got relayed to the owner of the search since `computed_at`
+    stale: bool,
+    /// Number of times the entry got marked stale, to know whether it happened during a refresh
+    invalidations: u64,
+}
+
+#[derive(Debug)]
+struct Inner {
+    config: SearchCacheConfig,
+    entries: Mutex<HashMap<(UserId, SearchId), Entry>>,
+}
+
+/// Counts of a search, possibly served from the cache
+#[derive(Clone, Copy, Debug)]
+pub struct CachedCounts {
+    pub counts: SearchCounts,
+    pub computed_at: Time,
+    /// Whether no action could have changed the counts since `computed_at`
+    pub fresh: bool,
+}
+
+/// Materialized counts of the searches that are slow to compute, disabled by default
+#[derive(Clone, Debug, Default)]
+pub struct SearchCache(Option<Arc<Inner>>);
+
+impl SearchCache {
+    pub fn new(config: SearchCacheConfig) -> SearchCache {
+        SearchCache(Some(Arc::new(Inner {
+            config,
+            entries: Mutex::new(HashMap::new()),
+        })))
+    }
+
+    /// Returns the counts of `search`, computing them if they are not cached yet
+    ///
+    /// Cached counts are returned even when out of date, the refresh being left to `run` so
+    /// that polling a slow search does not run it more than once per `interval_secs`.
+    pub async fn counts(
+        &self,
+        conn: &mut sqlx::PgConnection,
+        owner: UserId,
+        search: &Search,
+        now: Time,
+    ) -> Result<CachedCounts, Error> {
+        let inner = match &self.0 {
+            None => {
+                let counts = db::count_search_results(conn, owner, &search.filter, now).await?;
+                return Ok(CachedCounts {
+                    counts,
+                    computed_at: now,
+                    fresh: true,
+                });
+            }
+            Some(inner) => inner,
+        };
+        let key = (owner, search.id);
+        let cached = {
+            let mut entries = inner.entries();
+            entries
+                .get_mut(&key)
+                .filter(|e| e.filter == search.filter)
+                .map(|e| {
+                    e.requested_at = now;
+                    CachedCounts {
+                        counts: e.counts,
+                        computed_at: e.computed_at,
+                        fresh: inner.is_fresh(e, now),
+                    }
+                })
+        };
+        if let Some(cached) = cached {
+            return Ok(cached);
+        }
+
+        let start = Instant::now();
+        let counts = db::count_search_results(conn, owner, &search.filter, now).await?;
+        if start.elapsed() >= Duration::from_millis(inner.config.min_query_ms) {
+            tracing::debug!(?owner, search=?search.id, "caching the counts of a slow search");
+            inner.entries().insert(
+                key,
+                Entry {
+                    filter: search.filter.clone(),
+                    counts,
+                    computed_at: now,
+                    requested_at: now,
+                    stale: false,
+                    invalidations: 0,
+                },
+            );
+        } else {
+            inner.entries().remove(&key);
+        }
+        Ok(CachedCounts {
+            counts,
+            computed_at: now,
+            fresh: true,
+        })
+    }
+
+    /// Marks the counts of the searches of `users` as out of date, as they got relayed actions
+    pub fn invalidate<'a>(&self, users: impl Iterator<Item = &'a UserId>) {
+        let inner = match &self.0 {
+            None => return,
+            Some(inner) => inner,
+        };
+        let mut entries = inner.entries();
+        for u in users {
+            for ((owner, _), e) in entries.iter_mut() {
+                if owner == u {
+                    e.stale = true;
+                    e.invalidations += 1;
+                }
+            }
+        }
+    }
+}
+
+impl Inner {
+    fn entries(&self) -> MutexGuard<'_, HashMap<(UserId, SearchId), Entry>> {
+        self.entries
+            .lock()
+            .expect("search cache mutex got poisoned")
+    }
+
+    fn is_fresh(&self, e: &Entry, now: Time) -> bool {
+        !e.stale && now - e.computed_at < chrono::Duration::seconds(self.config.max_age_secs as i64)
+    }
+}
+
+/// Periodically computes again the cached counts that are out of date
+///
+/// This pauses in maintenance mode, so that the database is left alone, badges then serving the
+/// last computed counts.
+pub async fn run(db: PgPool, cache: SearchCache, clock: Clock, maintenance: Maintenance) {
+    let interval_secs = match &cache.0 {
+        None => return,
+        Some(inner) => inner.config.interval_secs,
+    };
+    let mut interval = tokio::time::interval(Duration::from_secs(interval_secs));
+    loop {
+        interval.tick().await;
+        if maintenance.is_read_only() {
+            continue;
+        }
+        if let Err(err) = run_once(&db, &cache, &clock).await {
+            tracing::error!(?err, "error while refreshing cached search counts");
+        }
+    }
+}
+
+pub(crate) async fn run_once(
+    db: &PgPool,
+    cache: &SearchCache,
+    clock: &Clock,
+) -> anyhow::Result<()> {
+    let inner = match &cache.0 {
+        None => return Ok(()),
+        Some(inner) => inner,
+    };
+    let now = clock.now();
+    let max_age = chrono::Duration::seconds(inner.config.max_age_secs as i64);
+    let to_refresh = {
+        let mut entries = inner.entries();
+        entries.retain(|_, e| now - e.requested_at < max_age);
+        entries
+            .iter()
+            .filter(|(_, e)| !inner.is_fresh(e, now))
+            .map(|(k, e)| (*k, e.filter.clone(), e.invalidations))
+            .collect::<Vec<_>>()
+    };
+    let mut conn = db.acquire().await.context("acquiring db connection")?;
+    for ((owner, search), filter, invalidations) in to_refresh {
+        let counts = db::count_search_results(&mut *conn, owner, &filter, now)
+            .await
+            .with_context(|| format!("refreshing counts of search {search:?}"))?;
+        let mut entries = inner.entries();
+        if let Some(e) = entries.get_mut(&(owner, search)) {
+            if e.filter == filter {
+                e.counts = counts;
+                e.computed_at = now;
+                // Another action may have been relayed while the counts were being computed
+                e.stale = e.invalidations != invalidations;
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,116 @@
+use std::{
+    sync::{Arc, Mutex, MutexGuard},
+    time::Duration,
+};
+
+use anyhow::Context;
+use risuto_api::{action_counter, Action, Clock, TelemetryReport, Time};
+
+fn default_interval_secs() -> u64 {
+    60 * 60
+}
+
+/// Set in the `[telemetry]` section of the config file to opt into sending anonymous usage
+/// counters, aggregated per day, to `endpoint`
+#[derive(Clone, Debug, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TelemetryConfig {
+    /// URL the `TelemetryReport`s get posted to as JSON, once their days are over
+    pub endpoint: String,
+
+    /// Time between two checks for finished days to send
+    #[serde(default = "default_interval_secs")]
+    pub interval_secs: u64,
+}
+
+#[derive(Debug)]
+struct Inner {
+    config: TelemetryConfig,
+    pending: Mutex<TelemetryReport>,
+}
+
+impl Inner {
+    fn pending(&self) -> MutexGuard<'_, TelemetryReport> {
+        self.pending
+            .lock()
+            .expect("telemetry counters mutex got poisoned")
+    }
+}
+
+/// Counters waiting to be sent, disabled by default
+#[derive(Clone, Debug, Default)]
+pub struct Telemetry(Option<Arc<Inner>>);
+
+impl Telemetry {
+    pub fn new(config: TelemetryConfig) -> Telemetry {
+        Telemetry(Some(Arc::new(Inner {
+            config,
+            pending: Mutex::new(TelemetryReport::default()),
+        })))
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.0.is_some()
+    }
+
+    pub fn record_action(&self, a: &Action, now: Time) {
+        if let Some(inner) = &self.0 {
+            let mut pending = inner.pending();
+            pending.record(now.date_naive(), action_counter(a));
+        }
+    }
+
+    /// Adds the counters of a client that opted in, assuming they got validated
+    pub fn merge(&self, report: TelemetryReport) {
+        if let Some(inner) = &self.0 {
+            inner.pending().merge(report);
+        }
+    }
+
+    /// Counters waiting to be sent, `None` if telemetry is disabled
+    pub fn pending(&self) -> Option<TelemetryReport> {
+        self.0.as_ref().map(|inner| inner.pending().clone())
+    }
+}
+
+/// Periodically sends the counters of the days that are over
+pub async fn run(telemetry: Telemetry, clock: Clock) {
+    let interval_secs = match &telemetry.0 {
+        None => return,
+        Some(inner) => inner.config.interval_secs,
+    };
+    let mut interval = tokio::time::interval(Duration::from_secs(interval_secs));
+    loop {
+        interval.tick().await;
+        if let Err(err) = run_once(&telemetry, &clock).await {
+            tracing::warn!(?err, "error while sending telemetry");
+        }
+    }
+}
+
+pub(crate) async fn run_once(telemetry: &Telemetry, clock: &Clock) -> anyhow::Result<()> {
+    let inner = match &telemetry.0 {
+        None => return Ok(()),
+        Some(inner) => inner,
+    };
+    let report = {
+        let mut pending = inner.pending();
+        pending.take_before(clock.now().date_naive())
+    };
+    if report.is_empty() {
+        return Ok(());
+    }
+    let endpoint = &inner.config.endpoint;
+    let res = reqwest::Client::new()
+        .post(endpoint)
+        .json(&report)
+        .send()
+        .await
+        .and_then(|resp| resp.error_for_status());
+    if let Err(err) = res {
+        // Keep the counters for the next attempt
+        inner.pending().merge(report);
+        return Err(err).with_context(|| format!("posting telemetry report to {endpoint:?}"));
+    }
+    Ok(())
+}
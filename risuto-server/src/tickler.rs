@@ -0,0 +1,58 @@
+use std::time::Duration;
+
+use anyhow::Context;
+use risuto_api::{Action, Clock, Event, EventData};
+
+use crate::{
+    db::{self, PostgresDb, Provenance},
+    extractors::{Maintenance, PgPool},
+    rules, UserFeeds,
+};
+
+// TODO: this could be smarter and sleep until the next tickler actually becomes due
+const CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Periodically unarchives and schedules for now the tasks whose tickler is due
+///
+/// Nothing happens in maintenance mode, due ticklers then fire once it gets lifted.
+pub async fn run(db: PgPool, feeds: UserFeeds, clock: Clock, maintenance: Maintenance) {
+    let mut interval = tokio::time::interval(CHECK_INTERVAL);
+    loop {
+        interval.tick().await;
+        if maintenance.is_read_only() {
+            continue;
+        }
+        if let Err(err) = run_once(&db, &feeds, &clock).await {
+            tracing::error!(?err, "error while running ticklers");
+        }
+    }
+}
+
+pub(crate) async fn run_once(db: &PgPool, feeds: &UserFeeds, clock: &Clock) -> anyhow::Result<()> {
+    let mut conn = db.acquire().await.context("acquiring db connection")?;
+    let now = clock.now();
+    for (owner, task) in db::due_ticklers(&mut *conn, now).await? {
+        tracing::debug!(?task, "tickler is due, bringing task back");
+        let mut actions = Vec::new();
+        for data in [
+            EventData::SetArchived(false),
+            EventData::ScheduleFor(Some(now)),
+        ] {
+            let e = Event::at(owner, task, now, data);
+            let mut pg = PostgresDb {
+                conn: &mut *conn,
+                user: owner,
+            };
+            db::submit_event(&mut pg, e.clone(), &Provenance::default())
+                .await
+                .with_context(|| format!("submitting tickler event {e:?}"))?;
+            actions.push(Action::NewEvent(e));
+        }
+        feeds.relay_actions(&mut *conn, actions.clone()).await;
+        // The tickler fired anyway, so failing rules only get logged
+        if let Err(err) = rules::apply(&mut *conn, feeds, now, actions).await {
+            tracing::error!(?err, ?task, "error while applying rules after tickler");
+        }
+    }
+    Ok(())
+}
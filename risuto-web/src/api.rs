@@ -1,20 +1,25 @@
-use chrono::Utc;
 use futures::{channel::oneshot, pin_mut, select, FutureExt, SinkExt, StreamExt};
 use risuto_client::{
     api::{self, Time, Uuid},
-    DbDump,
+    DbDump, TaskIndex,
 };
 use ws_stream_wasm::{WsMessage, WsMeta};
 
-use crate::{ui, LoginInfo};
+use crate::{ui, util, LoginInfo};
+
+/// Reported to the server with each submitted action, to be shown in the activity view
+const APP_VERSION: &str = concat!("risuto-web/", env!("CARGO_PKG_VERSION"));
 
 // TODO: make below chrono::Duration once https://github.com/chronotope/chrono/issues/309 fixeds
-// Pings will be sent every PING_INTERVAL
-const PING_INTERVAL_SECS: i64 = 10;
-// If the interval between two pongs is more than DISCONNECT_INTERVAL, disconnect
-const DISCONNECT_INTERVAL_SECS: i64 = 20;
+// Pings will be sent every PING_INTERVAL, or every HIDDEN_PING_INTERVAL while the page is hidden
+const PING_INTERVAL_SECS: i64 = api::FEED_PING_INTERVAL_SECS as i64;
+const HIDDEN_PING_INTERVAL_SECS: i64 = 60;
+// If the interval between two pongs is more than twice the ping interval, disconnect
 // Space each reconnect attempt by ATTEMPT_SPACING
 const ATTEMPT_SPACING_SECS: i64 = 1;
+// Check whether an unreachable server came back, or left maintenance mode, every
+// CONNECTIVITY_CHECK_INTERVAL
+const CONNECTIVITY_CHECK_INTERVAL_SECS: i64 = 5;
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
@@ -29,6 +34,39 @@ pub enum Error {
 
     #[error("parsing error message")]
     ParsingError(#[source] anyhow::Error),
+
+    #[error("server failed handling the request with status {0}")]
+    Server(reqwest::StatusCode),
+}
+
+impl Error {
+    /// Whether the server could not be reached or could not handle the request, as opposed to it
+    /// rejecting the request, so that the request is worth retrying later
+    pub fn is_connectivity(&self) -> bool {
+        // http error statuses are returned as responses once the retry policy gives up, so
+        // failing to send means the connection failed, and error messages that cannot be parsed
+        // come from eg. a reverse proxy in front of a server that is down
+        matches!(
+            self,
+            Error::SendingRequest(_) | Error::ParsingError(_) | Error::Server(_)
+        )
+    }
+}
+
+/// Builds the URL for an API endpoint, `host` possibly including a base path
+fn api_url(host: &str, endpoint: &str) -> String {
+    format!("{}/api/{}", host.trim_end_matches('/'), endpoint)
+}
+
+/// Builds the URL for a websocket endpoint, `host` possibly including a base path
+fn ws_url(host: &str, endpoint: &str) -> String {
+    format!(
+        "ws{}/ws/{}",
+        host.trim_end_matches('/')
+            .strip_prefix("http")
+            .expect("TODO"),
+        endpoint
+    )
 }
 
 async fn submit<T: for<'de> serde::Deserialize<'de>>(
@@ -38,28 +76,57 @@ async fn submit<T: for<'de> serde::Deserialize<'de>>(
     if resp.status() == reqwest::StatusCode::OK {
         return resp.json().await.map_err(Error::ParsingResponse);
     }
+    Err(parse_error(resp).await)
+}
+
+async fn parse_error(resp: reqwest::Response) -> Error {
+    let status = resp.status();
     let resp = match resp.bytes().await {
         Ok(resp) => resp,
-        Err(e) => return Err(Error::ParsingResponse(e)),
+        Err(e) => return Error::ParsingResponse(e),
     };
     match api::Error::parse(&resp) {
-        Ok(err) => Err(Error::Api(err)),
-        Err(err) => Err(Error::ParsingError(err)),
+        // Maintenance mode is a server error status too, but one the user can be told about
+        Ok(api::Error::ReadOnly) => Error::Api(api::Error::ReadOnly),
+        Ok(_) if status.is_server_error() => Error::Server(status),
+        Ok(err) => Error::Api(err),
+        Err(err) => Error::ParsingError(err),
     }
 }
 
 pub async fn auth(host: String, session: api::NewSession) -> Result<api::AuthToken, Error> {
+    submit(crate::CLIENT.post(api_url(&host, "auth")).json(&session)).await
+}
+
+pub async fn fetch_instance_info(host: String) -> Result<api::InstanceInfo, Error> {
+    submit(crate::CLIENT.get(api_url(&host, "instance-info"))).await
+}
+
+pub async fn fetch_changelog(login: LoginInfo) -> Result<Vec<api::ChangelogEntry>, Error> {
     submit(
         crate::CLIENT
-            .post(format!("{}/api/auth", host))
-            .json(&session),
+            .get(api_url(&login.host, "changelog"))
+            .bearer_auth(login.token.0),
     )
     .await
 }
 
+pub async fn register(host: String, registration: api::Registration) -> Result<(), Error> {
+    let resp = crate::CLIENT
+        .post(api_url(&host, "register"))
+        .json(&registration)
+        .send()
+        .await
+        .map_err(Error::SendingRequest)?;
+    if resp.status().is_success() {
+        return Ok(());
+    }
+    Err(parse_error(resp).await)
+}
+
 pub async fn unauth(host: String, token: api::AuthToken) {
     let resp = crate::CLIENT
-        .post(format!("{}/api/unauth", host))
+        .post(api_url(&host, "unauth"))
         .bearer_auth(token.0)
         .send()
         .await;
@@ -78,10 +145,8 @@ where
 {
     // TODO: at least handle unauthorized error
     let req = match body {
-        None => crate::CLIENT.get(format!("{}/api/{}", login.host, fetcher)),
-        Some(body) => crate::CLIENT
-            .post(format!("{}/api/{}", login.host, fetcher))
-            .json(body),
+        None => crate::CLIENT.get(api_url(&login.host, fetcher)),
+        Some(body) => crate::CLIENT.post(api_url(&login.host, fetcher)).json(body),
     };
     req.bearer_auth(login.token.0)
         .send()
@@ -92,7 +157,8 @@ where
         .expect("failed to parse data from server") // TODO: should eg be a popup
 }
 
-async fn fetch_db_dump(login: &LoginInfo) -> DbDump {
+/// Returns the database, along with whether the server left out some of the tasks
+async fn fetch_db_dump(login: &LoginInfo) -> (DbDump, bool) {
     let mut db = DbDump {
         owner: fetch(login, "whoami", None).await,
         users: im::HashMap::new(),
@@ -100,17 +166,18 @@ async fn fetch_db_dump(login: &LoginInfo) -> DbDump {
         searches: im::HashMap::new(),
         perms: im::HashMap::new(),
         tasks: im::HashMap::new(),
+        index: TaskIndex::default(),
     };
 
     db.add_users(fetch(login, "fetch-users", None).await);
     db.add_tags(fetch(login, "fetch-tags", None).await);
     db.add_searches(fetch(login, "fetch-searches", None).await);
-    let (tasks, events): (Vec<api::Task>, Vec<api::Event>) =
+    let res: api::SearchResults =
         fetch(login, "search-tasks", Some(&api::Query::Archived(false))).await;
-    db.add_tasks(tasks);
-    db.add_events_and_refresh_all(events);
+    db.add_tasks(res.tasks);
+    db.add_events_and_refresh_all(res.events);
 
-    db
+    (db, res.truncated)
 }
 
 async fn sleep_for(d: chrono::Duration) {
@@ -120,7 +187,35 @@ async fn sleep_for(d: chrono::Duration) {
 }
 
 async fn sleep_until(t: Time) {
-    sleep_for(t - Utc::now()).await
+    sleep_for(t - crate::CLOCK.now()).await
+}
+
+/// Waits until the server at `host` successfully answers again, bypassing the retry policy
+pub async fn wait_for_connectivity(host: String) {
+    loop {
+        let resp = reqwest::Client::new()
+            .get(api_url(&host, "instance-info"))
+            .send()
+            .await;
+        match resp {
+            Ok(resp) if resp.status().is_success() => return,
+            Ok(resp) => tracing::debug!(status = ?resp.status(), "server is still failing"),
+            Err(err) => tracing::debug!(?err, "server is still unreachable"),
+        }
+        sleep_for(chrono::Duration::seconds(CONNECTIVITY_CHECK_INTERVAL_SECS)).await;
+    }
+}
+
+/// Waits until the server at `host` leaves maintenance mode
+pub async fn wait_for_writable(host: String) {
+    loop {
+        sleep_for(chrono::Duration::seconds(CONNECTIVITY_CHECK_INTERVAL_SECS)).await;
+        match fetch_instance_info(host.clone()).await {
+            Ok(info) if !info.read_only => return,
+            Ok(_) => tracing::debug!("server is still in maintenance mode"),
+            Err(err) => tracing::debug!(?err, "failed checking for maintenance mode"),
+        }
+    }
 }
 
 pub async fn start_event_feed(
@@ -130,6 +225,9 @@ pub async fn start_event_feed(
 ) {
     let mut first_attempt = true;
     'reconnect: loop {
+        if cancel.is_canceled() {
+            return;
+        }
         match first_attempt {
             true => first_attempt = false,
             false => {
@@ -140,10 +238,7 @@ pub async fn start_event_feed(
         }
 
         // Connect to websocket
-        let ws_url = format!(
-            "ws{}/ws/action-feed",
-            login.host.strip_prefix("http").expect("TODO")
-        );
+        let ws_url = ws_url(&login.host, "action-feed");
         let mut sock = match WsMeta::connect(ws_url, None).await {
             Ok((_, s)) => s,
             Err(_) => continue 'reconnect, // TODO: maybe the url is no tthe right one?
@@ -160,25 +255,31 @@ pub async fn start_event_feed(
             Some(r) => r,
             None => continue 'reconnect,
         };
-        assert_eq!(res, WsMessage::Text("ok".into())); // TODO: handle permission denied response
+        assert_eq!(res, WsMessage::Text(api::FEED_AUTH_OK.into())); // TODO: handle permission denied response
         tracing::info!("successfully authenticated to event feed");
         feed_sender.send_message(ui::AppMsg::WebsocketConnected);
 
         // Fetch the database
         // TODO: this should happen async from the websocket handling to not risk stalling the connection.
         // ui::App should already be ready to handle it thanks to its connection_state member
-        let db = fetch_db_dump(&login).await;
-        tracing::info!("successfully fetched database");
-        feed_sender.send_message(ui::AppMsg::ReceivedDb(db));
+        let (db, truncated) = fetch_db_dump(&login).await;
+        if cancel.is_canceled() {
+            return;
+        }
+        tracing::info!(?truncated, "successfully fetched database");
+        feed_sender.send_message(ui::AppMsg::ReceivedDb(db, truncated));
 
         // Finally, run the event feed
-        let mut next_ping = Utc::now();
-        let mut last_pong = Utc::now();
+        let mut next_ping = crate::CLOCK.now();
+        let mut last_pong = crate::CLOCK.now();
         let mut sock = sock.fuse();
         let mut cancellation = cancel.cancellation().fuse();
         loop {
-            let delay_pong_reception =
-                sleep_until(last_pong + chrono::Duration::seconds(DISCONNECT_INTERVAL_SECS)).fuse();
+            let ping_interval = chrono::Duration::seconds(match util::page_hidden() {
+                true => HIDDEN_PING_INTERVAL_SECS,
+                false => PING_INTERVAL_SECS,
+            });
+            let delay_pong_reception = sleep_until(last_pong + ping_interval * 2).fuse();
             let delay_ping_send = sleep_until(next_ping).fuse();
             pin_mut!(delay_ping_send, delay_pong_reception);
             select! {
@@ -189,18 +290,28 @@ pub async fn start_event_feed(
                 }
                 _ = delay_pong_reception => continue 'reconnect,
                 _ = delay_ping_send => {
-                    sock.send(WsMessage::Text("ping".to_string())).await.expect("TODO");
-                    next_ping += chrono::Duration::seconds(PING_INTERVAL_SECS);
+                    sock.send(WsMessage::Text(api::FEED_PING.to_string())).await.expect("TODO");
+                    next_ping += ping_interval;
                 }
                 msg = sock.next() => {
-                    let msg: api::FeedMessage = match msg {
+                    let msg: Result<api::FeedMessage, _> = match msg {
                         None => continue 'reconnect,
                         Some(WsMessage::Text(t)) => serde_json::from_str(&t),
                         Some(WsMessage::Binary(b)) => serde_json::from_slice(&b),
-                    }.expect("TODO");
+                    };
+                    let msg = match msg {
+                        Ok(msg) => msg,
+                        Err(err) => {
+                            // Most likely a message introduced by a server upgrade
+                            tracing::error!(?err, "failed parsing feed message");
+                            feed_sender.send_message(ui::AppMsg::ClientOutdated);
+                            return;
+                        }
+                    };
                     match msg {
-                        api::FeedMessage::Pong => last_pong = Utc::now(),
+                        api::FeedMessage::Pong => last_pong = crate::CLOCK.now(),
                         api::FeedMessage::Action(a) => feed_sender.send_message(ui::AppMsg::NewNetworkAction(a)),
+                        api::FeedMessage::Actions(a) => feed_sender.send_message(ui::AppMsg::NewNetworkActions(a)),
                     }
                 }
             }
@@ -208,18 +319,263 @@ pub async fn start_event_feed(
     }
 }
 
-pub async fn send_action(login: &LoginInfo, action: api::Action) {
-    let res = crate::CLIENT
-        .post(format!("{}/api/submit-action", login.host))
+/// Returns the subset of `events` that the server already committed
+pub async fn committed_events(
+    login: &LoginInfo,
+    events: Vec<api::EventId>,
+) -> Result<Vec<api::EventId>, Error> {
+    submit(
+        crate::CLIENT
+            .post(api_url(&login.host, "committed-events"))
+            .bearer_auth(login.token.0)
+            .json(&events),
+    )
+    .await
+}
+
+pub async fn send_action(login: &LoginInfo, action: api::Action) -> Result<(), Error> {
+    let resp = crate::CLIENT
+        .post(api_url(&login.host, "submit-action"))
         .bearer_auth(login.token.0)
+        .header(api::APP_VERSION_HEADER, APP_VERSION)
         .json(&action)
         .send()
-        .await;
-    match res {
-        // TODO: panicking on server message is Bad(tm)
-        // TODO: at least handle 403 forbidden answers
-        Ok(r) if r.status().is_success() => (),
-        Ok(r) => panic!("got non-successful response to event submission: {:?}", r),
-        Err(e) => panic!("got reqwest error {:?}", e),
+        .await
+        .map_err(Error::SendingRequest)?;
+    if resp.status().is_success() {
+        return Ok(());
+    }
+    Err(parse_error(resp).await)
+}
+
+/// Asks the server whether submitting `action` would succeed, without submitting it
+pub async fn validate_action(login: LoginInfo, action: api::Action) -> Result<(), Error> {
+    let resp = crate::CLIENT
+        .post(api_url(&login.host, "validate-action"))
+        .bearer_auth(login.token.0)
+        .json(&action)
+        .send()
+        .await
+        .map_err(Error::SendingRequest)?;
+    if resp.status().is_success() {
+        return Ok(());
+    }
+    Err(parse_error(resp).await)
+}
+
+pub async fn fetch_activity(
+    login: LoginInfo,
+    page: api::ActivityPage,
+) -> Result<Vec<api::Event>, Error> {
+    submit(
+        crate::CLIENT
+            .post(api_url(&login.host, "fetch-activity"))
+            .bearer_auth(login.token.0)
+            .json(&page),
+    )
+    .await
+}
+
+/// Builds the URL at which the avatar with hash `hash` can be fetched
+pub fn avatar_url(host: &str, hash: &str) -> String {
+    api_url(host, &format!("avatars/{hash}"))
+}
+
+pub async fn set_avatar(
+    login: LoginInfo,
+    content_type: String,
+    data: Vec<u8>,
+) -> Result<String, Error> {
+    submit(
+        crate::CLIENT
+            .post(api_url(&login.host, "set-avatar"))
+            .bearer_auth(login.token.0)
+            .header(reqwest::header::CONTENT_TYPE, content_type)
+            .body(data),
+    )
+    .await
+}
+
+/// Builds the URL of the public badge for a search shared with `token`
+pub fn badge_url(host: &str, token: &Uuid) -> String {
+    api_url(host, &format!("badge/{token}.svg"))
+}
+
+/// Creates a new share token for `search`, revoking the previous one
+pub async fn share_search(login: LoginInfo, search: api::SearchId) -> Result<Uuid, Error> {
+    submit(
+        crate::CLIENT
+            .post(api_url(&login.host, "share-search"))
+            .bearer_auth(login.token.0)
+            .json(&search),
+    )
+    .await
+}
+
+/// Builds the URL of the Atom feed of a search readable with `token`
+pub fn feed_url(host: &str, token: &Uuid) -> String {
+    api_url(host, &format!("feeds/{token}.atom"))
+}
+
+/// Creates a new feed token for `search`, revoking the previous one
+pub async fn create_search_feed(login: LoginInfo, search: api::SearchId) -> Result<Uuid, Error> {
+    submit(
+        crate::CLIENT
+            .post(api_url(&login.host, "create-search-feed"))
+            .bearer_auth(login.token.0)
+            .json(&search),
+    )
+    .await
+}
+
+/// Checks that a search exported by another user can be imported, without importing it
+pub async fn validate_search(login: LoginInfo, search: api::SearchExport) -> Result<(), Error> {
+    let resp = crate::CLIENT
+        .post(api_url(&login.host, "validate-search"))
+        .bearer_auth(login.token.0)
+        .json(&search)
+        .send()
+        .await
+        .map_err(Error::SendingRequest)?;
+    if resp.status().is_success() {
+        return Ok(());
     }
+    Err(parse_error(resp).await)
+}
+
+pub async fn import_search(
+    login: LoginInfo,
+    search: api::SearchExport,
+) -> Result<api::Search, Error> {
+    submit(
+        crate::CLIENT
+            .post(api_url(&login.host, "import-search"))
+            .bearer_auth(login.token.0)
+            .json(&search),
+    )
+    .await
+}
+
+pub async fn fetch_rules(login: LoginInfo) -> Result<Vec<api::Rule>, Error> {
+    submit(
+        crate::CLIENT
+            .get(api_url(&login.host, "fetch-rules"))
+            .bearer_auth(login.token.0),
+    )
+    .await
+}
+
+pub async fn set_rule(login: LoginInfo, rule: api::Rule) -> Result<(), Error> {
+    let resp = crate::CLIENT
+        .post(api_url(&login.host, "set-rule"))
+        .bearer_auth(login.token.0)
+        .json(&rule)
+        .send()
+        .await
+        .map_err(Error::SendingRequest)?;
+    if resp.status().is_success() {
+        return Ok(());
+    }
+    Err(parse_error(resp).await)
+}
+
+pub async fn delete_rule(login: LoginInfo, rule: api::RuleId) -> Result<(), Error> {
+    let resp = crate::CLIENT
+        .post(api_url(&login.host, "delete-rule"))
+        .bearer_auth(login.token.0)
+        .json(&rule)
+        .send()
+        .await
+        .map_err(Error::SendingRequest)?;
+    if resp.status().is_success() {
+        return Ok(());
+    }
+    Err(parse_error(resp).await)
+}
+
+pub async fn create_invite(login: LoginInfo, invite: api::NewInvite) -> Result<Uuid, Error> {
+    submit(
+        crate::CLIENT
+            .post(api_url(&login.host, "create-invite"))
+            .bearer_auth(login.token.0)
+            .json(&invite),
+    )
+    .await
+}
+
+pub async fn set_tag_permission(login: LoginInfo, perm: api::TagPermission) -> Result<(), Error> {
+    let resp = crate::CLIENT
+        .post(api_url(&login.host, "set-tag-permission"))
+        .bearer_auth(login.token.0)
+        .json(&perm)
+        .send()
+        .await
+        .map_err(Error::SendingRequest)?;
+    if resp.status().is_success() {
+        return Ok(());
+    }
+    Err(parse_error(resp).await)
+}
+
+pub async fn set_tag_sections(login: LoginInfo, sections: api::TagSections) -> Result<(), Error> {
+    let resp = crate::CLIENT
+        .post(api_url(&login.host, "set-tag-sections"))
+        .bearer_auth(login.token.0)
+        .json(&sections)
+        .send()
+        .await
+        .map_err(Error::SendingRequest)?;
+    if resp.status().is_success() {
+        return Ok(());
+    }
+    Err(parse_error(resp).await)
+}
+
+pub async fn fetch_duplicates(login: LoginInfo) -> Result<Vec<api::DuplicateCandidate>, Error> {
+    submit(
+        crate::CLIENT
+            .get(api_url(&login.host, "fetch-duplicates"))
+            .bearer_auth(login.token.0),
+    )
+    .await
+}
+
+pub async fn submit_telemetry(login: LoginInfo, report: api::TelemetryReport) -> Result<(), Error> {
+    let resp = crate::CLIENT
+        .post(api_url(&login.host, "submit-telemetry"))
+        .bearer_auth(login.token.0)
+        .json(&report)
+        .send()
+        .await
+        .map_err(Error::SendingRequest)?;
+    if resp.status().is_success() {
+        return Ok(());
+    }
+    Err(parse_error(resp).await)
+}
+
+pub async fn fetch_tag_permissions(
+    login: LoginInfo,
+    tag: api::TagId,
+) -> Result<Vec<(api::UserId, api::AuthInfo)>, Error> {
+    submit(
+        crate::CLIENT
+            .post(api_url(&login.host, "fetch-tag-permissions"))
+            .bearer_auth(login.token.0)
+            .json(&tag),
+    )
+    .await
+}
+
+pub async fn fetch_event_provenance(
+    login: LoginInfo,
+    events: Vec<api::EventId>,
+) -> Result<Vec<api::EventProvenance>, Error> {
+    submit(
+        crate::CLIENT
+            .post(api_url(&login.host, "event-provenance"))
+            .bearer_auth(login.token.0)
+            .json(&events),
+    )
+    .await
 }
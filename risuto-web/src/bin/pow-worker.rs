@@ -0,0 +1,9 @@
+use gloo_worker::Registrable;
+
+// risuto-web has no library target, so include the worker definition directly
+#[path = "../pow_worker.rs"]
+mod pow_worker;
+
+fn main() {
+    pow_worker::PowWorker::registrar().register();
+}
@@ -2,25 +2,37 @@
 
 use gloo_storage::{LocalStorage, Storage};
 use risuto_client::api::AuthToken;
+use std::rc::Rc;
 use wasm_bindgen_futures::spawn_local;
 use yew::prelude::*;
 
 mod api;
+mod pow_worker;
 mod ui;
 mod util;
 
-const KEY_LOGIN: &str = "login";
+const KEY_LOGIN: &str = "login"; // currently active account
+const KEY_ACCOUNTS: &str = "accounts";
 
 lazy_static::lazy_static! {
-    static ref CLIENT: reqwest_middleware::ClientWithMiddleware = reqwest_middleware::ClientBuilder::new(reqwest::Client::new())
-        .with(reqwest_retry::RetryTransientMiddleware::new_with_policy(
-            reqwest_retry::policies::ExponentialBackoff::builder()
-                .retry_bounds(std::time::Duration::from_secs(1), std::time::Duration::from_secs(30))
-                .build_with_max_retries(u32::MAX)
-                // we can stay a long time off-network, and concurrent requests are limited to 1 anyway with the submission queue
-                // (except for the unauth requests but we do want to try our best actually delivering them too anyway)
-        ))
-        .build();
+    /// Source of the current time for everything the client computes or submits
+    static ref CLOCK: risuto_client::api::Clock = risuto_client::api::Clock::system();
+
+    static ref CLIENT: reqwest_middleware::ClientWithMiddleware = {
+        // Going over the retries makes requests fail with a network error, upon which ui::App
+        // considers the server unreachable and keeps the submission queue until it comes back
+        let policy = util::retry_policy();
+        reqwest_middleware::ClientBuilder::new(reqwest::Client::new())
+            .with(reqwest_retry::RetryTransientMiddleware::new_with_policy(
+                reqwest_retry::policies::ExponentialBackoff::builder()
+                    .retry_bounds(
+                        std::time::Duration::from_secs(policy.min_interval_secs),
+                        std::time::Duration::from_secs(policy.max_interval_secs),
+                    )
+                    .build_with_max_retries(policy.max_retries.unwrap_or(u32::MAX))
+            ))
+            .build()
+    };
 }
 
 fn main() {
@@ -64,23 +76,57 @@ pub struct LoginInfo {
     token: AuthToken,
 }
 
+impl LoginInfo {
+    /// Identifies the account this login is for, independently of the session token
+    pub fn account_id(&self) -> String {
+        format!("{}@{}", self.user, self.host)
+    }
+}
+
 pub enum MainMsg {
     Login(LoginInfo),
     Logout,
+    SwitchAccount(LoginInfo),
+    AddAccount,
+    CancelAddAccount,
 }
 
 pub struct Main {
+    accounts: Rc<Vec<LoginInfo>>,
     login: Option<LoginInfo>,
     logout: Option<LoginInfo>, // info saved from login info, without the token
 }
 
+impl Main {
+    fn save_accounts(&self) {
+        LocalStorage::set(KEY_ACCOUNTS, &*self.accounts)
+            .expect("failed saving accounts to LocalStorage");
+    }
+
+    fn set_active_account(&mut self, login: Option<LoginInfo>) {
+        match &login {
+            Some(info) => LocalStorage::set(KEY_LOGIN, info)
+                .expect("failed saving login info to LocalStorage"),
+            None => LocalStorage::delete(KEY_LOGIN),
+        }
+        self.login = login;
+    }
+}
+
 impl Component for Main {
     type Message = MainMsg;
     type Properties = ();
 
     fn create(_ctx: &Context<Self>) -> Self {
+        let login: Option<LoginInfo> = LocalStorage::get(KEY_LOGIN).ok();
+        let mut accounts: Vec<LoginInfo> = LocalStorage::get(KEY_ACCOUNTS).unwrap_or_default();
+        if accounts.is_empty() {
+            // Login info saved before multiple accounts were supported
+            accounts.extend(login.clone());
+        }
         Main {
-            login: LocalStorage::get(KEY_LOGIN).ok(),
+            accounts: Rc::new(accounts),
+            login,
             logout: None,
         }
     }
@@ -88,21 +134,37 @@ impl Component for Main {
     fn update(&mut self, _ctx: &Context<Self>, msg: Self::Message) -> bool {
         match msg {
             MainMsg::Login(info) => {
-                LocalStorage::set(KEY_LOGIN, &info)
-                    .expect("failed saving login info to LocalStorage");
-                self.login = Some(info);
+                let accounts = Rc::make_mut(&mut self.accounts);
+                match accounts
+                    .iter_mut()
+                    .find(|a| a.account_id() == info.account_id())
+                {
+                    Some(a) => *a = info.clone(),
+                    None => accounts.push(info.clone()),
+                }
+                self.save_accounts();
+                self.set_active_account(Some(info));
             }
             MainMsg::Logout => {
                 // TODO: warn the user upon logout that unsynced changes may be lost
                 let login = self.login.take().expect("got logout while not logged in");
                 spawn_local(api::unauth(login.host.clone(), login.token));
-                LocalStorage::delete(KEY_LOGIN);
+                Rc::make_mut(&mut self.accounts).retain(|a| a.account_id() != login.account_id());
+                self.save_accounts();
+                self.set_active_account(self.accounts.first().cloned());
                 self.logout = Some(LoginInfo {
                     host: login.host,
                     user: login.user,
                     token: AuthToken::stub(),
                 });
             }
+            MainMsg::SwitchAccount(info) => self.set_active_account(Some(info)),
+            MainMsg::AddAccount => {
+                // Do not touch the saved active account, so that reloading goes back to it
+                self.login = None;
+                self.logout = None;
+            }
+            MainMsg::CancelAddAccount => self.login = LocalStorage::get(KEY_LOGIN).ok(),
         }
         true
     }
@@ -114,13 +176,21 @@ impl Component for Main {
                     <ui::Login
                         info={self.logout.clone()}
                         on_authed={ctx.link().callback(MainMsg::Login)}
+                        on_cancel={
+                            (!self.accounts.is_empty())
+                                .then(|| ctx.link().callback(|_| MainMsg::CancelAddAccount))
+                        }
                     />
                 </div>
             },
             Some(login) => html! {
                 <ui::App
+                    key={login.account_id()}
                     login={login.clone()}
+                    accounts={self.accounts.clone()}
                     on_logout={ctx.link().callback(|_| MainMsg::Logout)}
+                    on_switch_account={ctx.link().callback(MainMsg::SwitchAccount)}
+                    on_add_account={ctx.link().callback(|_| MainMsg::AddAccount)}
                 />
             },
         }
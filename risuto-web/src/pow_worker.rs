@@ -0,0 +1,26 @@
+//! Web worker computing the login proof-of-work, which would otherwise block the UI thread
+//! for seconds on slow devices.
+//!
+//! This file is shared between the main binary and the `pow-worker` binary, so it must not
+//! depend on anything else from risuto-web.
+
+use gloo_worker::{HandlerId, Worker, WorkerScope};
+use risuto_client::api::NewSession;
+
+pub struct PowWorker;
+
+impl Worker for PowWorker {
+    type Message = ();
+    type Input = String; // password
+    type Output = String; // proof of work
+
+    fn create(_scope: &WorkerScope<Self>) -> Self {
+        PowWorker
+    }
+
+    fn update(&mut self, _scope: &WorkerScope<Self>, _msg: Self::Message) {}
+
+    fn received(&mut self, scope: &WorkerScope<Self>, password: Self::Input, id: HandlerId) {
+        scope.respond(id, NewSession::compute_pow(&password));
+    }
+}
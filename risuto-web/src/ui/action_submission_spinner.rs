@@ -1,35 +1,78 @@
-use risuto_client::api::Action;
-use std::collections::VecDeque;
+use risuto_client::{api::Action, ActionExt, DbDump};
+use std::{collections::VecDeque, rc::Rc};
 use yew::prelude::*;
 
+use crate::{ui, util};
+
 #[derive(Clone, PartialEq, Properties)]
 pub struct ActionSubmissionSpinnerProps {
+    pub db: Rc<DbDump>,
     pub actions_pending_submission: VecDeque<Action>,
+    pub actions_dead_letter: Rc<Vec<ui::DeadLetter>>,
+    pub on_retry_dead_letter: Callback<usize>,
+    pub on_discard_dead_letter: Callback<usize>,
 }
 
 #[function_component(ActionSubmissionSpinner)]
 pub fn action_submission_spinner(p: &ActionSubmissionSpinnerProps) -> Html {
+    let has_dead_letters = !p.actions_dead_letter.is_empty();
+    let nothing_to_show = p.actions_pending_submission.is_empty() && !has_dead_letters;
     html! {
         <div class="float-above dropdown">
             <button
                 class={ classes!(
                     "events-pending-spinner",
-                    p.actions_pending_submission.is_empty().then(|| "no-events"),
-                    "btn", "btn-secondary", "btn-circle", "mt-3"
+                    nothing_to_show.then(|| "no-events"),
+                    "btn", if has_dead_letters { "btn-danger" } else { "btn-secondary" }, "btn-circle", "mt-3"
                 ) }
                 type="button"
                 data-bs-toggle="dropdown"
             >
-                <span class="spinner-border spinner-border-sm" role="status" aria-hidden="true"></span>
-                <span class="visually-hidden">{ "Submitting events..." }</span>
+                if p.actions_pending_submission.is_empty() {
+                    <span class="bi-exclamation-triangle-fill" aria-hidden="true"></span>
+                    <span class="visually-hidden">{ "Some events failed submission" }</span>
+                } else {
+                    <span class="spinner-border spinner-border-sm" role="status" aria-hidden="true"></span>
+                    <span class="visually-hidden">{ "Submitting events..." }</span>
+                }
             </button>
             <ul class={ classes!(
                 "events-pending-list",
-                p.actions_pending_submission.is_empty().then(|| "no-events"),
+                nothing_to_show.then(|| "no-events"),
                 "dropdown-menu", "dropdown-menu-dark"
             ) }>
                 { for p.actions_pending_submission.iter().map(|e| html! {
-                    <li>{ format!("{:?}", e) }</li> // TODO: make events prettier
+                    <li>{ e.describe(&p.db) }</li>
+                }) }
+                if has_dead_letters {
+                    <li><h6 class="dropdown-header">{ "Failed submission" }</h6></li>
+                }
+                { for p.actions_dead_letter.iter().enumerate().map(|(i, d)| {
+                    let export = {
+                        let action = d.action.clone();
+                        Callback::from(move |_| {
+                            let json = serde_json::to_string_pretty(&action)
+                                .expect("failed serializing action");
+                            util::download("failed-action.json", "application/json", &json);
+                        })
+                    };
+                    html! {
+                        <li class="px-3 py-1">
+                            <div>{ d.action.describe(&p.db) }</div>
+                            <div class="text-danger small">{ &d.error }</div>
+                            <div class="btn-group btn-group-sm mt-1">
+                                <button type="button" class="btn btn-outline-light" onclick={p.on_retry_dead_letter.reform(move |_| i)}>
+                                    { "Retry" }
+                                </button>
+                                <button type="button" class="btn btn-outline-light" onclick={p.on_discard_dead_letter.reform(move |_| i)}>
+                                    { "Discard" }
+                                </button>
+                                <button type="button" class="btn btn-outline-light" onclick={export}>
+                                    { "Export" }
+                                </button>
+                            </div>
+                        </li>
+                    }
                 }) }
             </ul>
         </div>
@@ -0,0 +1,235 @@
+use std::{collections::HashMap, rc::Rc};
+
+use futures::FutureExt;
+use risuto_client::{
+    api::{ActivityPage, Event, EventData, EventId, EventProvenance, TagId},
+    date, DbDump,
+};
+use yew::prelude::*;
+
+use crate::{api, util, LoginInfo};
+
+const PAGE_SIZE: i64 = 50;
+
+#[derive(Clone, PartialEq, Properties)]
+pub struct ActivityViewProps {
+    pub login: LoginInfo,
+    pub db: Rc<DbDump>,
+    pub on_close: Callback<()>,
+}
+
+pub enum ActivityViewMsg {
+    LoadMore,
+    Loaded(Result<Vec<Event>, api::Error>),
+    ProvenanceLoaded(Result<Vec<EventProvenance>, api::Error>),
+}
+
+pub struct ActivityView {
+    events: Vec<Event>,
+    provenance: HashMap<EventId, EventProvenance>,
+    loading: bool,
+    exhausted: bool,
+    error: bool,
+}
+
+impl ActivityView {
+    fn load_more(&mut self, ctx: &Context<Self>) {
+        self.loading = true;
+        let page = ActivityPage {
+            before: self.events.last().map(|e| e.id),
+            limit: PAGE_SIZE,
+        };
+        ctx.link().send_future(
+            api::fetch_activity(ctx.props().login.clone(), page).map(ActivityViewMsg::Loaded),
+        );
+    }
+}
+
+impl Component for ActivityView {
+    type Message = ActivityViewMsg;
+    type Properties = ActivityViewProps;
+
+    fn create(ctx: &Context<Self>) -> Self {
+        let mut this = ActivityView {
+            events: Vec::new(),
+            provenance: HashMap::new(),
+            loading: false,
+            exhausted: false,
+            error: false,
+        };
+        this.load_more(ctx);
+        this
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            ActivityViewMsg::LoadMore => {
+                if !self.loading && !self.exhausted {
+                    self.error = false;
+                    self.load_more(ctx);
+                }
+            }
+            ActivityViewMsg::Loaded(Ok(events)) => {
+                self.loading = false;
+                self.exhausted = (events.len() as i64) < PAGE_SIZE;
+                let ids = events.iter().map(|e| e.id).collect();
+                ctx.link().send_future(
+                    api::fetch_event_provenance(ctx.props().login.clone(), ids)
+                        .map(ActivityViewMsg::ProvenanceLoaded),
+                );
+                self.events.extend(events);
+            }
+            ActivityViewMsg::ProvenanceLoaded(Ok(provenance)) => {
+                self.provenance
+                    .extend(provenance.into_iter().map(|p| (p.event, p)));
+            }
+            ActivityViewMsg::ProvenanceLoaded(Err(err)) => {
+                // Provenance is only informative, so just don't show it
+                tracing::warn!(?err, "failed fetching event provenance");
+                return false;
+            }
+            ActivityViewMsg::Loaded(Err(err)) => {
+                tracing::error!(?err, "failed fetching activity");
+                self.loading = false;
+                self.error = true;
+            }
+        }
+        true
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let db = &ctx.props().db;
+        let now = crate::CLOCK.now();
+        html! {
+            <div class="container my-4">
+                <div class="d-flex align-items-center mb-4">
+                    <h1 class="flex-fill">{ "Activity" }</h1>
+                    <button
+                        type="button"
+                        class="btn btn-secondary"
+                        onclick={ctx.props().on_close.reform(|_| ())}
+                    >
+                        { "Back" }
+                    </button>
+                </div>
+                <ul class="list-group activity-list">
+                    { for self.events.iter().map(|e| html! {
+                        <li class="list-group-item">
+                            <span
+                                class="text-muted me-3"
+                                title={ date::absolute_label(&e.date, &util::local_tz()) }
+                            >
+                                { date::relative_label(&e.date, &now, &util::local_tz()) }
+                            </span>
+                            { for db.users.get(&e.owner_id).and_then(|u| u.avatar_hash.as_ref()).map(|h| html! {
+                                <img
+                                    class="avatar me-2"
+                                    src={ api::avatar_url(&ctx.props().login.host, h) }
+                                    alt=""
+                                />
+                            }) }
+                            <strong>{ user_name(db, e) }</strong>
+                            { " " }
+                            { describe(db, &e.data) }
+                            { " on " }
+                            <a href={ util::Route::Task(e.task_id, comment_of(e)).hash() }>
+                                <em>{ task_title(db, e) }</em>
+                            </a>
+                            { for self.provenance.get(&e.id).and_then(describe_provenance).map(|p| html! {
+                                <span class="text-muted ms-2">{ p }</span>
+                            }) }
+                        </li>
+                    }) }
+                </ul>
+                if self.error {
+                    <div class="alert alert-danger mt-3">
+                        { "Failed loading activity from the server" }
+                    </div>
+                }
+                if self.loading {
+                    <div class="text-center mt-3">
+                        <span class="spinner-border" role="status"></span>
+                    </div>
+                } else if !self.exhausted {
+                    <div class="text-center mt-3">
+                        <button
+                            type="button"
+                            class="btn btn-primary"
+                            onclick={ctx.link().callback(|_| ActivityViewMsg::LoadMore)}
+                        >
+                            { "Load more" }
+                        </button>
+                    </div>
+                }
+            </div>
+        }
+    }
+}
+
+fn describe_provenance(p: &EventProvenance) -> Option<String> {
+    match (&p.device, &p.app_version) {
+        (None, None) => None,
+        (Some(device), None) => Some(format!("from {device}")),
+        (None, Some(version)) => Some(format!("with {version}")),
+        (Some(device), Some(version)) => Some(format!("from {device} with {version}")),
+    }
+}
+
+fn user_name(db: &DbDump, e: &Event) -> String {
+    db.users
+        .get(&e.owner_id)
+        .map(|u| u.name.clone())
+        .unwrap_or_else(|| String::from("Unknown user"))
+}
+
+fn task_title(db: &DbDump, e: &Event) -> String {
+    db.tasks
+        .get(&e.task_id)
+        .map(|t| (*t.current_title).clone())
+        .unwrap_or_else(|| String::from("an archived task"))
+}
+
+/// Comment that `e` is about, if any
+fn comment_of(e: &Event) -> Option<EventId> {
+    match &e.data {
+        EventData::AddComment { .. } => Some(e.id),
+        EventData::EditComment { comment_id, .. } => Some(*comment_id),
+        _ => None,
+    }
+}
+
+fn tag_name(db: &DbDump, tag: &TagId) -> String {
+    db.tags
+        .get(tag)
+        .map(|t| format!("#{}", t.name))
+        .unwrap_or_else(|| String::from("a tag"))
+}
+
+fn describe(db: &DbDump, d: &EventData) -> String {
+    match d {
+        EventData::SetTitle(t) => format!("renamed to “{t}”"),
+        EventData::SetDone(true) => String::from("marked as done"),
+        EventData::SetDone(false) => String::from("marked as not done"),
+        EventData::SetArchived(true) => String::from("archived"),
+        EventData::SetArchived(false) => String::from("unarchived"),
+        EventData::BlockedUntil(Some(_)) => String::from("blocked"),
+        EventData::BlockedUntil(None) => String::from("unblocked"),
+        EventData::ScheduleFor(Some(_)) => String::from("scheduled"),
+        EventData::ScheduleFor(None) => String::from("unscheduled"),
+        EventData::SetPriority(Some(p)) => format!("set priority {}", p.name().to_uppercase()),
+        EventData::SetPriority(None) => String::from("removed the priority"),
+        EventData::SetOrder { .. } => String::from("reordered"),
+        EventData::AddTag { tag, .. } => format!("added {}", tag_name(db, tag)),
+        EventData::RmTag(tag) => format!("removed {}", tag_name(db, tag)),
+        EventData::SetSection {
+            tag,
+            section: Some(s),
+        } => format!("moved to “{s}” in {}", tag_name(db, tag)),
+        EventData::SetSection { tag, section: None } => {
+            format!("took out of the sections of {}", tag_name(db, tag))
+        }
+        EventData::AddComment { .. } => String::from("commented"),
+        EventData::EditComment { .. } => String::from("edited a comment"),
+        EventData::SetEventRead { .. } => String::from("read a comment"),
+    }
+}
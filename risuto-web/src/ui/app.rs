@@ -1,11 +1,25 @@
-use futures::{channel::oneshot, executor::block_on};
+use futures::{
+    channel::oneshot,
+    executor::block_on,
+    future::{self, AbortHandle},
+    Future, FutureExt,
+};
 use gloo_storage::{LocalStorage, Storage};
 use risuto_client::{
-    api::{Action, Event, EventData, Order, Search},
+    api::{
+        Action, ChangelogEntry, DayStart, DuplicateCandidate, Event, EventData, EventId,
+        InstanceInfo, Order, OrderId, Search, SearchExport, SearchId, Tag, TagId, TaskId,
+    },
     DbDump, Task,
 };
-use std::{collections::VecDeque, rc::Rc, sync::Arc};
-use wasm_bindgen_futures::spawn_local;
+use std::{
+    cell::RefCell,
+    collections::{HashMap, HashSet, VecDeque},
+    rc::Rc,
+    sync::Arc,
+};
+use wasm_bindgen::{closure::Closure, JsCast};
+use wasm_bindgen_futures::{spawn_local, JsFuture};
 use yew::prelude::*;
 
 use crate::{
@@ -15,24 +29,100 @@ use crate::{
 };
 
 const KEY_ACTS_PENDING_SUBMISSION: &str = "actions-pending-submission";
+const KEY_ACTS_DEAD_LETTER: &str = "actions-dead-letter";
 
 #[derive(Clone, PartialEq, Properties)]
 pub struct AppProps {
     pub login: LoginInfo,
+    pub accounts: Rc<Vec<LoginInfo>>,
     pub on_logout: Callback<()>,
+    pub on_switch_account: Callback<LoginInfo>,
+    pub on_add_account: Callback<()>,
 }
 
 pub enum AppMsg {
     Logout,
 
     WebsocketConnected,
-    ReceivedDb(DbDump),
+    /// The database, and whether the server left out some tasks because there were too many
+    ReceivedDb(DbDump, bool),
     WebsocketDisconnected,
 
     SetActiveSearch(Search),
+    /// The page location changed, eg. by following a link to a task
+    RouteChanged,
+    /// The page got hidden or shown again
+    VisibilityChanged,
+    /// Open the command palette, with the task its task actions apply to
+    OpenPalette(Option<TaskId>),
+    ClosePalette,
+    SetDayStart(DayStart),
+    SetHighlightRules(Vec<util::HighlightRule>),
+    SetView(AppView),
+    AcceptPlan(Vec<TaskId>),
+    TagUpdated(Tag),
+    MarkAllRead,
+    EditTitle(TaskId, String),
+    SetAvatar(web_sys::File),
+    ShareSearch(SearchId),
+    SubscribeSearch(SearchId),
+    ExportSearch(SearchId),
+    SearchImported(Search),
+    InstanceInfoLoaded(Result<InstanceInfo, api::Error>),
+    ChangelogLoaded(Result<Vec<ChangelogEntry>, api::Error>),
+    AcknowledgeChangelog,
+    DuplicatesLoaded(Result<Vec<DuplicateCandidate>, api::Error>),
     NewUserAction(Action),
     NewNetworkAction(Action),
+    NewNetworkActions(Vec<Action>),
+    /// Time to apply the actions received from the network since the last animation frame
+    FlushNetworkActions,
     ActionSubmissionComplete,
+    ActionSubmissionFailed(String),
+    ServerUnreachable,
+    ServerReachable,
+    ServerReadOnly,
+    ServerWritable,
+    /// Stop or resume all network requests, on the user's demand
+    SetWorkingOffline(bool),
+    ClientOutdated,
+    RetryDeadLetter(usize),
+    DiscardDeadLetter(usize),
+    ActionsAlreadyCommitted(Vec<EventId>),
+}
+
+/// Action that could not be submitted, kept aside so as not to block the rest of the queue
+#[derive(Clone, Debug, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct DeadLetter {
+    pub action: Action,
+    pub error: String,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum AppView {
+    Tasks,
+    Print,
+    Activity,
+    PlanDay,
+    TagPermissions(TagId),
+    Telemetry,
+    ImportSearch,
+    Rules,
+}
+
+impl AppView {
+    /// Name of the usage counter for opening this view, if it is a feature worth counting
+    fn feature(&self) -> Option<&'static str> {
+        match self {
+            AppView::Tasks | AppView::Telemetry => None,
+            AppView::Print => Some("print-view"),
+            AppView::Activity => Some("activity-view"),
+            AppView::PlanDay => Some("plan-day"),
+            AppView::TagPermissions(_) => Some("tag-permissions"),
+            AppView::ImportSearch => Some("import-search"),
+            AppView::Rules => Some("rules"),
+        }
+    }
 }
 
 #[derive(Clone, PartialEq)]
@@ -44,10 +134,62 @@ pub enum ConnState {
 
 pub struct App {
     db: Rc<DbDump>,
+    /// Dump as last received from the server, before applying the local changes on top of it
+    synced_db: Rc<DbDump>,
+    /// Whether the server left out some tasks from `db`, because there were too many
+    db_truncated: bool,
+    /// Tasks that may be duplicates of each task, according to the server
+    duplicates: Rc<HashMap<TaskId, Vec<TaskId>>>,
     connection_state: ConnState,
+    /// Whether the last http request reached the server, independently of the websocket
+    server_reachable: bool,
+    /// Whether the server is in maintenance mode, holding back the submission queue
+    read_only: bool,
+    /// Set when the server no longer supports this version of the app, until the page is refreshed
+    outdated: bool,
+    /// Whether the user chose to stop all network requests, until they go back online
+    working_offline: bool,
     active_search: Search,
+    day_start: DayStart,
+    highlight_rules: Rc<Vec<util::HighlightRule>>,
+    view: AppView,
+    instance: Option<Rc<InstanceInfo>>,
+    /// Changelog entries the user did not acknowledge yet, shown until they do
+    changelog: Option<Rc<Vec<ChangelogEntry>>>,
     actions_pending_submission: VecDeque<Action>, // push_back, pop_front
+    actions_pending_submission_key: String,       // one queue per account
+    actions_dead_letter: Rc<Vec<DeadLetter>>,
+    actions_dead_letter_key: String,
     feed_canceller: oneshot::Receiver<()>,
+    /// Submission of the action at the head of the queue, if any is in flight
+    submission: Option<AbortHandle>,
+    /// Checks for the server to be reachable or to leave maintenance mode, if any is running
+    reachability_check: Option<AbortHandle>,
+    writability_check: Option<AbortHandle>,
+    /// Route from the page location, waiting for the database to be loaded to be followed
+    pending_route: Option<util::Route>,
+    /// Task to scroll to once it is rendered
+    scroll_to_task: Option<TaskId>,
+    route_listener: Closure<dyn Fn()>,
+    /// Whether the page is hidden, in which case actions from the network wait in `actions_buffered`
+    hidden: bool,
+    /// Actions from the network, waiting for the next animation frame or for the page to be shown
+    actions_buffered: Vec<Action>,
+    /// Whether a `FlushNetworkActions` is already due at the next animation frame
+    flush_requested: bool,
+    visibility_listener: Closure<dyn Fn()>,
+    /// `Some` while the command palette is open, with the task its task actions apply to
+    palette: Option<Option<TaskId>>,
+    palette_listener: Closure<dyn Fn(web_sys::KeyboardEvent)>,
+
+    /// Task lists of the active search, along with the dump and search they were computed from
+    task_lists: RefCell<Option<(Rc<DbDump>, Search, TaskLists)>>,
+    /// Dump and search the browser tab title was last computed from
+    tab_status_of: Option<(Rc<DbDump>, Search)>,
+
+    // Callbacks kept across renders, so that the task lists do not re-render for nothing
+    on_action: Callback<Action>,
+    on_title_change: Callback<(TaskId, String)>,
 }
 
 #[derive(Clone)]
@@ -58,42 +200,166 @@ struct TaskLists {
 }
 
 impl App {
+    fn save_actions_pending_submission(&self) {
+        LocalStorage::set(
+            &self.actions_pending_submission_key,
+            &self.actions_pending_submission,
+        )
+        .expect("failed saving queue to local storage");
+    }
+
+    fn save_actions_dead_letter(&self) {
+        LocalStorage::set(&self.actions_dead_letter_key, &*self.actions_dead_letter)
+            .expect("failed saving dead letter list to local storage");
+    }
+
+    /// Removes the currently-submitted action from the queue, and starts submitting the next one
+    fn pop_submitted_action(&mut self, ctx: &Context<Self>) -> Option<Action> {
+        let res = self.actions_pending_submission.pop_front();
+        self.save_actions_pending_submission();
+        self.submit_queue_head(ctx);
+        res
+    }
+
+    /// Starts submitting the action at the head of the queue, unless working offline
+    fn submit_queue_head(&mut self, ctx: &Context<Self>) {
+        self.submission = None;
+        if self.working_offline {
+            return;
+        }
+        if let Some(a) = self.actions_pending_submission.front() {
+            self.submission = Some(send_action(ctx, a.clone()));
+        }
+    }
+
     fn locally_insert_new_action(&mut self, a: Action) {
-        let db = Rc::make_mut(&mut self.db);
-        match a {
-            Action::NewUser(u) => {
-                db.add_users(vec![u]);
-            }
-            Action::NewTask(t, top_comm) => {
-                let mut task = Task::from(t.clone());
-                task.add_event(Event {
-                    id: t.top_comment_id,
-                    owner_id: t.owner_id,
-                    date: t.date,
-                    task_id: t.id,
-                    data: EventData::AddComment {
-                        text: top_comm,
-                        parent_id: None,
-                    },
-                });
-                task.refresh_metadata(&db.owner);
-                db.tasks.insert(t.id, Arc::new(task));
+        // Cloning the dump when it is shared with children props is cheap, see `DbDump`
+        Rc::make_mut(&mut self.db).apply_action(a);
+    }
+
+    /// Applies the buffered network actions at the next animation frame, so that a burst of
+    /// them only sorts and renders the task lists once
+    fn request_flush(&mut self, ctx: &Context<Self>) {
+        if self.flush_requested {
+            return;
+        }
+        self.flush_requested = true;
+        let link = ctx.link().clone();
+        let flush = Closure::once_into_js(move || link.send_message(AppMsg::FlushNetworkActions));
+        web_sys::window()
+            .expect("no web_sys window")
+            .request_animation_frame(flush.unchecked_ref())
+            .expect("failed requesting animation frame");
+    }
+
+    /// Open tasks of the Today search, in their current order
+    fn today_open_tasks(&self) -> Vec<Arc<Task>> {
+        let mut tasks = self
+            .db
+            .search_at(
+                &Search::today(util::local_tz(), self.day_start),
+                &crate::CLOCK.now(),
+            )
+            .expect("Failed running today search");
+        tasks.retain(|t| !t.is_done);
+        tasks
+    }
+
+    /// Shows the search or task pointed to by `route`
+    fn apply_route(&mut self, route: util::Route) {
+        match route {
+            util::Route::Search(id) => {
+                let search = if id == SearchId::today() {
+                    Some(Search::today(util::local_tz(), self.day_start))
+                } else if id == SearchId::untagged() {
+                    Some(Search::untagged())
+                } else if let Some(search) = self.db.searches.get(&id) {
+                    Some(search.clone())
+                } else {
+                    self.db.tags.get(&TagId(id.0)).map(Search::for_tag)
+                };
+                match search {
+                    Some(search) => {
+                        self.active_search = search;
+                        self.view = AppView::Tasks;
+                    }
+                    None => tracing::warn!(?id, "followed link to unknown search"),
+                }
             }
-            Action::NewEvent(e) => match db.tasks.get_mut(&e.task_id) {
-                None => tracing::warn!(evt=?e, "got event for task not in db"),
-                Some(t) => {
-                    let task = Arc::make_mut(t);
-                    task.add_event(e);
-                    task.refresh_metadata(&db.owner);
+            // Comment threads are not displayed yet, so comment links just lead to their task
+            util::Route::Task(id, _) => {
+                let task = match self.db.tasks.get(&id) {
+                    Some(task) => task,
+                    None => {
+                        tracing::warn!(?id, "followed link to unknown task");
+                        return;
+                    }
+                };
+                let is_shown = self
+                    .db
+                    .search_at(&self.active_search, &crate::CLOCK.now())
+                    .map(|tasks| tasks.iter().any(|t| t.id == id))
+                    .unwrap_or(false);
+                if !is_shown {
+                    let mut tags = task
+                        .current_tags
+                        .keys()
+                        .filter_map(|t| self.db.tags.get(t))
+                        .collect::<Vec<_>>();
+                    util::sort_tags(&self.db.owner, &mut tags, |t| t);
+                    self.active_search = match tags.first() {
+                        Some(tag) => Search::for_tag(tag),
+                        None => Search::untagged(),
+                    };
                 }
-            },
+                self.view = AppView::Tasks;
+                self.scroll_to_task = Some(id);
+            }
         }
     }
 
+    /// Updates the browser tab title and favicon, if the dump or the active search changed
+    fn update_tab_status(&mut self) {
+        if let Some((db, search)) = &self.tab_status_of {
+            if Rc::ptr_eq(db, &self.db) && *search == self.active_search {
+                return;
+            }
+        }
+        let now = crate::CLOCK.now();
+        let overdue = self
+            .current_task_lists()
+            .open
+            .iter()
+            .filter(|t| t.scheduled_for.map(|s| s < now).unwrap_or(false))
+            .count();
+        let unread = self
+            .db
+            .tasks
+            .values()
+            .filter(|t| !t.is_archived)
+            .map(|t| t.unread_comments(&self.db.owner).len())
+            .sum();
+        util::set_tab_status(&self.active_search.name, overdue, unread);
+        self.tab_status_of = Some((self.db.clone(), self.active_search.clone()));
+    }
+
+    /// Task lists of the active search, only re-running it if the dump or the search changed
     fn current_task_lists(&self) -> TaskLists {
+        let mut cache = self.task_lists.borrow_mut();
+        if let Some((db, search, lists)) = &*cache {
+            if Rc::ptr_eq(db, &self.db) && *search == self.active_search {
+                return lists.clone();
+            }
+        }
+        let lists = self.compute_task_lists();
+        *cache = Some((self.db.clone(), self.active_search.clone(), lists.clone()));
+        lists
+    }
+
+    fn compute_task_lists(&self) -> TaskLists {
         let mut all_tasks = self
             .db
-            .search(&self.active_search)
+            .search_at(&self.active_search, &crate::CLOCK.now())
             .expect("Failed running current active search");
         match self.active_search.order {
             Order::Tag(tag) => {
@@ -129,30 +395,119 @@ impl Component for App {
     type Properties = AppProps;
 
     fn create(ctx: &Context<Self>) -> Self {
-        // Connect to websocket event feed
-        let feed_sender = ctx.link().clone();
-        let (feed_cancel_receiver, feed_canceller) = oneshot::channel();
-        spawn_local(api::start_event_feed(
-            ctx.props().login.clone(),
-            feed_sender,
-            feed_cancel_receiver,
-        ));
+        let feed_canceller = start_event_feed(ctx);
+
+        ctx.link().send_future(
+            api::fetch_instance_info(ctx.props().login.host.clone())
+                .map(AppMsg::InstanceInfoLoaded),
+        );
 
         // Load event submission queue
-        let actions_pending_submission: VecDeque<Action> =
-            LocalStorage::get(KEY_ACTS_PENDING_SUBMISSION).unwrap_or(VecDeque::new());
+        let actions_pending_submission_key = format!(
+            "{KEY_ACTS_PENDING_SUBMISSION}-{}",
+            ctx.props().login.account_id()
+        );
+        let mut actions_pending_submission: VecDeque<Action> =
+            LocalStorage::get(&actions_pending_submission_key)
+                .or_else(|_| {
+                    // Queue saved before multiple accounts were supported, there was only one account then
+                    let legacy = LocalStorage::get(KEY_ACTS_PENDING_SUBMISSION);
+                    LocalStorage::delete(KEY_ACTS_PENDING_SUBMISSION);
+                    legacy
+                })
+                .unwrap_or(VecDeque::new());
+        coalesce_actions(&mut actions_pending_submission);
+
+        // Load actions that previously failed submission
+        let actions_dead_letter_key =
+            format!("{KEY_ACTS_DEAD_LETTER}-{}", ctx.props().login.account_id());
+        let actions_dead_letter: Vec<DeadLetter> =
+            LocalStorage::get(&actions_dead_letter_key).unwrap_or_default();
 
         // Start event submission if need be
-        if !actions_pending_submission.is_empty() {
-            send_action(ctx, actions_pending_submission[0].clone());
-        }
+        let submission = actions_pending_submission
+            .front()
+            .map(|a| send_action(ctx, a.clone()));
+
+        // Follow links to searches and tasks while the app is open
+        let route_listener = {
+            let link = ctx.link().clone();
+            Closure::<dyn Fn()>::new(move || link.send_message(AppMsg::RouteChanged))
+        };
+        web_sys::window()
+            .expect("no web_sys window")
+            .add_event_listener_with_callback("hashchange", route_listener.as_ref().unchecked_ref())
+            .expect("failed listening for location changes");
+
+        // Stop refreshing the UI while the page is hidden
+        let visibility_listener = {
+            let link = ctx.link().clone();
+            Closure::<dyn Fn()>::new(move || link.send_message(AppMsg::VisibilityChanged))
+        };
+        web_sys::window()
+            .and_then(|w| w.document())
+            .expect("no web_sys document")
+            .add_event_listener_with_callback(
+                "visibilitychange",
+                visibility_listener.as_ref().unchecked_ref(),
+            )
+            .expect("failed listening for visibility changes");
+
+        // Open the command palette on Ctrl+K
+        let palette_listener = {
+            let link = ctx.link().clone();
+            Closure::<dyn Fn(web_sys::KeyboardEvent)>::new(move |e: web_sys::KeyboardEvent| {
+                if (e.ctrl_key() || e.meta_key()) && e.key().eq_ignore_ascii_case("k") {
+                    e.prevent_default();
+                    link.send_message(AppMsg::OpenPalette(util::focused_task()));
+                }
+            })
+        };
+        web_sys::window()
+            .expect("no web_sys window")
+            .add_event_listener_with_callback("keydown", palette_listener.as_ref().unchecked_ref())
+            .expect("failed listening for key presses");
 
+        let day_start = util::day_start();
         App {
             db: Rc::new(DbDump::stub()),
+            synced_db: Rc::new(DbDump::stub()),
+            db_truncated: false,
+            duplicates: Rc::new(HashMap::new()),
             connection_state: ConnState::Disconnected,
-            active_search: Search::today(util::local_tz()),
+            server_reachable: true,
+            read_only: false,
+            outdated: false,
+            working_offline: false,
+            active_search: Search::today(util::local_tz(), day_start),
+            day_start,
+            highlight_rules: Rc::new(util::highlight_rules()),
+            view: AppView::Tasks,
+            instance: None,
+            changelog: None,
             actions_pending_submission,
+            actions_pending_submission_key,
+            actions_dead_letter: Rc::new(actions_dead_letter),
+            actions_dead_letter_key,
             feed_canceller,
+            submission,
+            reachability_check: None,
+            writability_check: None,
+            pending_route: util::route_from_location(),
+            scroll_to_task: None,
+            route_listener,
+            hidden: util::page_hidden(),
+            actions_buffered: Vec::new(),
+            flush_requested: false,
+            visibility_listener,
+            palette: None,
+            palette_listener,
+            task_lists: RefCell::new(None),
+            tab_status_of: None,
+            on_action: ctx.link().callback(AppMsg::NewUserAction),
+            on_title_change: ctx
+                .link()
+                .callback(|(task, title)| AppMsg::EditTitle(task, title)),
         }
     }
 
@@ -160,17 +515,37 @@ impl Component for App {
         match msg {
             AppMsg::Logout => {
                 self.feed_canceller.close(); // This should be unneeded as it closes on drop, but better safe than sorry
-                LocalStorage::delete(KEY_ACTS_PENDING_SUBMISSION);
+                LocalStorage::delete(&self.actions_pending_submission_key);
+                LocalStorage::delete(&self.actions_dead_letter_key);
                 ctx.props().on_logout.emit(());
             }
+            AppMsg::WebsocketConnected | AppMsg::WebsocketDisconnected | AppMsg::ReceivedDb(..)
+                if self.working_offline =>
+            {
+                // Sent by the feed before it noticed it got cancelled
+                return false;
+            }
             AppMsg::WebsocketConnected => {
                 self.connection_state = ConnState::WebsocketConnected(VecDeque::new());
+                // The server may have been upgraded while we were disconnected
+                ctx.link().send_future(
+                    api::fetch_instance_info(ctx.props().login.host.clone())
+                        .map(AppMsg::InstanceInfoLoaded),
+                );
             }
             AppMsg::WebsocketDisconnected => {
                 self.connection_state = ConnState::Disconnected;
             }
-            AppMsg::ReceivedDb(db) => {
+            AppMsg::ReceivedDb(db, truncated) => {
+                // The new dump already includes the actions received until now
+                self.actions_buffered.clear();
                 self.db = Rc::new(db);
+                self.synced_db = self.db.clone();
+                self.db_truncated = truncated;
+                // Dead letters stay applied locally until they get retried or discarded
+                for d in self.actions_dead_letter.clone().iter() {
+                    self.locally_insert_new_action(d.action.clone());
+                }
                 for a in self.actions_pending_submission.clone() {
                     self.locally_insert_new_action(a.clone());
                 }
@@ -182,9 +557,245 @@ impl Component for App {
                     self.locally_insert_new_action(a);
                 }
                 self.connection_state = ConnState::Connected;
+                check_committed_actions(ctx, &self.actions_pending_submission);
+                ctx.link().send_future(
+                    api::fetch_duplicates(ctx.props().login.clone()).map(AppMsg::DuplicatesLoaded),
+                );
+                if let Some(route) = self.pending_route.take() {
+                    self.apply_route(route);
+                }
             }
             AppMsg::SetActiveSearch(search) => {
+                util::set_location_route(&util::Route::Search(search.id));
+                self.active_search = search;
+            }
+            AppMsg::RouteChanged => {
+                let route = match util::route_from_location() {
+                    Some(route) => route,
+                    None => return false,
+                };
+                match self.connection_state {
+                    ConnState::Connected => self.apply_route(route),
+                    _ => self.pending_route = Some(route),
+                }
+            }
+            AppMsg::SetDayStart(day_start) => {
+                util::save_day_start(day_start);
+                self.day_start = day_start;
+                if self.active_search.id == SearchId::today() {
+                    self.active_search = Search::today(util::local_tz(), day_start);
+                }
+            }
+            AppMsg::SetHighlightRules(rules) => {
+                util::save_highlight_rules(&rules);
+                self.highlight_rules = Rc::new(rules);
+            }
+            AppMsg::SetView(view) => {
+                if let Some(feature) = view.feature() {
+                    util::record_feature(feature);
+                }
+                self.view = view;
+            }
+            AppMsg::AcceptPlan(planned) => {
+                let today = self.today_open_tasks();
+                for e in util::compute_plan_events(self.db.owner, &planned, &today) {
+                    ctx.link()
+                        .send_message(AppMsg::NewUserAction(Action::NewEvent(e)));
+                }
+                self.active_search = Search::today(util::local_tz(), self.day_start);
+                self.view = AppView::Tasks;
+            }
+            AppMsg::TagUpdated(tag) => {
+                // The server also makes our feed resync, but showing the change can happen right away
+                Rc::make_mut(&mut self.db).tags.insert(tag.id, tag);
+            }
+            AppMsg::MarkAllRead => {
+                util::record_feature("mark-all-read");
+                let tasks = self.current_task_lists();
+                let events = tasks
+                    .open
+                    .iter()
+                    .chain(tasks.done.iter())
+                    .chain(tasks.backlog.iter())
+                    .flat_map(|t| util::mark_read_events(self.db.owner, t))
+                    .collect::<Vec<_>>();
+                for e in events {
+                    ctx.link()
+                        .send_message(AppMsg::NewUserAction(Action::NewEvent(e)));
+                }
+                return false;
+            }
+            AppMsg::EditTitle(task, title) => {
+                if let Some(task) = self.db.tasks.get(&task) {
+                    for e in util::parse_new_title(&self.db, title, task) {
+                        ctx.link()
+                            .send_message(AppMsg::NewUserAction(Action::NewEvent(e)));
+                    }
+                }
+                return false;
+            }
+            AppMsg::SetAvatar(file) => {
+                // The server relays the updated user once the avatar is stored
+                let login = ctx.props().login.clone();
+                spawn_local(async move {
+                    let data = match JsFuture::from(file.array_buffer()).await {
+                        Ok(data) => js_sys::Uint8Array::new(&data).to_vec(),
+                        Err(err) => {
+                            tracing::error!(?err, "failed reading avatar file");
+                            return;
+                        }
+                    };
+                    let message = match api::set_avatar(login, file.type_(), data).await {
+                        Ok(_) => return,
+                        Err(api::Error::Api(risuto_client::api::Error::InvalidFile(reason))) => {
+                            format!("Your avatar was rejected: {reason}")
+                        }
+                        Err(err) => {
+                            tracing::error!(?err, "failed uploading avatar");
+                            String::from("Failed uploading your avatar, please try again later")
+                        }
+                    };
+                    let window = web_sys::window().expect("no web_sys window");
+                    let _ = window.alert_with_message(&message);
+                });
+                return false;
+            }
+            AppMsg::InstanceInfoLoaded(Ok(info)) => {
+                if !info.supports_client(env!("CARGO_PKG_VERSION")) {
+                    ctx.link().send_message(AppMsg::ClientOutdated);
+                }
+                if info.read_only {
+                    ctx.link().send_message(AppMsg::ServerReadOnly);
+                }
+                if info.telemetry && util::telemetry_enabled() {
+                    send_telemetry(ctx);
+                }
+                ctx.link().send_future(
+                    api::fetch_changelog(ctx.props().login.clone()).map(AppMsg::ChangelogLoaded),
+                );
+                self.instance = Some(Rc::new(info));
+            }
+            AppMsg::ChangelogLoaded(Ok(entries)) => {
+                let account = ctx.props().login.account_id();
+                let latest = match entries.first() {
+                    None => return false,
+                    Some(e) => e.installed_at,
+                };
+                match util::changelog_seen(&account) {
+                    // New users do not need to be told what changed before they came
+                    None => {
+                        util::save_changelog_seen(&account, latest);
+                        return false;
+                    }
+                    Some(seen) => {
+                        let unseen = entries
+                            .into_iter()
+                            .filter(|e| e.installed_at > seen)
+                            .collect::<Vec<_>>();
+                        if unseen.is_empty() {
+                            return false;
+                        }
+                        self.changelog = Some(Rc::new(unseen));
+                    }
+                }
+            }
+            AppMsg::ChangelogLoaded(Err(err)) => {
+                // Only informative, so just don't show it
+                tracing::warn!(?err, "failed fetching changelog");
+                return false;
+            }
+            AppMsg::AcknowledgeChangelog => {
+                if let Some(latest) = self.changelog.take().and_then(|c| c.first().cloned()) {
+                    util::save_changelog_seen(&ctx.props().login.account_id(), latest.installed_at);
+                }
+            }
+            AppMsg::DuplicatesLoaded(Ok(candidates)) => {
+                let mut duplicates = HashMap::<TaskId, Vec<TaskId>>::new();
+                for c in candidates {
+                    duplicates.entry(c.task).or_default().push(c.other);
+                    duplicates.entry(c.other).or_default().push(c.task);
+                }
+                self.duplicates = Rc::new(duplicates);
+            }
+            AppMsg::DuplicatesLoaded(Err(err)) => {
+                // Only hints, so just don't show them
+                tracing::warn!(?err, "failed fetching duplicate candidates");
+                return false;
+            }
+            AppMsg::InstanceInfoLoaded(Err(api::Error::ParsingResponse(err))) => {
+                // Instance info is extended along with the API, so this version is likely too old
+                tracing::warn!(?err, "failed parsing instance info");
+                ctx.link().send_message(AppMsg::ClientOutdated);
+                return false;
+            }
+            AppMsg::ClientOutdated => {
+                // Stop receiving messages we may not be able to parse, the queue stays saved
+                self.feed_canceller.close();
+                self.outdated = true;
+            }
+            AppMsg::InstanceInfoLoaded(Err(err)) => {
+                // Only informative, so just don't show it
+                tracing::warn!(?err, "failed fetching instance info");
+                return false;
+            }
+            AppMsg::ShareSearch(search) => {
+                let login = ctx.props().login.clone();
+                spawn_local(async move {
+                    let window = web_sys::window().expect("no web_sys window");
+                    let _ = match api::share_search(login.clone(), search).await {
+                        Ok(token) => window.prompt_with_message_and_default(
+                            "Embeddable status badge for this search, anyone with this link can see its counts:",
+                            &api::badge_url(&login.host, &token),
+                        ),
+                        Err(err) => {
+                            tracing::error!(?err, "failed sharing search");
+                            window
+                                .alert_with_message("Failed sharing this search, please try again later")
+                                .map(|()| None)
+                        }
+                    };
+                });
+                return false;
+            }
+            AppMsg::SubscribeSearch(search) => {
+                let login = ctx.props().login.clone();
+                spawn_local(async move {
+                    let window = web_sys::window().expect("no web_sys window");
+                    let _ = match api::create_search_feed(login.clone(), search).await {
+                        Ok(token) => window.prompt_with_message_and_default(
+                            "Atom feed of this search, anyone with this link can read its tasks, and getting a new link revokes this one:",
+                            &api::feed_url(&login.host, &token),
+                        ),
+                        Err(err) => {
+                            tracing::error!(?err, "failed creating search feed");
+                            window
+                                .alert_with_message("Failed creating a feed for this search, please try again later")
+                                .map(|()| None)
+                        }
+                    };
+                });
+                return false;
+            }
+            AppMsg::ExportSearch(search) => {
+                util::record_feature("export-search");
+                if let Some(search) = self.db.searches.get(&search) {
+                    let json = serde_json::to_string(&SearchExport::from_search(search))
+                        .expect("failed serializing search export");
+                    let window = web_sys::window().expect("no web_sys window");
+                    let _ = window.prompt_with_message_and_default(
+                        "Copy this search to share it, others can then import it from their sidebar:",
+                        &json,
+                    );
+                }
+                return false;
+            }
+            AppMsg::SearchImported(search) => {
+                // The server also makes our feed resync, but showing the search can happen right away
+                Rc::make_mut(&mut self.db)
+                    .searches
+                    .insert(search.id, search.clone());
                 self.active_search = search;
+                self.view = AppView::Tasks;
             }
             AppMsg::NewUserAction(a) => {
                 tracing::debug!("got new user action {a:?}");
@@ -197,75 +808,362 @@ impl Component for App {
 
                 // Submit the event to the upload queue and update our state
                 self.actions_pending_submission.push_back(a.clone());
-                LocalStorage::set(
-                    KEY_ACTS_PENDING_SUBMISSION,
-                    &self.actions_pending_submission,
-                )
-                .expect("failed saving queue to local storage");
+                coalesce_actions(&mut self.actions_pending_submission);
+                self.save_actions_pending_submission();
                 tracing::trace!("actions pending submission queue saved");
                 if self.actions_pending_submission.len() == 1 {
                     // this is the first event from the queue
-                    send_action(ctx, a.clone());
+                    self.submit_queue_head(ctx);
                     tracing::debug!("started action submission with action {a:?}");
                 }
                 self.locally_insert_new_action(a.clone());
                 tracing::debug!("handled new user action {a:?}");
             }
-            AppMsg::NewNetworkAction(a) => self.locally_insert_new_action(a),
+            AppMsg::NewNetworkAction(a) if self.hidden => {
+                self.actions_buffered.push(a);
+                return false;
+            }
+            AppMsg::NewNetworkActions(a) if self.hidden => {
+                self.actions_buffered.extend(a);
+                return false;
+            }
+            AppMsg::NewNetworkAction(a) => {
+                self.actions_buffered.push(a);
+                self.request_flush(ctx);
+                return false;
+            }
+            AppMsg::NewNetworkActions(a) => {
+                self.actions_buffered.extend(a);
+                self.request_flush(ctx);
+                return false;
+            }
+            AppMsg::FlushNetworkActions => {
+                self.flush_requested = false;
+                // Actions buffered while hidden are applied by `VisibilityChanged` instead
+                if self.hidden || self.actions_buffered.is_empty() {
+                    return false;
+                }
+                let actions = std::mem::take(&mut self.actions_buffered);
+                Rc::make_mut(&mut self.db).apply_actions(actions);
+            }
+            AppMsg::OpenPalette(task) => {
+                if self.view != AppView::Tasks {
+                    return false;
+                }
+                util::record_feature("command-palette");
+                self.palette = Some(task);
+            }
+            AppMsg::ClosePalette => {
+                self.palette = None;
+            }
+            AppMsg::VisibilityChanged => {
+                self.hidden = util::page_hidden();
+                if self.hidden || self.actions_buffered.is_empty() {
+                    return false;
+                }
+                let actions = std::mem::take(&mut self.actions_buffered);
+                Rc::make_mut(&mut self.db).apply_actions(actions);
+            }
             AppMsg::ActionSubmissionComplete => {
-                self.actions_pending_submission.pop_front();
-                LocalStorage::set(
-                    KEY_ACTS_PENDING_SUBMISSION,
-                    &self.actions_pending_submission,
-                )
-                .expect("failed saving queue to local storage");
-                if !self.actions_pending_submission.is_empty() {
-                    let e = self.actions_pending_submission[0].clone();
-                    send_action(ctx, e);
+                self.pop_submitted_action(ctx);
+            }
+            AppMsg::ActionSubmissionFailed(error) => {
+                if let Some(action) = self.pop_submitted_action(ctx) {
+                    tracing::warn!(?action, ?error, "moving action to the dead letter list");
+                    Rc::make_mut(&mut self.actions_dead_letter).push(DeadLetter { action, error });
+                    self.save_actions_dead_letter();
+                }
+            }
+            AppMsg::ServerUnreachable => {
+                // Keep the action at the head of the queue, to submit it again once the server is back
+                self.submission = None;
+                if !self.server_reachable {
+                    return false;
+                }
+                self.server_reachable = false;
+                self.reachability_check = Some(send_abortable_future(
+                    ctx,
+                    api::wait_for_connectivity(ctx.props().login.host.clone())
+                        .map(|()| AppMsg::ServerReachable),
+                ));
+            }
+            AppMsg::ServerReachable => {
+                self.server_reachable = true;
+                self.reachability_check = None;
+                self.submit_queue_head(ctx);
+            }
+            AppMsg::ServerReadOnly => {
+                // Keep the action at the head of the queue, to submit it again once maintenance is over
+                self.submission = None;
+                if self.read_only {
+                    return false;
+                }
+                self.read_only = true;
+                self.writability_check = Some(send_abortable_future(
+                    ctx,
+                    api::wait_for_writable(ctx.props().login.host.clone())
+                        .map(|()| AppMsg::ServerWritable),
+                ));
+            }
+            AppMsg::ServerWritable => {
+                self.read_only = false;
+                self.writability_check = None;
+                self.submit_queue_head(ctx);
+            }
+            AppMsg::SetWorkingOffline(true) => {
+                if self.working_offline {
+                    return false;
+                }
+                util::record_feature("work-offline");
+                // Stop all network requests, the queue and the local changes staying as they are
+                self.working_offline = true;
+                self.feed_canceller.close();
+                let running = [
+                    self.submission.take(),
+                    self.reachability_check.take(),
+                    self.writability_check.take(),
+                ];
+                for handle in running.into_iter().flatten() {
+                    handle.abort();
+                }
+                self.connection_state = ConnState::Disconnected;
+                // Checked again upon the first submission once back online
+                self.server_reachable = true;
+                self.read_only = false;
+            }
+            AppMsg::SetWorkingOffline(false) => {
+                if !self.working_offline {
+                    return false;
+                }
+                // Resync like upon reconnection: the queue gets replayed over the fetched database,
+                // after dropping the actions the server already committed
+                self.working_offline = false;
+                self.feed_canceller = start_event_feed(ctx);
+                self.submit_queue_head(ctx);
+            }
+            AppMsg::RetryDeadLetter(i) => {
+                let d = Rc::make_mut(&mut self.actions_dead_letter).remove(i);
+                self.save_actions_dead_letter();
+                // The action was already applied locally when first submitted
+                self.actions_pending_submission.push_back(d.action);
+                self.save_actions_pending_submission();
+                if self.actions_pending_submission.len() == 1 {
+                    self.submit_queue_head(ctx);
+                }
+            }
+            AppMsg::DiscardDeadLetter(i) => {
+                Rc::make_mut(&mut self.actions_dead_letter).remove(i);
+                self.save_actions_dead_letter();
+                // Revert the local effects of the action by refetching the database, over which the
+                // remaining actions get replayed. If not connected, this will happen upon reconnection
+                if !self.working_offline && self.connection_state == ConnState::Connected {
+                    self.feed_canceller.close();
+                    self.connection_state = ConnState::Disconnected;
+                    self.feed_canceller = start_event_feed(ctx);
+                }
+            }
+            AppMsg::ActionsAlreadyCommitted(events) => {
+                // The first action is currently being submitted, so leave it to send_action
+                let in_flight = self.actions_pending_submission.pop_front();
+                self.actions_pending_submission.retain(|a| {
+                    a.committed_event_id()
+                        .map(|e| !events.contains(&e))
+                        .unwrap_or(true)
+                });
+                if let Some(a) = in_flight {
+                    self.actions_pending_submission.push_front(a);
                 }
+                tracing::debug!(
+                    num_committed = events.len(),
+                    "dropped already-committed actions from the submission queue"
+                );
+                self.save_actions_pending_submission();
             }
         }
         true
     }
 
     fn view(&self, ctx: &Context<Self>) -> Html {
+        util::record_render("App");
+        if self.outdated {
+            return html! {
+                <div class="container my-4">
+                    <div class="alert alert-warning">
+                        <h4 class="alert-heading">{ "This page is out of date" }</h4>
+                        <p>
+                            { "The server got upgraded and no longer supports this version of the app. " }
+                            { "Your changes that were not sent yet are saved, and will be sent after refreshing." }
+                        </p>
+                        <button
+                            type="button"
+                            class="btn btn-primary"
+                            onclick={Callback::from(|_| {
+                                let _ = web_sys::window().expect("no web_sys window").location().reload();
+                            })}
+                        >
+                            { "Refresh" }
+                        </button>
+                    </div>
+                </div>
+            };
+        }
         let tasks = self.current_task_lists();
 
+        match self.view {
+            AppView::Tasks => (),
+            AppView::Print => {
+                return html! {
+                    <ui::PrintView
+                        db={ self.db.clone() }
+                        title={ self.active_search.name.clone() }
+                        tasks_open={ tasks.open }
+                        tasks_done={ tasks.done }
+                        tasks_backlog={ tasks.backlog }
+                        on_close={ ctx.link().callback(|_| AppMsg::SetView(AppView::Tasks)) }
+                    />
+                };
+            }
+            AppView::Activity => {
+                return html! {
+                    <ui::ActivityView
+                        login={ ctx.props().login.clone() }
+                        db={ self.db.clone() }
+                        on_close={ ctx.link().callback(|_| AppMsg::SetView(AppView::Tasks)) }
+                    />
+                };
+            }
+            AppView::PlanDay => {
+                return html! {
+                    <ui::PlanDayView
+                        tasks={ Rc::new(self.today_open_tasks()) }
+                        on_accept={ ctx.link().callback(AppMsg::AcceptPlan) }
+                        on_close={ ctx.link().callback(|_| AppMsg::SetView(AppView::Tasks)) }
+                    />
+                };
+            }
+            AppView::Telemetry => {
+                return html! {
+                    <ui::TelemetryView
+                        on_close={ ctx.link().callback(|_| AppMsg::SetView(AppView::Tasks)) }
+                    />
+                };
+            }
+            AppView::ImportSearch => {
+                return html! {
+                    <ui::ImportSearchView
+                        login={ ctx.props().login.clone() }
+                        db={ self.db.clone() }
+                        on_imported={ ctx.link().callback(AppMsg::SearchImported) }
+                        on_close={ ctx.link().callback(|_| AppMsg::SetView(AppView::Tasks)) }
+                    />
+                };
+            }
+            AppView::Rules => {
+                return html! {
+                    <ui::RulesView
+                        login={ ctx.props().login.clone() }
+                        db={ self.db.clone() }
+                        on_close={ ctx.link().callback(|_| AppMsg::SetView(AppView::Tasks)) }
+                    />
+                };
+            }
+            AppView::TagPermissions(tag) => {
+                return html! {
+                    <ui::TagPermissionsView
+                        login={ ctx.props().login.clone() }
+                        db={ self.db.clone() }
+                        { tag }
+                        on_tag_updated={ ctx.link().callback(AppMsg::TagUpdated) }
+                        on_close={ ctx.link().callback(|_| AppMsg::SetView(AppView::Tasks)) }
+                    />
+                };
+            }
+        }
+
         let on_order_change = {
             let owner = self.db.owner.clone();
             let search = self.active_search.clone();
             let tasks = tasks.clone();
+            // The open list of a tag with sections also displays the section headings
+            let sections = match self.active_search.order {
+                Order::Tag(tag) => self
+                    .db
+                    .tags
+                    .get(&tag)
+                    .filter(|t| !t.sections.is_empty())
+                    .map(|t| (tag, t.sections.clone())),
+                _ => None,
+            };
             ctx.link().batch_callback(move |e: TaskOrderChangeEvent| {
-                let task_id = match e.before.list {
-                    ListType::Open => tasks.open[e.before.index].id,
-                    ListType::Done => tasks.done[e.before.index].id,
-                    ListType::Backlog => tasks.backlog[e.before.index].id,
+                let task_id = match (&e.before.list, &sections) {
+                    (ListType::Open, Some((tag, sections))) => {
+                        let groups = util::split_sections(sections, tag, &tasks.open);
+                        match util::section_list_items(&groups)[e.before.index] {
+                            (g, Some(i)) => groups[g].1[i].id,
+                            (_, None) => panic!("section headings cannot be dragged"),
+                        }
+                    }
+                    (ListType::Open, None) => tasks.open[e.before.index].id,
+                    (ListType::Done, _) => tasks.done[e.before.index].id,
+                    (ListType::Backlog, _) => tasks.backlog[e.before.index].id,
                 };
                 let mut insert_into = match e.after.list {
                     ListType::Open => (*tasks.open).clone(),
                     ListType::Done => (*tasks.done).clone(),
                     ListType::Backlog => (*tasks.backlog).clone(),
                 };
-                if e.before.list == e.after.list {
-                    insert_into.remove(e.before.index);
+                insert_into.retain(|t| t.id != task_id);
+                let mut index = e.after.index;
+                let mut section_evt = None;
+                if let (ListType::Open, Some((tag, sections))) = (&e.after.list, &sections) {
+                    // Insert the task right after the element it was dropped after, within its section
+                    let mut groups = util::split_sections(sections, tag, &insert_into);
+                    let (group, group_index) = match index {
+                        0 => (0, 0),
+                        _ => match util::section_list_items(&groups)[index - 1] {
+                            (g, None) => (g, 0),
+                            (g, Some(i)) => (g, i + 1),
+                        },
+                    };
+                    let (section, group_tasks) = groups.swap_remove(group);
+                    let current_section = tasks
+                        .open
+                        .iter()
+                        .chain(tasks.done.iter())
+                        .chain(tasks.backlog.iter())
+                        .find(|t| t.id == task_id)
+                        .and_then(|t| t.current_tags.get(tag))
+                        .and_then(|t| t.section.clone());
+                    if current_section != section {
+                        section_evt = Some(Event::at(
+                            owner,
+                            task_id,
+                            crate::CLOCK.now(),
+                            EventData::SetSection { tag: *tag, section },
+                        ));
+                    }
+                    insert_into = group_tasks;
+                    index = group_index;
                 }
                 let evts = util::compute_reordering_events(
                     owner,
                     &search,
                     task_id,
-                    e.after.index,
+                    index,
                     e.after.list.is_backlog(),
                     &insert_into,
                 );
                 let mut evts = evts
                     .into_iter()
+                    .chain(section_evt)
                     .map(Action::NewEvent)
                     .map(AppMsg::NewUserAction)
                     .collect::<Vec<_>>();
                 if e.before.list.is_done() != e.after.list.is_done() {
-                    evts.push(AppMsg::NewUserAction(Action::NewEvent(Event::now(
+                    evts.push(AppMsg::NewUserAction(Action::NewEvent(Event::at(
                         owner,
                         task_id,
+                        crate::CLOCK.now(),
                         EventData::SetDone(e.after.list.is_done()),
                     ))));
                 }
@@ -291,34 +1189,370 @@ impl Component for App {
                             tags={ self.db.tags.clone() }
                             current_user={ self.db.owner }
                             active_search={ self.active_search.id }
+                            day_start={ self.day_start }
                             on_select_search={ ctx.link().callback(AppMsg::SetActiveSearch) }
+                            on_share_search={ ctx.link().callback(AppMsg::ShareSearch) }
+                            on_subscribe_search={ ctx.link().callback(AppMsg::SubscribeSearch) }
+                            on_export_search={ ctx.link().callback(AppMsg::ExportSearch) }
+                            on_import_search={ ctx.link().callback(|_| AppMsg::SetView(AppView::ImportSearch)) }
+                            on_share_tag={ ctx.link().callback(|t| AppMsg::SetView(AppView::TagPermissions(t))) }
                         />
                     </nav>
                     <main class="col-md-10 h-100 p-0">
                         <ui::MainView
                             connection_state={ self.connection_state.clone() }
+                            server_reachable={ self.server_reachable }
+                            read_only={ self.read_only }
+                            working_offline={ self.working_offline }
+                            on_set_working_offline={ ctx.link().callback(AppMsg::SetWorkingOffline) }
                             actions_pending_submission={ self.actions_pending_submission.clone() }
+                            actions_dead_letter={ self.actions_dead_letter.clone() }
+                            on_retry_dead_letter={ ctx.link().callback(AppMsg::RetryDeadLetter) }
+                            on_discard_dead_letter={ ctx.link().callback(AppMsg::DiscardDeadLetter) }
                             db={ self.db.clone() }
+                            db_truncated={ self.db_truncated }
+                            duplicates={ self.duplicates.clone() }
                             { current_tag }
                             { user_knows_current_tag }
+                            search={ self.active_search.id }
                             tasks_open={ tasks.open }
                             tasks_done={ tasks.done }
                             tasks_backlog={ tasks.backlog }
+                            current_account={ ctx.props().login.clone() }
+                            accounts={ ctx.props().accounts.clone() }
+                            instance={ self.instance.clone() }
+                            day_start={ self.day_start }
+                            on_set_day_start={ ctx.link().callback(AppMsg::SetDayStart) }
+                            highlight_rules={ self.highlight_rules.clone() }
+                            on_set_highlight_rules={ ctx.link().callback(AppMsg::SetHighlightRules) }
                             on_logout={ ctx.link().callback(|_| AppMsg::Logout) }
-                            on_action={ ctx.link().callback(AppMsg::NewUserAction) }
+                            on_switch_account={ ctx.props().on_switch_account.clone() }
+                            on_add_account={ ctx.props().on_add_account.clone() }
+                            on_print={ ctx.link().callback(|_| AppMsg::SetView(AppView::Print)) }
+                            on_activity={ ctx.link().callback(|_| AppMsg::SetView(AppView::Activity)) }
+                            on_telemetry={ ctx.link().callback(|_| AppMsg::SetView(AppView::Telemetry)) }
+                            on_rules={ ctx.link().callback(|_| AppMsg::SetView(AppView::Rules)) }
+                            on_plan_day={ ctx.link().callback(|_| AppMsg::SetView(AppView::PlanDay)) }
+                            on_mark_all_read={ ctx.link().callback(|_| AppMsg::MarkAllRead) }
+                            on_set_avatar={ ctx.link().callback(AppMsg::SetAvatar) }
+                            on_action={ self.on_action.clone() }
+                            on_title_change={ self.on_title_change.clone() }
                             { on_order_change }
                         />
                     </main>
                 </div>
+                if let Some(task) = self.palette {
+                    <ui::CommandPalette
+                        login={ ctx.props().login.clone() }
+                        working_offline={ self.working_offline }
+                        db={ self.db.clone() }
+                        day_start={ self.day_start }
+                        { task }
+                        on_select_search={ ctx.link().callback(AppMsg::SetActiveSearch) }
+                        on_action={ self.on_action.clone() }
+                        on_close={ ctx.link().callback(|_| AppMsg::ClosePalette) }
+                    />
+                }
+                if let Some(entries) = &self.changelog {
+                    <ui::ChangelogDialog
+                        entries={ entries.clone() }
+                        on_acknowledge={ ctx.link().callback(|_| AppMsg::AcknowledgeChangelog) }
+                    />
+                }
+                if util::debug_sync_diff() {
+                    <ui::SyncDiffOverlay synced={ self.synced_db.clone() } db={ self.db.clone() } />
+                }
             </div>
         }
     }
+
+    fn rendered(&mut self, _ctx: &Context<Self>, _first_render: bool) {
+        util::log_render_counts();
+        self.update_tab_status();
+        if let Some(task) = self.scroll_to_task.take() {
+            let element = web_sys::window()
+                .and_then(|w| w.document())
+                .and_then(|d| d.get_element_by_id(&util::task_element_id(&task)));
+            match element {
+                Some(element) => element.scroll_into_view(),
+                None => tracing::warn!(?task, "linked task is not displayed"),
+            }
+        }
+    }
+
+    fn destroy(&mut self, _ctx: &Context<Self>) {
+        util::clear_tab_status();
+        if let Some(window) = web_sys::window() {
+            let _ = window.remove_event_listener_with_callback(
+                "hashchange",
+                self.route_listener.as_ref().unchecked_ref(),
+            );
+            let _ = window.remove_event_listener_with_callback(
+                "keydown",
+                self.palette_listener.as_ref().unchecked_ref(),
+            );
+            if let Some(document) = window.document() {
+                let _ = document.remove_event_listener_with_callback(
+                    "visibilitychange",
+                    self.visibility_listener.as_ref().unchecked_ref(),
+                );
+            }
+        }
+    }
+}
+
+/// Events that fully override any previous event with the same key
+#[derive(Eq, Hash, PartialEq)]
+enum CoalescingKey {
+    Title(TaskId),
+    Done(TaskId),
+    Archived(TaskId),
+    BlockedUntil(TaskId),
+    ScheduleFor(TaskId),
+    Priority(TaskId),
+    Order(TaskId, OrderId),
+    Section(TaskId, TagId),
+}
+
+fn coalescing_key(a: &Action) -> Option<CoalescingKey> {
+    let e = match a {
+        Action::NewEvent(e) => e,
+        _ => return None,
+    };
+    Some(match &e.data {
+        EventData::SetTitle(_) => CoalescingKey::Title(e.task_id),
+        EventData::SetDone(_) => CoalescingKey::Done(e.task_id),
+        EventData::SetArchived(_) => CoalescingKey::Archived(e.task_id),
+        EventData::BlockedUntil(_) => CoalescingKey::BlockedUntil(e.task_id),
+        EventData::ScheduleFor(_) => CoalescingKey::ScheduleFor(e.task_id),
+        EventData::SetPriority(_) => CoalescingKey::Priority(e.task_id),
+        EventData::SetOrder { order, .. } => CoalescingKey::Order(e.task_id, order.clone()),
+        EventData::SetSection { tag, .. } => CoalescingKey::Section(e.task_id, *tag),
+        _ => return None,
+    })
+}
+
+/// Drops the queued actions that are superseded by a later action of the queue, eg. all
+/// title changes of a task but the last one.
+///
+/// The first action is left alone, as it may currently be being submitted.
+fn coalesce_actions(actions: &mut VecDeque<Action>) {
+    let mut seen = HashSet::new();
+    for i in (1..actions.len()).rev() {
+        if let Some(k) = coalescing_key(&actions[i]) {
+            if !seen.insert(k) {
+                tracing::trace!(action=?actions[i], "coalescing superseded action");
+                actions.remove(i);
+            }
+        }
+    }
 }
 
-fn send_action(ctx: &Context<App>, a: Action) {
+/// Asks the server which of the queued actions it already committed, eg. if we got
+/// disconnected after the server received an action but before we got its answer
+fn check_committed_actions(ctx: &Context<App>, actions: &VecDeque<Action>) {
+    // Skip the first action, as it is currently being submitted anyway
+    let events = actions
+        .iter()
+        .skip(1)
+        .filter_map(|a| a.committed_event_id())
+        .collect::<Vec<_>>();
+    if events.is_empty() {
+        return;
+    }
     let info = ctx.props().login.clone();
     ctx.link().send_future(async move {
-        api::send_action(&info, a).await;
-        AppMsg::ActionSubmissionComplete
+        match api::committed_events(&info, events).await {
+            Ok(committed) => AppMsg::ActionsAlreadyCommitted(committed),
+            Err(err) => {
+                tracing::error!(?err, "failed checking for already-committed actions");
+                AppMsg::ActionsAlreadyCommitted(Vec::new())
+            }
+        }
     });
 }
+
+/// Connects to the websocket event feed, that then fetches the database, until the returned
+/// receiver gets closed or dropped
+fn start_event_feed(ctx: &Context<App>) -> oneshot::Receiver<()> {
+    let (feed_cancel_receiver, feed_canceller) = oneshot::channel();
+    spawn_local(api::start_event_feed(
+        ctx.props().login.clone(),
+        ctx.link().clone(),
+        feed_cancel_receiver,
+    ));
+    feed_canceller
+}
+
+/// Like `send_future`, but aborting with the returned handle drops the future, along with the
+/// requests it is retrying
+fn send_abortable_future(
+    ctx: &Context<App>,
+    fut: impl 'static + Future<Output = AppMsg>,
+) -> AbortHandle {
+    let (fut, handle) = future::abortable(fut);
+    let link = ctx.link().clone();
+    spawn_local(async move {
+        if let Ok(msg) = fut.await {
+            link.send_message(msg);
+        }
+    });
+    handle
+}
+
+/// Sends the usage counters of the days that are over, keeping them for later upon failure
+fn send_telemetry(ctx: &Context<App>) {
+    let mut report = util::telemetry_report();
+    let past_days = report.take_before(util::telemetry_today());
+    if past_days.is_empty() {
+        return;
+    }
+    util::save_telemetry_report(&report);
+    let login = ctx.props().login.clone();
+    spawn_local(async move {
+        if let Err(err) = api::submit_telemetry(login, past_days.clone()).await {
+            tracing::warn!(?err, "failed sending telemetry");
+            if util::telemetry_enabled() {
+                let mut report = util::telemetry_report();
+                report.merge(past_days);
+                util::save_telemetry_report(&report);
+            }
+        }
+    });
+}
+
+fn send_action(ctx: &Context<App>, a: Action) -> AbortHandle {
+    let info = ctx.props().login.clone();
+    send_abortable_future(ctx, async move {
+        match api::send_action(&info, a).await {
+            Ok(()) => AppMsg::ActionSubmissionComplete,
+            Err(err) if err.is_connectivity() => {
+                tracing::warn!(?err, "server unreachable while submitting action");
+                AppMsg::ServerUnreachable
+            }
+            Err(api::Error::Api(risuto_client::api::Error::ReadOnly)) => {
+                tracing::info!("server in maintenance mode while submitting action");
+                AppMsg::ServerReadOnly
+            }
+            Err(err) => AppMsg::ActionSubmissionFailed(format!("{:#}", anyhow::Error::new(err))),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use risuto_client::api::{UserId, Uuid};
+
+    fn event(task: TaskId, data: EventData) -> Action {
+        Action::NewEvent(Event::at(
+            UserId(Uuid::new_v4()),
+            task,
+            crate::CLOCK.now(),
+            data,
+        ))
+    }
+
+    fn titles(actions: &VecDeque<Action>) -> Vec<&str> {
+        actions
+            .iter()
+            .map(|a| match a {
+                Action::NewEvent(Event {
+                    data: EventData::SetTitle(t),
+                    ..
+                }) => t.as_str(),
+                _ => "<not a title>",
+            })
+            .collect()
+    }
+
+    #[test]
+    fn keeps_only_the_last_title_change() {
+        let task = TaskId(Uuid::new_v4());
+        let mut actions = ["a", "b", "c", "d"]
+            .into_iter()
+            .map(|t| event(task, EventData::SetTitle(String::from(t))))
+            .collect::<VecDeque<_>>();
+        coalesce_actions(&mut actions);
+        // The first one may be currently being submitted
+        assert_eq!(titles(&actions), vec!["a", "d"]);
+    }
+
+    #[test]
+    fn keeps_only_the_last_order_change_per_order() {
+        let task = TaskId(Uuid::new_v4());
+        let set_order = |order: &OrderId, prio| {
+            event(
+                task,
+                EventData::SetOrder {
+                    order: order.clone(),
+                    prio,
+                },
+            )
+        };
+        let mut actions = VecDeque::from(vec![
+            set_order(&OrderId::today(), 0),
+            set_order(&OrderId::today(), 1),
+            set_order(&OrderId::untagged(), 2),
+            set_order(&OrderId::today(), 3),
+            set_order(&OrderId::untagged(), 4),
+        ]);
+        coalesce_actions(&mut actions);
+        let prios = actions
+            .iter()
+            .map(|a| match a {
+                Action::NewEvent(Event {
+                    data: EventData::SetOrder { prio, .. },
+                    ..
+                }) => *prio,
+                _ => panic!("unexpected action {a:?}"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(prios, vec![0, 3, 4]);
+    }
+
+    #[test]
+    fn keeps_changes_to_different_tasks() {
+        let (t1, t2) = (TaskId(Uuid::new_v4()), TaskId(Uuid::new_v4()));
+        let mut actions = VecDeque::from(vec![
+            event(t1, EventData::SetTitle(String::from("a"))),
+            event(t1, EventData::SetTitle(String::from("b"))),
+            event(t2, EventData::SetTitle(String::from("c"))),
+        ]);
+        coalesce_actions(&mut actions);
+        assert_eq!(titles(&actions), vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn keeps_non_coalescable_actions() {
+        let task = TaskId(Uuid::new_v4());
+        let tag = TagId(Uuid::new_v4());
+        let comment = |text: &str| {
+            event(
+                task,
+                EventData::AddComment {
+                    text: String::from(text),
+                    parent_id: None,
+                },
+            )
+        };
+        let mut actions = VecDeque::from(vec![
+            comment("first"),
+            comment("second"),
+            event(task, EventData::RmTag(tag)),
+            event(
+                task,
+                EventData::AddTag {
+                    tag,
+                    prio: 0,
+                    backlog: false,
+                },
+            ),
+            event(task, EventData::RmTag(tag)),
+            comment("second"),
+        ]);
+        let expected = actions.clone();
+        coalesce_actions(&mut actions);
+        assert_eq!(actions, expected);
+    }
+}
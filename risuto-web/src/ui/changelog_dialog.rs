@@ -0,0 +1,54 @@
+use std::rc::Rc;
+
+use risuto_client::api::ChangelogEntry;
+use yew::prelude::*;
+
+use crate::util;
+
+#[derive(Clone, PartialEq, Properties)]
+pub struct ChangelogDialogProps {
+    /// Entries the user did not acknowledge yet, most recent first
+    pub entries: Rc<Vec<ChangelogEntry>>,
+    pub on_acknowledge: Callback<()>,
+}
+
+/// "What's new" dialog, shown once after the instance got upgraded
+#[function_component(ChangelogDialog)]
+pub fn changelog_dialog(p: &ChangelogDialogProps) -> Html {
+    let tz = util::local_tz();
+    html! {
+        <div class="modal d-block changelog-dialog" tabindex="-1">
+            <div class="modal-dialog modal-dialog-scrollable">
+                <div class="modal-content">
+                    <div class="modal-header">
+                        <h5 class="modal-title">{ "What's new" }</h5>
+                    </div>
+                    <div class="modal-body">
+                        { for p.entries.iter().map(|e| html! {
+                            <>
+                                <h6>
+                                    { format!("Version {}", e.version) }
+                                    <small class="text-muted ms-2">
+                                        { e.installed_at.with_timezone(&tz).format("%Y-%m-%d").to_string() }
+                                    </small>
+                                </h6>
+                                <ul>
+                                    { for e.notes.iter().map(|n| html! { <li>{ n.clone() }</li> }) }
+                                </ul>
+                            </>
+                        }) }
+                    </div>
+                    <div class="modal-footer">
+                        <button
+                            type="button"
+                            class="btn btn-primary"
+                            onclick={p.on_acknowledge.reform(|_| ())}
+                        >
+                            { "Got it" }
+                        </button>
+                    </div>
+                </div>
+            </div>
+        </div>
+    }
+}
@@ -0,0 +1,282 @@
+use std::{collections::HashMap, rc::Rc};
+
+use risuto_client::{
+    api::{Action, DayStart, Event, EventData, Priority, Search, TaskId},
+    DbDump,
+};
+use wasm_bindgen_futures::spawn_local;
+use yew::prelude::*;
+
+use crate::{api, util, LoginInfo};
+
+/// Maximum number of commands listed at once
+const MAX_SHOWN: usize = 10;
+
+#[derive(Clone, PartialEq, Properties)]
+pub struct CommandPaletteProps {
+    pub login: LoginInfo,
+    pub working_offline: bool,
+    pub db: Rc<DbDump>,
+    pub day_start: DayStart,
+    /// Task the task actions apply to, if any
+    pub task: Option<TaskId>,
+    pub on_select_search: Callback<Search>,
+    pub on_action: Callback<Action>,
+    pub on_close: Callback<()>,
+}
+
+#[derive(Clone)]
+enum Command {
+    Search(Search),
+    Task(String, EventData),
+    QuickAdd(String),
+}
+
+impl Command {
+    fn label(&self) -> String {
+        match self {
+            Command::Search(s) => format!("Go to {}", s.name),
+            Command::Task(label, _) => label.clone(),
+            Command::QuickAdd(title) => format!("Add task \"{title}\""),
+        }
+    }
+}
+
+fn commands(p: &CommandPaletteProps) -> Vec<Command> {
+    let db = &p.db;
+    let mut res = vec![Command::Search(Search::today(
+        util::local_tz(),
+        p.day_start,
+    ))];
+    let mut searches = db.searches.values().collect::<Vec<_>>();
+    searches.sort_by_key(|s| (s.priority, &s.name, s.id));
+    res.extend(searches.into_iter().cloned().map(Command::Search));
+    let mut tags = db.tags.values().filter(|t| !t.archived).collect::<Vec<_>>();
+    util::sort_tags(&db.owner, &mut tags, |t| t);
+    res.extend(
+        tags.into_iter()
+            .map(|t| Command::Search(Search::for_tag(t))),
+    );
+    res.push(Command::Search(Search::untagged()));
+
+    if let Some(task) = p.task.and_then(|t| db.tasks.get(&t)) {
+        let done = match task.is_done {
+            true => "Mark as not done",
+            false => "Mark as done",
+        };
+        res.push(Command::Task(
+            String::from(done),
+            EventData::SetDone(!task.is_done),
+        ));
+        let archived = match task.is_archived {
+            true => "Unarchive",
+            false => "Archive",
+        };
+        res.push(Command::Task(
+            String::from(archived),
+            EventData::SetArchived(!task.is_archived),
+        ));
+        res.push(Command::Task(
+            String::from("Schedule for now"),
+            EventData::ScheduleFor(Some(crate::CLOCK.now())),
+        ));
+        if task.scheduled_for.is_some() {
+            res.push(Command::Task(
+                String::from("Unschedule"),
+                EventData::ScheduleFor(None),
+            ));
+        }
+        res.extend(Priority::ALL.into_iter().map(|prio| {
+            Command::Task(
+                format!("Set priority {}", prio.name().to_uppercase()),
+                EventData::SetPriority(Some(prio)),
+            )
+        }));
+        if task.priority.is_some() {
+            res.push(Command::Task(
+                String::from("Clear priority"),
+                EventData::SetPriority(None),
+            ));
+        }
+    }
+    res
+}
+
+#[function_component(CommandPalette)]
+pub fn command_palette(p: &CommandPaletteProps) -> Html {
+    let query = use_state(String::new);
+    let highlighted = use_state(|| 0);
+    let input_ref = use_node_ref();
+    {
+        let input_ref = input_ref.clone();
+        use_effect_with_deps(
+            move |_| {
+                if let Some(input) = input_ref.cast::<web_sys::HtmlInputElement>() {
+                    let _ = input.focus();
+                }
+            },
+            (),
+        );
+    }
+    // Labels of the task commands the server rejected, with the reason why
+    let rejected = use_state(HashMap::<String, String>::new);
+
+    let mut shown = commands(p)
+        .into_iter()
+        .filter_map(|c| util::fuzzy_score(&query, &c.label()).map(|s| (s, c)))
+        .collect::<Vec<_>>();
+    shown.sort_by_key(|(s, _)| *s);
+    let mut shown = shown
+        .into_iter()
+        .map(|(_, c)| c)
+        .take(MAX_SHOWN)
+        .collect::<Vec<_>>();
+    if !query.trim().is_empty() {
+        shown.push(Command::QuickAdd(String::from(query.trim())));
+    }
+    let highlighted_index = (*highlighted).min(shown.len().saturating_sub(1));
+
+    let run = {
+        let db = p.db.clone();
+        let task = p.task;
+        let on_select_search = p.on_select_search.clone();
+        let on_action = p.on_action.clone();
+        let on_close = p.on_close.clone();
+        let rejected = rejected.clone();
+        let login = p.login.clone();
+        let working_offline = p.working_offline;
+        Callback::from(move |c: Command| {
+            if matches!(&c, Command::Task(label, _) if rejected.contains_key(label)) {
+                return;
+            }
+            match c {
+                Command::Search(s) => on_select_search.emit(s),
+                Command::Task(label, data) => {
+                    let task = match task {
+                        Some(task) => task,
+                        None => return,
+                    };
+                    let action =
+                        Action::NewEvent(Event::at(db.owner, task, crate::CLOCK.now(), data));
+                    if working_offline {
+                        on_action.emit(action);
+                    } else {
+                        // The local is_authorized check does not know about eg. permission
+                        // changes since the last sync, so ask the server whether the command
+                        // would actually go through before closing the palette
+                        let (login, rejected) = (login.clone(), rejected.clone());
+                        let (on_action, on_close) = (on_action.clone(), on_close.clone());
+                        spawn_local(async move {
+                            match api::validate_action(login, action.clone()).await {
+                                Err(api::Error::Api(err)) => {
+                                    let mut r = (*rejected).clone();
+                                    r.insert(label, err.to_string());
+                                    rejected.set(r);
+                                }
+                                // Submit anyway if the server could not answer
+                                _ => {
+                                    on_action.emit(action);
+                                    on_close.emit(());
+                                }
+                            }
+                        });
+                        return;
+                    }
+                }
+                Command::QuickAdd(title) => {
+                    for a in util::new_task_actions(&db, &title) {
+                        on_action.emit(a);
+                    }
+                }
+            }
+            on_close.emit(());
+        })
+    };
+    let onkeydown = {
+        let shown = shown.clone();
+        let highlighted = highlighted.clone();
+        let run = run.clone();
+        let on_close = p.on_close.clone();
+        Callback::from(move |e: web_sys::KeyboardEvent| match &e.key() as &str {
+            "ArrowDown" => {
+                e.prevent_default();
+                highlighted.set((highlighted_index + 1).min(shown.len().saturating_sub(1)));
+            }
+            "ArrowUp" => {
+                e.prevent_default();
+                highlighted.set(highlighted_index.saturating_sub(1));
+            }
+            "Enter" => {
+                if let Some(c) = shown.get(highlighted_index) {
+                    run.emit(c.clone());
+                }
+            }
+            "Escape" => on_close.emit(()),
+            _ => (),
+        })
+    };
+    let oninput = {
+        let query = query.clone();
+        let highlighted = highlighted.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: web_sys::HtmlInputElement = e.target_unchecked_into();
+            query.set(input.value());
+            highlighted.set(0);
+        })
+    };
+    let on_backdrop_click = {
+        let on_close = p.on_close.clone();
+        Callback::from(move |e: MouseEvent| {
+            if e.target() == e.current_target() {
+                on_close.emit(());
+            }
+        })
+    };
+    let task_title = p
+        .task
+        .and_then(|t| p.db.tasks.get(&t))
+        .map(|t| t.current_title.clone());
+
+    html! {
+        <div class="modal d-block command-palette" tabindex="-1" onclick={on_backdrop_click}>
+            <div class="modal-dialog">
+                <div class="modal-content">
+                    <input
+                        ref={input_ref}
+                        type="text"
+                        class="form-control form-control-lg border-0"
+                        placeholder="Type a command, a search or a new task title"
+                        aria-label="Command"
+                        value={(*query).clone()}
+                        {oninput}
+                        {onkeydown}
+                    />
+                    if let Some(title) = task_title {
+                        <div class="px-3 py-1 small text-muted border-top">
+                            { format!("Task actions apply to \"{title}\"") }
+                        </div>
+                    }
+                    <ul class="list-group list-group-flush">
+                        { for shown.into_iter().enumerate().map(|(i, c)| {
+                            let active = (i == highlighted_index).then_some("active");
+                            let label = c.label();
+                            let reason = rejected.get(&label).cloned();
+                            let disabled = reason.is_some().then_some("disabled");
+                            let onclick = run.reform(move |_| c.clone());
+                            html! {
+                                <li
+                                    class={classes!("list-group-item", "list-group-item-action", active, disabled)}
+                                    aria-disabled={ reason.is_some().to_string() }
+                                    title={ reason }
+                                    {onclick}
+                                >
+                                    { label }
+                                </li>
+                            }
+                        }) }
+                    </ul>
+                </div>
+            </div>
+        </div>
+    }
+}
@@ -0,0 +1,219 @@
+use std::{rc::Rc, sync::Arc};
+
+use risuto_client::{
+    api::{EventId, Time, UserId},
+    Comment, DbDump, Task,
+};
+use yew::prelude::*;
+
+use crate::util;
+
+#[derive(Clone, PartialEq, Properties)]
+pub struct ExportButtonProps {
+    pub db: Rc<DbDump>,
+    pub tasks_open: Rc<Vec<Arc<Task>>>,
+    pub tasks_done: Rc<Vec<Arc<Task>>>,
+    pub tasks_backlog: Rc<Vec<Arc<Task>>>,
+}
+
+#[derive(Clone, Copy)]
+enum Format {
+    Csv,
+    Markdown,
+}
+
+#[function_component(ExportButton)]
+pub fn export_button(p: &ExportButtonProps) -> Html {
+    let export = |format| {
+        let p = p.clone();
+        Callback::from(move |_| {
+            let lists = [
+                ("Open", &p.tasks_open),
+                ("Done", &p.tasks_done),
+                ("Backlog", &p.tasks_backlog),
+            ];
+            match format {
+                Format::Csv => util::download("tasks.csv", "text/csv", &to_csv(&p.db, &lists)),
+                Format::Markdown => {
+                    util::download("tasks.md", "text/markdown", &to_markdown(&p.db, &lists))
+                }
+            }
+        })
+    };
+    html! {
+        <div class="float-above dropdown">
+            <button
+                type="button"
+                class="btn btn-light btn-circle mt-3 ms-3 bi-btn bi-download fs-6"
+                title="Export"
+                data-bs-toggle="dropdown"
+            >
+            </button>
+            <ul class="dropdown-menu dropdown-menu-dark mt-3">
+                <li><a class="dropdown-item" href="#" onclick={export(Format::Csv)}>
+                    <span class="bi-filetype-csv me-2" aria-hidden="true"></span>
+                    {"Export as CSV"}
+                </a></li>
+                <li><a class="dropdown-item" href="#" onclick={export(Format::Markdown)}>
+                    <span class="bi-markdown me-2" aria-hidden="true"></span>
+                    {"Export as Markdown"}
+                </a></li>
+            </ul>
+        </div>
+    }
+}
+
+fn tag_names(db: &DbDump, t: &Task) -> Vec<String> {
+    let mut tags = t
+        .current_tags
+        .keys()
+        .filter_map(|tag| db.tags.get(tag))
+        .collect::<Vec<_>>();
+    util::sort_tags(&db.owner, &mut tags, |t| t);
+    tags.into_iter().map(|t| t.name.clone()).collect()
+}
+
+fn format_time(t: &Option<Time>) -> String {
+    t.map(|t| {
+        t.with_timezone(&util::local_tz())
+            .format("%Y-%m-%d %H:%M")
+            .to_string()
+    })
+    .unwrap_or_default()
+}
+
+fn csv_field(f: &str) -> String {
+    if f.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", f.replace('"', "\"\""))
+    } else {
+        String::from(f)
+    }
+}
+
+fn to_csv(db: &DbDump, lists: &[(&str, &Rc<Vec<Arc<Task>>>)]) -> String {
+    let mut res =
+        String::from("title,tags,list,done,priority,created,scheduled_for,blocked_until\r\n");
+    for (list, tasks) in lists {
+        for t in tasks.iter() {
+            let fields = [
+                (*t.current_title).clone(),
+                tag_names(db, t).join(" "),
+                String::from(*list),
+                t.is_done.to_string(),
+                t.priority.map(|p| p.name()).unwrap_or("").to_string(),
+                format_time(&Some(t.date)),
+                format_time(&t.scheduled_for),
+                format_time(&t.blocked_until),
+            ];
+            let fields = fields.iter().map(|f| csv_field(f)).collect::<Vec<_>>();
+            res += &fields.join(",");
+            res += "\r\n";
+        }
+    }
+    res
+}
+
+fn to_markdown(db: &DbDump, lists: &[(&str, &Rc<Vec<Arc<Task>>>)]) -> String {
+    let mut res = String::new();
+    for (list, tasks) in lists.iter().filter(|(_, tasks)| !tasks.is_empty()) {
+        res += &format!("## {list}\n\n");
+        for t in tasks.iter() {
+            let done = if t.is_done { 'x' } else { ' ' };
+            res += &format!("- [{done}] {}", t.current_title);
+            for tag in tag_names(db, t) {
+                res += &format!(" #{tag}");
+            }
+            if t.scheduled_for.is_some() {
+                res += &format!(" (scheduled for {})", format_time(&t.scheduled_for));
+            }
+            if t.blocked_until.is_some() {
+                res += &format!(" (blocked until {})", format_time(&t.blocked_until));
+            }
+            res += "\n";
+        }
+        res += "\n";
+    }
+    res
+}
+
+/// Renders `t` along with its metadata and comment thread, for pasting into wikis or emails
+pub fn task_to_markdown(db: &DbDump, t: &Task) -> String {
+    let mut res = format!("# {}\n\n", t.current_title);
+    let status = match (t.is_archived, t.is_done) {
+        (true, _) => "archived",
+        (false, true) => "done",
+        (false, false) => "open",
+    };
+    res += &format!("- Status: {status}\n");
+    let tags = tag_names(db, t);
+    if !tags.is_empty() {
+        let tags = tags.iter().map(|t| format!("#{t}")).collect::<Vec<_>>();
+        res += &format!("- Tags: {}\n", tags.join(" "));
+    }
+    if let Some(prio) = t.priority {
+        res += &format!("- Priority: {}\n", prio.name().to_uppercase());
+    }
+    if t.scheduled_for.is_some() {
+        res += &format!("- Scheduled for: {}\n", format_time(&t.scheduled_for));
+    }
+    if t.blocked_until.is_some() {
+        res += &format!("- Blocked until: {}\n", format_time(&t.blocked_until));
+    }
+    res += &format!(
+        "- Created: {} by {}\n",
+        format_time(&Some(t.date)),
+        user_name(db, &t.owner_id)
+    );
+    let description = comment_text(&t.top_comment);
+    if !description.is_empty() {
+        res += &format!("\n{description}\n");
+    }
+    if !t.current_comments.is_empty() {
+        res += "\n## Comments\n";
+        for c in t.current_comments.values().flat_map(|v| v.iter()) {
+            comment_to_markdown(db, t, c, 1, &mut res);
+        }
+    }
+    res
+}
+
+fn user_name(db: &DbDump, user: &UserId) -> String {
+    db.users
+        .get(user)
+        .map(|u| u.name.clone())
+        .unwrap_or_else(|| String::from("unknown user"))
+}
+
+/// Latest version of the text of `c`
+fn comment_text(c: &Comment) -> String {
+    c.edits
+        .values()
+        .next_back()
+        .and_then(|v| v.last())
+        .cloned()
+        .unwrap_or_default()
+}
+
+/// Appends `c` and its replies to `res` as a blockquote `depth` levels deep
+fn comment_to_markdown(db: &DbDump, t: &Task, c: &Comment, depth: usize, res: &mut String) {
+    let quote = ">".repeat(depth);
+    let (author, date) = match find_event(t, &c.creation_id) {
+        Some((owner, date)) => (user_name(db, &owner), format_time(&Some(date))),
+        None => (String::from("unknown user"), String::new()),
+    };
+    *res += &format!("\n{quote} **{author}**, {date}:\n{quote}\n");
+    for line in comment_text(c).lines() {
+        *res += &format!("{quote} {line}\n");
+    }
+    for child in c.children.values().flat_map(|v| v.iter()) {
+        comment_to_markdown(db, t, child, depth + 1, res);
+    }
+}
+
+fn find_event(t: &Task, id: &EventId) -> Option<(UserId, Time)> {
+    t.events
+        .values()
+        .flat_map(|v| v.iter())
+        .find(|e| e.id == *id)
+        .map(|e| (e.owner_id, e.date))
+}
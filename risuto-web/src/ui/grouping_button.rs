@@ -0,0 +1,40 @@
+use yew::prelude::*;
+
+use crate::util;
+
+#[derive(Clone, PartialEq, Properties)]
+pub struct GroupingButtonProps {
+    pub grouping: Option<util::Grouping>,
+    pub on_set_grouping: Callback<Option<util::Grouping>>,
+}
+
+#[function_component(GroupingButton)]
+pub fn grouping_button(p: &GroupingButtonProps) -> Html {
+    let item = |by: Option<util::Grouping>, label: String| {
+        let active = (p.grouping == by).then_some("active");
+        let onclick = p.on_set_grouping.reform(move |_| by);
+        html! {
+            <li><a class={classes!("dropdown-item", active)} href="#" {onclick}>
+                { label }
+            </a></li>
+        }
+    };
+    html! {
+        <div class="float-above dropdown">
+            <button
+                type="button"
+                class="btn btn-light btn-circle mt-3 ms-3 bi-btn bi-collection fs-6"
+                title="Group by"
+                data-bs-toggle="dropdown"
+            >
+            </button>
+            <ul class="dropdown-menu dropdown-menu-dark mt-3">
+                <li><h6 class="dropdown-header">{ "Group by" }</h6></li>
+                { item(None, String::from("No grouping")) }
+                { for util::Grouping::ALL
+                    .into_iter()
+                    .map(|by| item(Some(by), format!("By {}", by.name()))) }
+            </ul>
+        </div>
+    }
+}
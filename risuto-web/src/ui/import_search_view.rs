@@ -0,0 +1,165 @@
+use std::rc::Rc;
+
+use futures::FutureExt;
+use risuto_client::{
+    api::{Search, SearchExport},
+    DbDump, QueryExt,
+};
+use yew::prelude::*;
+
+use crate::{api, util, LoginInfo};
+
+#[derive(Clone, PartialEq, Properties)]
+pub struct ImportSearchViewProps {
+    pub login: LoginInfo,
+    pub db: Rc<DbDump>,
+    pub on_imported: Callback<Search>,
+    pub on_close: Callback<()>,
+}
+
+pub enum ImportSearchViewMsg {
+    SetJson(String),
+    Validated(SearchExport, Result<(), api::Error>),
+    Import,
+    Imported(Result<Search, api::Error>),
+}
+
+pub struct ImportSearchView {
+    /// Search pasted by the user, while the server checks it can be imported
+    pasted: Option<SearchExport>,
+    /// Search pasted by the user, once the server checked it can be imported
+    validated: Option<SearchExport>,
+    error: Option<String>,
+}
+
+fn describe_error(err: &api::Error) -> String {
+    match err {
+        api::Error::Api(risuto_client::api::Error::TagNotFound(_)) => String::from(
+            "This search refers to a tag you cannot see, ask its owner to share it with you first",
+        ),
+        api::Error::Api(err) => format!("This search is not valid: {err}"),
+        _ => String::from("Failed checking this search, please try again later"),
+    }
+}
+
+impl Component for ImportSearchView {
+    type Message = ImportSearchViewMsg;
+    type Properties = ImportSearchViewProps;
+
+    fn create(_ctx: &Context<Self>) -> Self {
+        ImportSearchView {
+            pasted: None,
+            validated: None,
+            error: None,
+        }
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            ImportSearchViewMsg::SetJson(json) => {
+                self.pasted = None;
+                self.validated = None;
+                self.error = None;
+                if json.trim().is_empty() {
+                    return true;
+                }
+                match serde_json::from_str::<SearchExport>(&json) {
+                    Ok(search) => {
+                        self.pasted = Some(search.clone());
+                        ctx.link().send_future(
+                            api::validate_search(ctx.props().login.clone(), search.clone())
+                                .map(move |res| ImportSearchViewMsg::Validated(search, res)),
+                        );
+                    }
+                    Err(err) => {
+                        self.error = Some(format!("This is not an exported search: {err}"));
+                    }
+                }
+            }
+            // The user kept typing since this check was sent
+            ImportSearchViewMsg::Validated(search, _) if self.pasted.as_ref() != Some(&search) => {
+                return false;
+            }
+            ImportSearchViewMsg::Validated(search, Ok(())) => {
+                self.validated = Some(search);
+            }
+            ImportSearchViewMsg::Validated(_, Err(err)) => {
+                tracing::warn!(?err, "search to import was rejected");
+                self.error = Some(describe_error(&err));
+            }
+            ImportSearchViewMsg::Import => {
+                if let Some(search) = self.validated.clone() {
+                    ctx.link().send_future(
+                        api::import_search(ctx.props().login.clone(), search)
+                            .map(ImportSearchViewMsg::Imported),
+                    );
+                }
+                return false;
+            }
+            ImportSearchViewMsg::Imported(Ok(search)) => {
+                ctx.props().on_imported.emit(search);
+            }
+            ImportSearchViewMsg::Imported(Err(err)) => {
+                tracing::error!(?err, "failed importing search");
+                self.error = Some(describe_error(&err));
+            }
+        }
+        true
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let oninput = ctx.link().callback(|e: InputEvent| {
+            let input: web_sys::HtmlTextAreaElement = e.target_unchecked_into();
+            ImportSearchViewMsg::SetJson(input.value())
+        });
+        html! {
+            <div class="container my-4">
+                <div class="d-flex align-items-center mb-4">
+                    <h1 class="flex-fill">{ "Import a search" }</h1>
+                    <button
+                        type="button"
+                        class="btn btn-secondary"
+                        onclick={ctx.props().on_close.reform(|_| ())}
+                    >
+                        { "Back" }
+                    </button>
+                </div>
+                <p>
+                    { "Paste below a search that was exported from the sidebar, to add it to \
+                       your custom searches." }
+                </p>
+                <textarea
+                    class="form-control font-monospace mb-3"
+                    rows="8"
+                    placeholder="{\"name\": ..., \"filter\": ..., \"order\": ...}"
+                    aria-label="Exported search"
+                    {oninput}
+                />
+                if let Some(error) = &self.error {
+                    <div class="alert alert-danger">{ error.clone() }</div>
+                }
+                if let Some(search) = &self.validated {
+                    <div class="alert alert-success">
+                        <strong>{ search.name.clone() }</strong>
+                        <div class="font-monospace">
+                            {
+                                search.filter.to_search(
+                                    &ctx.props().db.tags,
+                                    &util::local_tz(),
+                                    util::day_start(),
+                                )
+                            }
+                        </div>
+                    </div>
+                    <button
+                        type="button"
+                        class="btn btn-primary"
+                        onclick={ctx.link().callback(|_| ImportSearchViewMsg::Import)}
+                    >
+                        { "Import" }
+                    </button>
+                }
+            </div>
+        }
+    }
+}
@@ -1,17 +1,26 @@
 use futures::FutureExt;
+use gloo_worker::{Spawnable, WorkerBridge};
 use risuto_client::api::Error as ApiError;
-use risuto_client::api::{AuthToken, NewSession};
+use risuto_client::api::{
+    AuthToken, InstanceInfo, NewSession, NewUser, Registration, UserId, Uuid,
+};
 use yew::prelude::*;
 
 use crate::{
     api::{self, Error},
-    LoginInfo,
+    pow_worker::PowWorker,
+    util, LoginInfo,
 };
 
+/// Path at which trunk makes the proof-of-work worker available, relative to index.html
+const POW_WORKER_PATH: &str = "pow-worker.js";
+
 #[derive(Clone, PartialEq, Properties)]
 pub struct LoginProps {
     pub info: Option<LoginInfo>,
     pub on_authed: Callback<LoginInfo>,
+    #[prop_or_default]
+    pub on_cancel: Option<Callback<()>>,
 }
 
 pub struct Login {
@@ -19,6 +28,28 @@ pub struct Login {
     user: String,
     pass: String,
     error: Option<&'static str>,
+    status: Option<LoginStatus>,
+    pow_worker: WorkerBridge<PowWorker>,
+    instance: Option<InstanceInfo>,
+    /// Set when opened through an invite link, in which case the account gets created first
+    invite: Option<Uuid>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum LoginStatus {
+    Registering,
+    ComputingPow,
+    Authenticating,
+}
+
+impl LoginStatus {
+    fn message(&self) -> &'static str {
+        match self {
+            LoginStatus::Registering => "Creating your account...",
+            LoginStatus::ComputingPow => "Securing the connection...",
+            LoginStatus::Authenticating => "Authenticating...",
+        }
+    }
 }
 
 pub enum LoginMsg {
@@ -26,6 +57,9 @@ pub enum LoginMsg {
     UserChanged(String),
     PassChanged(String),
     SubmitClicked,
+    Registered(Result<(), Error>),
+    PowComputed(String),
+    InstanceInfo(String, Result<InstanceInfo, Error>),
     Authed(String, String, Result<AuthToken, Error>),
 }
 
@@ -34,6 +68,16 @@ fn get_device() -> anyhow::Result<String> {
     // TODO: add more details, see https://github.com/ardaku/whoami/issues/52
 }
 
+impl Login {
+    fn fetch_instance_info(&self, ctx: &Context<Self>) {
+        let host = self.host.clone();
+        ctx.link().send_future(
+            api::fetch_instance_info(String::from(host.trim_end_matches('/')))
+                .map(move |info| LoginMsg::InstanceInfo(host, info)),
+        );
+    }
+}
+
 impl Component for Login {
     type Message = LoginMsg;
     type Properties = LoginProps;
@@ -41,34 +85,115 @@ impl Component for Login {
     fn create(ctx: &Context<Self>) -> Self {
         let (host, user) = match &ctx.props().info {
             Some(i) => (i.host.clone(), i.user.clone()),
-            None => (String::new(), String::new()),
+            None => (util::default_host(), String::new()),
         };
-        Self {
+        let pow_worker = {
+            let link = ctx.link().clone();
+            PowWorker::spawner()
+                .callback(move |pow| link.send_message(LoginMsg::PowComputed(pow)))
+                .spawn(POW_WORKER_PATH)
+        };
+        let this = Self {
             host,
             user,
             pass: String::new(),
             error: None,
-        }
+            status: None,
+            pow_worker,
+            instance: None,
+            invite: util::invite_from_location(),
+        };
+        this.fetch_instance_info(ctx);
+        this
     }
 
     fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        if let LoginMsg::Authed(..) | LoginMsg::Registered(Err(_)) = msg {
+            self.status = None;
+        }
         match msg {
-            LoginMsg::HostChanged(h) => self.host = h,
+            LoginMsg::HostChanged(h) => {
+                self.host = h;
+                self.instance = None;
+                self.fetch_instance_info(ctx);
+            }
+            LoginMsg::InstanceInfo(host, info) => {
+                if host != self.host {
+                    return false; // the host changed since this request was sent
+                }
+                // Instances that do not serve this information are not an error
+                self.instance = info.ok();
+            }
             LoginMsg::UserChanged(u) => self.user = u,
             LoginMsg::PassChanged(p) => self.pass = p,
             LoginMsg::SubmitClicked => {
+                if self.status.is_some() {
+                    return false;
+                }
+                self.error = None;
+                match self.invite {
+                    Some(invite) => {
+                        self.status = Some(LoginStatus::Registering);
+                        let registration = Registration {
+                            invite,
+                            user: NewUser::new(
+                                UserId(Uuid::new_v4()),
+                                self.user.clone(),
+                                self.pass.clone(),
+                            ),
+                        };
+                        let host = String::from(self.host.trim_end_matches('/'));
+                        ctx.link().send_future(
+                            api::register(host, registration).map(LoginMsg::Registered),
+                        );
+                    }
+                    None => {
+                        self.status = Some(LoginStatus::ComputingPow);
+                        self.pow_worker.send(self.pass.clone());
+                    }
+                }
+            }
+            LoginMsg::Registered(Ok(())) => {
+                // Now that the account exists, log into it
+                self.invite = None;
+                let location = web_sys::window().expect("no web_sys window").location();
+                let _ = location.set_hash("");
+                self.status = Some(LoginStatus::ComputingPow);
+                self.pow_worker.send(self.pass.clone());
+            }
+            LoginMsg::Registered(Err(Error::Api(ApiError::NameAlreadyUsed(_)))) => {
+                self.error = Some("This username is already taken, please pick another one.");
+            }
+            LoginMsg::Registered(Err(Error::Api(ApiError::InvalidName(_)))) => {
+                self.error =
+                    Some("Usernames can only contain letters, digits, dashes and underscores.");
+            }
+            LoginMsg::Registered(Err(Error::Api(ApiError::PermissionDenied))) => {
+                self.error = Some("This invite link is invalid or has already been used.");
+            }
+            LoginMsg::Registered(Err(err)) => {
+                tracing::error!(?err, "registration failed");
+                self.error = Some("Failed creating your account. Maybe the URL is mistyped?");
+            }
+            LoginMsg::PowComputed(pow) => {
+                if self.status != Some(LoginStatus::ComputingPow) {
+                    return false;
+                }
                 let device = get_device().unwrap_or_else(|_| String::from("Unknown device"));
-                let session = NewSession::new(self.user.clone(), self.pass.clone(), device);
-                let host = self.host.clone();
+                let session = NewSession {
+                    user: self.user.clone(),
+                    password: self.pass.clone(),
+                    device,
+                    pow,
+                };
+                let host = String::from(self.host.trim_end_matches('/'));
                 let user = self.user.clone();
                 ctx.link().send_future(
-                    api::auth(self.host.clone(), session)
+                    api::auth(host.clone(), session)
                         .map(move |token| LoginMsg::Authed(host, user, token)),
                 );
-                // TODO: show some kind of indicator that auth is in progress?
-                // making host/user disabled would also avoid the need of passing them through Authed
+                self.status = Some(LoginStatus::Authenticating);
                 // TODO: reuse the Client built in App
-                return false;
             }
             LoginMsg::Authed(host, user, Ok(token)) => {
                 ctx.props().on_authed.emit(LoginInfo { host, user, token });
@@ -90,6 +215,10 @@ impl Component for Login {
                     "The server seems to not be a valid risuto server. Maybe the URL is mistyped?",
                 );
             }
+            LoginMsg::Authed(_, _, Err(Error::Server(status))) => {
+                tracing::error!(?status, "login failed due to server error");
+                self.error = Some("The server is currently failing, please try again later.");
+            }
             LoginMsg::Authed(_, _, Err(Error::Api(ApiError::PermissionDenied))) => {
                 tracing::error!("login failed due to permission denied");
                 self.error =
@@ -114,9 +243,19 @@ impl Component for Login {
                 })
             };
         }
+        let busy = self.status.is_some();
         html! {<>
             <div class="text-center my-4">
-                <h1>{ "Login" }</h1>
+                {for self.instance.as_ref().and_then(|i| i.logo_url.clone()).map(|logo| html! {
+                    <img class="instance-logo mb-3" src={logo} alt="" />
+                })}
+                <h1>
+                    { if self.invite.is_some() { "Create your account" } else { "Login" } }
+                    {for self.instance.as_ref().map(|i| format!(" to {}", i.name))}
+                </h1>
+                {for self.instance.as_ref().map(|i| html! {
+                    <p class="text-muted">{ i.registration.description() }</p>
+                })}
             </div>
             {for self.error.map(|err| html! {
                 <div class="alert alert-danger">
@@ -133,6 +272,7 @@ impl Component for Login {
                         placeholder="https://example.org"
                         value={self.host.clone()}
                         onchange={callback_for!(HostChanged)}
+                        disabled={busy}
                     />
                 </div>
                 <div class="input-group mb-3">
@@ -144,6 +284,7 @@ impl Component for Login {
                         placeholder="user"
                         value={self.user.clone()}
                         onchange={callback_for!(UserChanged)}
+                        disabled={busy}
                     />
                 </div>
                 <div class="input-group mb-3">
@@ -155,14 +296,31 @@ impl Component for Login {
                         placeholder="pass"
                         value={self.pass.clone()}
                         onchange={callback_for!(PassChanged)}
+                        disabled={busy}
                     />
                 </div>
                 <input
                     type="button"
                     class="btn btn-primary"
                     onclick={ctx.link().callback(|_| LoginMsg::SubmitClicked)}
-                    value="Connect"
+                    value={ if self.invite.is_some() { "Create account" } else { "Connect" } }
+                    disabled={busy}
                 />
+                {for ctx.props().on_cancel.as_ref().map(|on_cancel| html! {
+                    <input
+                        type="button"
+                        class="btn btn-secondary ms-2"
+                        onclick={on_cancel.reform(|_| ())}
+                        value="Cancel"
+                        disabled={busy}
+                    />
+                })}
+                {for self.status.map(|status| html! {
+                    <span class="ms-3">
+                        <span class="spinner-border spinner-border-sm me-2" role="status"></span>
+                        { status.message() }
+                    </span>
+                })}
             </form>
         </>}
     }
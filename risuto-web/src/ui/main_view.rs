@@ -1,9 +1,13 @@
-use crate::ui;
+use crate::{ui, util, LoginInfo};
 use risuto_client::{
-    api::{Action, TagId},
+    api::{Action, DayStart, Event, InstanceInfo, SearchId, TagId, TaskId},
     DbDump, Task,
 };
-use std::{collections::VecDeque, rc::Rc, sync::Arc};
+use std::{
+    collections::{HashMap, VecDeque},
+    rc::Rc,
+    sync::Arc,
+};
 use yew::prelude::*;
 
 #[derive(Debug, Eq, PartialEq)]
@@ -48,20 +52,51 @@ pub struct TaskOrderChangeEvent {
 #[derive(Clone, PartialEq, Properties)]
 pub struct MainViewProps {
     pub connection_state: ui::ConnState,
+    pub server_reachable: bool,
+    pub read_only: bool,
+    pub working_offline: bool,
+    pub on_set_working_offline: Callback<bool>,
     pub actions_pending_submission: VecDeque<Action>,
+    pub actions_dead_letter: Rc<Vec<ui::DeadLetter>>,
+    pub on_retry_dead_letter: Callback<usize>,
+    pub on_discard_dead_letter: Callback<usize>,
     pub db: Rc<DbDump>,
+    pub db_truncated: bool,
+    /// Tasks that may be duplicates of each task
+    pub duplicates: Rc<HashMap<TaskId, Vec<TaskId>>>,
     pub current_tag: Option<TagId>,
     pub user_knows_current_tag: bool,
+    /// Search the lists are the results of, that remembers how they get grouped
+    pub search: SearchId,
     pub tasks_open: Rc<Vec<Arc<Task>>>,
     pub tasks_done: Rc<Vec<Arc<Task>>>,
     pub tasks_backlog: Rc<Vec<Arc<Task>>>,
+    pub current_account: LoginInfo,
+    pub accounts: Rc<Vec<LoginInfo>>,
+    pub instance: Option<Rc<InstanceInfo>>,
+    pub day_start: DayStart,
+    pub on_set_day_start: Callback<DayStart>,
+    pub highlight_rules: Rc<Vec<util::HighlightRule>>,
+    pub on_set_highlight_rules: Callback<Vec<util::HighlightRule>>,
     pub on_logout: Callback<()>,
+    pub on_switch_account: Callback<LoginInfo>,
+    pub on_add_account: Callback<()>,
+    pub on_print: Callback<()>,
+    pub on_activity: Callback<()>,
+    pub on_telemetry: Callback<()>,
+    pub on_rules: Callback<()>,
+    pub on_plan_day: Callback<()>,
+    pub on_mark_all_read: Callback<()>,
+    pub on_set_avatar: Callback<web_sys::File>,
     pub on_action: Callback<Action>,
+    pub on_title_change: Callback<(TaskId, String)>,
     pub on_order_change: Callback<TaskOrderChangeEvent>,
 }
 
 #[function_component(MainView)]
 pub fn main_view(p: &MainViewProps) -> Html {
+    util::record_render("MainView");
+
     // The lists must be sortable
     let ref_open = use_node_ref();
     let ref_done = use_node_ref();
@@ -166,22 +201,109 @@ pub fn main_view(p: &MainViewProps) -> Html {
         })
     };
 
+    // Keep the callback stable across renders, so that task lists only re-render when they change
+    let on_event = use_callback(
+        |e: Event, on_action| on_action.emit(Action::NewEvent(e)),
+        p.on_action.clone(),
+    );
+
+    // Grouping is saved per search in local storage, so re-render after changing it
+    let force_update = use_force_update();
+    let grouping = util::list_grouping(&p.search);
+    let on_set_grouping = use_callback(
+        {
+            let force_update = force_update.clone();
+            move |by: Option<util::Grouping>, search| {
+                let grouping = util::ListGrouping {
+                    by,
+                    collapsed: Default::default(),
+                };
+                util::save_list_grouping(search, &grouping);
+                force_update.force_update();
+            }
+        },
+        p.search,
+    );
+    let on_toggle_group = use_callback(
+        move |key: String, search| {
+            let mut grouping = util::list_grouping(search);
+            if !grouping.collapsed.remove(&key) {
+                grouping.collapsed.insert(key);
+            }
+            util::save_list_grouping(search, &grouping);
+            force_update.force_update();
+        },
+        p.search,
+    );
+
+    let sections = p
+        .current_tag
+        .and_then(|t| p.db.tags.get(&t))
+        .map(|t| t.sections.clone())
+        .unwrap_or_default();
+
     // Put everything together
     html! {
         <div class="h-100 d-flex flex-column overflow-hidden position-relative">
             <div ref={empty_ref}></div>
-            <ui::OfflineBanner connection_state={p.connection_state.clone()} />
+            <ui::OfflineBanner
+                connection_state={p.connection_state.clone()}
+                server_reachable={p.server_reachable}
+                read_only={p.read_only}
+                working_offline={p.working_offline}
+            />
 
             // Top float-above bar corner
             <div class="float-above-container">
-                <ui::SearchBar db={ p.db.clone() } />
-                <ui::ActionSubmissionSpinner actions_pending_submission={ p.actions_pending_submission.clone() } />
+                <ui::SearchBar db={ p.db.clone() } day_start={ p.day_start } />
+                <ui::ActionSubmissionSpinner
+                    db={ p.db.clone() }
+                    actions_pending_submission={ p.actions_pending_submission.clone() }
+                    actions_dead_letter={ p.actions_dead_letter.clone() }
+                    on_retry_dead_letter={ p.on_retry_dead_letter.clone() }
+                    on_discard_dead_letter={ p.on_discard_dead_letter.clone() }
+                />
                 <ui::NewTaskButton db={ p.db.clone() } on_action={ p.on_action.clone() }/>
-                <ui::SettingsMenu on_logout={ p.on_logout.clone() } />
+                <ui::GroupingButton grouping={ grouping.by } { on_set_grouping } />
+                <ui::ExportButton
+                    db={ p.db.clone() }
+                    tasks_open={ p.tasks_open.clone() }
+                    tasks_done={ p.tasks_done.clone() }
+                    tasks_backlog={ p.tasks_backlog.clone() }
+                />
+                <ui::SettingsMenu
+                    current_account={ p.current_account.clone() }
+                    accounts={ p.accounts.clone() }
+                    instance={ p.instance.clone() }
+                    day_start={ p.day_start }
+                    on_set_day_start={ p.on_set_day_start.clone() }
+                    highlight_rules={ p.highlight_rules.clone() }
+                    on_set_highlight_rules={ p.on_set_highlight_rules.clone() }
+                    db={ p.db.clone() }
+                    on_logout={ p.on_logout.clone() }
+                    on_switch_account={ p.on_switch_account.clone() }
+                    on_add_account={ p.on_add_account.clone() }
+                    on_print={ p.on_print.clone() }
+                    on_activity={ p.on_activity.clone() }
+                    on_telemetry={ p.on_telemetry.clone() }
+                    on_rules={ p.on_rules.clone() }
+                    on_plan_day={ p.on_plan_day.clone() }
+                    on_mark_all_read={ p.on_mark_all_read.clone() }
+                    on_set_avatar={ p.on_set_avatar.clone() }
+                    working_offline={ p.working_offline }
+                    on_set_working_offline={ p.on_set_working_offline.clone() }
+                />
             </div>
 
             // Main task list
             <div class="flex-fill overflow-auto p-0">
+                if p.db_truncated {
+                    <div class="alert alert-warning m-lg-5 mb-lg-0">
+                        { "There are too many tasks to show them all, only the most recently \
+                           created ones were loaded, whatever the order of this list. Refine your \
+                           search, or archive the tasks you are done with, to see the others." }
+                    </div>
+                }
                 <div class="m-lg-5">
                     <ui::TaskList
                         ref_this={ ref_open }
@@ -189,7 +311,14 @@ pub fn main_view(p: &MainViewProps) -> Html {
                         current_tag={ p.current_tag.clone() }
                         user_knows_current_tag={ p.user_knows_current_tag }
                         tasks={ p.tasks_open.clone() }
-                        on_event={ p.on_action.reform(Action::NewEvent) }
+                        { sections }
+                        grouping={ grouping.clone() }
+                        on_toggle_group={ on_toggle_group.clone() }
+                        day_start={ p.day_start }
+                        highlight_rules={ p.highlight_rules.clone() }
+                        duplicates={ p.duplicates.clone() }
+                        on_event={ on_event.clone() }
+                        on_title_change={ p.on_title_change.clone() }
                     />
                 </div>
 
@@ -200,7 +329,13 @@ pub fn main_view(p: &MainViewProps) -> Html {
                         current_tag={ p.current_tag.clone() }
                         user_knows_current_tag={ p.user_knows_current_tag }
                         tasks={ p.tasks_done.clone() }
-                        on_event={ p.on_action.reform(Action::NewEvent) }
+                        grouping={ grouping.clone() }
+                        on_toggle_group={ on_toggle_group.clone() }
+                        day_start={ p.day_start }
+                        highlight_rules={ p.highlight_rules.clone() }
+                        duplicates={ p.duplicates.clone() }
+                        on_event={ on_event.clone() }
+                        on_title_change={ p.on_title_change.clone() }
                     />
                 </div>
             </div>
@@ -229,7 +364,13 @@ pub fn main_view(p: &MainViewProps) -> Html {
                             current_tag={ p.current_tag.clone() }
                             user_knows_current_tag={ p.user_knows_current_tag }
                             tasks={ p.tasks_backlog.clone() }
-                            on_event={ p.on_action.reform(Action::NewEvent) }
+                            grouping={ grouping.clone() }
+                            on_toggle_group={ on_toggle_group.clone() }
+                            day_start={ p.day_start }
+                            highlight_rules={ p.highlight_rules.clone() }
+                            duplicates={ p.duplicates.clone() }
+                            on_event={ on_event.clone() }
+                        on_title_change={ p.on_title_change.clone() }
                         />
                     </div>
                 </div>
@@ -1,8 +1,26 @@
 mod action_submission_spinner;
 pub use action_submission_spinner::ActionSubmissionSpinner;
 
+mod activity_view;
+pub use activity_view::ActivityView;
+
 mod app;
-pub use app::{App, AppMsg, ConnState};
+pub use app::{App, AppMsg, AppView, ConnState, DeadLetter};
+
+mod changelog_dialog;
+pub use changelog_dialog::ChangelogDialog;
+
+mod command_palette;
+pub use command_palette::CommandPalette;
+
+mod export_button;
+pub use export_button::ExportButton;
+
+mod grouping_button;
+pub use grouping_button::GroupingButton;
+
+mod import_search_view;
+pub use import_search_view::ImportSearchView;
 
 mod login;
 pub use login::Login;
@@ -16,6 +34,15 @@ pub use new_task_button::NewTaskButton;
 mod offline_banner;
 pub use offline_banner::OfflineBanner;
 
+mod plan_day_view;
+pub use plan_day_view::PlanDayView;
+
+mod print_view;
+pub use print_view::PrintView;
+
+mod rules_view;
+pub use rules_view::RulesView;
+
 mod search_bar;
 pub use search_bar::SearchBar;
 
@@ -25,8 +52,20 @@ pub use settings_menu::SettingsMenu;
 mod search_list;
 pub use search_list::SearchList;
 
+mod sync_diff_overlay;
+pub use sync_diff_overlay::SyncDiffOverlay;
+
+mod tag_permissions_view;
+pub use tag_permissions_view::TagPermissionsView;
+
+mod telemetry_view;
+pub use telemetry_view::TelemetryView;
+
 mod task_list;
 pub use task_list::TaskList;
 
 mod task_list_item;
 pub use task_list_item::TaskListItem;
+
+mod user_picker;
+pub use user_picker::UserPicker;
@@ -1,9 +1,6 @@
 use std::rc::Rc;
 
-use risuto_client::{
-    api::{self, Action, EventId, TaskId, Uuid},
-    DbDump,
-};
+use risuto_client::{api::Action, DbDump};
 use yew::prelude::*;
 
 use crate::util;
@@ -23,21 +20,9 @@ pub fn new_task_button(p: &NewTaskButtonProps) -> Html {
     let on_submit = {
         let db = p.db.clone();
         let on_action = p.on_action.clone();
-        Callback::from(move |title| {
-            let task_id = TaskId(Uuid::new_v4());
-            let (title, evts) = util::parse_tag_changes(&*db, task_id, title);
-            on_action.emit(Action::NewTask(
-                api::Task {
-                    id: task_id,
-                    owner_id: db.owner,
-                    date: chrono::Utc::now(),
-                    initial_title: title,
-                    top_comment_id: EventId(Uuid::new_v4()),
-                },
-                String::from(""), // TODO: allow setting initial top comment value
-            ));
-            for e in evts {
-                on_action.emit(Action::NewEvent(e));
+        Callback::from(move |title: String| {
+            for a in util::new_task_actions(&db, &title) {
+                on_action.emit(a);
             }
         })
     };
@@ -4,16 +4,29 @@ use yew::prelude::*;
 #[derive(Clone, PartialEq, Properties)]
 pub struct OfflineBannerProps {
     pub connection_state: ui::ConnState,
+    pub server_reachable: bool,
+    pub read_only: bool,
+    /// Whether the user chose to stop all network requests
+    pub working_offline: bool,
 }
 
 #[function_component(OfflineBanner)]
 pub fn offline_banner(p: &OfflineBannerProps) -> Html {
-    let offline = !matches!(p.connection_state, ui::ConnState::Connected);
-    let offline_banner_message = match p.connection_state {
-        ui::ConnState::Disconnected => "Currently offline. Trying to reconnect...",
-        ui::ConnState::WebsocketConnected(_) | ui::ConnState::Connected => {
-            "Currently reconnecting..."
+    let offline = p.working_offline
+        || p.read_only
+        || !p.server_reachable
+        || !matches!(p.connection_state, ui::ConnState::Connected);
+    let offline_banner_message = match (p.server_reachable, &p.connection_state) {
+        _ if p.working_offline => {
+            "Working offline, your changes are kept until you go back online from the settings."
         }
+        (false, _) => "Cannot reach the server, your changes are kept until it is back...",
+        (true, ui::ConnState::Disconnected) => "Currently offline. Trying to reconnect...",
+        (true, ui::ConnState::WebsocketConnected(_)) => "Currently reconnecting...",
+        (true, ui::ConnState::Connected) if p.read_only => {
+            "The server is under maintenance, your changes are kept until it is over..."
+        }
+        (true, ui::ConnState::Connected) => "Currently reconnecting...",
     };
 
     html! {
@@ -24,7 +37,11 @@ pub fn offline_banner(p: &OfflineBannerProps) -> Html {
             ) }
             aria-hidden={ if offline { "false" } else { "true" } }
         >
-            <div class="spinner-border spinner-border-sm m-2" role="status"></div>
+            if p.working_offline {
+                <div class="bi-airplane-fill m-2" aria-hidden="true"></div>
+            } else {
+                <div class="spinner-border spinner-border-sm m-2" role="status"></div>
+            }
             <div>{ offline_banner_message }</div>
         </div>
     }
@@ -0,0 +1,116 @@
+use std::{collections::HashSet, rc::Rc, sync::Arc};
+
+use risuto_client::{api::TaskId, date, plan, Task};
+use yew::prelude::*;
+
+use crate::util;
+
+#[derive(Clone, PartialEq, Properties)]
+pub struct PlanDayViewProps {
+    /// Tasks of the Today search, in their current order
+    pub tasks: Rc<Vec<Arc<Task>>>,
+    pub on_accept: Callback<Vec<TaskId>>,
+    pub on_close: Callback<()>,
+}
+
+#[function_component(PlanDayView)]
+pub fn plan_day_view(p: &PlanDayViewProps) -> Html {
+    let capacity = use_state(util::plan_capacity);
+    let skipped = use_state(HashSet::<TaskId>::new);
+
+    let now = crate::CLOCK.now();
+    let proposal = plan::plan_day(&p.tasks, &now, *capacity);
+
+    let on_capacity_change = {
+        let capacity = capacity.clone();
+        Callback::from(move |e: Event| {
+            let input: web_sys::HtmlInputElement = e.target_unchecked_into();
+            if let Ok(c) = input.value().parse::<usize>() {
+                if c > 0 {
+                    util::save_plan_capacity(c);
+                    capacity.set(c);
+                }
+            }
+        })
+    };
+    let on_accept = {
+        let planned = proposal
+            .iter()
+            .map(|t| t.id)
+            .filter(|t| !skipped.contains(t))
+            .collect::<Vec<_>>();
+        p.on_accept.reform(move |_| planned.clone())
+    };
+    let tz = util::local_tz();
+
+    html! {
+        <div class="container my-4">
+            <div class="d-flex align-items-center mb-4">
+                <h1 class="flex-fill">{ "Plan my day" }</h1>
+                <button
+                    type="button"
+                    class="btn btn-secondary"
+                    onclick={p.on_close.reform(|_| ())}
+                >
+                    { "Back" }
+                </button>
+            </div>
+            <div class="d-flex align-items-center mb-3">
+                <label class="me-2" for="plan-capacity">{ "Number of tasks to plan" }</label>
+                <input
+                    type="number"
+                    min="1"
+                    class="form-control w-auto"
+                    id="plan-capacity"
+                    value={capacity.to_string()}
+                    onchange={on_capacity_change}
+                />
+            </div>
+            if proposal.is_empty() {
+                <div class="alert alert-info">{ "Nothing left to do today!" }</div>
+            } else {
+                <ul class="list-group mb-3">
+                    { for proposal.iter().map(|t| {
+                        let id = t.id;
+                        let is_planned = !skipped.contains(&id);
+                        let on_toggle = {
+                            let skipped = skipped.clone();
+                            Callback::from(move |_| {
+                                let mut s = (*skipped).clone();
+                                if !s.remove(&id) {
+                                    s.insert(id);
+                                }
+                                skipped.set(s);
+                            })
+                        };
+                        html! {
+                            <li class="list-group-item d-flex align-items-center">
+                                <input
+                                    type="checkbox"
+                                    class="form-check-input me-3"
+                                    checked={is_planned}
+                                    onchange={on_toggle}
+                                />
+                                if let Some(prio) = t.priority {
+                                    <span
+                                        class={classes!("bi", "bi-flag-fill", "me-2", format!("priority-{}", prio.name()))}
+                                        title={format!("Priority {}", prio.name().to_uppercase())}
+                                    ></span>
+                                }
+                                <span class="flex-fill">{ &*t.current_title }</span>
+                                if let Some(scheduled) = t.scheduled_for {
+                                    <span class="text-muted" title={date::absolute_label(&scheduled, &tz)}>
+                                        { date::relative_label(&scheduled, &now, &tz) }
+                                    </span>
+                                }
+                            </li>
+                        }
+                    }) }
+                </ul>
+                <button type="button" class="btn btn-primary" onclick={on_accept}>
+                    { "Accept plan" }
+                </button>
+            }
+        </div>
+    }
+}
@@ -0,0 +1,98 @@
+use std::{rc::Rc, sync::Arc};
+
+use risuto_client::{Comment, DbDump, Task};
+use yew::prelude::*;
+
+use crate::util;
+
+#[derive(Clone, PartialEq, Properties)]
+pub struct PrintViewProps {
+    pub db: Rc<DbDump>,
+    pub title: String,
+    pub tasks_open: Rc<Vec<Arc<Task>>>,
+    pub tasks_done: Rc<Vec<Arc<Task>>>,
+    pub tasks_backlog: Rc<Vec<Arc<Task>>>,
+    pub on_close: Callback<()>,
+}
+
+#[function_component(PrintView)]
+pub fn print_view(p: &PrintViewProps) -> Html {
+    let with_comments = use_state(|| false);
+    let on_toggle_comments = {
+        let with_comments = with_comments.clone();
+        Callback::from(move |_| with_comments.set(!*with_comments))
+    };
+    let on_print = Callback::from(|_| {
+        web_sys::window()
+            .expect("no web_sys window")
+            .print()
+            .expect("failed opening print dialog")
+    });
+    let lists = [
+        ("Open", &p.tasks_open),
+        ("Done", &p.tasks_done),
+        ("Backlog", &p.tasks_backlog),
+    ];
+    html! {
+        <div class="print-view container my-4">
+            <div class="d-print-none mb-4">
+                <button type="button" class="btn btn-primary me-2" onclick={on_print}>
+                    <span class="bi-printer me-2" aria-hidden="true"></span>
+                    {"Print"}
+                </button>
+                <button type="button" class="btn btn-secondary me-3" onclick={p.on_close.reform(|_| ())}>
+                    {"Back"}
+                </button>
+                <input
+                    type="checkbox"
+                    class="form-check-input me-2"
+                    id="print-with-comments"
+                    checked={*with_comments}
+                    onchange={on_toggle_comments}
+                />
+                <label class="form-check-label" for="print-with-comments">{"Include comments"}</label>
+            </div>
+            <h1>{ &p.title }</h1>
+            { for lists.iter().filter(|(_, tasks)| !tasks.is_empty()).map(|(name, tasks)| html! {
+                <>
+                    <h2>{ name }</h2>
+                    <ul class="print-checklist">
+                        { for tasks.iter().map(|t| print_task(&p.db, t, *with_comments)) }
+                    </ul>
+                </>
+            }) }
+        </div>
+    }
+}
+
+fn print_task(db: &DbDump, t: &Task, with_comments: bool) -> Html {
+    let mut tags = t
+        .current_tags
+        .keys()
+        .filter_map(|tag| db.tags.get(tag))
+        .collect::<Vec<_>>();
+    util::sort_tags(&db.owner, &mut tags, |t| t);
+    let comments = with_comments.then(|| {
+        std::iter::once(&t.top_comment)
+            .chain(t.current_comments.values().flat_map(|v| v.iter()))
+            .filter_map(last_edit)
+            .filter(|c| !c.is_empty())
+            .collect::<Vec<_>>()
+    });
+    html! {
+        <li>
+            <span class={classes!("bi", if t.is_done { "bi-check-square" } else { "bi-square" }, "me-2")}></span>
+            { &*t.current_title }
+            { for tags.iter().map(|tag| html! {
+                <span class="tag-pill rounded-pill ms-2">{ &tag.name }</span>
+            }) }
+            { for comments.into_iter().flatten().map(|c| html! {
+                <p class="print-comment">{ c }</p>
+            }) }
+        </li>
+    }
+}
+
+fn last_edit(c: &Comment) -> Option<&String> {
+    c.edits.get_max()?.1.last()
+}
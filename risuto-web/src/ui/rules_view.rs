@@ -0,0 +1,429 @@
+use std::rc::Rc;
+
+use futures::FutureExt;
+use risuto_client::{
+    api::{Query, Rule, RuleAction, RuleId, RuleTrigger, TagId, TimeQuery, Uuid},
+    DbDump, QueryExt,
+};
+use yew::prelude::*;
+
+use crate::{api, util, LoginInfo};
+
+#[derive(Clone, PartialEq, Properties)]
+pub struct RulesViewProps {
+    pub login: LoginInfo,
+    pub db: Rc<DbDump>,
+    pub on_close: Callback<()>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum ActionKind {
+    AddTag,
+    ScheduleFor,
+    Notify,
+}
+
+impl ActionKind {
+    const ALL: [ActionKind; 3] = [
+        ActionKind::AddTag,
+        ActionKind::ScheduleFor,
+        ActionKind::Notify,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            ActionKind::AddTag => "add a tag",
+            ActionKind::ScheduleFor => "schedule it",
+            ActionKind::Notify => "bring it back to today",
+        }
+    }
+}
+
+/// Rule being written in the form at the bottom of the page
+struct Draft {
+    id: RuleId,
+    name: String,
+    trigger: RuleTrigger,
+    filter: String,
+    action: ActionKind,
+    tag: Option<TagId>,
+    day_offset: i64,
+}
+
+impl Draft {
+    fn new() -> Draft {
+        Draft {
+            id: RuleId(Uuid::new_v4()),
+            name: String::new(),
+            trigger: RuleTrigger::NewTask,
+            filter: String::new(),
+            action: ActionKind::Notify,
+            tag: None,
+            day_offset: 1,
+        }
+    }
+}
+
+pub enum RulesViewMsg {
+    Loaded(Result<Vec<Rule>, api::Error>),
+    SetDraftName(String),
+    SetDraftTrigger(RuleTrigger),
+    SetDraftFilter(String),
+    SetDraftAction(ActionKind),
+    SetDraftTag(Option<TagId>),
+    SetDraftDayOffset(i64),
+    Create,
+    Save(Rule),
+    Saved(Rule, Result<(), api::Error>),
+    Delete(RuleId),
+    Deleted(RuleId, Result<(), api::Error>),
+}
+
+pub struct RulesView {
+    /// `None` while loading
+    rules: Option<Vec<Rule>>,
+    draft: Draft,
+    error: Option<String>,
+}
+
+fn describe_error(err: &api::Error) -> String {
+    match err {
+        api::Error::Api(risuto_client::api::Error::TagNotFound(_)) => {
+            String::from("This rule refers to a tag you cannot see")
+        }
+        api::Error::Api(err) => format!("This rule is not valid: {err}"),
+        _ => String::from("Failed saving this rule, please try again later"),
+    }
+}
+
+fn describe_action(db: &DbDump, action: &RuleAction) -> String {
+    match action {
+        RuleAction::AddTag(tag) => format!("add #{}", db.tag_name(tag).unwrap_or("unknown tag")),
+        RuleAction::ScheduleFor(TimeQuery::DayRelative { day_offset: 0, .. }) => {
+            String::from("schedule it for today")
+        }
+        RuleAction::ScheduleFor(TimeQuery::DayRelative { day_offset: 1, .. }) => {
+            String::from("schedule it for tomorrow")
+        }
+        RuleAction::ScheduleFor(TimeQuery::DayRelative { day_offset, .. }) => {
+            format!("schedule it in {day_offset} days")
+        }
+        RuleAction::ScheduleFor(TimeQuery::Absolute(t)) => format!(
+            "schedule it for {}",
+            t.with_timezone(&util::local_tz()).format("%Y-%m-%d %H:%M")
+        ),
+        RuleAction::Notify => String::from("bring it back to today"),
+    }
+}
+
+impl Component for RulesView {
+    type Message = RulesViewMsg;
+    type Properties = RulesViewProps;
+
+    fn create(ctx: &Context<Self>) -> Self {
+        ctx.link()
+            .send_future(api::fetch_rules(ctx.props().login.clone()).map(RulesViewMsg::Loaded));
+        RulesView {
+            rules: None,
+            draft: Draft::new(),
+            error: None,
+        }
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            RulesViewMsg::Loaded(Ok(mut rules)) => {
+                rules.sort_unstable_by(|a, b| (&a.name, a.id).cmp(&(&b.name, b.id)));
+                self.rules = Some(rules);
+            }
+            RulesViewMsg::Loaded(Err(err)) => {
+                tracing::error!(?err, "failed fetching rules");
+                self.error = Some(String::from("Failed loading your rules"));
+            }
+            RulesViewMsg::SetDraftName(name) => {
+                self.draft.name = name;
+            }
+            RulesViewMsg::SetDraftTrigger(trigger) => {
+                self.draft.trigger = trigger;
+            }
+            RulesViewMsg::SetDraftFilter(filter) => {
+                self.draft.filter = filter;
+            }
+            RulesViewMsg::SetDraftAction(action) => {
+                self.draft.action = action;
+            }
+            RulesViewMsg::SetDraftTag(tag) => {
+                self.draft.tag = tag;
+            }
+            RulesViewMsg::SetDraftDayOffset(day_offset) => {
+                self.draft.day_offset = day_offset;
+            }
+            RulesViewMsg::Create => {
+                let db = &ctx.props().db;
+                let action = match (self.draft.action, self.draft.tag) {
+                    (ActionKind::AddTag, Some(tag)) => RuleAction::AddTag(tag),
+                    (ActionKind::AddTag, None) => {
+                        self.error = Some(String::from("Pick the tag this rule should add"));
+                        return true;
+                    }
+                    (ActionKind::ScheduleFor, _) => {
+                        RuleAction::ScheduleFor(TimeQuery::DayRelative {
+                            timezone: util::local_tz(),
+                            day_offset: self.draft.day_offset,
+                            day_start: util::day_start(),
+                        })
+                    }
+                    (ActionKind::Notify, _) => RuleAction::Notify,
+                };
+                let filter = match self.draft.filter.trim() {
+                    "" => Query::All(vec![]),
+                    filter => Query::from_search(db, &util::local_tz(), util::day_start(), filter),
+                };
+                let rule = Rule {
+                    id: self.draft.id,
+                    owner_id: db.owner,
+                    name: self.draft.name.trim().to_string(),
+                    enabled: true,
+                    trigger: self.draft.trigger,
+                    filter,
+                    action,
+                };
+                ctx.link().send_message(RulesViewMsg::Save(rule));
+                return false;
+            }
+            RulesViewMsg::Save(rule) => {
+                ctx.link().send_future(
+                    api::set_rule(ctx.props().login.clone(), rule.clone())
+                        .map(move |res| RulesViewMsg::Saved(rule, res)),
+                );
+                return false;
+            }
+            RulesViewMsg::Saved(rule, Ok(())) => {
+                self.error = None;
+                if rule.id == self.draft.id {
+                    self.draft = Draft::new();
+                }
+                if let Some(rules) = &mut self.rules {
+                    match rules.iter_mut().find(|r| r.id == rule.id) {
+                        Some(r) => *r = rule,
+                        None => rules.push(rule),
+                    }
+                }
+            }
+            RulesViewMsg::Saved(_, Err(err)) => {
+                tracing::error!(?err, "failed saving rule");
+                self.error = Some(describe_error(&err));
+            }
+            RulesViewMsg::Delete(rule) => {
+                ctx.link().send_future(
+                    api::delete_rule(ctx.props().login.clone(), rule)
+                        .map(move |res| RulesViewMsg::Deleted(rule, res)),
+                );
+                return false;
+            }
+            RulesViewMsg::Deleted(rule, Ok(())) => {
+                self.error = None;
+                if let Some(rules) = &mut self.rules {
+                    rules.retain(|r| r.id != rule);
+                }
+            }
+            RulesViewMsg::Deleted(_, Err(err)) => {
+                tracing::error!(?err, "failed deleting rule");
+                self.error = Some(String::from("Failed deleting this rule"));
+            }
+        }
+        true
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let db = &ctx.props().db;
+        let mut tags = db.tags.values().collect::<Vec<_>>();
+        tags.sort_unstable_by_key(|t| (&t.name, t.id));
+        html! {
+            <div class="container my-4">
+                <div class="d-flex align-items-center mb-4">
+                    <h1 class="flex-fill">{ "Automation rules" }</h1>
+                    <button
+                        type="button"
+                        class="btn btn-secondary"
+                        onclick={ctx.props().on_close.reform(|_| ())}
+                    >
+                        { "Back" }
+                    </button>
+                </div>
+                <p>
+                    { "Rules act on your behalf whenever someone changes a task you can see, as \
+                       long as the task then matches their search." }
+                </p>
+                if let Some(error) = &self.error {
+                    <div class="alert alert-danger">{ error.clone() }</div>
+                }
+                if let Some(rules) = &self.rules {
+                    <ul class="list-group">
+                        { for rules.iter().map(|rule| {
+                            let toggled = Rule {
+                                enabled: !rule.enabled,
+                                ..rule.clone()
+                            };
+                            let id = rule.id;
+                            html! {
+                                <li class="list-group-item d-flex align-items-center">
+                                    <input
+                                        type="checkbox"
+                                        class="form-check-input me-3"
+                                        title="Enabled"
+                                        checked={rule.enabled}
+                                        onchange={ctx.link().callback(move |_| RulesViewMsg::Save(toggled.clone()))}
+                                    />
+                                    <div class="flex-fill">
+                                        <strong>{ rule.name.clone() }</strong>
+                                        <div class="text-muted">
+                                            { format!("When {}, on tasks matching ", rule.trigger.name()) }
+                                            <span class="font-monospace">
+                                                { rule.filter.to_search(&db.tags, &util::local_tz(), util::day_start()) }
+                                            </span>
+                                            { format!(", {}", describe_action(db, &rule.action)) }
+                                        </div>
+                                    </div>
+                                    <button
+                                        type="button"
+                                        class="btn btn-outline-danger bi-btn bi-trash"
+                                        title="Delete"
+                                        onclick={ctx.link().callback(move |_| RulesViewMsg::Delete(id))}
+                                    >
+                                    </button>
+                                </li>
+                            }
+                        }) }
+                    </ul>
+                    <h2 class="h5 mt-4">{ "New rule" }</h2>
+                    <div class="row g-2 align-items-center">
+                        <div class="col-12">
+                            <input
+                                type="text"
+                                class="form-control"
+                                placeholder="Name"
+                                aria-label="Name"
+                                value={self.draft.name.clone()}
+                                oninput={ctx.link().callback(|e: InputEvent| {
+                                    let input: web_sys::HtmlInputElement = e.target_unchecked_into();
+                                    RulesViewMsg::SetDraftName(input.value())
+                                })}
+                            />
+                        </div>
+                        <div class="col-auto">{ "When" }</div>
+                        <div class="col">
+                            <select
+                                class="form-select"
+                                aria-label="Trigger"
+                                onchange={ctx.link().callback(|e: Event| {
+                                    let select: web_sys::HtmlSelectElement = e.target_unchecked_into();
+                                    let trigger = RuleTrigger::ALL
+                                        .into_iter()
+                                        .find(|t| t.name() == select.value())
+                                        .unwrap_or(RuleTrigger::NewTask);
+                                    RulesViewMsg::SetDraftTrigger(trigger)
+                                })}
+                            >
+                                { for RuleTrigger::ALL.into_iter().map(|t| html! {
+                                    <option value={t.name()} selected={t == self.draft.trigger}>
+                                        { t.name() }
+                                    </option>
+                                }) }
+                            </select>
+                        </div>
+                        <div class="col-auto">{ "on tasks matching" }</div>
+                        <div class="col">
+                            <input
+                                type="text"
+                                class="form-control font-monospace"
+                                placeholder="Any task"
+                                aria-label="Search"
+                                value={self.draft.filter.clone()}
+                                oninput={ctx.link().callback(|e: InputEvent| {
+                                    let input: web_sys::HtmlInputElement = e.target_unchecked_into();
+                                    RulesViewMsg::SetDraftFilter(input.value())
+                                })}
+                            />
+                        </div>
+                        <div class="col-auto">
+                            <select
+                                class="form-select"
+                                aria-label="Action"
+                                onchange={ctx.link().callback(|e: Event| {
+                                    let select: web_sys::HtmlSelectElement = e.target_unchecked_into();
+                                    let action = ActionKind::ALL
+                                        .into_iter()
+                                        .find(|a| a.name() == select.value())
+                                        .unwrap_or(ActionKind::Notify);
+                                    RulesViewMsg::SetDraftAction(action)
+                                })}
+                            >
+                                { for ActionKind::ALL.into_iter().map(|a| html! {
+                                    <option value={a.name()} selected={a == self.draft.action}>
+                                        { a.name() }
+                                    </option>
+                                }) }
+                            </select>
+                        </div>
+                        if self.draft.action == ActionKind::AddTag {
+                            <div class="col-auto">
+                                <select
+                                    class="form-select"
+                                    aria-label="Tag"
+                                    onchange={ctx.link().callback(|e: Event| {
+                                        let select: web_sys::HtmlSelectElement = e.target_unchecked_into();
+                                        RulesViewMsg::SetDraftTag(Uuid::try_parse(&select.value()).ok().map(TagId))
+                                    })}
+                                >
+                                    <option value="" selected={self.draft.tag.is_none()}>
+                                        { "Pick a tag" }
+                                    </option>
+                                    { for tags.iter().map(|t| html! {
+                                        <option
+                                            value={t.id.0.to_string()}
+                                            selected={self.draft.tag == Some(t.id)}
+                                        >
+                                            { format!("#{}", t.name) }
+                                        </option>
+                                    }) }
+                                </select>
+                            </div>
+                        }
+                        if self.draft.action == ActionKind::ScheduleFor {
+                            <div class="col-auto">{ "in" }</div>
+                            <div class="col-auto">
+                                <input
+                                    type="number"
+                                    class="form-control"
+                                    min="0"
+                                    aria-label="Days"
+                                    value={self.draft.day_offset.to_string()}
+                                    onchange={ctx.link().callback(|e: Event| {
+                                        let input: web_sys::HtmlInputElement = e.target_unchecked_into();
+                                        RulesViewMsg::SetDraftDayOffset(input.value().parse().unwrap_or(0))
+                                    })}
+                                />
+                            </div>
+                            <div class="col-auto">{ "days" }</div>
+                        }
+                        <div class="col-auto">
+                            <button
+                                type="button"
+                                class="btn btn-primary"
+                                disabled={self.draft.name.trim().is_empty()}
+                                onclick={ctx.link().callback(|_| RulesViewMsg::Create)}
+                            >
+                                { "Add rule" }
+                            </button>
+                        </div>
+                    </div>
+                } else if self.error.is_none() {
+                    <div class="text-center mt-3">
+                        <span class="spinner-border" role="status"></span>
+                    </div>
+                }
+            </div>
+        }
+    }
+}
@@ -1,7 +1,7 @@
 use std::{rc::Rc, sync::Arc};
 
 use risuto_client::{
-    api::{Order, OrderType, Query, Search, SearchId},
+    api::{DayStart, Order, OrderType, Query, Search, SearchId},
     DbDump, QueryExt, Task,
 };
 use yew::prelude::*;
@@ -11,6 +11,7 @@ use crate::util;
 #[derive(Clone, PartialEq, Properties)]
 pub struct SearchBarProps {
     pub db: Rc<DbDump>,
+    pub day_start: DayStart,
 }
 
 #[function_component(SearchBar)]
@@ -27,15 +28,21 @@ pub fn search_bar(p: &SearchBarProps) -> Html {
     let results = use_state(|| None::<SearchResults>);
     let query_local = {
         let db = p.db.clone();
+        let day_start = p.day_start;
         let results = results.clone();
         Callback::from(move |e: web_sys::InputEvent| {
+            if util::page_hidden() {
+                // eg. filled in by the browser, the results will be computed on the next keystroke
+                return;
+            }
             let search: web_sys::HtmlInputElement = e.target_unchecked_into();
             let search = search.value();
             let search = search.trim();
             results.set(match search.len() {
                 0 => None,
                 _ => {
-                    let filter = Query::from_search(&db, &util::local_tz(), search.trim());
+                    let filter =
+                        Query::from_search(&db, &util::local_tz(), day_start, search.trim());
                     tracing::debug!("searching with query {:?}", filter);
                     tracing::debug!("(parsed from {:?})", search.trim());
                     let search = Search {
@@ -45,7 +52,7 @@ pub fn search_bar(p: &SearchBarProps) -> Html {
                         order: Order::LastEventDate(OrderType::Desc),
                         priority: 0,
                     };
-                    if let Ok(res) = db.search(&search) {
+                    if let Ok(res) = db.search_at(&search, &crate::CLOCK.now()) {
                         Some(SearchResults::Local(res))
                     } else {
                         None
@@ -1,6 +1,9 @@
 use std::iter;
 
-use risuto_client::api::{Search, SearchId, Tag, TagId, UserId};
+use risuto_client::{
+    api::{DayStart, Search, SearchId, Tag, TagId, UserId},
+    QueryExt,
+};
 use yew::prelude::*;
 
 use crate::util;
@@ -11,54 +14,148 @@ pub struct SearchListProps {
     pub tags: im::HashMap<TagId, Tag>,
     pub current_user: UserId,
     pub active_search: SearchId,
+    pub day_start: DayStart,
     pub on_select_search: Callback<Search>,
+    pub on_share_tag: Callback<TagId>,
+    pub on_share_search: Callback<SearchId>,
+    pub on_subscribe_search: Callback<SearchId>,
+    pub on_export_search: Callback<SearchId>,
+    pub on_import_search: Callback<()>,
+}
+
+enum Share {
+    /// Manage who has access to a tag the current user owns
+    Tag(TagId),
+
+    /// Get a public badge for a custom search, or export it for other users to import
+    Search(SearchId),
 }
 
 enum Item {
-    Search(Search),
+    Search(Search, Option<Share>),
     Separator(&'static str),
+    ImportButton,
+    ArchivedToggle(usize),
 }
 
 #[function_component(SearchList)]
 pub fn search_list(p: &SearchListProps) -> Html {
     let mut searches = p.searches.values().collect::<Vec<_>>();
     searches.sort_by_key(|s| (s.priority, &s.name, s.id));
+    let tz = util::local_tz();
+    let show_archived = use_state(|| false);
     let mut tags = p.tags.values().collect::<Vec<_>>();
     util::sort_tags(&p.current_user, &mut tags, |t| t);
-    let list_items = iter::once(Item::Search(Search::today(util::local_tz())))
-        .chain(iter::once(Item::Separator("Custom Searches")))
-        .chain(p.searches.values().cloned().map(Item::Search))
-        .chain(iter::once(Item::Separator("Tags")))
-        .chain(tags.into_iter().map(Search::for_tag).map(Item::Search))
-        .chain(iter::once(Item::Search(Search::untagged())))
-        .map(|it| match it {
-            Item::Separator(name) => html! {
-                <li class="category border-bottom p-1">
-                    { name }
+    let archived = tags.iter().filter(|t| t.archived).count();
+    // The active search stays listed even when it is an archived tag, so it can be seen
+    tags.retain(|t| !t.archived || *show_archived || SearchId(t.id.0) == p.active_search);
+    let list_items = iter::once(Item::Search(
+        Search::today(util::local_tz(), p.day_start),
+        None,
+    ))
+    .chain(iter::once(Item::Separator("Custom Searches")))
+    .chain(
+        p.searches
+            .values()
+            .map(|s| Item::Search(s.clone(), Some(Share::Search(s.id)))),
+    )
+    .chain(iter::once(Item::ImportButton))
+    .chain(iter::once(Item::Separator("Tags")))
+    .chain(tags.into_iter().map(|t| {
+        let owned = (t.owner_id == p.current_user).then_some(Share::Tag(t.id));
+        Item::Search(Search::for_tag(t), owned)
+    }))
+    .chain(iter::once(Item::Search(Search::untagged(), None)))
+    .chain((archived > 0).then_some(Item::ArchivedToggle(archived)))
+    .map(|it| match it {
+        Item::Separator(name) => html! {
+            <li class="category border-bottom p-1">
+                { name }
+            </li>
+        },
+        Item::ImportButton => {
+            let onclick = p.on_import_search.reform(|_| ());
+            html! {
+                <li class="p-2">
+                    <button type="button" class="btn btn-sm btn-link p-0" { onclick }>
+                        { "Import a search" }
+                    </button>
+                </li>
+            }
+        }
+        Item::ArchivedToggle(count) => {
+            let onclick = {
+                let show_archived = show_archived.clone();
+                Callback::from(move |_| show_archived.set(!*show_archived))
+            };
+            let label = match *show_archived {
+                true => format!("Hide archived tags ({count})"),
+                false => format!("Show archived tags ({count})"),
+            };
+            html! {
+                <li class="p-2">
+                    <button type="button" class="btn btn-sm btn-link p-0" { onclick }>
+                        { label }
+                    </button>
+                </li>
+            }
+        }
+        Item::Search(search, share) => {
+            let is_active = match search.id == p.active_search {
+                true => "active",
+                false => "",
+            };
+            let on_select_tag = {
+                let search = search.clone();
+                p.on_select_search.reform(move |_| search.clone())
+            };
+            let filter = search.filter.to_search(&p.tags, &tz, p.day_start);
+            html! {
+                <li class={classes!(is_active, "border-bottom", "p-2")}>
+                    { for share.into_iter().flat_map(|share| match share {
+                        Share::Tag(tag) => {
+                            vec![("bi-people-fill", "Share", p.on_share_tag.reform(move |_| tag))]
+                        }
+                        Share::Search(search) => vec![
+                            (
+                                "bi-patch-check",
+                                "Get a status badge",
+                                p.on_share_search.reform(move |_| search),
+                            ),
+                            (
+                                "bi-rss",
+                                "Subscribe as a feed",
+                                p.on_subscribe_search.reform(move |_| search),
+                            ),
+                            (
+                                "bi-box-arrow-up",
+                                "Export",
+                                p.on_export_search.reform(move |_| search),
+                            ),
+                        ],
+                    }).map(|(icon, title, onclick)| {
+                        html! {
+                            <button
+                                type="button"
+                                class={classes!("btn", "btn-sm", "bi-btn", icon, "float-end")}
+                                { title }
+                                { onclick }
+                            >
+                            </button>
+                        }
+                    }) }
+                    <a
+                        class={classes!("nav-link", is_active)}
+                        href={util::Route::Search(search.id).hash()}
+                        title={filter}
+                        onclick={on_select_tag}
+                    >
+                        { search.name.clone() }
+                    </a>
                 </li>
-            },
-            Item::Search(search) => {
-                let is_active = match search.id == p.active_search {
-                    true => "active",
-                    false => "",
-                };
-                let on_select_tag = {
-                    let search = search.clone();
-                    p.on_select_search.reform(move |_| search.clone())
-                };
-                html! {
-                    <li class={classes!(is_active, "border-bottom", "p-2")}>
-                        <a
-                            class={classes!("nav-link", is_active)}
-                            href={format!("#search-{}", js_sys::encode_uri(&search.name))}
-                            onclick={on_select_tag}
-                        >
-                            { search.name.clone() }
-                        </a>
-                    </li>
-                }
             }
-        });
+        }
+    });
     html! {
         <ul class="nav flex-column">
             { for list_items }
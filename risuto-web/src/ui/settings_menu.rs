@@ -1,12 +1,118 @@
+use risuto_client::{
+    api::{DayStart, InstanceInfo},
+    DbDump,
+};
+use std::rc::Rc;
 use yew::prelude::*;
 
+use crate::{util, LoginInfo};
+
+/// Times the user can pick for their days to end, for late-night tasks to count as the day before
+const DAY_START_CHOICES: [(u16, &str); 5] = [
+    (0, "midnight"),
+    (60, "1am"),
+    (2 * 60, "2am"),
+    (3 * 60, "3am"),
+    (4 * 60, "4am"),
+];
+
 #[derive(Clone, PartialEq, Properties)]
 pub struct SettingsMenuProps {
+    pub current_account: LoginInfo,
+    pub accounts: Rc<Vec<LoginInfo>>,
+    pub instance: Option<Rc<InstanceInfo>>,
+    pub day_start: DayStart,
+    pub on_set_day_start: Callback<DayStart>,
+    pub highlight_rules: Rc<Vec<util::HighlightRule>>,
+    pub on_set_highlight_rules: Callback<Vec<util::HighlightRule>>,
+    /// Used to resolve the tag names of highlight rules
+    pub db: Rc<DbDump>,
     pub on_logout: Callback<()>,
+    pub on_switch_account: Callback<LoginInfo>,
+    pub on_add_account: Callback<()>,
+    pub on_print: Callback<()>,
+    pub on_activity: Callback<()>,
+    pub on_telemetry: Callback<()>,
+    pub on_rules: Callback<()>,
+    pub on_plan_day: Callback<()>,
+    pub on_mark_all_read: Callback<()>,
+    pub on_set_avatar: Callback<web_sys::File>,
+    pub working_offline: bool,
+    pub on_set_working_offline: Callback<bool>,
 }
 
 #[function_component(SettingsMenu)]
 pub fn settings_menu(p: &SettingsMenuProps) -> Html {
+    let current_account = p.current_account.account_id();
+    let on_avatar_change = p.on_set_avatar.filter_reform(|e: Event| {
+        let input: web_sys::HtmlInputElement = e.target_unchecked_into();
+        let file = input.files().and_then(|f| f.get(0));
+        input.set_value("");
+        // No file when the user cancelled the file picker
+        file
+    });
+    let on_day_start_change = p.on_set_day_start.reform(|e: Event| {
+        let select: web_sys::HtmlSelectElement = e.target_unchecked_into();
+        DayStart {
+            minutes: select.value().parse().unwrap_or(0),
+        }
+    });
+    let on_aging_change = Callback::from(|e: Event| {
+        let input: web_sys::HtmlInputElement = e.target_unchecked_into();
+        let mut thresholds = input
+            .value()
+            .split(',')
+            .filter_map(|t| t.trim().parse::<i64>().ok())
+            .filter(|t| *t > 0)
+            .collect::<Vec<_>>();
+        thresholds.sort_unstable();
+        thresholds.dedup();
+        util::save_aging_thresholds(&thresholds);
+        input.set_value(&aging_thresholds_label(&util::aging_thresholds()));
+    });
+    let (db, day_start) = (p.db.clone(), p.day_start);
+    let on_highlight_change = p.on_set_highlight_rules.reform(move |e: Event| {
+        let input: web_sys::HtmlTextAreaElement = e.target_unchecked_into();
+        let rules = input
+            .value()
+            .lines()
+            .filter_map(|l| util::HighlightRule::parse(&db, day_start, l))
+            .collect::<Vec<_>>();
+        input.set_value(&highlight_rules_label(&rules));
+        rules
+    });
+    let on_placement_change = Callback::from(|e: Event| {
+        let select: web_sys::HtmlSelectElement = e.target_unchecked_into();
+        util::save_new_task_placement(util::NewTaskPlacement {
+            at_bottom: select.value() == "bottom",
+            ..util::new_task_placement()
+        });
+    });
+    let on_backlog_change = Callback::from(|e: Event| {
+        let input: web_sys::HtmlInputElement = e.target_unchecked_into();
+        util::save_new_task_placement(util::NewTaskPlacement {
+            in_backlog: input.checked(),
+            ..util::new_task_placement()
+        });
+    });
+    let placement = util::new_task_placement();
+    let on_max_retries_change = Callback::from(|e: Event| {
+        let input: web_sys::HtmlInputElement = e.target_unchecked_into();
+        // An empty field means retrying forever
+        let max_retries = input.value().trim().parse::<u32>().ok();
+        util::save_retry_policy(util::RetryPolicy {
+            max_retries,
+            ..util::retry_policy()
+        });
+    });
+    let on_telemetry_change = Callback::from(|e: Event| {
+        let input: web_sys::HtmlInputElement = e.target_unchecked_into();
+        util::save_telemetry_enabled(input.checked());
+    });
+    let on_offline_change = p.on_set_working_offline.reform(|e: Event| {
+        let input: web_sys::HtmlInputElement = e.target_unchecked_into();
+        input.checked()
+    });
     html! {
         <div class="float-above dropdown">
             <button
@@ -17,6 +123,153 @@ pub fn settings_menu(p: &SettingsMenuProps) -> Html {
             >
             </button>
             <ul class="dropdown-menu dropdown-menu-dark mt-3">
+                {for p.instance.as_ref().map(|i| html! {<>
+                    <li><h6 class="dropdown-header">{ i.name.clone() }</h6></li>
+                    <li><hr class="dropdown-divider" /></li>
+                </>})}
+                <li><h6 class="dropdown-header">{"Accounts"}</h6></li>
+                { for p.accounts.iter().map(|a| {
+                    let is_current = a.account_id() == current_account;
+                    let onclick = {
+                        let a = a.clone();
+                        p.on_switch_account.reform(move |_| a.clone())
+                    };
+                    html! {
+                        <li><a
+                            class={classes!("dropdown-item", is_current.then_some("active"))}
+                            href="#"
+                            { onclick }
+                        >
+                            <span class="bi-person-fill me-2" aria-hidden="true"></span>
+                            { a.account_id() }
+                        </a></li>
+                    }
+                }) }
+                <li><a class="dropdown-item" href="#" onclick={p.on_add_account.reform(|_| ())}>
+                    <span class="bi-person-plus-fill me-2" aria-hidden="true"></span>
+                    {"Add account"}
+                </a></li>
+                <li><label class="dropdown-item" role="button">
+                    <span class="bi-person-bounding-box me-2" aria-hidden="true"></span>
+                    {"Change avatar"}
+                    <input type="file" accept="image/*" class="d-none" onchange={on_avatar_change} />
+                </label></li>
+                <li><hr class="dropdown-divider" /></li>
+                <li><label class="dropdown-item d-flex align-items-center">
+                    <span class="bi-moon-stars-fill me-2" aria-hidden="true"></span>
+                    <span class="me-2">{"Days end at"}</span>
+                    <select class="form-select form-select-sm w-auto" onchange={on_day_start_change}>
+                        { for DAY_START_CHOICES.iter().map(|(minutes, label)| html! {
+                            <option value={minutes.to_string()} selected={p.day_start.minutes == *minutes}>
+                                { label }
+                            </option>
+                        }) }
+                    </select>
+                </label></li>
+                <li><label class="dropdown-item d-flex align-items-center">
+                    <span class="bi-hourglass-bottom me-2" aria-hidden="true"></span>
+                    <span class="me-2">{"Tasks age after"}</span>
+                    <input
+                        type="text"
+                        class="form-control form-control-sm w-auto me-2"
+                        size="8"
+                        value={aging_thresholds_label(&util::aging_thresholds())}
+                        onchange={on_aging_change}
+                    />
+                    <span>{"days"}</span>
+                </label></li>
+                <li><label class="dropdown-item d-flex align-items-center">
+                    <span class="bi-plus-square me-2" aria-hidden="true"></span>
+                    <span class="me-2">{"New tasks go to the"}</span>
+                    <select class="form-select form-select-sm w-auto" onchange={on_placement_change}>
+                        <option value="top" selected={!placement.at_bottom}>{"top"}</option>
+                        <option value="bottom" selected={placement.at_bottom}>{"bottom"}</option>
+                    </select>
+                </label></li>
+                <li><label class="dropdown-item d-flex align-items-center">
+                    <input
+                        type="checkbox"
+                        class="form-check-input me-2"
+                        checked={placement.in_backlog}
+                        onchange={on_backlog_change}
+                    />
+                    {"Put new tasks in the backlog"}
+                </label></li>
+                <li><label class="dropdown-item d-flex align-items-center">
+                    <span class="bi-palette-fill me-2" aria-hidden="true"></span>
+                    <span class="me-2">{"Highlight"}</span>
+                    <textarea
+                        class="form-control form-control-sm"
+                        rows="2"
+                        placeholder="red: prio:p1"
+                        title="One rule per line, a color then a search, for example 'red: prio:p1'"
+                        value={highlight_rules_label(&p.highlight_rules)}
+                        onchange={on_highlight_change}
+                    />
+                </label></li>
+                <li><label class="dropdown-item d-flex align-items-center" title="Takes effect after reloading">
+                    <span class="bi-arrow-repeat me-2" aria-hidden="true"></span>
+                    <span class="me-2">{"Retry requests"}</span>
+                    <input
+                        type="number"
+                        min="0"
+                        class="form-control form-control-sm w-auto me-2"
+                        placeholder="forever"
+                        value={util::retry_policy().max_retries.map(|r| r.to_string()).unwrap_or_default()}
+                        onchange={on_max_retries_change}
+                    />
+                    <span>{"times"}</span>
+                </label></li>
+                <li><label
+                    class="dropdown-item d-flex align-items-center"
+                    title="Stops all network requests, your changes being sent once back online"
+                >
+                    <input
+                        type="checkbox"
+                        class="form-check-input me-2"
+                        checked={p.working_offline}
+                        onchange={on_offline_change}
+                    />
+                    {"Work offline"}
+                </label></li>
+                <li><a class="dropdown-item" href="#" onclick={p.on_plan_day.reform(|_| ())}>
+                    <span class="bi-calendar-check me-2" aria-hidden="true"></span>
+                    {"Plan my day"}
+                </a></li>
+                <li><a class="dropdown-item" href="#" onclick={p.on_mark_all_read.reform(|_| ())}>
+                    <span class="bi-check2-all me-2" aria-hidden="true"></span>
+                    {"Mark all as read"}
+                </a></li>
+                <li><a class="dropdown-item" href="#" onclick={p.on_activity.reform(|_| ())}>
+                    <span class="bi-clock-history me-2" aria-hidden="true"></span>
+                    {"Activity"}
+                </a></li>
+                <li><a class="dropdown-item" href="#" onclick={p.on_rules.reform(|_| ())}>
+                    <span class="bi-lightning-charge me-2" aria-hidden="true"></span>
+                    {"Automation rules"}
+                </a></li>
+                if p.instance.as_ref().map(|i| i.telemetry).unwrap_or(false) {
+                    <li><label
+                        class="dropdown-item d-flex align-items-center"
+                        title="Counts how often you use each feature, without anything about your tasks"
+                    >
+                        <input
+                            type="checkbox"
+                            class="form-check-input me-2"
+                            checked={util::telemetry_enabled()}
+                            onchange={on_telemetry_change}
+                        />
+                        {"Send anonymous usage statistics"}
+                    </label></li>
+                    <li><a class="dropdown-item" href="#" onclick={p.on_telemetry.reform(|_| ())}>
+                        <span class="bi-bar-chart-line me-2" aria-hidden="true"></span>
+                        {"Usage statistics"}
+                    </a></li>
+                }
+                <li><a class="dropdown-item" href="#" onclick={p.on_print.reform(|_| ())}>
+                    <span class="bi-printer me-2" aria-hidden="true"></span>
+                    {"Print view"}
+                </a></li>
                 <li><a class="dropdown-item" href="#" onclick={p.on_logout.reform(|_| ())}>
                     <span class="bi-power me-2" aria-hidden="true"></span>
                     {"Logout"}
@@ -25,3 +278,19 @@ pub fn settings_menu(p: &SettingsMenuProps) -> Html {
         </div>
     }
 }
+
+fn aging_thresholds_label(thresholds: &[i64]) -> String {
+    thresholds
+        .iter()
+        .map(|t| t.to_string())
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn highlight_rules_label(rules: &[util::HighlightRule]) -> String {
+    rules
+        .iter()
+        .map(|r| r.label())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
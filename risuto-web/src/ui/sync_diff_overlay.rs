@@ -0,0 +1,68 @@
+use std::rc::Rc;
+
+use risuto_client::{Change, DbDump};
+use yew::prelude::*;
+
+#[derive(Clone, PartialEq, Properties)]
+pub struct SyncDiffOverlayProps {
+    /// Dump as last received from the server
+    pub synced: Rc<DbDump>,
+    pub db: Rc<DbDump>,
+}
+
+fn describe<Id>(kind: &str, c: &Change<Id>, name: impl Fn(&Id) -> Option<String>) -> String
+where
+    Id: std::fmt::Debug,
+{
+    let (sign, id, fields) = match c {
+        Change::Added(id) => ("+", id, String::new()),
+        Change::Removed(id) => ("-", id, String::new()),
+        Change::Modified(id, fields) => ("~", id, format!(": {}", fields.join(", "))),
+    };
+    let name = name(id).unwrap_or_else(|| format!("{id:?}"));
+    format!("{sign} {kind} \"{name}\"{fields}")
+}
+
+/// Debugging overlay listing what changed locally since the last sync with the server
+#[function_component(SyncDiffOverlay)]
+pub fn sync_diff_overlay(p: &SyncDiffOverlayProps) -> Html {
+    let diff = p.synced.diff(&p.db);
+    let task_name = |id: &_| {
+        p.db.tasks
+            .get(id)
+            .or_else(|| p.synced.tasks.get(id))
+            .map(|t| String::clone(&t.current_title))
+    };
+    let tag_name = |id: &_| {
+        p.db.tags
+            .get(id)
+            .or_else(|| p.synced.tags.get(id))
+            .map(|t| t.name.clone())
+    };
+    let search_name = |id: &_| {
+        p.db.searches
+            .get(id)
+            .or_else(|| p.synced.searches.get(id))
+            .map(|s| s.name.clone())
+    };
+    let lines = diff
+        .tasks
+        .iter()
+        .map(|c| describe("task", c, task_name))
+        .chain(diff.tags.iter().map(|c| describe("tag", c, tag_name)))
+        .chain(
+            diff.searches
+                .iter()
+                .map(|c| describe("search", c, search_name)),
+        )
+        .collect::<Vec<_>>();
+    html! {
+        <div class="sync-diff-overlay position-fixed bottom-0 start-0 m-2 p-2 small bg-dark text-light opacity-75">
+            <div class="fw-bold">{ "Changed since last sync" }</div>
+            if lines.is_empty() {
+                <div>{ "Nothing" }</div>
+            }
+            { for lines.into_iter().map(|l| html! { <div class="font-monospace">{ l }</div> }) }
+        </div>
+    }
+}
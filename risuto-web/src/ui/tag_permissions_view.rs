@@ -0,0 +1,293 @@
+use std::{collections::HashMap, rc::Rc};
+
+use futures::FutureExt;
+use risuto_client::{
+    api::{
+        AuthInfo, NewInvite, Role, Tag, TagId, TagPermission, TagSections, User, UserId, Uuid,
+        INVITE_VALIDITY_DAYS,
+    },
+    DbDump,
+};
+use yew::prelude::*;
+
+use crate::{api, ui, util, LoginInfo};
+
+#[derive(Clone, PartialEq, Properties)]
+pub struct TagPermissionsViewProps {
+    pub login: LoginInfo,
+    pub db: Rc<DbDump>,
+    pub tag: TagId,
+    pub on_tag_updated: Callback<Tag>,
+    pub on_close: Callback<()>,
+}
+
+pub enum TagPermissionsViewMsg {
+    Loaded(Result<Vec<(UserId, AuthInfo)>, api::Error>),
+    SetRole(UserId, Option<Role>),
+    RoleSet(UserId, Option<Role>, Result<(), api::Error>),
+    SetInviteRole(Role),
+    CreateInvite,
+    InviteCreated(Result<Uuid, api::Error>),
+    SetSections(Vec<String>),
+    SectionsSet(Vec<String>, Result<(), api::Error>),
+}
+
+pub struct TagPermissionsView {
+    /// `None` while loading
+    perms: Option<HashMap<UserId, AuthInfo>>,
+    error: Option<&'static str>,
+    invite_role: Role,
+}
+
+impl Component for TagPermissionsView {
+    type Message = TagPermissionsViewMsg;
+    type Properties = TagPermissionsViewProps;
+
+    fn create(ctx: &Context<Self>) -> Self {
+        ctx.link().send_future(
+            api::fetch_tag_permissions(ctx.props().login.clone(), ctx.props().tag)
+                .map(TagPermissionsViewMsg::Loaded),
+        );
+        TagPermissionsView {
+            perms: None,
+            error: None,
+            invite_role: Role::Editor,
+        }
+    }
+
+    fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
+        match msg {
+            TagPermissionsViewMsg::Loaded(Ok(perms)) => {
+                self.perms = Some(perms.into_iter().collect());
+            }
+            TagPermissionsViewMsg::Loaded(Err(err)) => {
+                tracing::error!(?err, "failed fetching tag permissions");
+                self.error = Some("Failed loading the permissions of this tag");
+            }
+            TagPermissionsViewMsg::SetRole(user, role) => {
+                let perm = TagPermission {
+                    tag: ctx.props().tag,
+                    user,
+                    role,
+                };
+                ctx.link().send_future(
+                    api::set_tag_permission(ctx.props().login.clone(), perm)
+                        .map(move |res| TagPermissionsViewMsg::RoleSet(user, role, res)),
+                );
+                return false;
+            }
+            TagPermissionsViewMsg::RoleSet(user, role, Ok(())) => {
+                self.error = None;
+                if let Some(perms) = &mut self.perms {
+                    match role {
+                        Some(role) => perms.insert(user, role.auth_info()),
+                        None => perms.remove(&user),
+                    };
+                }
+            }
+            TagPermissionsViewMsg::RoleSet(_, _, Err(err)) => {
+                tracing::error!(?err, "failed setting tag permission");
+                self.error = Some("Failed changing the permissions of this tag");
+            }
+            TagPermissionsViewMsg::SetInviteRole(role) => {
+                self.invite_role = role;
+                return false;
+            }
+            TagPermissionsViewMsg::CreateInvite => {
+                let invite = NewInvite {
+                    tags: vec![ctx.props().tag],
+                    role: self.invite_role,
+                };
+                ctx.link().send_future(
+                    api::create_invite(ctx.props().login.clone(), invite)
+                        .map(TagPermissionsViewMsg::InviteCreated),
+                );
+                return false;
+            }
+            TagPermissionsViewMsg::InviteCreated(Ok(token)) => {
+                self.error = None;
+                let window = web_sys::window().expect("no web_sys window");
+                let _ = window.prompt_with_message_and_default(
+                    &format!(
+                        "Send this link to the person you want to invite, it can only be used \
+                         once within {INVITE_VALIDITY_DAYS} days:"
+                    ),
+                    &util::invite_link(&token),
+                );
+            }
+            TagPermissionsViewMsg::InviteCreated(Err(err)) => {
+                tracing::error!(?err, "failed creating invite");
+                self.error = Some("Failed creating an invite link");
+            }
+            TagPermissionsViewMsg::SetSections(sections) => {
+                let data = TagSections {
+                    tag: ctx.props().tag,
+                    sections: sections.clone(),
+                };
+                ctx.link().send_future(
+                    api::set_tag_sections(ctx.props().login.clone(), data)
+                        .map(move |res| TagPermissionsViewMsg::SectionsSet(sections, res)),
+                );
+                return false;
+            }
+            TagPermissionsViewMsg::SectionsSet(sections, Ok(())) => {
+                self.error = None;
+                if let Some(tag) = ctx.props().db.tags.get(&ctx.props().tag) {
+                    ctx.props().on_tag_updated.emit(Tag {
+                        sections,
+                        ..tag.clone()
+                    });
+                }
+            }
+            TagPermissionsViewMsg::SectionsSet(_, Err(err)) => {
+                tracing::error!(?err, "failed setting tag sections");
+                self.error = Some("Failed changing the sections of this tag");
+            }
+        }
+        true
+    }
+
+    fn view(&self, ctx: &Context<Self>) -> Html {
+        let db = &ctx.props().db;
+        let tag = db.tags.get(&ctx.props().tag);
+        let tag_name = tag.map(|t| t.name.clone()).unwrap_or_default();
+        let sections = tag.map(|t| t.sections.join(", ")).unwrap_or_default();
+        html! {
+            <div class="container my-4">
+                <div class="d-flex align-items-center mb-4">
+                    <h1 class="flex-fill">{ format!("Sharing of #{tag_name}") }</h1>
+                    <button
+                        type="button"
+                        class="btn btn-secondary"
+                        onclick={ctx.props().on_close.reform(|_| ())}
+                    >
+                        { "Back" }
+                    </button>
+                </div>
+                if let Some(error) = self.error {
+                    <div class="alert alert-danger">{ error }</div>
+                }
+                if let Some(perms) = &self.perms {
+                    <ul class="list-group">
+                        { for shared_with(db, perms).into_iter().map(|u| {
+                            let current = perms.get(&u.id);
+                            let user = u.id;
+                            let onchange = ctx.link().callback(move |e: Event| {
+                                let select: web_sys::HtmlSelectElement = e.target_unchecked_into();
+                                let role = Role::ALL.into_iter().find(|r| r.name() == select.value());
+                                TagPermissionsViewMsg::SetRole(user, role)
+                            });
+                            html! {
+                                <li class="list-group-item d-flex align-items-center">
+                                    if let Some(hash) = &u.avatar_hash {
+                                        <img
+                                            class="avatar me-2"
+                                            src={ api::avatar_url(&ctx.props().login.host, hash) }
+                                            alt=""
+                                        />
+                                    }
+                                    <span class="flex-fill">{ u.name.clone() }</span>
+                                    <select class="form-select w-auto" { onchange }>
+                                        <option value="" selected={current.is_none()}>
+                                            { "No access" }
+                                        </option>
+                                        { for Role::ALL.into_iter().map(|r| html! {
+                                            <option
+                                                value={r.name()}
+                                                selected={current.and_then(Role::from_auth_info) == Some(r)}
+                                            >
+                                                { r.name() }
+                                            </option>
+                                        }) }
+                                        if current.is_some() && current.and_then(Role::from_auth_info).is_none() {
+                                            <option value="custom" selected=true disabled=true>
+                                                { "Custom" }
+                                            </option>
+                                        }
+                                    </select>
+                                </li>
+                            }
+                        }) }
+                    </ul>
+                    <div class="d-flex align-items-center mt-4">
+                        <span class="flex-fill">{ "Share with another user, as a viewer at first" }</span>
+                        <div class="w-auto">
+                            <ui::UserPicker
+                                host={ ctx.props().login.host.clone() }
+                                db={ db.clone() }
+                                exclude={ perms.keys().copied().chain([db.owner]).collect::<Vec<_>>() }
+                                on_pick={ctx.link().callback(|user| {
+                                    TagPermissionsViewMsg::SetRole(user, Some(Role::Viewer))
+                                })}
+                            />
+                        </div>
+                    </div>
+                    <div class="d-flex align-items-center mt-4">
+                        <span class="flex-fill">{ "Invite someone who has no account yet as" }</span>
+                        <select
+                            class="form-select w-auto me-2"
+                            onchange={ctx.link().callback(|e: Event| {
+                                let select: web_sys::HtmlSelectElement = e.target_unchecked_into();
+                                TagPermissionsViewMsg::SetInviteRole(
+                                    Role::parse(&select.value()).unwrap_or(Role::Editor),
+                                )
+                            })}
+                        >
+                            { for Role::ALL.into_iter().map(|r| html! {
+                                <option value={r.name()} selected={r == self.invite_role}>
+                                    { r.name() }
+                                </option>
+                            }) }
+                        </select>
+                        <button
+                            type="button"
+                            class="btn btn-primary"
+                            onclick={ctx.link().callback(|_| TagPermissionsViewMsg::CreateInvite)}
+                        >
+                            { "Create invite link" }
+                        </button>
+                    </div>
+                    <div class="d-flex align-items-center mt-4">
+                        <label class="flex-fill" for="tag-sections">
+                            { "Split the list into sections, separated by commas" }
+                        </label>
+                        <input
+                            type="text"
+                            class="form-control w-auto"
+                            id="tag-sections"
+                            placeholder="Doing, Next, Later"
+                            value={sections}
+                            onchange={ctx.link().callback(|e: Event| {
+                                let input: web_sys::HtmlInputElement = e.target_unchecked_into();
+                                TagPermissionsViewMsg::SetSections(
+                                    input
+                                        .value()
+                                        .split(',')
+                                        .map(|s| s.trim())
+                                        .filter(|s| !s.is_empty())
+                                        .map(String::from)
+                                        .collect(),
+                                )
+                            })}
+                        />
+                    </div>
+                } else if self.error.is_none() {
+                    <div class="text-center mt-3">
+                        <span class="spinner-border" role="status"></span>
+                    </div>
+                }
+            </div>
+        }
+    }
+}
+
+/// Users other than the owner who have access to the tag, sorted by name
+fn shared_with<'a>(db: &'a DbDump, perms: &HashMap<UserId, AuthInfo>) -> Vec<&'a User> {
+    let mut users = db
+        .users
+        .values()
+        .filter(|u| u.id != db.owner && perms.contains_key(&u.id))
+        .collect::<Vec<_>>();
+    users.sort_unstable_by_key(|u| (&u.name, u.id));
+    users
+}
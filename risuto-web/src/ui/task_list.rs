@@ -1,41 +1,141 @@
 use risuto_client::{
-    api::{Event, TagId},
-    DbDump, Task,
+    api::{DayStart, Event, TagId, TaskId},
+    DbDump, QueryExt, Task,
 };
-use std::{rc::Rc, sync::Arc};
+use std::{collections::HashMap, rc::Rc, sync::Arc};
 use yew::prelude::*;
 
-use crate::ui;
+use crate::{ui, util};
 
-#[derive(Clone, PartialEq, Properties)]
+#[derive(Clone, Properties)]
 pub struct TaskListProps {
     pub ref_this: NodeRef,
     pub db: Rc<DbDump>,
     pub current_tag: Option<TagId>,
     pub user_knows_current_tag: bool,
     pub tasks: Rc<Vec<Arc<Task>>>,
+    /// Sections of the current tag to split the list into, if any
+    #[prop_or_default]
+    pub sections: Vec<String>,
+    /// Grouping to split the list along, which takes precedence over sections
+    #[prop_or_default]
+    pub grouping: util::ListGrouping,
+    /// Called with the key of a group whose header got clicked
+    #[prop_or_default]
+    pub on_toggle_group: Callback<String>,
+    pub day_start: DayStart,
+    pub highlight_rules: Rc<Vec<util::HighlightRule>>,
+    /// Tasks that may be duplicates of each task
+    pub duplicates: Rc<HashMap<TaskId, Vec<TaskId>>>,
     pub on_event: Callback<Event>,
+    pub on_title_change: Callback<(TaskId, String)>,
+}
+
+// Lists that do not contain any changed task compare equal, as tasks are compared by pointer first
+impl PartialEq for TaskListProps {
+    fn eq(&self, other: &Self) -> bool {
+        self.ref_this == other.ref_this
+            && util::same_for_task_items(&self.db, &other.db)
+            && self.current_tag == other.current_tag
+            && self.user_knows_current_tag == other.user_knows_current_tag
+            && self.tasks == other.tasks
+            && self.sections == other.sections
+            && self.grouping == other.grouping
+            && self.on_toggle_group == other.on_toggle_group
+            && self.day_start == other.day_start
+            && self.highlight_rules == other.highlight_rules
+            && Rc::ptr_eq(&self.duplicates, &other.duplicates)
+            && self.on_event == other.on_event
+            && self.on_title_change == other.on_title_change
+    }
 }
 
 #[function_component(TaskList)]
 pub fn task_list(p: &TaskListProps) -> Html {
+    util::record_render("TaskList");
     // First, build the list items
-    let list_items = p.tasks.iter().map(|t| {
+    let list_item = |t: &Arc<Task>| {
+        let highlight = p
+            .highlight_rules
+            .iter()
+            .find(|r| {
+                r.query
+                    .matches_at(t, &p.db.tags, &crate::CLOCK.now())
+                    .unwrap_or(false)
+            })
+            .map(|r| r.color.class());
         html! {
             <ui::TaskListItem
+                key={ t.id.0.to_string() }
                 task={ t.clone() }
+                { highlight }
+                duplicate_of={ p.duplicates.get(&t.id).cloned().unwrap_or_default() }
                 db={ p.db.clone() }
                 current_tag={ p.current_tag.clone() }
                 user_knows_current_tag={ p.user_knows_current_tag }
                 on_event={ p.on_event.clone() }
+                on_title_change={ p.on_title_change.clone() }
             />
         }
-    });
+    };
+    let list_items = match (p.grouping.by, &p.current_tag, p.sections.is_empty()) {
+        (Some(by), _, _) => util::group_tasks(&p.db, by, p.day_start, &p.tasks)
+            .into_iter()
+            .map(|g| {
+                let collapsed = p.grouping.collapsed.contains(&g.key);
+                let onclick = {
+                    let key = g.key.clone();
+                    p.on_toggle_group.reform(move |_| key.clone())
+                };
+                let chevron = match collapsed {
+                    true => "bi-chevron-right",
+                    false => "bi-chevron-down",
+                };
+                html! {
+                    <>
+                        <li class="list-group-item task-list-section task-list-group">
+                            <button
+                                type="button"
+                                class="btn btn-link p-0 text-reset text-decoration-none"
+                                aria-expanded={ (!collapsed).to_string() }
+                                {onclick}
+                            >
+                                <span class={classes!(chevron, "me-2")} aria-hidden="true"></span>
+                                { g.label }
+                                <span class="badge rounded-pill text-bg-secondary ms-2">
+                                    { g.tasks.len().to_string() }
+                                </span>
+                            </button>
+                        </li>
+                        if !collapsed {
+                            { for g.tasks.iter().map(list_item) }
+                        }
+                    </>
+                }
+            })
+            .collect::<Html>(),
+        (None, Some(tag), false) => util::split_sections(&p.sections, tag, &p.tasks)
+            .into_iter()
+            .map(|(section, tasks)| {
+                html! {
+                    <>
+                        if let Some(section) = section {
+                            <li class="list-group-item task-list-section">{ section }</li>
+                        }
+                        { for tasks.iter().map(list_item) }
+                    </>
+                }
+            })
+            .collect::<Html>(),
+        _ => p.tasks.iter().map(list_item).collect::<Html>(),
+    };
+    // Groups do not map to anything tasks could be moved to, so reordering gets disabled
+    let grouped = p.grouping.by.is_some().then_some("task-list-grouped");
 
     // Then, put everything together
     html! {
-        <ul ref={p.ref_this.clone()} class="task-list list-group">
-            { for list_items }
+        <ul ref={p.ref_this.clone()} class={classes!("task-list", "list-group", grouped)}>
+            { list_items }
         </ul>
     }
 }
@@ -2,24 +2,49 @@ use std::{rc::Rc, sync::Arc};
 
 use chrono::{Datelike, Timelike};
 use risuto_client::{
-    api::{midnight_on, Event, EventData, TagId, Time},
-    DbDump, Task,
+    api::{Event, EventData, Priority, TagId, TaskId, Time},
+    date, DbDump, Task,
 };
+use wasm_bindgen_futures::{spawn_local, JsFuture};
 use yew::prelude::*;
 
+use super::export_button::task_to_markdown;
 use crate::util;
 
-#[derive(Clone, PartialEq, Properties)]
+#[derive(Clone, Properties)]
 pub struct TaskListItemProps {
     pub db: Rc<DbDump>,
     pub current_tag: Option<TagId>,
     pub user_knows_current_tag: bool,
     pub task: Arc<Task>,
+    /// Class of the highlight rule this task matches, if any
+    #[prop_or_default]
+    pub highlight: Option<&'static str>,
+    /// Tasks this one may be a duplicate of
+    #[prop_or_default]
+    pub duplicate_of: Vec<TaskId>,
     pub on_event: Callback<Event>,
+    /// Title edits are turned into events by the App, as they depend on the other tasks
+    pub on_title_change: Callback<(TaskId, String)>,
+}
+
+impl PartialEq for TaskListItemProps {
+    fn eq(&self, other: &Self) -> bool {
+        self.task.id == other.task.id
+            && self.task.revision == other.task.revision
+            && util::same_for_task_items(&self.db, &other.db)
+            && self.current_tag == other.current_tag
+            && self.user_knows_current_tag == other.user_knows_current_tag
+            && self.highlight == other.highlight
+            && self.duplicate_of == other.duplicate_of
+            && self.on_event == other.on_event
+            && self.on_title_change == other.on_title_change
+    }
 }
 
 #[function_component(TaskListItem)]
 pub fn task_list(p: &TaskListItemProps) -> Html {
+    util::record_render("TaskListItem");
     let mut tags = p
         .task
         .current_tags
@@ -37,7 +62,10 @@ pub fn task_list(p: &TaskListItemProps) -> Html {
         }
     });
     html! { // align items vertically but also let them stretch
-        <li class={classes!(p.task.is_done.then(|| "task-item-done"), "list-group-item", "p-0")}>
+        <li
+            id={util::task_element_id(&p.task.id)}
+            class={classes!(p.task.is_done.then(|| "task-item-done"), "list-group-item", p.highlight, "p-0")}
+        >
             <div class="d-flex align-items-stretch p-1">
                 <div class="drag-handle d-flex align-items-center">
                     <div class="bi-btn bi-grip-vertical p-2"></div>
@@ -47,11 +75,20 @@ pub fn task_list(p: &TaskListItemProps) -> Html {
                         db={p.db.clone()}
                         task={p.task.clone()}
                         center_vertically={no_tags}
-                        on_event={p.on_event.clone()}
+                        on_title_change={
+                            let task = p.task.id;
+                            p.on_title_change.reform(move |title| (task, title))
+                        }
                     />
                     <div class="px-3">{ for tags }</div>
                 </div>
                 <div class="d-flex align-items-center">
+                    <DuplicateHint ..p.clone() />
+                    <UnreadIndicator ..p.clone() />
+                    <AgeIndicator ..p.clone() />
+                    <TitleConflictButton ..p.clone() />
+                    <CopyMarkdownButton ..p.clone() />
+                    <PriorityButton ..p.clone() />
                     <TimesetButton
                         current_date={ p.task.scheduled_for }
                         label="Schedule for"
@@ -59,7 +96,7 @@ pub fn task_list(p: &TaskListItemProps) -> Html {
                         on_time_set={
                             let db = p.db.clone();
                             let task = p.task.clone();
-                            p.on_event.reform(move |t| Event::now(db.owner, task.id, EventData::ScheduleFor(t)))
+                            p.on_event.reform(move |t| Event::at(db.owner, task.id, crate::CLOCK.now(), EventData::ScheduleFor(t)))
                         }
                     />
                     <TimesetButton
@@ -69,7 +106,7 @@ pub fn task_list(p: &TaskListItemProps) -> Html {
                         on_time_set={
                             let db = p.db.clone();
                             let task = p.task.clone();
-                            p.on_event.reform(move |t| Event::now(db.owner, task.id, EventData::BlockedUntil(t)))
+                            p.on_event.reform(move |t| Event::at(db.owner, task.id, crate::CLOCK.now(), EventData::BlockedUntil(t)))
                         }
                     />
                     <ButtonDoneChange ..p.clone() />
@@ -84,7 +121,7 @@ pub struct TitleDivProps {
     pub db: Rc<DbDump>,
     pub task: Arc<Task>,
     pub center_vertically: bool,
-    pub on_event: Callback<Event>,
+    pub on_title_change: Callback<String>,
 }
 
 #[function_component(TitleDiv)]
@@ -95,17 +132,20 @@ fn title_div(p: &TitleDivProps) -> Html {
         let div_ref = div_ref.clone();
         let db = p.db.clone();
         let task = p.task.clone();
-        let on_event = p.on_event.clone();
+        let on_title_change = p.on_title_change.clone();
         Callback::from(move |()| {
             let div = div_ref
                 .cast::<web_sys::HtmlElement>()
                 .expect("validated while div_ref is not attached to an html element");
             let text = div.text_content().expect("div_ref has no text_content");
-            let evts = parse_new_title(&db, text, &task);
-            let changed_title = evts.iter().any(|e| matches!(e, Event { task_id, data: EventData::SetTitle(_), .. } if task_id == &task.id));
-            for e in evts {
-                on_event.emit(e);
-            }
+            let (title, _) = util::parse_tag_changes(
+                &db,
+                task.id,
+                text.clone(),
+                util::NewTaskPlacement::default(),
+            );
+            let changed_title = title != *task.current_title;
+            on_title_change.emit(text);
             div.blur().expect("failed blurring div_ref");
             if !changed_title {
                 // TODO: find a way to force yew to resync html dom with its vdom even if the vdom doesn't change
@@ -142,12 +182,195 @@ fn title_div(p: &TitleDivProps) -> Html {
     }
 }
 
-fn parse_new_title(db: &DbDump, title: String, task: &Task) -> Vec<Event> {
-    let (title, mut evts) = util::parse_tag_changes(db, task.id, title);
-    if &title != &*task.current_title {
-        evts.push(Event::now(db.owner, task.id, EventData::SetTitle(title)));
+#[function_component(DuplicateHint)]
+fn duplicate_hint(p: &TaskListItemProps) -> Html {
+    let first = match p.duplicate_of.first() {
+        Some(first) => *first,
+        None => return html! {},
+    };
+    let titles = p
+        .duplicate_of
+        .iter()
+        .filter_map(|t| p.db.tasks.get(t))
+        .map(|t| format!("\"{}\"", t.current_title))
+        .collect::<Vec<_>>();
+    html! {
+        <a
+            class="btn bi-btn bi-files text-warning px-2"
+            href={util::Route::Task(first, None).hash()}
+            title={format!("Possible duplicate of {}", titles.join(", "))}
+        >
+        </a>
+    }
+}
+
+#[function_component(UnreadIndicator)]
+fn unread_indicator(p: &TaskListItemProps) -> Html {
+    let unread = p.task.unread_comments(&p.db.owner).len();
+    if unread == 0 {
+        return html! {};
+    }
+    let on_click = {
+        let owner = p.db.owner;
+        let task = p.task.clone();
+        let on_event = p.on_event.clone();
+        Callback::from(move |_| {
+            for e in util::mark_read_events(owner, &task) {
+                on_event.emit(e);
+            }
+        })
+    };
+    html! {
+        <button
+            type="button"
+            class="btn bi-btn bi-chat-dots-fill text-info px-2"
+            title={format!("{unread} unread comment(s), click to mark as read")}
+            onclick={on_click}
+        >
+        </button>
+    }
+}
+
+#[function_component(CopyMarkdownButton)]
+fn copy_markdown_button(p: &TaskListItemProps) -> Html {
+    let on_click = {
+        let db = p.db.clone();
+        let task = p.task.clone();
+        Callback::from(move |_| {
+            let markdown = task_to_markdown(&db, &task);
+            spawn_local(async move {
+                if let Err(err) = JsFuture::from(util::copy_to_clipboard(&markdown)).await {
+                    tracing::error!(?err, "failed copying task to the clipboard");
+                    let window = web_sys::window().expect("no web_sys window");
+                    let _ = window.alert_with_message("Failed copying the task to the clipboard");
+                }
+            });
+        })
+    };
+    html! {
+        <button
+            type="button"
+            class="btn bi-btn bi-markdown px-2"
+            title="Copy as Markdown"
+            onclick={on_click}
+        >
+        </button>
+    }
+}
+
+#[function_component(AgeIndicator)]
+fn age_indicator(p: &TaskListItemProps) -> Html {
+    if p.task.is_done {
+        return html! {};
+    }
+    let idle_days = (crate::CLOCK.now() - p.task.last_event_time()).num_days();
+    let level = util::aging_thresholds()
+        .iter()
+        .filter(|t| idle_days > **t)
+        .count();
+    if level == 0 {
+        return html! {};
+    }
+    html! {
+        <span
+            class={classes!("age-dot", format!("age-{}", level.min(3)), "mx-2")}
+            title={format!("No activity for {idle_days} days")}
+        ></span>
+    }
+}
+
+#[function_component(TitleConflictButton)]
+fn title_conflict_button(p: &TaskListItemProps) -> Html {
+    let other_title = match &p.task.title_conflict {
+        Some(t) => t.clone(),
+        None => return html! {},
+    };
+    // Re-setting either title resolves the conflict
+    let keep_title = |title: Arc<String>| {
+        let owner = p.db.owner;
+        let task = p.task.id;
+        p.on_event.reform(move |_| {
+            Event::at(
+                owner,
+                task,
+                crate::CLOCK.now(),
+                EventData::SetTitle(String::clone(&title)),
+            )
+        })
+    };
+    html! {
+        <div class="dropdown">
+            <button
+                type="button"
+                class="btn bi-btn bi-exclamation-triangle-fill text-warning px-2"
+                title="Conflicting title changes"
+                data-bs-toggle="dropdown"
+            >
+            </button>
+            <ul class="dropdown-menu">
+                <li><h6 class="dropdown-header">{ "The title was changed concurrently" }</h6></li>
+                <li><a class="dropdown-item" href="#" onclick={keep_title(p.task.current_title.clone())}>
+                    { format!("Keep “{}”", p.task.current_title) }
+                </a></li>
+                <li><a class="dropdown-item" href="#" onclick={keep_title(other_title.clone())}>
+                    { format!("Restore “{other_title}”") }
+                </a></li>
+            </ul>
+        </div>
+    }
+}
+
+#[function_component(PriorityButton)]
+fn priority_button(p: &TaskListItemProps) -> Html {
+    let set_priority = |priority: Option<Priority>| {
+        let owner = p.db.owner;
+        let task = p.task.id;
+        p.on_event.reform(move |_| {
+            Event::at(
+                owner,
+                task,
+                crate::CLOCK.now(),
+                EventData::SetPriority(priority),
+            )
+        })
+    };
+    let (icon, color, title) = match p.task.priority {
+        Some(prio) => (
+            "bi-flag-fill",
+            Some(format!("priority-{}", prio.name())),
+            format!("Priority {}", prio.name().to_uppercase()),
+        ),
+        None => ("bi-flag", None, String::from("Set priority")),
+    };
+    html! {
+        <div class="dropdown">
+            <button
+                type="button"
+                class={classes!("btn", "bi-btn", icon, color, "px-2")}
+                { title }
+                data-bs-toggle="dropdown"
+            >
+            </button>
+            <ul class="dropdown-menu">
+                { for Priority::ALL.into_iter().map(|prio| html! {
+                    <li><a
+                        class={classes!("dropdown-item", (p.task.priority == Some(prio)).then(|| "active"))}
+                        href="#"
+                        onclick={set_priority(Some(prio))}
+                    >
+                        <span class={classes!("bi", "bi-flag-fill", "me-2", format!("priority-{}", prio.name()))}></span>
+                        { prio.name().to_uppercase() }
+                    </a></li>
+                }) }
+                if p.task.priority.is_some() {
+                    <li><hr class="dropdown-divider" /></li>
+                    <li><a class="dropdown-item" href="#" onclick={set_priority(None)}>
+                        { "No priority" }
+                    </a></li>
+                }
+            </ul>
+        </div>
     }
-    evts
 }
 
 #[function_component(ButtonDoneChange)]
@@ -164,8 +387,14 @@ fn button_done_change(p: &TaskListItemProps) -> Html {
         let owner = p.db.owner;
         let task = p.task.id;
         let currently_done = p.task.is_done;
-        p.on_event
-            .reform(move |_| Event::now(owner, task, EventData::SetDone(!currently_done)))
+        p.on_event.reform(move |_| {
+            Event::at(
+                owner,
+                task,
+                crate::CLOCK.now(),
+                EventData::SetDone(!currently_done),
+            )
+        })
     };
     html! {
         <button
@@ -219,49 +448,65 @@ fn timeset_button(p: &TimesetButtonProps) -> Html {
             on_time_set.emit(date);
         })
     };
-    let on_button_click = {
+    let open_input = {
         let input_ref = input_ref.clone();
+        let is_shown = is_shown.clone();
+        Callback::from(move |()| {
+            is_shown.set(true);
+            let input = input_ref
+                .cast::<web_sys::HtmlInputElement>()
+                .expect("input is not an html element");
+            input.focus().expect("failed focusing date picker");
+            util::show_picker(&input);
+        })
+    };
+    let on_button_click = {
         let is_shown = is_shown.clone();
         let close_input = close_input.clone();
-        Callback::from(move |_| {
-            if *is_shown {
-                close_input.emit(());
-            } else {
-                is_shown.set(true);
-                let input = input_ref
-                    .cast::<web_sys::HtmlInputElement>()
-                    .expect("input is not an html element");
-                input.focus().expect("failed focusing date picker");
-                util::show_picker(&input);
-            }
+        let open_input = open_input.clone();
+        Callback::from(move |_| match *is_shown {
+            true => close_input.emit(()),
+            false => open_input.emit(()),
         })
     };
     let current_date = p.current_date.map(|t| t.with_timezone(&util::local_tz()));
-    let timeset_label = current_date
-        .and_then(|d| {
-            let remaining = d.signed_duration_since(chrono::Utc::now());
-            let since_beginning_of_day = d.signed_duration_since(midnight_on(
-                chrono::Utc::now().date_naive(),
-                &util::local_tz(),
-            ));
-            match remaining {
-                r if r > chrono::Duration::days(365) => Some(format!("{}", d.year())),
-                r if r > chrono::Duration::days(1) => Some(format!("{}/{}", d.month(), d.day())),
-                r if r > chrono::Duration::seconds(0) => {
-                    Some(format!("{}h{}", d.hour(), d.minute()))
-                }
-                _ if since_beginning_of_day > chrono::Duration::seconds(0) => {
-                    Some(String::from("(today)"))
-                }
-                _ => None, // task blocked or scheduled for the past is just not blocked/scheduled
-            }
-        })
-        .map(|l| {
-            html! {
-                <span class="timeset-label rounded-pill">
-                    { l }
-                </span>
-            }
+    let now = crate::CLOCK.now();
+    let start_of_today = util::day_start()
+        .start_relative(&now, &util::local_tz(), 0)
+        .expect("failed computing the start of today")
+        .with_timezone(&chrono::Utc);
+    let timeset_label = p
+        .current_date
+        // task blocked or scheduled before today is just not blocked/scheduled
+        .filter(|d| *d >= start_of_today)
+        .map(|d| {
+            let set_date = |date: Option<Time>| p.on_time_set.reform(move |_| date);
+            html! {<>
+                <button
+                    type="button"
+                    class="timeset-label rounded-pill"
+                    title={ date::absolute_label(&d, &util::local_tz()) }
+                    data-bs-toggle="dropdown"
+                >
+                    { date::relative_label(&d, &now, &util::local_tz()) }
+                </button>
+                <ul class="dropdown-menu">
+                    <li><h6 class="dropdown-header">{ p.label }</h6></li>
+                    <li><a class="dropdown-item" href="#" onclick={set_date(Some(shift_days(&d, 1)))}>
+                        { "+1 day" }
+                    </a></li>
+                    <li><a class="dropdown-item" href="#" onclick={set_date(Some(shift_days(&d, 7)))}>
+                        { "+1 week" }
+                    </a></li>
+                    <li><a class="dropdown-item" href="#" onclick={open_input.reform(|_| ())}>
+                        { "Pick date" }
+                    </a></li>
+                    <li><hr class="dropdown-divider" /></li>
+                    <li><a class="dropdown-item" href="#" onclick={set_date(None)}>
+                        { "Clear" }
+                    </a></li>
+                </ul>
+            </>}
         });
     let start_value = current_date.map(|d| {
         format!(
@@ -275,14 +520,16 @@ fn timeset_button(p: &TimesetButtonProps) -> Html {
     });
     html! {
         <div class={ classes!("timeset-container", "d-flex", "align-items-center", is_shown.then(|| "shown")) }>
-            <button
-                type="button"
-                class={ classes!("timeset-button", "btn", "bi-btn", p.icon, "px-2") }
-                title={ p.label }
-                onclick={ on_button_click }
-            >
+            <div class="timeset-button">
+                <button
+                    type="button"
+                    class={ classes!("btn", "bi-btn", p.icon, "px-2") }
+                    title={ p.label }
+                    onclick={ on_button_click }
+                >
+                </button>
                 { for timeset_label }
-            </button>
+            </div>
             <div class={ classes!("timeset-input", is_shown.then(|| "shown")) }>
                 <input
                     ref={ input_ref }
@@ -296,3 +543,15 @@ fn timeset_button(p: &TimesetButtonProps) -> Html {
         </div>
     }
 }
+
+/// Same local time as `d`, `days` days later, keeping to the wall clock across DST changes
+fn shift_days(d: &Time, days: i64) -> Time {
+    let tz = util::local_tz();
+    let shifted = d.with_timezone(&tz).naive_local() + chrono::Duration::days(days);
+    let shifted = shifted
+        .and_local_timezone(tz)
+        .earliest()
+        // the time does not exist on that day, keep the same duration instead
+        .unwrap_or_else(|| d.with_timezone(&tz) + chrono::Duration::days(days));
+    shifted.with_timezone(&chrono::Utc)
+}
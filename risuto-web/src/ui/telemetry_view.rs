@@ -0,0 +1,51 @@
+use risuto_client::api::TelemetryReport;
+use yew::prelude::*;
+
+use crate::util;
+
+#[derive(Clone, PartialEq, Properties)]
+pub struct TelemetryViewProps {
+    pub on_close: Callback<()>,
+}
+
+/// Shows the usage counters this device will send, exactly as they will be sent
+#[function_component(TelemetryView)]
+pub fn telemetry_view(p: &TelemetryViewProps) -> Html {
+    let enabled = util::telemetry_enabled();
+    let mut report = util::telemetry_report();
+    let past_days = report.take_before(util::telemetry_today());
+    let json = |r: &TelemetryReport| {
+        serde_json::to_string_pretty(r).expect("failed serializing telemetry report")
+    };
+
+    html! {
+        <div class="container my-4">
+            <div class="d-flex align-items-center mb-4">
+                <h1 class="flex-fill">{ "Usage statistics" }</h1>
+                <button
+                    type="button"
+                    class="btn btn-secondary"
+                    onclick={p.on_close.reform(|_| ())}
+                >
+                    { "Back" }
+                </button>
+            </div>
+            <p>
+                { "When enabled in the settings, this device counts how many times per day you use \
+                   each feature of the app. Nothing else is recorded: neither your tasks, nor your \
+                   searches, nor who you are. The counters of each day get sent to this instance \
+                   once the day is over, for its administrators to know which features matter." }
+            </p>
+            if !enabled {
+                <div class="alert alert-secondary">
+                    { "Usage statistics are disabled on this device, so nothing gets recorded." }
+                </div>
+            } else {
+                <h2 class="h5">{ "Will be sent upon next opening the app" }</h2>
+                <pre class="border rounded p-2">{ json(&past_days) }</pre>
+                <h2 class="h5">{ "Today so far, sent once the day is over" }</h2>
+                <pre class="border rounded p-2">{ json(&report) }</pre>
+            }
+        </div>
+    }
+}
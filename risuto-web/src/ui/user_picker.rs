@@ -0,0 +1,140 @@
+use std::rc::Rc;
+
+use risuto_client::{
+    api::{User, UserId},
+    DbDump,
+};
+use yew::prelude::*;
+
+use crate::{api, util};
+
+/// Maximum number of users proposed at once
+const MAX_SHOWN: usize = 8;
+
+#[derive(Clone, PartialEq, Properties)]
+pub struct UserPickerProps {
+    /// Server host, to fetch the avatars from
+    pub host: String,
+    pub db: Rc<DbDump>,
+    /// Users not to propose, eg. the current user or the ones that were already picked
+    #[prop_or_default]
+    pub exclude: Vec<UserId>,
+    #[prop_or(AttrValue::Static("Search users"))]
+    pub placeholder: AttrValue,
+    pub on_pick: Callback<UserId>,
+}
+
+/// Text input proposing the users whose name matches what was typed
+#[function_component(UserPicker)]
+pub fn user_picker(p: &UserPickerProps) -> Html {
+    let query = use_state(String::new);
+    let highlighted = use_state(|| 0);
+    let open = use_state(|| false);
+
+    let mut shown =
+        p.db.users
+            .values()
+            .filter(|u| !p.exclude.contains(&u.id))
+            .filter_map(|u| util::fuzzy_score(&query, &u.name).map(|s| (s, u)))
+            .collect::<Vec<_>>();
+    shown.sort_unstable_by_key(|(s, u)| (*s, &u.name, u.id));
+    let shown = shown
+        .into_iter()
+        .map(|(_, u)| u.clone())
+        .take(MAX_SHOWN)
+        .collect::<Vec<User>>();
+    let highlighted_index = (*highlighted).min(shown.len().saturating_sub(1));
+
+    let pick = {
+        let query = query.clone();
+        let open = open.clone();
+        let on_pick = p.on_pick.clone();
+        Callback::from(move |user: UserId| {
+            query.set(String::new());
+            open.set(false);
+            on_pick.emit(user);
+        })
+    };
+    let onkeydown = {
+        let shown = shown.iter().map(|u| u.id).collect::<Vec<_>>();
+        let highlighted = highlighted.clone();
+        let open = open.clone();
+        let pick = pick.clone();
+        Callback::from(move |e: web_sys::KeyboardEvent| match &e.key() as &str {
+            "ArrowDown" => {
+                e.prevent_default();
+                open.set(true);
+                highlighted.set((highlighted_index + 1).min(shown.len().saturating_sub(1)));
+            }
+            "ArrowUp" => {
+                e.prevent_default();
+                highlighted.set(highlighted_index.saturating_sub(1));
+            }
+            "Enter" => {
+                if let Some(u) = shown.get(highlighted_index) {
+                    e.prevent_default();
+                    pick.emit(*u);
+                }
+            }
+            "Escape" => open.set(false),
+            _ => (),
+        })
+    };
+    let oninput = {
+        let query = query.clone();
+        let highlighted = highlighted.clone();
+        let open = open.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: web_sys::HtmlInputElement = e.target_unchecked_into();
+            query.set(input.value());
+            highlighted.set(0);
+            open.set(true);
+        })
+    };
+
+    html! {
+        <div class="position-relative user-picker">
+            <input
+                type="text"
+                class="form-control"
+                placeholder={p.placeholder.clone()}
+                aria-label={p.placeholder.clone()}
+                aria-autocomplete="list"
+                aria-expanded={ open.to_string() }
+                value={(*query).clone()}
+                onfocus={ let open = open.clone(); Callback::from(move |_| open.set(true)) }
+                onblur={ let open = open.clone(); Callback::from(move |_| open.set(false)) }
+                {oninput}
+                {onkeydown}
+            />
+            if *open && !shown.is_empty() {
+                <ul class="dropdown-menu show w-100" role="listbox">
+                    { for shown.into_iter().enumerate().map(|(i, u)| {
+                        let active = (i == highlighted_index).then_some("active");
+                        // mousedown rather than click, as the input losing focus closes the list
+                        let onmousedown = {
+                            let pick = pick.clone();
+                            let user = u.id;
+                            Callback::from(move |e: MouseEvent| {
+                                e.prevent_default();
+                                pick.emit(user);
+                            })
+                        };
+                        html! {
+                            <li role="option" aria-selected={ active.is_some().to_string() }>
+                                <a class={classes!("dropdown-item", active)} href="#" {onmousedown}>
+                                    if let Some(hash) = &u.avatar_hash {
+                                        <img class="avatar me-2" src={ api::avatar_url(&p.host, hash) } alt="" />
+                                    } else {
+                                        <span class="bi-person-fill me-2" aria-hidden="true"></span>
+                                    }
+                                    { u.name.clone() }
+                                </a>
+                            </li>
+                        }
+                    }) }
+                </ul>
+            }
+        </div>
+    }
+}
@@ -1,11 +1,190 @@
-use std::{str::FromStr, sync::Arc};
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet, HashMap},
+    str::FromStr,
+    sync::Arc,
+};
 
+use gloo_storage::{LocalStorage, Storage};
 use risuto_client::{
-    api::{Event, EventData, Order, Query, Search, Tag, TaskId, UserId},
-    DbDump, Task,
+    api::{
+        self, Action, DayStart, Event, EventData, EventId, Order, OrderId, Query, Search, SearchId,
+        Tag, TagId, TaskId, TelemetryReport, UserId, Uuid, FEATURE_COUNTER_PREFIX,
+    },
+    DbDump, QueryExt, Task,
 };
 use wasm_bindgen::prelude::*;
 
+const KEY_DAY_START: &str = "day-start";
+const KEY_PLAN_CAPACITY: &str = "plan-capacity";
+const KEY_AGING_THRESHOLDS: &str = "aging-thresholds";
+const KEY_PROFILE_RENDERS: &str = "profile-renders";
+const KEY_DEBUG_SYNC_DIFF: &str = "debug-sync-diff";
+const KEY_RETRY_POLICY: &str = "retry-policy";
+const KEY_HIGHLIGHT_RULES: &str = "highlight-rules";
+const KEY_NEW_TASK_PLACEMENT: &str = "new-task-placement";
+const KEY_TELEMETRY_ENABLED: &str = "telemetry-enabled";
+const KEY_TELEMETRY_REPORT: &str = "telemetry-report";
+const KEY_LIST_GROUPING_PREFIX: &str = "list-grouping-";
+const KEY_CHANGELOG_SEEN_PREFIX: &str = "changelog-seen-";
+
+/// Number of tasks "Plan my day" proposes when the user never picked one
+const DEFAULT_PLAN_CAPACITY: usize = 5;
+
+/// How http requests to the server get retried on network errors and server failures
+#[derive(Clone, Copy, Debug, Eq, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct RetryPolicy {
+    pub min_interval_secs: u64,
+    pub max_interval_secs: u64,
+    /// `None` to retry forever
+    pub max_retries: Option<u32>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> RetryPolicy {
+        // Past that, the server is considered unreachable and connectivity checks take over
+        RetryPolicy {
+            min_interval_secs: 1,
+            max_interval_secs: 30,
+            max_retries: Some(5),
+        }
+    }
+}
+
+/// Where tasks land in the lists of the tags they get created with
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct NewTaskPlacement {
+    /// Add them at the bottom of the list rather than at the top
+    pub at_bottom: bool,
+    /// Add them to the tag's backlog rather than to its open list
+    pub in_backlog: bool,
+}
+
+/// Color tasks matching a search get highlighted with
+#[derive(Clone, Copy, Debug, Eq, PartialEq, serde::Deserialize, serde::Serialize)]
+pub enum HighlightColor {
+    Red,
+    Yellow,
+    Green,
+    Blue,
+    Grey,
+}
+
+impl HighlightColor {
+    pub const ALL: [HighlightColor; 5] = [
+        HighlightColor::Red,
+        HighlightColor::Yellow,
+        HighlightColor::Green,
+        HighlightColor::Blue,
+        HighlightColor::Grey,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            HighlightColor::Red => "red",
+            HighlightColor::Yellow => "yellow",
+            HighlightColor::Green => "green",
+            HighlightColor::Blue => "blue",
+            HighlightColor::Grey => "grey",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<HighlightColor> {
+        HighlightColor::ALL
+            .into_iter()
+            .find(|c| c.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Bootstrap class of list items highlighted with this color
+    pub fn class(&self) -> &'static str {
+        match self {
+            HighlightColor::Red => "list-group-item-danger",
+            HighlightColor::Yellow => "list-group-item-warning",
+            HighlightColor::Green => "list-group-item-success",
+            HighlightColor::Blue => "list-group-item-primary",
+            HighlightColor::Grey => "list-group-item-secondary",
+        }
+    }
+}
+
+/// Highlights the tasks matching `search` with `color`, the first matching rule wins
+#[derive(Clone, Debug, Eq, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct HighlightRule {
+    pub color: HighlightColor,
+    pub search: String,
+
+    /// `search` as parsed when the rule got saved, so that rendering task lists does not need to
+    /// parse it again
+    pub query: Query,
+}
+
+impl HighlightRule {
+    /// Parses a rule written as `color: search`
+    pub fn parse(db: &DbDump, day_start: DayStart, line: &str) -> Option<HighlightRule> {
+        let (color, search) = line.split_once(':')?;
+        let search = search.trim();
+        if search.is_empty() {
+            return None;
+        }
+        Some(HighlightRule {
+            color: HighlightColor::parse(color.trim())?,
+            search: String::from(search),
+            query: Query::from_search(db, &local_tz(), day_start, search),
+        })
+    }
+
+    pub fn label(&self) -> String {
+        format!("{}: {}", self.color.name(), self.search)
+    }
+}
+
+/// Dimension along which the task lists can get split into groups
+#[derive(Clone, Copy, Debug, Eq, PartialEq, serde::Deserialize, serde::Serialize)]
+pub enum Grouping {
+    /// First tag of the task, in the order they are displayed
+    Tag,
+    /// Day the task is scheduled for
+    ScheduledDay,
+    /// User who created the task
+    Owner,
+}
+
+impl Grouping {
+    pub const ALL: [Grouping; 3] = [Grouping::Tag, Grouping::ScheduledDay, Grouping::Owner];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Grouping::Tag => "tag",
+            Grouping::ScheduledDay => "scheduled day",
+            Grouping::Owner => "owner",
+        }
+    }
+}
+
+/// How the lists of a search get grouped on this device
+#[derive(Clone, Debug, Default, Eq, PartialEq, serde::Deserialize, serde::Serialize)]
+pub struct ListGrouping {
+    pub by: Option<Grouping>,
+    /// Keys of the groups the user collapsed, as returned by `group_tasks`
+    pub collapsed: BTreeSet<String>,
+}
+
+/// Group of tasks of a task list, as split by `group_tasks`
+#[derive(Clone, Debug, PartialEq)]
+pub struct TaskGroup {
+    /// Identifies the group across renders, for remembering whether it is collapsed
+    pub key: String,
+    pub label: String,
+    pub tasks: Vec<Arc<Task>>,
+}
+
+/// Days without any event after which tasks get an increasingly warm age indicator
+const DEFAULT_AGING_THRESHOLDS: [i64; 3] = [7, 30, 90];
+
+// this value was taken after intense finger-based wind-speed-taking
+// basically we can add 2^(64-40) items at the beginning or end this way, and intersperse 40 items in-between other items, all without a redistribution
+const SPACING: i64 = 1 << 40;
+
 #[wasm_bindgen(inline_js = "
     export function show_picker(elt) {
         elt.showPicker();
@@ -13,11 +192,26 @@ use wasm_bindgen::prelude::*;
     export function get_timezone() {
         return Intl.DateTimeFormat().resolvedOptions().timeZone;
     }
+    export function download(filename, mime, content) {
+        const a = document.createElement('a');
+        a.href = URL.createObjectURL(new Blob([content], { type: mime }));
+        a.download = filename;
+        a.click();
+        URL.revokeObjectURL(a.href);
+    }
+    export function copy_to_clipboard(text) {
+        return navigator.clipboard.writeText(text);
+    }
 ")]
 extern "C" {
     // TODO: remove once https://github.com/rustwasm/wasm-bindgen/pull/3215 gets released
     pub fn show_picker(elt: &web_sys::HtmlInputElement);
     fn get_timezone() -> String;
+    /// Have the browser save `content` as a file named `filename`
+    pub fn download(filename: &str, mime: &str, content: &str);
+    /// Put `text` in the clipboard, the promise fails if the browser denies it
+    // TODO: use web_sys::Clipboard once it is no longer behind web_sys_unstable_apis
+    pub fn copy_to_clipboard(text: &str) -> js_sys::Promise;
 }
 
 lazy_static::lazy_static! {
@@ -31,6 +225,348 @@ pub fn local_tz() -> chrono_tz::Tz {
     LOCAL_TZ.clone()
 }
 
+/// Time at which the user's days start on this device, midnight by default
+pub fn day_start() -> DayStart {
+    LocalStorage::get(KEY_DAY_START)
+        .ok()
+        .filter(|d: &DayStart| d.validate().is_ok())
+        .unwrap_or_default()
+}
+
+pub fn save_day_start(day_start: DayStart) {
+    LocalStorage::set(KEY_DAY_START, day_start).expect("failed saving day start to local storage");
+}
+
+/// Number of tasks the user wants "Plan my day" to fill their day with
+pub fn plan_capacity() -> usize {
+    LocalStorage::get(KEY_PLAN_CAPACITY)
+        .ok()
+        .filter(|c: &usize| *c > 0)
+        .unwrap_or(DEFAULT_PLAN_CAPACITY)
+}
+
+pub fn save_plan_capacity(capacity: usize) {
+    LocalStorage::set(KEY_PLAN_CAPACITY, capacity)
+        .expect("failed saving plan capacity to local storage");
+}
+
+/// Days without any event after which tasks are shown as aging, in increasing order
+pub fn aging_thresholds() -> Vec<i64> {
+    LocalStorage::get(KEY_AGING_THRESHOLDS)
+        .ok()
+        .filter(|t: &Vec<i64>| !t.is_empty())
+        .unwrap_or_else(|| DEFAULT_AGING_THRESHOLDS.to_vec())
+}
+
+pub fn save_aging_thresholds(thresholds: &[i64]) {
+    LocalStorage::set(KEY_AGING_THRESHOLDS, thresholds)
+        .expect("failed saving aging thresholds to local storage");
+}
+
+/// Placement the user configured on this device for the tasks they create
+pub fn new_task_placement() -> NewTaskPlacement {
+    LocalStorage::get(KEY_NEW_TASK_PLACEMENT).unwrap_or_default()
+}
+
+pub fn save_new_task_placement(placement: NewTaskPlacement) {
+    LocalStorage::set(KEY_NEW_TASK_PLACEMENT, placement)
+        .expect("failed saving new task placement to local storage");
+}
+
+/// Rules the user configured on this device to highlight tasks, in decreasing precedence
+pub fn highlight_rules() -> Vec<HighlightRule> {
+    LocalStorage::get(KEY_HIGHLIGHT_RULES).unwrap_or_default()
+}
+
+pub fn save_highlight_rules(rules: &[HighlightRule]) {
+    LocalStorage::set(KEY_HIGHLIGHT_RULES, rules)
+        .expect("failed saving highlight rules to local storage");
+}
+
+/// Retry policy the user configured on this device, only used for http clients created later on
+pub fn retry_policy() -> RetryPolicy {
+    LocalStorage::get(KEY_RETRY_POLICY)
+        .ok()
+        .filter(|p: &RetryPolicy| p.min_interval_secs <= p.max_interval_secs)
+        .unwrap_or_default()
+}
+
+pub fn save_retry_policy(policy: RetryPolicy) {
+    LocalStorage::set(KEY_RETRY_POLICY, policy)
+        .expect("failed saving retry policy to local storage");
+}
+
+/// Whether the user opted into sending anonymous usage counters from this device
+pub fn telemetry_enabled() -> bool {
+    LocalStorage::get(KEY_TELEMETRY_ENABLED).unwrap_or(false)
+}
+
+/// Also forgets the counters not sent yet when opting out
+pub fn save_telemetry_enabled(enabled: bool) {
+    LocalStorage::set(KEY_TELEMETRY_ENABLED, enabled)
+        .expect("failed saving telemetry choice to local storage");
+    if !enabled {
+        LocalStorage::delete(KEY_TELEMETRY_REPORT);
+    }
+}
+
+/// Usage counters recorded on this device that were not sent yet
+pub fn telemetry_report() -> TelemetryReport {
+    LocalStorage::get(KEY_TELEMETRY_REPORT).unwrap_or_default()
+}
+
+pub fn save_telemetry_report(report: &TelemetryReport) {
+    LocalStorage::set(KEY_TELEMETRY_REPORT, report)
+        .expect("failed saving telemetry report to local storage");
+}
+
+/// Counts one use of `feature` today, if the user opted into telemetry
+pub fn record_feature(feature: &str) {
+    if !telemetry_enabled() {
+        return;
+    }
+    let mut report = telemetry_report();
+    report.record(
+        telemetry_today(),
+        &format!("{FEATURE_COUNTER_PREFIX}{feature}"),
+    );
+    save_telemetry_report(&report);
+}
+
+/// Day usage counters get recorded for, days being over at local midnight
+pub fn telemetry_today() -> chrono::NaiveDate {
+    crate::CLOCK.now().with_timezone(&local_tz()).date_naive()
+}
+
+fn list_grouping_key(search: &SearchId) -> String {
+    format!("{KEY_LIST_GROUPING_PREFIX}{}", search.0)
+}
+
+pub fn list_grouping(search: &SearchId) -> ListGrouping {
+    LocalStorage::get(list_grouping_key(search)).unwrap_or_default()
+}
+
+pub fn save_list_grouping(search: &SearchId, grouping: &ListGrouping) {
+    LocalStorage::set(list_grouping_key(search), grouping)
+        .expect("failed saving list grouping to local storage");
+}
+
+fn changelog_seen_key(account: &str) -> String {
+    format!("{KEY_CHANGELOG_SEEN_PREFIX}{account}")
+}
+
+/// When the most recent server version whose changelog the user acknowledged got installed,
+/// `None` if they never got shown any changelog with `account`
+pub fn changelog_seen(account: &str) -> Option<api::Time> {
+    LocalStorage::get(changelog_seen_key(account)).ok()
+}
+
+pub fn save_changelog_seen(account: &str, installed_at: api::Time) {
+    LocalStorage::set(changelog_seen_key(account), installed_at)
+        .expect("failed saving changelog acknowledgement to local storage");
+}
+
+thread_local! {
+    static PROFILE_RENDERS: bool = LocalStorage::get(KEY_PROFILE_RENDERS).unwrap_or(false);
+    static DEBUG_SYNC_DIFF: bool = LocalStorage::get(KEY_DEBUG_SYNC_DIFF).unwrap_or(false);
+    static RENDER_COUNTS: RefCell<BTreeMap<&'static str, usize>> = RefCell::new(BTreeMap::new());
+}
+
+/// Records that `component` got rendered, for the render profiling harness
+///
+/// Setting the `profile-renders` local storage key to `true` and reloading makes the app log,
+/// after each of its renders, how many times each component got rendered in-between.
+pub fn record_render(component: &'static str) {
+    if PROFILE_RENDERS.with(|p| *p) {
+        RENDER_COUNTS.with(|c| *c.borrow_mut().entry(component).or_default() += 1);
+    }
+}
+
+/// Logs and resets the render counts gathered by `record_render`
+pub fn log_render_counts() {
+    if PROFILE_RENDERS.with(|p| *p) {
+        let counts = RENDER_COUNTS.with(|c| std::mem::take(&mut *c.borrow_mut()));
+        tracing::info!(?counts, "rendered components");
+    }
+}
+
+/// Whether to show the overlay listing what changed locally since the last sync
+///
+/// Setting the `debug-sync-diff` local storage key to `true` and reloading enables it.
+pub fn debug_sync_diff() -> bool {
+    DEBUG_SYNC_DIFF.with(|d| *d)
+}
+
+/// How well `query` matches `name`, lower being better, or `None` if it does not match
+///
+/// All the characters of `query` must appear in order in `name`, ignoring case. Matches that
+/// start early and have few gaps between the matched characters are preferred.
+pub fn fuzzy_score(query: &str, name: &str) -> Option<usize> {
+    let mut score = 0;
+    let mut name = name.chars().flat_map(char::to_lowercase).enumerate();
+    let mut last = None;
+    for q in query.chars().flat_map(char::to_lowercase) {
+        if q.is_whitespace() {
+            continue;
+        }
+        let (i, _) = name.find(|(_, c)| *c == q)?;
+        score += match last {
+            None => i,
+            Some(last) => i - last - 1,
+        };
+        last = Some(i);
+    }
+    Some(score)
+}
+
+/// Guess the server host from the page location, assuming risuto-web is served by the
+/// risuto deployment itself, possibly under a sub-path
+pub fn default_host() -> String {
+    let location = web_sys::window().expect("no web_sys window").location();
+    let origin = location.origin().unwrap_or_default();
+    let path = location.pathname().unwrap_or_default();
+    let path = path.trim_end_matches("index.html").trim_end_matches('/');
+    format!("{origin}{path}")
+}
+
+/// Prefix of the page location's hash for invite links
+const INVITE_HASH_PREFIX: &str = "#invite=";
+
+/// Builds a link to this web app that lets someone register with the invite `token`
+pub fn invite_link(token: &Uuid) -> String {
+    let location = web_sys::window().expect("no web_sys window").location();
+    let origin = location.origin().unwrap_or_default();
+    let path = location.pathname().unwrap_or_default();
+    format!("{origin}{path}{INVITE_HASH_PREFIX}{token}")
+}
+
+/// Returns the invite token the page was opened with, if any
+pub fn invite_from_location() -> Option<Uuid> {
+    let location = web_sys::window().expect("no web_sys window").location();
+    let hash = location.hash().ok()?;
+    Uuid::try_parse(hash.strip_prefix(INVITE_HASH_PREFIX)?).ok()
+}
+
+/// Place in the app that can be linked to, through the page location's hash
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Route {
+    Search(SearchId),
+    /// A task, along with one of its comments
+    Task(TaskId, Option<EventId>),
+}
+
+impl Route {
+    pub fn parse(hash: &str) -> Option<Route> {
+        let uuid = |s: &str| Uuid::try_parse(s).ok();
+        let parts = hash.strip_prefix("#/")?.split('/').collect::<Vec<_>>();
+        Some(match &parts[..] {
+            ["search", s] => Route::Search(SearchId(uuid(s)?)),
+            ["task", t] => Route::Task(TaskId(uuid(t)?), None),
+            ["task", t, "comment", c] => Route::Task(TaskId(uuid(t)?), Some(EventId(uuid(c)?))),
+            _ => return None,
+        })
+    }
+
+    pub fn hash(&self) -> String {
+        match self {
+            Route::Search(s) => format!("#/search/{}", s.0),
+            Route::Task(t, None) => format!("#/task/{}", t.0),
+            Route::Task(t, Some(c)) => format!("#/task/{}/comment/{}", t.0, c.0),
+        }
+    }
+}
+
+/// Returns the route the page location points to, if any
+pub fn route_from_location() -> Option<Route> {
+    let location = web_sys::window().expect("no web_sys window").location();
+    Route::parse(&location.hash().ok()?)
+}
+
+/// Points the page location to `route`, adding a browser history entry if it changed
+pub fn set_location_route(route: &Route) {
+    let location = web_sys::window().expect("no web_sys window").location();
+    let hash = route.hash();
+    if location.hash().ok().as_ref() != Some(&hash) {
+        let _ = location.set_hash(&hash);
+    }
+}
+
+/// Id of the html element showing task `task`, for scrolling to it
+pub fn task_element_id(task: &TaskId) -> String {
+    format!("task-{}", task.0)
+}
+
+/// Task whose list item contains the focused element, if any
+pub fn focused_task() -> Option<TaskId> {
+    let element = web_sys::window()
+        .and_then(|w| w.document())
+        .and_then(|d| d.active_element())?;
+    let task = element.closest("li[id^='task-']").ok()??;
+    let id = task.id();
+    let uuid = id.strip_prefix("task-")?;
+    Uuid::try_parse(uuid).ok().map(TaskId)
+}
+
+/// Whether the page is currently hidden, eg. in a background tab, so expensive work can wait
+pub fn page_hidden() -> bool {
+    web_sys::window()
+        .and_then(|w| w.document())
+        .map(|d| d.hidden())
+        .unwrap_or(false)
+}
+
+/// Id of the `<link rel="icon">` element of `index.html`
+const FAVICON_ID: &str = "favicon";
+
+const FAVICON: &str = r##"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 16 16"><rect width="16" height="16" rx="3" fill="#0d6efd"/><path d="M4 8.5l2.5 2.5L12 5.5" fill="none" stroke="#fff" stroke-width="2"/>"##;
+
+/// Red dot overlaid on the favicon while there are unread comments
+const FAVICON_BADGE: &str = r##"<circle cx="12.5" cy="3.5" r="3.5" fill="#dc3545"/>"##;
+
+/// Shows `search` and its counts in the browser tab title, and badges the favicon if `unread > 0`
+pub fn set_tab_status(search: &str, overdue: usize, unread: usize) {
+    let mut counts = Vec::new();
+    if overdue > 0 {
+        counts.push(format!("{overdue} overdue"));
+    }
+    if unread > 0 {
+        counts.push(format!("{unread} unread"));
+    }
+    let title = match counts.is_empty() {
+        true => format!("{search} - risuto"),
+        false => format!("{search} ({}) - risuto", counts.join(", ")),
+    };
+    set_tab(&title, unread > 0);
+}
+
+/// Resets the browser tab title and favicon, for when no account is shown
+pub fn clear_tab_status() {
+    set_tab("risuto", false);
+}
+
+fn set_tab(title: &str, badge: bool) {
+    let document = match web_sys::window().and_then(|w| w.document()) {
+        Some(document) => document,
+        None => return,
+    };
+    if document.title() != title {
+        document.set_title(title);
+    }
+    let badge = match badge {
+        true => FAVICON_BADGE,
+        false => "",
+    };
+    let href = format!(
+        "data:image/svg+xml,{}",
+        js_sys::encode_uri_component(&format!("{FAVICON}{badge}</svg>"))
+    );
+    if let Some(link) = document.get_element_by_id(FAVICON_ID) {
+        if link.get_attribute("href").as_ref() != Some(&href) {
+            let _ = link.set_attribute("href", &href);
+        }
+    }
+}
+
 pub fn sort_tags<'a, T, F>(current_user: &UserId, tags: &mut [T], get_tag: F)
 where
     F: for<'b> Fn(&'b T) -> &'a Tag,
@@ -55,18 +591,20 @@ pub fn compute_reordering_events(
     macro_rules! evt {
         ( $task:expr, $prio:expr ) => {
             match &search.order {
-                Order::Tag(tag) => Event::now(
+                Order::Tag(tag) => Event::at(
                     owner,
                     $task,
+                    crate::CLOCK.now(),
                     EventData::AddTag {
                         tag: tag.clone(),
                         prio: $prio,
                         backlog: into_backlog,
                     },
                 ),
-                Order::Custom(order) => Event::now(
+                Order::Custom(order) => Event::at(
                     owner,
                     $task,
+                    crate::CLOCK.now(),
                     EventData::SetOrder {
                         order: order.clone(),
                         prio: $prio,
@@ -89,10 +627,6 @@ pub fn compute_reordering_events(
             }
         };
     }
-    // this value was taken after intense finger-based wind-speed-taking
-    // basically we can add 2^(64-40) items at the beginning or end this way, and intersperse 40 items in-between other items, all without a redistribution
-    const SPACING: i64 = 1 << 40;
-
     if into.len() == 0 {
         // Easy case: inserting into an empty list
         return vec![evt!(task, 0)];
@@ -148,7 +682,220 @@ pub fn compute_reordering_events(
         .collect()
 }
 
-pub fn parse_tag_changes(db: &DbDump, task_id: TaskId, mut title: String) -> (String, Vec<Event>) {
+/// Splits `tasks` along the `sections` of `tag`, keeping their order within each section
+///
+/// The first group has no heading, and holds the tasks that are in no section or in a section
+/// that no longer exists. Sections without any task are kept, so that tasks can be moved there.
+pub fn split_sections(
+    sections: &[String],
+    tag: &TagId,
+    tasks: &[Arc<Task>],
+) -> Vec<(Option<String>, Vec<Arc<Task>>)> {
+    let mut groups = std::iter::once(None)
+        .chain(sections.iter().cloned().map(Some))
+        .map(|s| (s, Vec::new()))
+        .collect::<Vec<_>>();
+    for t in tasks {
+        let section = t.current_tags.get(tag).and_then(|t| t.section.as_ref());
+        let group = section
+            .and_then(|s| sections.iter().position(|n| n == s))
+            .map(|i| i + 1)
+            .unwrap_or(0);
+        groups[group].1.push(t.clone());
+    }
+    groups
+}
+
+/// Lists the elements of a task list split by `split_sections`, as they are displayed
+///
+/// Each element is the index of its group, along with its index within the group for tasks,
+/// or `None` for section headings.
+pub fn section_list_items(
+    groups: &[(Option<String>, Vec<Arc<Task>>)],
+) -> Vec<(usize, Option<usize>)> {
+    groups
+        .iter()
+        .enumerate()
+        .flat_map(|(g, (section, tasks))| {
+            section
+                .as_ref()
+                .map(|_| (g, None))
+                .into_iter()
+                .chain((0..tasks.len()).map(move |i| (g, Some(i))))
+        })
+        .collect()
+}
+
+/// Splits `tasks` along `by`, keeping their order within each group
+///
+/// Groups are sorted by tag as in the sidebar, by day, or by user name, and only groups that
+/// have tasks are returned. Tasks without any tag or not scheduled come last.
+pub fn group_tasks(
+    db: &DbDump,
+    by: Grouping,
+    day_start: DayStart,
+    tasks: &[Arc<Task>],
+) -> Vec<TaskGroup> {
+    let tz = local_tz();
+    let mut tag_ranks = db.tags.values().collect::<Vec<_>>();
+    sort_tags(&db.owner, &mut tag_ranks, |t| t);
+    let tag_ranks = tag_ranks
+        .into_iter()
+        .enumerate()
+        .map(|(i, t)| (t.id, i))
+        .collect::<HashMap<_, _>>();
+    // Rank, key and label of the group of each task, groups being sorted by rank then key
+    let group_of = |t: &Task| -> ((usize, String), String, String) {
+        match by {
+            Grouping::Tag => t
+                .current_tags
+                .keys()
+                .filter_map(|tag| Some((*tag_ranks.get(tag)?, db.tags.get(tag)?)))
+                .min_by_key(|(rank, _)| *rank)
+                .map(|(rank, tag)| {
+                    let label = format!("#{}", tag.name);
+                    ((rank, String::new()), tag.id.0.to_string(), label)
+                })
+                .unwrap_or_else(|| {
+                    let rank = (usize::MAX, String::new());
+                    (rank, String::from("none"), String::from("No tag"))
+                }),
+            Grouping::ScheduledDay => match &t.scheduled_for {
+                None => {
+                    let rank = (usize::MAX, String::new());
+                    (rank, String::from("none"), String::from("Not scheduled"))
+                }
+                Some(s) => {
+                    let day = day_start.day_of(s, &tz);
+                    let label = day.format("%a %b %-d, %Y").to_string();
+                    ((0, day.to_string()), day.to_string(), label)
+                }
+            },
+            Grouping::Owner => {
+                let name = db
+                    .users
+                    .get(&t.owner_id)
+                    .map(|u| u.name.clone())
+                    .unwrap_or_else(|| String::from("Unknown user"));
+                ((0, name.clone()), t.owner_id.0.to_string(), name)
+            }
+        }
+    };
+    let mut groups = Vec::<((usize, String), TaskGroup)>::new();
+    for t in tasks {
+        let (rank, key, label) = group_of(t);
+        match groups.iter_mut().find(|(_, g)| g.key == key) {
+            Some((_, g)) => g.tasks.push(t.clone()),
+            None => groups.push((
+                rank,
+                TaskGroup {
+                    key,
+                    label,
+                    tasks: vec![t.clone()],
+                },
+            )),
+        }
+    }
+    groups.sort_by(|(ra, a), (rb, b)| (ra, &a.key).cmp(&(rb, &b.key)));
+    groups.into_iter().map(|(_, g)| g).collect()
+}
+
+/// Marks as read for `owner` all the comments of `task` they did not read yet
+pub fn mark_read_events(owner: UserId, task: &Task) -> Vec<Event> {
+    task.unread_comments(&owner)
+        .into_iter()
+        .map(|event_id| {
+            Event::at(
+                owner,
+                task.id,
+                crate::CLOCK.now(),
+                EventData::SetEventRead {
+                    event_id,
+                    now_read: true,
+                },
+            )
+        })
+        .collect()
+}
+
+/// Reorders Today so that `planned` comes first, followed by the rest of `today` in its current order
+pub fn compute_plan_events(owner: UserId, planned: &[TaskId], today: &[Arc<Task>]) -> Vec<Event> {
+    let order = OrderId::today();
+    let planned_tasks = planned
+        .iter()
+        .filter_map(|id| today.iter().find(|t| t.id == *id));
+    let other_tasks = today.iter().filter(|t| !planned.contains(&t.id));
+    planned_tasks
+        .chain(other_tasks)
+        .enumerate()
+        .map(|(i, t)| (t, (i as i64).checked_mul(SPACING).unwrap()))
+        .filter(|(t, prio)| t.prio_order(&order) != Some(*prio))
+        .map(|(t, prio)| {
+            Event::at(
+                owner,
+                t.id,
+                crate::CLOCK.now(),
+                EventData::SetOrder {
+                    order: order.clone(),
+                    prio,
+                },
+            )
+        })
+        .collect()
+}
+
+/// Whether task list items render the same with dumps `a` and `b`
+///
+/// Items only render the owner's view of tags from the dump, so this lets them skip re-rendering
+/// whenever an unrelated task changes.
+pub fn same_for_task_items(a: &DbDump, b: &DbDump) -> bool {
+    a.owner == b.owner && (a.tags.ptr_eq(&b.tags) || a.tags == b.tags)
+}
+
+/// Events to submit when the user edits the title of `task` to `title`, that may also add or
+/// remove tags with `+tag` and `-tag` suffixes
+pub fn parse_new_title(db: &DbDump, title: String, task: &Task) -> Vec<Event> {
+    let (title, mut evts) = parse_tag_changes(db, task.id, title, NewTaskPlacement::default());
+    if &title != &*task.current_title {
+        evts.extend(task.rename_events(db.owner, crate::CLOCK.now(), title));
+    }
+    evts
+}
+
+/// Actions creating a task titled `title`, after expanding its template and `+tag` suffixes
+pub fn new_task_actions(db: &DbDump, title: &str) -> Vec<Action> {
+    let task_id = TaskId(Uuid::new_v4());
+    let now = crate::CLOCK.now();
+    let user = db
+        .users
+        .get(&db.owner)
+        .map(|u| &u.name as &str)
+        .unwrap_or("");
+    let title = api::expand_title_template(title, &now.with_timezone(&local_tz()), user);
+    let (title, evts) = parse_tag_changes(db, task_id, title, new_task_placement());
+    let task = Action::NewTask(
+        api::Task {
+            id: task_id,
+            owner_id: db.owner,
+            date: now,
+            initial_title: title,
+            top_comment_id: EventId(Uuid::new_v4()),
+        },
+        String::from(""), // TODO: allow setting initial top comment value
+    );
+    std::iter::once(task)
+        .chain(evts.into_iter().map(Action::NewEvent))
+        .collect()
+}
+
+/// Strips the trailing `+tag` and `-tag` from `title`, returning the events adding the task to
+/// these tags at `placement` or removing it from them
+pub fn parse_tag_changes(
+    db: &DbDump,
+    task_id: TaskId,
+    mut title: String,
+    placement: NewTaskPlacement,
+) -> (String, Vec<Event>) {
     let mut res = Vec::new();
     loop {
         title.truncate(title.trim_end().len());
@@ -156,7 +903,12 @@ pub fn parse_tag_changes(db: &DbDump, task_id: TaskId, mut title: String) -> (St
         if let Some(i) = title.rfind(" -") {
             let tag_start = i + " -".len();
             if let Some(t) = title.get(tag_start..).and_then(|t| db.tag_id(t)) {
-                res.push(Event::now(db.owner, task_id, EventData::RmTag(t)));
+                res.push(Event::at(
+                    db.owner,
+                    task_id,
+                    crate::CLOCK.now(),
+                    EventData::RmTag(t),
+                ));
                 title.truncate(i);
                 continue;
             }
@@ -168,13 +920,24 @@ pub fn parse_tag_changes(db: &DbDump, task_id: TaskId, mut title: String) -> (St
                 let search = Search::stub_for_query_order(
                     Query::Tag {
                         tag: tag.id,
-                        backlog: Some(false),
+                        backlog: Some(placement.in_backlog),
                     },
                     Order::Tag(tag.id),
                 );
-                let tasks = db.search(&search).expect("Infallible search failed");
+                let tasks = db
+                    .search_at(&search, &crate::CLOCK.now())
+                    .expect("Infallible search failed");
+                let index = match placement.at_bottom {
+                    true => tasks.len(),
+                    false => 0,
+                };
                 res.extend(compute_reordering_events(
-                    db.owner, &search, task_id, 0, false, &tasks,
+                    db.owner,
+                    &search,
+                    task_id,
+                    index,
+                    placement.in_backlog,
+                    &tasks,
                 ));
                 title.truncate(i);
                 continue;
@@ -0,0 +1,429 @@
+//! Conformance tests for third-party clients of the action feed, see `risuto_api::FeedProtocol`
+//!
+//! This serves the API from the mock server, on the address passed as argument or
+//! `127.0.0.1:3000` by default, with a single user. Point the client under test at it and log
+//! in: the checks then run as the client connects to the action feed, and this exits with a
+//! failure status as soon as one of them fails.
+
+use std::{future::Future, net::SocketAddr, sync::Arc, time::Duration};
+
+use axum::{
+    extract::{
+        ws::{CloseFrame, Message, WebSocket},
+        State, WebSocketUpgrade,
+    },
+    http::{header, HeaderMap},
+    response::IntoResponse,
+    routing::{get, post},
+    Json, Router,
+};
+use futures::{channel::mpsc, StreamExt};
+use risuto_api::{
+    Action, AuthInfo, AuthToken, Error, Event, EventData, EventId, FeedMessage, FeedProtocol,
+    InstanceInfo, NewSession, NewUser, Query, Search, SearchResults, Tag, Task, TaskId, User,
+    UserId, Uuid, FEED_AUTH_DENIED, FEED_AUTH_OK, FEED_CLOSE_PERMISSION_DENIED,
+    FEED_CLOSE_PROTOCOL_ERROR, FEED_PATH, FEED_PING, FEED_PING_INTERVAL_SECS,
+};
+use risuto_mock_server::MockServer;
+use tokio::{sync::Mutex, time::timeout};
+
+const USER_NAME: &str = "conformance";
+const PASSWORD: &str = "conformance";
+
+/// Time left to log in with the client under test
+const LOGIN_TIMEOUT: Duration = Duration::from_secs(300);
+/// Time the client has to send its session token once connected
+const AUTH_TIMEOUT: Duration = Duration::from_secs(10);
+/// Time the client has to reconnect after the server closed the feed
+const RECONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+/// Close code the server restarting is simulated with
+const CLOSE_SERVICE_RESTART: u16 = 1012;
+
+struct Server {
+    mock: Mutex<MockServer>,
+    /// Action feed websockets, as the client opens them
+    sockets: mpsc::UnboundedSender<WebSocket>,
+}
+
+type AppState = Arc<Server>;
+
+struct ApiError(Error);
+
+impl From<Error> for ApiError {
+    fn from(e: Error) -> ApiError {
+        ApiError(e)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> axum::response::Response {
+        (self.0.status_code(), self.0.contents()).into_response()
+    }
+}
+
+fn token(headers: &HeaderMap) -> Result<AuthToken, ApiError> {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "))
+        .and_then(|t| Uuid::try_parse(t).ok())
+        .map(AuthToken)
+        .ok_or(ApiError(Error::PermissionDenied))
+}
+
+async fn instance_info() -> Json<InstanceInfo> {
+    Json(InstanceInfo::default())
+}
+
+async fn feed_protocol() -> Json<FeedProtocol> {
+    Json(FeedProtocol::current())
+}
+
+async fn auth(
+    State(s): State<AppState>,
+    Json(data): Json<NewSession>,
+) -> Result<Json<AuthToken>, ApiError> {
+    Ok(Json(s.mock.lock().await.auth(data)?))
+}
+
+async fn unauth(State(s): State<AppState>, headers: HeaderMap) -> Result<(), ApiError> {
+    Ok(s.mock.lock().await.unauth(token(&headers)?)?)
+}
+
+async fn whoami(State(s): State<AppState>, headers: HeaderMap) -> Result<Json<UserId>, ApiError> {
+    Ok(Json(s.mock.lock().await.whoami(token(&headers)?)?))
+}
+
+async fn fetch_users(
+    State(s): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<User>>, ApiError> {
+    Ok(Json(s.mock.lock().await.fetch_users(token(&headers)?)?))
+}
+
+async fn fetch_tags(
+    State(s): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<(Tag, AuthInfo)>>, ApiError> {
+    Ok(Json(s.mock.lock().await.fetch_tags(token(&headers)?)?))
+}
+
+async fn fetch_searches(
+    State(s): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<Search>>, ApiError> {
+    Ok(Json(s.mock.lock().await.fetch_searches(token(&headers)?)?))
+}
+
+async fn search_tasks(
+    State(s): State<AppState>,
+    headers: HeaderMap,
+    Json(q): Json<Query>,
+) -> Result<Json<SearchResults>, ApiError> {
+    Ok(Json(s.mock.lock().await.search_tasks(token(&headers)?, q)?))
+}
+
+async fn submit_action(
+    State(s): State<AppState>,
+    headers: HeaderMap,
+    Json(a): Json<Action>,
+) -> Result<(), ApiError> {
+    Ok(s.mock
+        .lock()
+        .await
+        .submit_action(token(&headers)?, a)
+        .await?)
+}
+
+async fn committed_events(
+    State(s): State<AppState>,
+    headers: HeaderMap,
+    Json(events): Json<Vec<EventId>>,
+) -> Result<Json<Vec<EventId>>, ApiError> {
+    let tok = token(&headers)?;
+    Ok(Json(s.mock.lock().await.committed_events(tok, events)?))
+}
+
+async fn action_feed(State(s): State<AppState>, ws: WebSocketUpgrade) -> impl IntoResponse {
+    ws.on_upgrade(move |sock| async move {
+        let _ = s.sockets.unbounded_send(sock);
+    })
+}
+
+fn close(code: u16, reason: &'static str) -> Message {
+    Message::Close(Some(CloseFrame {
+        code,
+        reason: reason.into(),
+    }))
+}
+
+/// Connection of the client under test to the action feed, once authenticated
+struct Feed {
+    sock: WebSocket,
+    token: AuthToken,
+    actions: mpsc::UnboundedReceiver<Action>,
+}
+
+impl Feed {
+    async fn send(&mut self, msg: &FeedMessage) -> Result<(), String> {
+        let json = serde_json::to_vec(msg).expect("serializing feed message");
+        self.sock
+            .send(Message::Binary(json))
+            .await
+            .map_err(|err| format!("sending {msg:?}: {err}"))
+    }
+
+    /// Relays the actions of the mock server until the client pings, failing if it sends
+    /// anything else or closes the feed
+    async fn until_ping(&mut self) -> Result<(), String> {
+        let ping_timeout = Duration::from_secs(2 * FEED_PING_INTERVAL_SECS);
+        let deadline = tokio::time::Instant::now() + ping_timeout;
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep_until(deadline) => {
+                    return Err(format!("client did not ping within {ping_timeout:?}"));
+                }
+                a = self.actions.next() => {
+                    let mut actions = vec![a.expect("mock server dropped the action feed")];
+                    while let Ok(Some(a)) = self.actions.try_next() {
+                        actions.push(a);
+                    }
+                    let msg = match actions.len() {
+                        1 => FeedMessage::Action(actions.pop().expect("checked length")),
+                        _ => FeedMessage::Actions(actions),
+                    };
+                    self.send(&msg).await?;
+                }
+                msg = self.sock.recv() => match msg {
+                    Some(Ok(Message::Text(t))) if t == FEED_PING => {
+                        return self.send(&FeedMessage::Pong).await;
+                    }
+                    None | Some(Ok(Message::Close(_))) => {
+                        return Err(String::from("client closed the feed"));
+                    }
+                    Some(Err(err)) => return Err(format!("reading from the feed: {err}")),
+                    Some(Ok(msg)) => {
+                        let _ = self
+                            .sock
+                            .send(close(FEED_CLOSE_PROTOCOL_ERROR, "unexpected message"))
+                            .await;
+                        return Err(format!("client sent unexpected frame {msg:?}"));
+                    }
+                },
+            }
+        }
+    }
+}
+
+/// Waits for the client to open the feed and send its session token
+async fn wait_for_token(
+    sockets: &mut mpsc::UnboundedReceiver<WebSocket>,
+    wait: Duration,
+) -> Result<(WebSocket, AuthToken), String> {
+    let mut sock = timeout(wait, sockets.next())
+        .await
+        .map_err(|_| format!("client did not connect within {wait:?}"))?
+        .expect("socket sender dropped");
+    let token = match timeout(AUTH_TIMEOUT, sock.recv()).await {
+        Ok(Some(Ok(Message::Text(t)))) => t,
+        Ok(msg) => return Err(format!("expected the session token, got {msg:?}")),
+        Err(_) => {
+            return Err(format!(
+                "client did not send its session token within {AUTH_TIMEOUT:?}"
+            ))
+        }
+    };
+    match Uuid::try_parse(&token) {
+        Ok(t) => Ok((sock, AuthToken(t))),
+        Err(_) => Err(format!("first frame {token:?} is not a session token")),
+    }
+}
+
+async fn deny(mut sock: WebSocket) {
+    let _ = sock
+        .send(Message::Text(String::from(FEED_AUTH_DENIED)))
+        .await;
+    let _ = sock
+        .send(close(FEED_CLOSE_PERMISSION_DENIED, FEED_AUTH_DENIED))
+        .await;
+}
+
+/// Waits for the client to open the feed, and authenticates it
+async fn accept(
+    server: &Server,
+    sockets: &mut mpsc::UnboundedReceiver<WebSocket>,
+    wait: Duration,
+) -> Result<Feed, String> {
+    let (mut sock, token) = wait_for_token(sockets, wait).await?;
+    let mut mock = server.mock.lock().await;
+    let actions = match mock.action_feed(token).await {
+        Ok(actions) => actions,
+        Err(_) => {
+            deny(sock).await;
+            return Err(format!("session token {token:?} is not valid"));
+        }
+    };
+    drop(mock);
+    sock.send(Message::Text(String::from(FEED_AUTH_OK)))
+        .await
+        .map_err(|err| format!("sending {FEED_AUTH_OK:?}: {err}"))?;
+    Ok(Feed {
+        sock,
+        token,
+        actions,
+    })
+}
+
+/// Checks that the client reconnects with `revoked`, and does not retry it once denied
+async fn denied(
+    sockets: &mut mpsc::UnboundedReceiver<WebSocket>,
+    revoked: AuthToken,
+) -> Result<(), String> {
+    let (sock, token) = wait_for_token(sockets, RECONNECT_TIMEOUT).await?;
+    if token != revoked {
+        return Err(format!(
+            "client reconnected with {token:?} instead of its session token"
+        ));
+    }
+    deny(sock).await;
+    match wait_for_token(sockets, RECONNECT_TIMEOUT).await {
+        Ok((sock, token)) if token == revoked => {
+            deny(sock).await;
+            Err(String::from("client retried its denied session token"))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Submits a task, as if from another device of the user
+async fn submit_task(server: &Server, token: AuthToken) -> TaskId {
+    let mut mock = server.mock.lock().await;
+    let user = mock.whoami(token).expect("checking session");
+    let task = Task {
+        id: TaskId(Uuid::new_v4()),
+        owner_id: user,
+        date: chrono::Utc::now(),
+        initial_title: String::from("Conformance task"),
+        top_comment_id: EventId(Uuid::new_v4()),
+    };
+    let id = task.id;
+    mock.submit_action(token, Action::NewTask(task, String::new()))
+        .await
+        .expect("submitting task");
+    id
+}
+
+/// Submits multiple events at once, that get relayed in a single message
+async fn submit_events(server: &Server, token: AuthToken, task: TaskId) {
+    let mut mock = server.mock.lock().await;
+    let user = mock.whoami(token).expect("checking session");
+    let events = [
+        EventData::SetTitle(String::from("Renamed conformance task")),
+        EventData::SetDone(true),
+    ];
+    let now = chrono::Utc::now();
+    for data in events {
+        mock.submit_action(token, Action::NewEvent(Event::at(user, task, now, data)))
+            .await
+            .expect("submitting event");
+    }
+}
+
+async fn check<T>(name: &str, f: impl Future<Output = Result<T, String>>) -> Result<T, String> {
+    let res = f.await;
+    match &res {
+        Ok(_) => println!("ok   {name}"),
+        Err(err) => println!("FAIL {name}: {err}"),
+    }
+    res
+}
+
+async fn run_checks(
+    server: &Server,
+    sockets: &mut mpsc::UnboundedReceiver<WebSocket>,
+) -> Result<(), String> {
+    let mut feed = check(
+        "authenticates with its session token",
+        accept(server, sockets, LOGIN_TIMEOUT),
+    )
+    .await?;
+    let task = submit_task(server, feed.token).await;
+    check("pings while receiving an action", feed.until_ping()).await?;
+    submit_events(server, feed.token, task).await;
+    check("pings while receiving multiple actions", feed.until_ping()).await?;
+
+    let _ = feed
+        .sock
+        .send(close(CLOSE_SERVICE_RESTART, "restart"))
+        .await;
+    let mut feed = check(
+        "reconnects after the server closed the feed",
+        accept(server, sockets, RECONNECT_TIMEOUT),
+    )
+    .await?;
+    check("pings after reconnecting", feed.until_ping()).await?;
+
+    server
+        .mock
+        .lock()
+        .await
+        .unauth(feed.token)
+        .expect("revoking session");
+    let _ = feed
+        .sock
+        .send(close(CLOSE_SERVICE_RESTART, "restart"))
+        .await;
+    check(
+        "does not retry a denied session token",
+        denied(sockets, feed.token),
+    )
+    .await
+}
+
+#[tokio::main]
+async fn main() {
+    let addr: SocketAddr = std::env::args()
+        .nth(1)
+        .as_deref()
+        .unwrap_or("127.0.0.1:3000")
+        .parse()
+        .expect("parsing listen address");
+
+    let mut mock = MockServer::new();
+    // The mock server does not hash passwords
+    let user = NewUser {
+        id: UserId(Uuid::new_v4()),
+        name: String::from(USER_NAME),
+        initial_password_hash: String::from(PASSWORD),
+    };
+    mock.admin_create_user(user, String::from(PASSWORD))
+        .await
+        .expect("creating user");
+
+    let (sockets, mut sockets_receiver) = mpsc::unbounded();
+    let server = Arc::new(Server {
+        mock: Mutex::new(mock),
+        sockets,
+    });
+    let app = Router::new()
+        .route("/api/instance-info", get(instance_info))
+        .route("/api/feed-protocol", get(feed_protocol))
+        .route("/api/auth", post(auth))
+        .route("/api/unauth", post(unauth))
+        .route("/api/whoami", get(whoami))
+        .route("/api/fetch-users", get(fetch_users))
+        .route("/api/fetch-tags", get(fetch_tags))
+        .route("/api/fetch-searches", get(fetch_searches))
+        .route("/api/search-tasks", post(search_tasks))
+        .route("/api/submit-action", post(submit_action))
+        .route("/api/committed-events", post(committed_events))
+        .route(FEED_PATH, get(action_feed))
+        .with_state(server.clone());
+    tokio::spawn(axum::Server::bind(&addr).serve(app.into_make_service()));
+
+    println!("serving on http://{addr}, log in as {USER_NAME:?} with password {PASSWORD:?}");
+    println!("keep the client in the foreground, as the checks expect regular pings");
+    if run_checks(&server, &mut sockets_receiver).await.is_err() {
+        std::process::exit(1);
+    }
+}
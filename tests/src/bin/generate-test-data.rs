@@ -17,6 +17,7 @@ const TASK_TITLE_LEN: usize = 10;
 
 const NUM_EVENTS: usize = 5000;
 const COMMENT_WORD_COUNT: usize = 10;
+const SECTIONS: [&str; 3] = ["Doing", "Next", "Later"];
 
 fn gen_n_items(table: &str, n: usize, mut f: impl FnMut(usize) -> String) {
     println!("INSERT INTO {} VALUES", table);
@@ -141,7 +142,11 @@ fn main() {
         let user = gen_user(&mut rng);
         let tag = gen_tag(&mut rng);
         let archived = rng.gen::<bool>();
-        format!("('{uuid}', '{user}', '{tag}', {archived})")
+        let sections = match rng.gen() {
+            true => SECTIONS.join(","),
+            false => String::new(),
+        };
+        format!("('{uuid}', '{user}', '{tag}', {archived}, '{{{sections}}}')")
     });
     let gen_tag = |rng: &mut StdRng| -> String { tags.choose(rng).unwrap().clone() };
 
@@ -184,6 +189,7 @@ fn main() {
             Order::ScheduledFor(OrderType::Desc) => "scheduled_for_desc",
             Order::BlockedUntil(OrderType::Asc) => "blocked_until_asc",
             Order::BlockedUntil(OrderType::Desc) => "blocked_until_desc",
+            Order::Priority => "priority",
         };
         format!("('{id}', '{owner}', '{name}', '{filter}', '{order_type}', '{prio}', {tag})")
     });
@@ -249,7 +255,7 @@ fn main() {
             )
         };
         let mut mk_bool = |rng: &mut StdRng| d_bool = if rng.gen() { "true" } else { "false" };
-        let mut mk_int = |rng: &mut StdRng| d_int = format!("{}", rng.gen::<i64>());
+        let mut mk_int = |i: i64| d_int = format!("{i}");
         let mut mk_time_maybe = |rng: &mut StdRng| {
             if rng.gen() {
                 d_time = format!("'{}'", gen_date(rng));
@@ -267,7 +273,7 @@ fn main() {
                 *date.borrow_mut() = par_date.checked_add_signed(offset).unwrap_or(failover);
             };
         let mut mk_order = |rng: &mut StdRng| d_order_id = format!("'{}'", gen_uuid(rng));
-        let d_type = match rng.gen_range(0..11) {
+        let d_type = match rng.gen_range(0..13) {
             // TODO: replace with gen_bolero::<DbEventType>
             0 => {
                 mk_text(&mut rng, true);
@@ -290,14 +296,14 @@ fn main() {
                 "schedule_for"
             }
             5 => {
-                mk_int(&mut rng);
+                mk_int(rng.gen());
                 mk_order(&mut rng);
                 "set_order"
             }
             6 => {
                 mk_tag(&mut rng);
                 mk_bool(&mut rng);
-                mk_int(&mut rng);
+                mk_int(rng.gen());
                 "add_tag"
             }
             7 => {
@@ -328,6 +334,19 @@ fn main() {
                 mk_parent(&mut rng, &comments);
                 "set_event_read"
             }
+            11 => {
+                if rng.gen() {
+                    mk_int(rng.gen_range(1..=4));
+                }
+                "set_priority"
+            }
+            12 => {
+                mk_tag(&mut rng);
+                if rng.gen() {
+                    d_text = format!("'{}'", SECTIONS.choose(&mut rng).unwrap());
+                }
+                "set_section"
+            }
             _ => panic!(),
         };
         let date = *date.borrow();